@@ -0,0 +1,159 @@
+// Copyright (c) 2015 - 2016, Alberto Corona <ac@albertocorona.com>
+// All rights reserved. This file is part of yabs, distributed under the BSD
+// 3-Clause license. For full terms please see the LICENSE file.
+
+use error::YabsError;
+use ext::glob_matches;
+
+use std::path::Path;
+
+// Include flags taking the directory as their next argument
+const SEPARATE_INCLUDE_FLAGS: &[&str] = &["-isystem", "-iquote", "-idirafter"];
+
+// Which sources a tool is run over: every source of the project, narrowed to
+// those of one binary or library and to those matching any of `patterns`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SourceSelector {
+    pub target: Option<String>,
+    pub patterns: Vec<String>,
+}
+
+impl SourceSelector {
+    pub fn all() -> SourceSelector {
+        SourceSelector::default()
+    }
+
+    pub fn target(name: &str) -> SourceSelector {
+        SourceSelector {
+            target: Some(name.to_owned()),
+            patterns: Vec::new(),
+        }
+    }
+
+    pub fn glob(pattern: &str) -> SourceSelector {
+        SourceSelector {
+            target: None,
+            patterns: vec![pattern.to_owned()],
+        }
+    }
+
+    // Whether `source` matches the patterns, the target is checked by the
+    // caller
+    pub fn matches(&self, source: &Path) -> Result<bool, YabsError> {
+        if self.patterns.is_empty() {
+            return Ok(true);
+        }
+        for pattern in &self.patterns {
+            if glob_matches(pattern, source)? {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+}
+
+// The compile command of one source taken apart for a tool template. `flags`
+// is everything the compiler is given besides the source and object, which
+// includes `includes` and `defines`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CompileParts {
+    pub source: String,
+    pub object: String,
+    pub flags: Vec<String>,
+    pub includes: Vec<String>,
+    pub defines: Vec<String>,
+}
+
+impl CompileParts {
+    // `compile_flags` starts with the compiler, `-c` and flags that only make
+    // sense when compiling are left out
+    pub fn new(compile_flags: &[String], source: &Path, object: &Path) -> CompileParts {
+        let flags: Vec<String> = compile_flags.iter()
+                                              .skip(1)
+                                              .filter(|arg| *arg != "-c" && *arg != "-MMD")
+                                              .cloned()
+                                              .collect();
+        let mut includes = Vec::new();
+        let mut defines = Vec::new();
+        let mut args = flags.iter();
+        while let Some(arg) = args.next() {
+            if SEPARATE_INCLUDE_FLAGS.contains(&arg.as_str()) {
+                includes.push(arg.clone());
+                includes.extend(args.next().cloned());
+            } else if arg.starts_with("-I") || SEPARATE_INCLUDE_FLAGS.iter().any(|flag| arg.starts_with(flag)) {
+                includes.push(arg.clone());
+            } else if arg.starts_with("-D") || arg.starts_with("-U") {
+                defines.push(arg.clone());
+            }
+        }
+        CompileParts {
+            source: source.display().to_string(),
+            object: object.display().to_string(),
+            flags,
+            includes,
+            defines,
+        }
+    }
+
+    fn list(&self, placeholder: &str) -> Option<&[String]> {
+        match placeholder {
+            "${flags}" => Some(&self.flags),
+            "${includes}" => Some(&self.includes),
+            "${defines}" => Some(&self.defines),
+            _ => None,
+        }
+    }
+
+    // The tool's argv for this source. A list placeholder standing alone
+    // becomes one argument per flag, inside a longer argument its flags are
+    // joined with spaces.
+    pub fn expand(&self, template: &[String]) -> Vec<String> {
+        let mut args = Vec::new();
+        for arg in template {
+            if let Some(list) = self.list(arg) {
+                args.extend(list.iter().cloned());
+                continue;
+            }
+            args.push(arg.replace("${src}", &self.source)
+                         .replace("${obj}", &self.object)
+                         .replace("${flags}", &self.flags.join(" "))
+                         .replace("${includes}", &self.includes.join(" "))
+                         .replace("${defines}", &self.defines.join(" ")));
+        }
+        args
+    }
+}
+
+#[test]
+fn test_expand_tool_template() {
+    use std::path::PathBuf;
+    let compile_flags: Vec<String> = ["gcc", "-c", "-std=c11", "-Wall", "-DNDEBUG", "-Iinclude", "-isystem",
+                                      "vendor", "-UDEBUG", "-MMD"]
+        .iter()
+        .map(|arg| arg.to_string())
+        .collect();
+    let parts = CompileParts::new(&compile_flags, &PathBuf::from("./src/a.c"), &PathBuf::from("./src/a.o"));
+    assert_eq!(parts.includes, vec!["-Iinclude", "-isystem", "vendor"]);
+    assert_eq!(parts.defines, vec!["-DNDEBUG", "-UDEBUG"]);
+    let template: Vec<String> = ["cppcheck", "${includes}", "${defines}", "--plist-output=${obj}.plist", "${src}"]
+        .iter()
+        .map(|arg| arg.to_string())
+        .collect();
+    assert_eq!(parts.expand(&template),
+               vec!["cppcheck",
+                    "-Iinclude",
+                    "-isystem",
+                    "vendor",
+                    "-DNDEBUG",
+                    "-UDEBUG",
+                    "--plist-output=./src/a.o.plist",
+                    "./src/a.c"]);
+    let template = vec![String::from("clang"), String::from("--analyze"), String::from("${flags}")];
+    assert_eq!(parts.expand(&template)[2..],
+               ["-std=c11", "-Wall", "-DNDEBUG", "-Iinclude", "-isystem", "vendor", "-UDEBUG"]);
+    assert_eq!(parts.expand(&[String::from("-x=${defines}")]), vec!["-x=-DNDEBUG -UDEBUG"]);
+    let selector = SourceSelector::glob("src/**/*.c");
+    assert!(selector.matches(Path::new("./src/a.c")).unwrap());
+    assert!(!selector.matches(Path::new("./test/a.c")).unwrap());
+    assert!(SourceSelector::all().matches(Path::new("./test/a.c")).unwrap());
+}
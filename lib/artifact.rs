@@ -0,0 +1,88 @@
+// Copyright (c) 2015 - 2016, Alberto Corona <ac@albertocorona.com>
+// All rights reserved. This file is part of yabs, distributed under the BSD
+// 3-Clause license. For full terms please see the LICENSE file.
+
+extern crate toml;
+
+use error::YabsError;
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+// What produced an artifact, recorded next to it in
+// `<artifact>.yabs-meta.toml`
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ArtifactMeta {
+    #[serde(rename = "flag-hash")]
+    pub flag_hash: String,
+    pub toolchain: String,
+    #[serde(rename = "build-hash")]
+    pub build_hash: String,
+    // Covers only the compile commands, objects are rebuilt when it changes
+    #[serde(rename = "compile-hash")]
+    #[serde(default)]
+    pub compile_hash: String,
+    // The build that produced the artifact, only for correlating logs and is
+    // never compared
+    #[serde(rename = "build-id")]
+    #[serde(default)]
+    pub build_id: String,
+}
+
+impl ArtifactMeta {
+    pub fn sidecar_path(artifact: &Path) -> PathBuf {
+        PathBuf::from(format!("{}.yabs-meta.toml", artifact.display()))
+    }
+
+    pub fn read(artifact: &Path) -> Result<ArtifactMeta, YabsError> {
+        let mut buffer = String::new();
+        File::open(ArtifactMeta::sidecar_path(artifact))?.read_to_string(&mut buffer)?;
+        Ok(toml::from_str(&buffer)?)
+    }
+
+    pub fn write(&self, artifact: &Path) -> Result<(), YabsError> {
+        let mut file = File::create(ArtifactMeta::sidecar_path(artifact))?;
+        Ok(file.write_all(toml::to_string(self)?.as_bytes())?)
+    }
+
+    pub fn remove(artifact: &Path) -> Result<(), YabsError> {
+        Ok(fs::remove_file(ArtifactMeta::sidecar_path(artifact))?)
+    }
+
+    // Describes how `self` differs from the configuration in `requested`, such
+    // as "different flags"
+    pub fn differences(&self, requested: &ArtifactMeta) -> Vec<String> {
+        let mut differences = Vec::new();
+        if self.flag_hash != requested.flag_hash {
+            differences.push(String::from("different flags"));
+        }
+        if self.toolchain != requested.toolchain {
+            differences.push(format!("toolchain '{}', requested '{}'",
+                                     self.toolchain,
+                                     requested.toolchain));
+        }
+        differences
+    }
+}
+
+#[test]
+fn test_artifact_meta_differences() {
+    let built = ArtifactMeta {
+        flag_hash: String::from("1"),
+        toolchain: String::from("gcc 13"),
+        ..Default::default()
+    };
+    let requested = ArtifactMeta {
+        flag_hash: String::from("1"),
+        toolchain: String::from("gcc 14"),
+        ..Default::default()
+    };
+    assert_eq!(built.differences(&requested), vec!["toolchain 'gcc 13', requested 'gcc 14'"]);
+    assert!(built.differences(&built).is_empty());
+    // Sidecars written before 'profile' and 'features' were dropped still
+    // read back
+    let old: ArtifactMeta = toml::from_str("profile = \"default\"\nfeatures = []\nflag-hash = \"1\"\n\
+                                            toolchain = \"gcc 13\"\nbuild-hash = \"2\"\n")
+        .unwrap();
+    assert_eq!(old.flag_hash, "1");
+}
@@ -0,0 +1,59 @@
+// Copyright (c) 2015 - 2016, Alberto Corona <ac@albertocorona.com>
+// All rights reserved. This file is part of yabs, distributed under the BSD
+// 3-Clause license. For full terms please see the LICENSE file.
+
+// Percentage of a 'max-size' budget an artifact may reach before yabs warns
+// about it, when 'max-size-warning' isn't set
+pub const DEFAULT_SIZE_WARNING: u64 = 90;
+
+const UNITS: &[(&str, u64)] = &[("b", 1),
+                                ("kb", 1000),
+                                ("kib", 1024),
+                                ("mb", 1000 * 1000),
+                                ("mib", 1024 * 1024),
+                                ("gb", 1000 * 1000 * 1000),
+                                ("gib", 1024 * 1024 * 1024)];
+
+// Bytes in a size such as `512KB`, `1.5 MiB` or `4096`. KB, MB and GB are
+// powers of 1000, KiB, MiB and GiB powers of 1024.
+pub fn parse_size(size: &str) -> Option<u64> {
+    let size = size.trim();
+    let split = size.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(size.len());
+    let (number, unit) = size.split_at(split);
+    let number = number.parse::<f64>().ok()?;
+    let unit = unit.trim().to_lowercase();
+    let multiplier = if unit.is_empty() {
+        1
+    } else {
+        UNITS.iter().find(|&&(name, _)| name == unit)?.1
+    };
+    Some((number * multiplier as f64).round() as u64)
+}
+
+// How `size` compares to the size recorded by an earlier build
+pub fn describe_change(previous: Option<u64>, size: u64) -> String {
+    match previous {
+        Some(previous) if size > previous => format!("{} bytes more than the last build", size - previous),
+        Some(previous) if size < previous => format!("{} bytes less than the last build", previous - size),
+        Some(_) => String::from("the same as the last build"),
+        None => String::from("no earlier size recorded"),
+    }
+}
+
+#[test]
+fn test_parse_size() {
+    assert_eq!(parse_size("512KB"), Some(512000));
+    assert_eq!(parse_size("512KiB"), Some(524288));
+    assert_eq!(parse_size("512 kib"), Some(524288));
+    assert_eq!(parse_size("1.5MiB"), Some(1572864));
+    assert_eq!(parse_size("2MB"), Some(2000000));
+    assert_eq!(parse_size("1GiB"), Some(1073741824));
+    assert_eq!(parse_size("4096"), Some(4096));
+    assert_eq!(parse_size("100B"), Some(100));
+    assert_eq!(parse_size("12XB"), None);
+    assert_eq!(parse_size("KB"), None);
+    assert_eq!(parse_size(""), None);
+    assert_eq!(describe_change(Some(1000), 1200), "200 bytes more than the last build");
+    assert_eq!(describe_change(Some(1000), 900), "100 bytes less than the last build");
+    assert_eq!(describe_change(None, 900), "no earlier size recorded");
+}
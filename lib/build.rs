@@ -7,31 +7,381 @@ extern crate toml;
 extern crate walkdir;
 extern crate ansi_term;
 
+use ansi_term::Colour;
 use desc::project::*;
+use desc::export::Export;
+use desc::inherit::{Origins, inherit};
+use desc::interpolate::interpolate;
+use desc::json::{is_json, parse_json, to_json};
+use desc::keys::{field_names, unknown_keys};
+use desc::modules::ModuleGraph;
+use desc::task::{Script, Task};
+use desc::tools::{ToolProbe, ToolRequirement, probe_tool};
+use analysis::{CompileParts, SourceSelector};
+use artifact::ArtifactMeta;
+use budget::{describe_change, parse_size};
+use cache::{CACHE_DIR, CacheStats, ObjectCache};
+use context::{BuildContext, KNOWN_OS};
+use deadline::Deadline;
+use diagnostics::{Severity, classify_link_output, diagnostics_by_file, link_suppress_patterns,
+                  parse_compiler_diagnostics, parse_gcov_summary};
 use error::{YabsError, YabsErrorKind};
-use ext::{Job, PrependEach, get_assumed_filename_for_dir, run_cmd, spawn_cmd};
+use events::{self, Event};
+use fscaps::FsCapabilities;
+use graph::{EdgeKind, Graph, NodeKind};
+use includes::{IncludeResolver, header_records, parse_depfile};
+use interrupt;
+use lock::ProjectLock;
+use log::LogLevel;
+use logger::{DIAGNOSTICS_TARGET, SUMMARY_TARGET, log_command};
+use plan::{BuildPlan, PlanDelta, PlanStep, StepKind};
+use report::{BuildReport, CompileTiming, LinkTiming, ToolReport, ToolRun};
+use space::{disk_space, free_space, human_size};
+use stale::StaleReason;
+use state::{BuildState, DEFAULT_STATE_DIR, ObjectRecord, SizeHistory, SourceHashes, StateCache};
+use verify::{VERIFY_DIR, is_system_path, strace_args, strace_reads};
+use ext::{CancellationToken, Job, PrependEach, ResponseFile, closest_match, closest_matches, command_from_args,
+          commit_temporary, default_jobs, discard_temporary, display_args, expand_glob, get_assumed_filename_for_dir,
+          hash_files, hash_strings, inline_toml, is_executable, is_out_of_space, json_string, log_output, new_build_id, run_args,
+          run_args_unlogged, run_args_within, run_cmd, shell_split, spawn_args, strip_ansi, temporary_path, tool_version};
 
-use std::collections::BTreeSet;
+use std::borrow::Cow;
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, BTreeSet};
 use std::env;
 use std::fs;
 use std::fs::File;
 use std::io::prelude::*;
-use std::path::{Path, PathBuf};
-use std::process::Child;
+use std::path::{Component, Path, PathBuf};
+use std::process::{Child, Command, Output, Stdio};
+use std::ptr;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-pub trait Buildable<T> {
-    fn path(&self) -> PathBuf;
+// Flags that tie a compile to a single input or read files relative to the
+// working directory, which batched compiles can't handle
+const UNBATCHABLE_FLAGS: &[&str] = &["-include",
+                                      "-imacros",
+                                      "-M",
+                                      "-save-temps",
+                                      "-fprofile-use",
+                                      "-x"];
+
+// More jobs than this per logical CPU are clamped, with a floor for machines
+// with few CPUs compiling through a distributed compiler
+const MAX_JOBS_PER_CPU: usize = 4;
+const MIN_MAX_JOBS: usize = 64;
+
+// Objects and profiles of `BuildFile::build_pgo`
+const PGO_DIR: &str = "pgo";
+
+// Flags every link of a build shares, gathered once per link
+pub struct LinkContext<'a> {
+    pub project: &'a ProjectDesc,
+    pub extra_flags: &'a [String],
+    pub lib_args: Vec<String>,
+    // LDFLAGS from the environment
+    pub env_flags: Vec<String>,
+    // The C++ compiler when any object is compiled from C++, so its runtime is
+    // linked in
+    pub driver: String,
+    // `-Wl,-rpath` for binaries
+    pub rpath_args: Vec<String>,
+}
+
+// A target whose objects are compiled from the project's sources and linked
+// into one or more outputs
+pub trait Buildable {
+    fn name(&self) -> String;
+
+    // Each output with the kind of link producing it. The first output
+    // decides which objects are stale.
+    fn outputs(&self) -> Vec<(TargetKind, PathBuf)>;
+
+    fn output_paths(&self) -> Vec<PathBuf> {
+        self.outputs().into_iter().map(|(_, path)| path).collect()
+    }
+
+    fn kinds(&self) -> Vec<TargetKind> {
+        self.outputs().into_iter().map(|(kind, _)| kind).collect()
+    }
+
+    // Objects linked into the target's outputs of `kind`, `binaries` are all
+    // binaries of the build file as their entry points can't be linked into
+    // each other
+    fn objects_of(&self,
+                  kind: TargetKind,
+                  project: &ProjectDesc,
+                  binaries: &[Binary])
+                  -> Result<Vec<PathBuf>, YabsError>;
+
+    // Objects linked into any of the target's outputs
+    fn objects(&self, project: &ProjectDesc, binaries: &[Binary]) -> Result<Vec<PathBuf>, YabsError> {
+        let mut objects = Vec::new();
+        for kind in self.kinds() {
+            for object in self.objects_of(kind, project, binaries)? {
+                if !objects.contains(&object) {
+                    objects.push(object);
+                }
+            }
+        }
+        Ok(objects)
+    }
+
+    // Whether outputs of `kind` link the target's position independent
+    // objects rather than the ones binaries and archives link
+    fn links_pic(&self, kind: TargetKind, project: &ProjectDesc) -> bool {
+        kind == TargetKind::DynamicLibrary && project.needs_pic_objects(&self.overrides())
+    }
+
+    // Which of the plain and the position independent objects the target's
+    // outputs link, see `links_pic`
+    fn object_flavors(&self, project: &ProjectDesc) -> Vec<bool> {
+        let mut flavors = Vec::new();
+        for kind in self.kinds() {
+            let pic = self.links_pic(kind, project);
+            if !flavors.contains(&pic) {
+                flavors.push(pic);
+            }
+        }
+        flavors
+    }
+
+    // The sources whose objects the target's outputs could link, with when
+    // each was last modified. Objects compiled for other targets aren't
+    // among them, so they don't make the target stale.
+    fn sources<'a>(&self, project: &'a ProjectDesc) -> Vec<(&'a Target, &'a SystemTime)> {
+        let owner = self.object_owner();
+        let mut sources = Vec::new();
+        for pic in self.object_flavors(project) {
+            sources.extend(project.file_mod_map
+                                  .iter()
+                                  .filter(|(target, _)| target.owner() == owner.as_deref() && target.is_pic() == pic));
+        }
+        sources
+    }
+
+    // The argv linking `objects` into `output`, an output of `kind`
+    fn link_args(&self,
+                 kind: TargetKind,
+                 output: &Path,
+                 link: &LinkContext,
+                 objects: Vec<String>)
+                 -> Vec<String>;
+
+    // The command linking `objects` into `output`, an output of `kind`. A
+    // build runs `link_args` itself, through a response file when the
+    // objects don't fit on the command line.
+    fn link(&self, kind: TargetKind, output: &Path, link: &LinkContext, objects: &[PathBuf]) -> Command {
+        command_from_args(&self.link_args(kind,
+                                          output,
+                                          link,
+                                          objects.iter().map(|object| object.to_string_lossy().into_owned()).collect()))
+    }
+
+    fn incremental_link_groups(&self) -> usize {
+        0
+    }
+
+    // 'max-size' of the target, each of its outputs has to fit it
+    fn max_size(&self) -> Option<String> {
+        None
+    }
+
+    // Directory holding the relocatable objects of each link group
+    fn partial_link_dir(&self) -> PathBuf {
+        PathBuf::from(self.name() + ".partial")
+    }
+
+    // What the target lays over the project's settings for itself
+    fn overrides(&self) -> Overrides {
+        Overrides::default()
+    }
+
+    // The name the target's objects are compiled for when they're compiled
+    // with settings of its own, so they aren't shared with other targets
+    fn object_owner(&self) -> Option<String> {
+        if self.overrides().changes_compiles() {
+            Some(self.name())
+        } else {
+            None
+        }
+    }
+
+    // Directory holding the objects compiled for the target alone
+    fn object_dir(&self) -> PathBuf {
+        PathBuf::from(self.name() + ".objects")
+    }
+
+    // Directory the target's outputs are written to instead of the project's
+    fn out_dir(&self) -> Option<PathBuf> {
+        None
+    }
+
+    // Whether a build of everything builds the target, those with
+    // 'default = false' are only built when they're named
+    fn is_default(&self) -> bool {
+        true
+    }
+
+    // Files besides its objects the target's link reads, it's linked again
+    // when one of them changes
+    fn link_inputs(&self) -> Vec<PathBuf> {
+        vec![]
+    }
 }
 
-impl<T> Buildable<T> for Binary {
-    fn path(&self) -> PathBuf {
-        PathBuf::from(self.name())
+impl Buildable for Binary {
+    fn name(&self) -> String {
+        Binary::name(self)
+    }
+
+    fn outputs(&self) -> Vec<(TargetKind, PathBuf)> {
+        vec![(TargetKind::Binary, PathBuf::from(self.output_name()))]
+    }
+
+    fn objects_of(&self,
+                  _: TargetKind,
+                  project: &ProjectDesc,
+                  binaries: &[Binary])
+                  -> Result<Vec<PathBuf>, YabsError> {
+        let owner = self.object_owner();
+        if binaries.len() == 1 {
+            project.object_list(owner.as_deref(), false, None)
+        } else {
+            project.object_list(owner.as_deref(),
+                                false,
+                                Some(binaries.iter()
+                                             .filter(|bin| bin.path() != self.path())
+                                             .cloned()
+                                             .collect()))
+        }
+    }
+
+    fn link_args(&self,
+                 _: TargetKind,
+                 output: &Path,
+                 link: &LinkContext,
+                 objects: Vec<String>)
+                 -> Vec<String> {
+        let mut args = vec![link.driver.clone()];
+        args.extend(link.project.target_args());
+        args.extend(link.project.lflags.as_ref().unwrap_or(&vec![]).prepend_each("-"));
+        args.extend(link.project.pkg_config_libs.iter().cloned());
+        args.extend(link.rpath_args.clone());
+        args.extend(link.env_flags.clone());
+        args.extend(link.project.sanitize_link_args());
+        args.extend(link.project.coverage_args());
+        args.extend(link.project.lto_args());
+        args.extend(link.extra_flags.iter().cloned());
+        args.push(String::from("-o"));
+        args.push(output.to_string_lossy().into_owned());
+        args.extend(objects);
+        args.extend(link.project.lib_dir_args());
+        args.extend(link.lib_args.clone());
+        args
+    }
+
+    fn incremental_link_groups(&self) -> usize {
+        Binary::incremental_link_groups(self)
+    }
+
+    fn max_size(&self) -> Option<String> {
+        Binary::max_size(self)
+    }
+
+    fn overrides(&self) -> Overrides {
+        Binary::overrides(self)
+    }
+
+    fn out_dir(&self) -> Option<PathBuf> {
+        self.bin_dir()
+    }
+
+    fn is_default(&self) -> bool {
+        Binary::is_default(self)
     }
 }
 
-impl<T> Buildable<T> for Library {
-    fn path(&self) -> PathBuf {
-        self.path()
+impl Buildable for Library {
+    fn name(&self) -> String {
+        Library::name(self)
+    }
+
+    fn outputs(&self) -> Vec<(TargetKind, PathBuf)> {
+        let mut outputs = Vec::new();
+        if self.is_static() {
+            outputs.push((TargetKind::StaticLibrary, self.static_file_name()));
+        }
+        if self.is_dynamic() {
+            outputs.push((TargetKind::DynamicLibrary, self.dynamic_file_name()));
+        }
+        outputs
+    }
+
+    fn objects_of(&self,
+                  kind: TargetKind,
+                  project: &ProjectDesc,
+                  _: &[Binary])
+                  -> Result<Vec<PathBuf>, YabsError> {
+        project.object_list(self.object_owner().as_deref(), self.links_pic(kind, project), None)
+    }
+
+    fn max_size(&self) -> Option<String> {
+        Library::max_size(self)
+    }
+
+    fn overrides(&self) -> Overrides {
+        Library::overrides(self)
+    }
+
+    fn out_dir(&self) -> Option<PathBuf> {
+        self.lib_out_dir()
+    }
+
+    fn is_default(&self) -> bool {
+        Library::is_default(self)
+    }
+
+    fn link_inputs(&self) -> Vec<PathBuf> {
+        self.version_script().into_iter().filter(|_| self.is_dynamic()).collect()
+    }
+
+    fn link_args(&self,
+                 kind: TargetKind,
+                 output: &Path,
+                 link: &LinkContext,
+                 objects: Vec<String>)
+                 -> Vec<String> {
+        if kind == TargetKind::StaticLibrary {
+            let mut args = vec![link.project.ar()];
+            args.extend(link.project.arflags());
+            args.push(output.to_string_lossy().into_owned());
+            args.extend(objects);
+            return args;
+        }
+        let mut args = vec![link.driver.clone(), String::from("-shared")];
+        args.extend(link.project.target_args());
+        args.extend(link.project.lflags.as_ref().unwrap_or(&vec![]).prepend_each("-"));
+        args.extend(link.project.pkg_config_libs.iter().cloned());
+        args.extend(link.env_flags.clone());
+        args.extend(link.project.sanitize_link_args());
+        args.extend(link.project.coverage_args());
+        args.extend(link.project.lto_args());
+        args.extend(link.extra_flags.iter().cloned());
+        if let Some(script) = self.version_script() {
+            if link.project.supports_version_scripts() {
+                args.push(format!("-Wl,--version-script={}", script.display()));
+            }
+        }
+        args.push(String::from("-o"));
+        args.push(output.to_string_lossy().into_owned());
+        args.extend(objects);
+        args.extend(link.project.lib_dir_args());
+        args.extend(link.lib_args.clone());
+        args
     }
 }
 
@@ -43,274 +393,4041 @@ pub struct BuildFile {
     binaries: Option<Vec<Binary>>,
     #[serde(rename = "lib")]
     libraries: Option<Vec<Library>>,
+    #[serde(rename = "task")]
+    tasks: Option<Vec<Task>>,
+    // Names standing for the binaries and libraries they list, or for the
+    // members of other groups they list
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    groups: BTreeMap<String, Vec<String>>,
+    // `[target.<os>]` sections, already merged into `project` once loaded
+    #[serde(rename = "target", default, skip_serializing)]
+    platforms: BTreeMap<String, Overrides>,
+    // Which buildfile each key came from, when there's more than one
+    #[serde(skip)]
+    origins: Origins,
+    // Flags added to every compile and link command, used by multi-stage
+    // builds such as `build_pgo`
+    #[serde(skip)]
+    extra_flags: Vec<String>,
+    #[serde(skip)]
+    build_id: String,
+    #[serde(skip)]
+    cancel: CancellationToken,
+    #[serde(skip)]
+    cache_stats: Cell<CacheStats>,
+    // Found when 'cxx-modules' is enabled
+    #[serde(skip)]
+    modules: Option<ModuleGraph>,
+    #[serde(skip)]
+    state: BuildState,
+    // What the filesystem holding the state directory can do, cached in the
+    // state by the first build
+    #[serde(skip)]
+    fs_caps: FsCapabilities,
+    #[serde(skip)]
+    report: RefCell<BuildReport>,
+    // Logs why each object is compiled, set by `yabs build --explain`
+    #[serde(skip)]
+    explain: bool,
+    #[serde(skip)]
+    deadline: Option<Deadline>,
+    // How long running compiles may go on past the deadline before they're
+    // killed, None waits for them
+    #[serde(skip)]
+    deadline_grace: Option<Duration>,
+    // The deadline of the running build
+    #[serde(skip)]
+    deadline_at: Cell<Option<Instant>>,
+    // Fail instead of waiting when another yabs process holds the lock
+    #[serde(skip)]
+    lock_fail_fast: bool,
+    // Whether yabs colors its own output, see `set_color_diagnostics`
+    #[serde(skip)]
+    color_diagnostics: bool,
+    // Cleans only say what they would remove, see `set_dry_run`
+    #[serde(skip)]
+    dry_run: bool,
+    // Every object is compiled and every output linked, see `set_force`
+    #[serde(skip)]
+    force: bool,
+    // The objects a forced build has queued, so objects shared between
+    // targets are compiled once
+    #[serde(skip)]
+    forced: RefCell<BTreeSet<PathBuf>>,
+    // The room the compiles queued so far may need, see `check_disk_space`
+    #[serde(skip)]
+    space_needed: Cell<u64>,
+    // Worked out once per build, see `current_artifact_meta`
+    #[serde(skip)]
+    artifact_meta: RefCell<Option<ArtifactMeta>>,
+    // Whether each compiler accepts `-fdiagnostics-color`, probed once
+    #[serde(skip)]
+    diagnostics_color_support: RefCell<BTreeMap<String, bool>>,
+}
+
+// How many of a target's stale objects have started compiling, counted when
+// they're spawned or copied from the object cache
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Progress {
+    started: usize,
+    total: usize,
+}
+
+impl Progress {
+    fn new(total: usize) -> Progress {
+        Progress { started: 0, total }
+    }
+
+    // Counts `count` more objects, returning a counter like `[ 37/212]`
+    fn advance(&mut self, count: usize) -> String {
+        self.started += count;
+        format!("[{:>width$}/{}]", self.started, self.total, width = self.total.to_string().len())
+    }
+}
+
+// What a pass over the job queue keeps track of while jobs run
+struct JobPass<'a> {
+    progress: &'a mut Progress,
+    running: Vec<(Job, Vec<Target>)>,
+    state: StateCache,
+    hashes: SourceHashes,
+    cache: Option<ObjectCache>,
+    // Cache keys of the objects being compiled
+    keys: BTreeMap<PathBuf, String>,
+    // `--version` of each compiler, part of every cache key
+    versions: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TargetKind {
+    Binary,
+    StaticLibrary,
+    DynamicLibrary,
+    Task,
+}
+
+impl TargetKind {
+    pub fn describe(&self) -> &'static str {
+        match *self {
+            TargetKind::Binary => "binary",
+            TargetKind::StaticLibrary => "static library",
+            TargetKind::DynamicLibrary => "dynamic library",
+            TargetKind::Task => "task",
+        }
+    }
+}
+
+// A source as listed by `BuildFile::sources_for`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceInfo {
+    pub source: PathBuf,
+    pub object: PathBuf,
+    pub modified: SystemTime,
+}
+
+// A target as listed by `BuildFile::targets`, a library built both ways is
+// listed once for each
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TargetInfo {
+    pub kind: TargetKind,
+    pub name: String,
+    // What the target builds, None for tasks
+    pub output: Option<PathBuf>,
+    // Whether a plain build builds it, never for tasks
+    pub default: bool,
+    pub exists: bool,
+    // Whether building it would do nothing, tasks are never considered up to
+    // date since whether a task runs depends on its command
+    pub up_to_date: bool,
+}
+
+// What building a binary or library would do, found by `BuildFile::status`
+#[derive(Debug, Clone, PartialEq)]
+pub struct TargetStatus {
+    pub name: String,
+    pub outputs: Vec<PathBuf>,
+    // Each object that would be compiled with why
+    pub stale_objects: Vec<(PathBuf, StaleReason)>,
+    pub objects: usize,
+    // Whether the outputs would be linked or archived again
+    pub relink: bool,
+}
+
+impl TargetStatus {
+    pub fn is_up_to_date(&self) -> bool {
+        self.stale_objects.is_empty() && !self.relink
+    }
+}
+
+// `BuildFile::status` for every binary and library
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BuildStatus {
+    pub targets: Vec<TargetStatus>,
+}
+
+impl BuildStatus {
+    // Whether a build would do anything
+    pub fn is_dirty(&self) -> bool {
+        self.targets.iter().any(|target| !target.is_up_to_date())
+    }
 }
 
 impl BuildFile {
     // Creates a `Profiles` from a toml file. Is essentiall `BuildFile::new`
     pub fn from_file<T: AsRef<Path>>(filepath: &T) -> Result<BuildFile, YabsError> {
+        BuildFile::from_file_with_context(filepath, BuildContext::from_env())
+    }
+
+    // Like `from_file`, reading environment variables, the working directory
+    // and the time from `context` instead of the process
+    pub fn from_file_with_context<T: AsRef<Path>>(filepath: &T,
+                                                  context: BuildContext)
+                                                  -> Result<BuildFile, YabsError> {
+        let filepath = context.cwd.join(filepath);
         let mut buffer = String::new();
-        let mut file = File::open(filepath)?;
+        let mut file = File::open(&filepath)?;
         file.read_to_string(&mut buffer)?;
-        let mut build_file: BuildFile = toml::from_str(&buffer)?;
-        build_file.project.find_source_files()?;
+        let mut value: toml::Value = if is_json(&filepath) { parse_json(&buffer)? } else { buffer.parse()? };
+        let origins = inherit(&filepath, &mut value)?;
+        let expanded = interpolate(&mut value, &context)?;
+        // Deserialized from the text when it's used as it is, so errors keep
+        // their line numbers
+        let mut build_file: BuildFile = if origins.is_empty() && !expanded && !is_json(&filepath) {
+            toml::from_str(&buffer)?
+        } else {
+            value.clone().try_into()?
+        };
+        build_file.origins = origins;
+        build_file.project.context = context;
+        build_file.check_unknown_keys(&value)?;
+        build_file.merge_platforms();
+        build_file.build_id = new_build_id(build_file.context());
+        build_file.project.validate()?;
+        for library in build_file.libraries.iter().flatten() {
+            validate_visibility(&library.overrides().visibility)?;
+        }
+        build_file.project.resolve_deps()?;
+        build_file.project.resolve_pkg_config()?;
+        build_file.project.warn_missing_dirs();
+        build_file.validate_names()?;
+        build_file.validate_tasks()?;
+        build_file.validate_groups()?;
+        build_file.validate_size_budgets()?;
+        build_file.state = BuildState::load(&build_file.project.state_dir());
+        build_file.project.source_records = build_file.state.sources.clone();
+        build_file.load_fs_caps();
+        build_file.find_objects()?;
+        build_file.scan_modules()?;
         Ok(build_file)
     }
 
-    pub fn print_sources(&mut self) {
-        for target in self.project.file_mod_map.keys() {
-            info!("{}", target.source().display());
+    // The capabilities a build probed before, see `probe_fs_caps`. Until one
+    // has the filesystem is trusted with everything, so loading writes nothing.
+    fn load_fs_caps(&mut self) {
+        self.fs_caps = self.state.fs_caps.unwrap_or_default();
+        self.adapt_to_fs_caps();
+    }
+
+    // Where modification times are too coarse to trust, sources are compared
+    // by their contents unless 'change-detection' says otherwise. True when
+    // that changed.
+    fn adapt_to_fs_caps(&mut self) -> bool {
+        if !self.fs_caps.fine_mtime && self.project.change_detection.is_none() {
+            self.project.change_detection = Some(ChangeDetection::Hash);
+            return true;
         }
+        false
     }
 
-    fn spawn_build_object(&self, target: &Target) -> Result<(String, Child), YabsError> {
-        let command = &format!("{CC} -c {CFLAGS} {INC} -o \"{OBJ}\" \"{SRC}\"",
-                CC =
-                    &self.project.compiler.as_ref().unwrap_or(&String::from("gcc")),
-                CFLAGS = &self.project
-                              .compiler_flags
-                              .as_ref()
-                              .unwrap_or(&vec![])
-                              .prepend_each("-")
-                              .join(" "),
-                INC = &self.project
-                           .include
-                           .as_ref()
-                           .unwrap_or(&vec![])
-                           .prepend_each("-I")
-                           .join(" "),
-                OBJ = target.object().to_str().unwrap(),
-                SRC = target.source().to_str().unwrap());
-        Ok((command.to_owned(), spawn_cmd(command)?))
-    }
-
-    fn build_object_queue<T: Buildable<T>>(&self,
-                                           build_target: &T)
-                                           -> Result<Vec<Target>, YabsError> {
-        let mut queue = BTreeSet::new();
-        let target_path = build_target.path();
-        if target_path.exists() {
-            for (target, modtime) in &self.project.file_mod_map {
-                if modtime > &fs::metadata(&target_path)?.modified()? || !target.object().exists() {
-                    queue.insert(target.clone());
-                }
+    // Probes the filesystem objects are written to before the first build
+    // writes any, warning about what it lacks. The result is cached in the
+    // state file.
+    fn probe_fs_caps(&mut self) -> Result<(), YabsError> {
+        if self.state.fs_caps.is_some() {
+            return Ok(());
+        }
+        let caps = FsCapabilities::probe(&self.object_root()?)?;
+        for limitation in caps.limitations() {
+            warn!("{}", limitation);
+        }
+        self.set_fs_caps(caps)?;
+        self.state.save()
+    }
+
+    // The directory objects are written under, created if need be
+    fn object_root(&self) -> Result<PathBuf, YabsError> {
+        let dir = self.project.resolve(self.project.object_dir().unwrap_or_else(|| PathBuf::from(".")));
+        fs::create_dir_all(&dir)?;
+        Ok(dir)
+    }
+
+    // Builds as if the filesystem could do what `caps` says instead of
+    // probing it, such as in tests that can't get hold of a FAT filesystem
+    pub fn set_fs_caps(&mut self, caps: FsCapabilities) -> Result<(), YabsError> {
+        self.fs_caps = caps;
+        self.state.fs_caps = Some(caps);
+        if self.adapt_to_fs_caps() {
+            self.project.record_sources()?;
+        }
+        Ok(())
+    }
+
+    // Finds the sources with the objects they're compiled into, the shared
+    // ones and those of each target compiling its own
+    fn find_objects(&mut self) -> Result<(), YabsError> {
+        self.project.find_source_files()?;
+        let owned: Vec<(String, PathBuf)> = self.buildables()
+                                                .into_iter()
+                                                .filter_map(|target| {
+                                                                target.object_owner()
+                                                                      .map(|owner| (owner, target.object_dir()))
+                                                            })
+                                                .collect();
+        let shared: Vec<(Target, SystemTime)> =
+            self.project.file_mod_map.iter().map(|(target, modtime)| (target.clone(), *modtime)).collect();
+        for (owner, dir) in &owned {
+            for (target, modtime) in &shared {
+                self.project.file_mod_map.insert(target.owned_by(owner, dir), *modtime);
             }
-        } else {
-            for target in self.project.file_mod_map.keys() {
-                if !target.object().exists() {
-                    queue.insert(target.clone());
-                }
+        }
+        // Dynamic libraries compile the objects they'd share with the rest
+        // again, position independent
+        let pic: Vec<Option<String>> = self.buildables()
+                                           .into_iter()
+                                           .filter(|target| target.object_flavors(&self.project).contains(&true))
+                                           .map(|target| target.object_owner())
+                                           .collect();
+        for owner in &pic {
+            let plain: Vec<(Target, SystemTime)> = self.project
+                                                       .targets_of(owner.as_deref(), false)
+                                                       .map(|(target, modtime)| (target.clone(), *modtime))
+                                                       .collect();
+            for (target, modtime) in plain {
+                self.project.file_mod_map.insert(target.position_independent(), modtime);
             }
         }
-        Ok(queue.iter().cloned().collect())
+        if let Some(dir) = self.project.object_dir() {
+            self.project.relocate_objects(&dir);
+        }
+        self.project.check_object_collisions()
     }
 
-    fn build_all_binaries(&mut self, jobs: usize) -> Result<(), YabsError> {
-        if !&self.binaries.is_some() {
-            return Ok(());
+    // Warns about keys none of the buildfile's tables know about, which fail
+    // the load when it's strict
+    fn check_unknown_keys(&self, buildfile: &toml::Value) -> Result<(), YabsError> {
+        let mut unknown = unknown_keys(buildfile, "", field_names::<BuildFile>());
+        for &(table, fields) in &[("project", field_names::<ProjectDesc>()),
+                                  ("bin", field_names::<Binary>()),
+                                  ("lib", field_names::<Library>()),
+                                  ("task", field_names::<Task>())] {
+            if let Some(value) = buildfile.get(table) {
+                unknown.extend(unknown_keys(value, table, fields));
+            }
+        }
+        if let Some(platforms) = buildfile.get("target").and_then(toml::Value::as_table) {
+            for (name, value) in platforms {
+                unknown.extend(unknown_keys(value, &format!("target.{}", name), field_names::<Overrides>()));
+            }
+        }
+        let strict = self.context().strict || self.project.strict.unwrap_or(false);
+        for key in &unknown {
+            if strict {
+                error!("{}", key);
+            } else {
+                warn!("{}", key);
+            }
         }
-        for binary in self.binaries.clone().unwrap() {
-            let job_queue = self.build_object_queue(&binary)?;
-            self.run_job_queue(job_queue, jobs)?;
-            self.build_binary(&binary)?;
+        if strict && !unknown.is_empty() {
+            bail!(YabsErrorKind::UnknownKeys(unknown.len()));
         }
         Ok(())
     }
 
-    fn run_job_queue(&self, mut job_queue: Vec<Target>, jobs: usize) -> Result<(), YabsError> {
-        let mut job_processes: Vec<Job> = Vec::new();
-        while !job_queue.is_empty() {
-            if job_processes.len() < jobs {
-                if let Some(target) = job_queue.pop() {
-                    let job = Job::new(self.spawn_build_object(&target)?);
-                    info!("{}", job.command());
-                    job_processes.push(job);
-                }
-            } else {
-                while !job_processes.is_empty() {
-                    if let Some(mut job) = job_processes.pop() {
-                        job.yield_self()?;
-                    }
-                }
+    // Merges the `[target.<os>]` section of the OS being built for into the
+    // project. Sections for an OS yabs doesn't know would never apply, so
+    // they're warned about.
+    fn merge_platforms(&mut self) {
+        for name in self.platforms.keys().filter(|name| !KNOWN_OS.contains(&name.as_str())) {
+            match closest_match(name, KNOWN_OS.iter().cloned()) {
+                Some(known) => {
+                    warn!("[target.{}] is ignored, '{}' isn't a known OS; did you mean `{}`?", name, name, known)
+                },
+                None => {
+                    warn!("[target.{}] is ignored, '{}' isn't a known OS, expected one of {}",
+                          name,
+                          name,
+                          KNOWN_OS.join(", "))
+                },
+            }
+        }
+        let os = match self.project.target_platform() {
+            Some(platform) => platform.os,
+            None => return,
+        };
+        if let Some(platform) = self.platforms.get(&os).cloned() {
+            debug!("merging [target.{}] into the project", os);
+            self.project.merge_overrides(&platform);
+        }
+    }
+
+    // The buildfile as JSON, which `from_file` reads back from a `.json` file
+    pub fn to_json_string(&self) -> Result<String, YabsError> {
+        Ok(to_json(&toml::Value::try_from(self)?, 0) + "\n")
+    }
+
+    // Every key of the project and its targets with the value the build uses
+    // and where it came from, as TOML that can be diffed against the
+    // buildfile. Keys without a value are left commented out.
+    pub fn effective_config(&self) -> Result<String, YabsError> {
+        let buildfile = toml::Value::try_from(self)?;
+        let project = buildfile.get("project");
+        let is_set = |key: &str| project.and_then(|project| project.get(key)).is_some();
+        let mut resolved: BTreeMap<&str, (toml::Value, String)> = BTreeMap::new();
+        let (compiler, source) = self.project.compiler_choice();
+        resolved.insert("compiler", (toml::Value::String(compiler), source));
+        for &(key, language) in &[("compiler-c", Language::C), ("compiler-cxx", Language::Cxx)] {
+            let (compiler, source) = self.project.compiler_choice_for(language);
+            resolved.insert(key, (toml::Value::String(compiler), source));
+        }
+        let ar_source = if self.project.ar.is_some() {
+            "buildfile"
+        } else if self.project.ar_is_explicit() {
+            "from AR"
+        } else {
+            "default"
+        };
+        resolved.insert("ar", (toml::Value::String(self.project.ar()), ar_source.to_owned()));
+        // What the build uses for the keys that aren't set
+        let defaults = [("arflags", toml::Value::String(self.project.arflags().join(" ")), "default"),
+                        ("jobs", toml::Value::Integer(self.job_count(0) as i64), "default, one per CPU"),
+                        ("respect-env", toml::Value::Boolean(self.project.respects_env()), "default"),
+                        ("use-env-flags", toml::Value::Boolean(self.project.uses_env_flags()), "default"),
+                        ("cache-max-size", toml::Value::Integer(self.project.cache_max_size() as i64), "default"),
+                        ("disk-space-factor", toml::Value::Integer(self.project.disk_space_factor() as i64), "default"),
+                        ("max-size-warning", toml::Value::Integer(self.project.max_size_warning() as i64), "default")];
+        for &(key, ref value, source) in &defaults {
+            if !is_set(key) {
+                resolved.insert(key, (value.clone(), source.to_owned()));
             }
         }
-        while !job_processes.is_empty() {
-            if let Some(mut job) = job_processes.pop() {
-                job.yield_self()?;
+        if self.project.sources_walked {
+            let sources = self.project
+                              .shared_targets()
+                              .map(|target| toml::Value::String(target.source().display().to_string()));
+            resolved.insert("src",
+                            (toml::Value::Array(sources.collect()), String::from("found in the project's directory")));
+        }
+        let mut config = String::from("[project]\n");
+        write_config_section(&mut config,
+                             project,
+                             field_names::<ProjectDesc>(),
+                             &resolved,
+                             &self.origins,
+                             "project");
+        for var in &["CFLAGS", "CXXFLAGS", "LDFLAGS"] {
+            let flags = self.project.env_flags(var)?;
+            if !flags.is_empty() {
+                config.push_str(&format!("# {} adds {}\n", var, display_args(&flags)));
             }
         }
+        for (index, binary) in self.binaries.iter().flatten().enumerate() {
+            self.write_target_config(&mut config,
+                                     &buildfile,
+                                     ("bin", index),
+                                     field_names::<Binary>(),
+                                     binary,
+                                     binary.max_size());
+        }
+        for (index, library) in self.libraries.iter().flatten().enumerate() {
+            self.write_target_config(&mut config,
+                                     &buildfile,
+                                     ("lib", index),
+                                     field_names::<Library>(),
+                                     library,
+                                     library.max_size());
+        }
+        for (index, _) in self.tasks.iter().flatten().enumerate() {
+            config.push_str("\n[[task]]\n");
+            write_config_section(&mut config,
+                                 buildfile.get("task").and_then(|tasks| tasks.get(index)),
+                                 field_names::<Task>(),
+                                 &BTreeMap::new(),
+                                 &self.origins,
+                                 &format!("task.{}", index));
+        }
+        Ok(config)
+    }
+
+    // The `index`th `[[bin]]` or `[[lib]]` of `effective_config`, followed by
+    // what it builds
+    fn write_target_config(&self,
+                           config: &mut String,
+                           buildfile: &toml::Value,
+                           (table, index): (&str, usize),
+                           fields: &[&str],
+                           buildable: &dyn Buildable,
+                           max_size: Option<String>) {
+        let mut resolved: BTreeMap<&str, (toml::Value, String)> = BTreeMap::new();
+        if let (None, Some(max_size)) = (max_size, self.project.max_size.as_ref()) {
+            resolved.insert("max-size", (toml::Value::String(max_size.clone()), String::from("from [project]")));
+        }
+        config.push_str(&format!("\n[[{}]]\n", table));
+        let target = buildfile.get(table).and_then(|targets| targets.get(index));
+        write_config_section(config, target, fields, &resolved, &self.origins, &format!("{}.{}", table, index));
+        let outputs: Vec<String> = self.output_paths(buildable).iter().map(|path| path.display().to_string()).collect();
+        config.push_str(&format!("# builds {}\n", outputs.join(", ")));
+    }
+
+    fn scan_modules(&mut self) -> Result<(), YabsError> {
+        if self.project.cxx_modules.unwrap_or(false) {
+            self.modules = Some(ModuleGraph::scan(&self.context().cwd, self.project.shared_targets())?);
+        }
         Ok(())
     }
 
-    fn build_binary(&self, binary: &Binary) -> Result<(), YabsError> {
-        let object_list = if self.binaries.as_ref().unwrap().len() == 1 {
-            self.project.object_list_as_string(None)?
-        } else {
-            self.project
-                .object_list_as_string(Some(self.binaries
-                                                .clone()
-                                                .unwrap()
-                                                .into_iter()
-                                                .filter(|bin| bin.path() != binary.path())
-                                                .collect::<Vec<Binary>>()))?
+    // Flags producing the module interface of `target` and finding those it
+    // imports, in the form clang understands
+    fn module_args(&self, target: &Target) -> Vec<String> {
+        let graph = match self.modules {
+            Some(ref graph) if Language::of(&target.source()) == Language::Cxx => graph,
+            _ => return Vec::new(),
         };
-        Ok(run_cmd(&format!("{CC} {LFLAGS} -o {BIN} {OBJ_LIST} {LIB_DIR} {LIBS}",
-                           CC = &self.project.compiler.as_ref().unwrap_or(&String::from("gcc")),
-                           LFLAGS = &self.project
-                                         .lflags
-                                         .as_ref()
-                                         .unwrap_or(&vec![])
-                                         .prepend_each("-")
-                                         .join(" "),
-                           BIN = binary.name(),
-                           OBJ_LIST = object_list,
-                           LIB_DIR = &self.project
-                                          .lib_dir
-                                          .as_ref()
-                                          .unwrap_or(&vec![])
-                                          .prepend_each("-L")
-                                          .join(" "),
-                           LIBS = &self.project.libs_as_string()))?)
+        let mut args = Vec::new();
+        if let Some(bmi) = graph.bmi_path(target) {
+            args.push(format!("-fmodule-output={}", bmi.display()));
+        }
+        for dir in graph.bmi_dirs() {
+            let dir = if dir == Path::new("") { PathBuf::from(".") } else { dir };
+            args.push(format!("-fprebuilt-module-path={}", dir.display()));
+        }
+        args
+    }
+
+    pub fn print_sources(&mut self) {
+        for target in self.project.shared_targets() {
+            info!("{}", target.source().display());
+        }
     }
 
-    pub fn build_static_library(&self, library: &Library) -> Result<(), YabsError> {
-        let object_list = &self.project.object_list_as_string(None)?;
-        Ok(run_cmd(&format!("{AR} {ARFLAGS} {LIB} {OBJ_LIST}",
-                           AR = &self.project.ar.as_ref().unwrap_or(&String::from("ar")),
-                           ARFLAGS =
-                               &self.project.arflags.as_ref().unwrap_or(&String::from("rcs")),
-                           LIB = library.static_file_name().display(),
-                           OBJ_LIST = object_list))?)
+    // The sources compiled into the binary or library `target_name`, or every
+    // source of the project without one
+    pub fn sources_for(&self, target_name: Option<&str>) -> Result<Vec<SourceInfo>, YabsError> {
+        let objects = match target_name {
+            Some(name) => {
+                let buildable = self.find_buildable(name)?;
+                Some(buildable.objects(&self.project, self.binaries.as_ref().unwrap_or(&vec![]))?)
+            },
+            None => None,
+        };
+        Ok(self.project
+               .file_mod_map
+               .iter()
+               .filter(|&(target, _)| match objects {
+                           Some(ref objects) => objects.contains(&target.object()),
+                           None => target.owner().is_none(),
+                       })
+               .map(|(target, modified)| {
+                        SourceInfo {
+                            source: target.source(),
+                            object: target.object(),
+                            modified: *modified,
+                        }
+                    })
+               .collect())
     }
 
-    pub fn build_dynamic_library(&self, library: &Library) -> Result<(), YabsError> {
-        let object_list = &self.project.object_list_as_string(None)?;
-        Ok(run_cmd(&format!("{CC} -shared -o {LIB} {OBJ_LIST} {LIBS}",
-                           CC = &self.project.compiler.as_ref().unwrap_or(&String::from("gcc")),
-                           LIB = library.dynamic_file_name().display(),
-                           OBJ_LIST = object_list,
-                           LIBS = &self.project.libs_as_string()))?)
+    // `sources_for` as a JSON array, modification times are in seconds since
+    // the Unix epoch
+    pub fn sources_json(&self, target_name: Option<&str>) -> Result<String, YabsError> {
+        let entries: Vec<String> = self.sources_for(target_name)?
+                                       .iter()
+                                       .map(|info| {
+                                                format!("{{\"source\":{},\"object\":{},\"modified\":{:.3}}}",
+                                                        json_string(&info.source.display().to_string()),
+                                                        json_string(&info.object.display().to_string()),
+                                                        info.modified
+                                                            .duration_since(UNIX_EPOCH)
+                                                            .unwrap_or_default()
+                                                            .as_secs_f64())
+                                            })
+                                       .collect();
+        Ok(format!("[{}]", entries.join(",")))
     }
 
-    pub fn build_library(&self, library: &Library) -> Result<(), YabsError> {
-        if library.is_static() {
-            self.build_static_library(library)?;
+    // Everything in the compile command for `target` except its input and
+    // output
+    fn compile_flags(&self, target: &Target) -> Result<Vec<String>, YabsError> {
+        let language = Language::of(&target.source());
+        let overrides = self.owner_overrides(target);
+        let compiler = overrides.compiler.clone().unwrap_or_else(|| self.project.compiler_for(language));
+        let mut args = vec![compiler, String::from("-c")];
+        args.extend(self.project.target_args());
+        args.extend(self.project.std_args(&target.source()));
+        args.extend(self.project.warning_args());
+        args.extend(self.project.sanitize_args());
+        args.extend(self.project.coverage_args());
+        args.extend(self.project.lto_args());
+        if target.is_pic() {
+            args.push(String::from("-fPIC"));
         }
-        if library.is_dynamic() {
-            self.build_dynamic_library(library)?;
+        args.extend(self.project.visibility_args(&overrides));
+        args.extend(self.project.compiler_flags.as_ref().unwrap_or(&vec![]).prepend_each("-"));
+        args.extend(self.project.pkg_config_cflags.iter().cloned());
+        args.extend(overrides.compiler_flags.as_ref().unwrap_or(&vec![]).prepend_each("-"));
+        args.extend(self.project.language_flags(language));
+        args.extend(self.module_args(target));
+        args.extend(self.project.env_compile_flags(&target.source())?);
+        args.extend(self.project.define_args());
+        args.extend(overrides.defines.as_ref().unwrap_or(&vec![]).prepend_each("-D"));
+        args.extend(self.project.include_args()?);
+        args.extend(self.project.include_args_of(overrides.include.as_ref().unwrap_or(&vec![]))?);
+        args.extend(self.extra_flags.clone());
+        // Writes where each header was found next to the object
+        if self.project.tracks_include_resolution() {
+            args.push(String::from("-MMD"));
         }
-        Ok(())
+        Ok(args)
     }
 
-    pub fn build_library_with_name(&mut self, name: &str, jobs: usize) -> Result<(), YabsError> {
-        if let Some(libraries) = self.libraries.as_ref() {
-            if let Some(library) = libraries.into_iter()
-                                            .find(|&lib| {
-                                                      lib.name() == name
-                                                  }) {
-                let job_queue = self.build_object_queue(library)?;
-                self.run_job_queue(job_queue, jobs)?;
-                self.build_library(library)?;
+    // The settings of the target `target` is compiled for, none for a shared
+    // object
+    fn owner_overrides(&self, target: &Target) -> Overrides {
+        let owner = match target.owner() {
+            Some(owner) => owner,
+            None => return Overrides::default(),
+        };
+        self.buildables()
+            .into_iter()
+            .find(|buildable| buildable.object_owner().as_deref() == Some(owner))
+            .map(|buildable| buildable.overrides())
+            .unwrap_or_default()
+    }
+
+    // Include directories searched for `target`'s headers, in order
+    fn include_search_path(&self, target: &Target) -> Result<Vec<PathBuf>, YabsError> {
+        Ok(self.compile_flags(target)?
+               .iter()
+               .filter_map(|arg| arg.strip_prefix("-I"))
+               .filter(|dir| !dir.is_empty())
+               .map(PathBuf::from)
+               .collect())
+    }
+
+    // gcc and clang given several sources with `-c` and no `-o` write
+    // `<stem>.o` for each of them into the working directory. That's only where
+    // the object belongs if it sits next to its source.
+    fn can_batch(&self, target: &Target) -> Result<bool, YabsError> {
+        if self.project.is_msvc() || self.modules.is_some() ||
+           target.object() != target.source().with_extension("o") {
+            return Ok(false);
+        }
+        Ok(!self.compile_flags(target)?
+                .iter()
+                .any(|arg| arg != "-MMD" && UNBATCHABLE_FLAGS.iter().any(|flag| arg.starts_with(flag))))
+    }
+
+    // Groups the queue into units compiled by a single process. Units are
+    // returned in the order they'll be popped, like the queue itself.
+    fn job_units(&self, job_queue: Vec<Target>) -> Result<Vec<Vec<Target>>, YabsError> {
+        if self.project.pipeline() != Pipeline::Batched {
+            return Ok(job_queue.into_iter().map(|target| vec![target]).collect());
+        }
+        let mut units: Vec<Vec<Target>> = Vec::new();
+        let mut open_batches: BTreeMap<(PathBuf, Vec<String>), usize> = BTreeMap::new();
+        for target in job_queue.into_iter().rev() {
+            if !self.can_batch(&target)? {
+                units.push(vec![target]);
+                continue;
+            }
+            let key = (source_dir(&target), self.compile_flags(&target)?);
+            match open_batches.get(&key).cloned() {
+                Some(index) if units[index].len() < self.project.batch_size() => {
+                    units[index].push(target)
+                },
+                _ => {
+                    open_batches.insert(key, units.len());
+                    units.push(vec![target]);
+                },
             }
-        } else {
-            bail!(YabsErrorKind::TargetNotFound("library".to_owned(), name.to_owned()))
         }
-        Ok(())
+        units.reverse();
+        Ok(units)
     }
 
-    pub fn build_binary_with_name(&mut self, name: &str, jobs: usize) -> Result<(), YabsError> {
-        if let Some(binaries) = self.binaries.as_ref() {
-            if let Some(binary) = binaries.into_iter()
-                                          .find(|&bin| {
-                                                    bin.name() == name
-                                                }) {
-                let job_queue = self.build_object_queue(binary)?;
-                self.run_job_queue(job_queue, jobs)?;
-                self.build_binary(binary)?;
+    fn spawn_unit(&self, unit: &[Target]) -> Result<(String, Child), YabsError> {
+        if unit.len() == 1 {
+            return self.spawn_build_object(&unit[0]);
+        }
+        let dir = source_dir(&unit[0]);
+        let cwd = self.context().cwd.clone();
+        let mut args: Vec<String> = self.compile_flags(&unit[0])?
+                                        .into_iter()
+                                        .chain(self.diagnostics_color_args(&unit[0]))
+                                        .map(|arg| match arg.strip_prefix("-I") {
+                                                 Some(inc) if Path::new(inc).is_relative() => {
+                                                     format!("-I{}", cwd.join(inc).display())
+                                                 },
+                                                 _ => arg,
+                                             })
+                                        .collect();
+        for target in unit {
+            // A failing source in a batch doesn't stop the others from being
+            // compiled, so its old object would otherwise survive
+            let object = self.project.resolve(target.object());
+            if object.exists() {
+                fs::remove_file(object)?;
             }
-        } else {
-            bail!(YabsErrorKind::TargetNotFound("binary".to_owned(), name.to_owned()))
+            args.push(target.source().file_name().unwrap().to_string_lossy().into_owned());
         }
-        Ok(())
+        let args = self.launched(args)?;
+        let command = format!("cd {} && {}",
+                              display_args(&[dir.to_string_lossy().into_owned()]),
+                              display_args(&args));
+        Ok((command, command_from_args(&args).current_dir(cwd.join(&dir)).stderr(Stdio::piped()).spawn()?))
     }
 
-    pub fn build_all_libraries(&mut self, jobs: usize) -> Result<(), YabsError> {
-        if !self.libraries.is_some() {
-            return Ok(());
+    // The compiler writes a temporary next to the object, which is renamed
+    // into place once it succeeds so a failed or killed compile never leaves
+    // a truncated object behind. The command is logged as it would be run
+    // without it.
+    fn spawn_build_object(&self, target: &Target) -> Result<(String, Child), YabsError> {
+        if let Some(dir) = target.object().parent() {
+            if dir != Path::new("") && !self.project.resolve(dir).exists() {
+                fs::create_dir_all(self.project.resolve(dir))?;
+            }
         }
-        for library in self.libraries.clone().unwrap() {
-            let job_queue = self.build_object_queue(&library)?;
-            self.run_job_queue(job_queue, jobs)?;
-            self.build_library(&library)?;
+        let command = display_args(&self.launched(self.object_command(target)?)?);
+        let mut args = self.compile_flags(target)?;
+        args.extend(self.diagnostics_color_args(target));
+        if args.iter().any(|arg| arg == "-MMD") {
+            // Otherwise named after the temporary
+            args.push(String::from("-MF"));
+            args.push(target.object().with_extension("d").to_string_lossy().into_owned());
         }
-        Ok(())
+        args.push(String::from("-o"));
+        args.push(temporary_path(&target.object()).to_string_lossy().into_owned());
+        args.push(target.source().to_string_lossy().into_owned());
+        let (_, child) = spawn_args(&self.launched(args)?, &self.context().cwd)?;
+        Ok((command, child))
     }
 
-    pub fn build(&mut self, jobs: usize) -> Result<(), YabsError> {
-        self.project.run_script(&self.project.before_script)?;
-        self.build_all_binaries(jobs)?;
-        self.build_all_libraries(jobs)?;
-        self.project.run_script(&self.project.after_script)?;
-        Ok(())
+    // Units of one source are compiled through a temporary, batches write
+    // their objects themselves
+    fn commit_unit(&self, unit: &[Target]) -> Result<(), YabsError> {
+        match unit {
+            [target] => commit_temporary(&self.project.resolve(target.object())),
+            _ => Ok(()),
+        }
+    }
+
+    fn discard_unit(&self, unit: &[Target]) {
+        if let [target] = unit {
+            discard_temporary(&self.project.resolve(target.object()));
+        }
+    }
+
+    // `args` run through 'compiler-launcher', which doesn't change which
+    // objects are stale
+    fn launched(&self, args: Vec<String>) -> Result<Vec<String>, YabsError> {
+        let mut launched = self.project.launcher_args()?;
+        launched.extend(args);
+        Ok(launched)
+    }
+
+    fn object_command(&self, target: &Target) -> Result<Vec<String>, YabsError> {
+        let mut args = self.compile_flags(target)?;
+        args.push(String::from("-o"));
+        args.push(target.object().to_string_lossy().into_owned());
+        args.push(target.source().to_string_lossy().into_owned());
+        Ok(args)
+    }
+
+    // The metadata an artifact built now with the current configuration would
+    // have. It hashes every source and asks the compiler for its version, so
+    // it's only worked out again by the next build.
+    pub fn current_artifact_meta(&self) -> Result<ArtifactMeta, YabsError> {
+        if let Some(ref meta) = *self.artifact_meta.borrow() {
+            return Ok(meta.clone());
+        }
+        let meta = self.artifact_meta_now()?;
+        self.artifact_meta.replace(Some(meta.clone()));
+        Ok(meta)
+    }
+
+    fn artifact_meta_now(&self) -> Result<ArtifactMeta, YabsError> {
+        let compile_hash = self.compile_hash()?;
+        let mut flags = vec![compile_hash.clone()];
+        flags.extend(self.project.lflags.clone().unwrap_or_default());
+        flags.extend(self.project.pkg_config_libs.iter().cloned());
+        flags.extend(self.project.env_flags("LDFLAGS")?);
+        flags.extend(self.project.target_args());
+        flags.extend(self.project.sanitize_link_args());
+        flags.extend(self.project.lto_args());
+        flags.extend(self.project.lib_dir_args());
+        flags.extend(self.project.libs.iter().flatten().map(|lib| lib.to_string()));
+        let sources: Vec<PathBuf> = self.project.shared_targets().map(|target| target.source()).collect();
+        Ok(ArtifactMeta {
+               flag_hash: format!("{:016x}", hash_strings(&flags)),
+               toolchain: tool_version(&self.project.compiler(), &self.context().cwd),
+               build_hash: format!("{:016x}", hash_files(&sources, &self.context().cwd)?),
+               compile_hash,
+               build_id: self.build_id.clone(),
+           })
     }
 
-    pub fn clean(&self) -> Result<(), YabsError> {
+    fn compile_hash(&self) -> Result<String, YabsError> {
+        let mut flags = Vec::new();
         for target in self.project.file_mod_map.keys() {
-            if target.object().exists() && fs::remove_file(target.object()).is_ok() {
-                info!("removed object '{}'", target.object().display());
+            flags.extend(self.compile_flags(target)?);
+        }
+        Ok(format!("{:016x}", hash_strings(&flags)))
+    }
+
+    // Objects are shared between targets, so objects compiled with different
+    // flags than the ones `artifact` was built with must not be linked into it
+    fn compile_flags_changed(&self, artifact: &Path) -> Result<bool, YabsError> {
+        match ArtifactMeta::read(&self.project.resolve(artifact)) {
+            Ok(meta) => Ok(meta.compile_hash != self.compile_hash()?),
+            Err(_) => Ok(false),
+        }
+    }
+
+    // Reads back what produced the artifact at `path`
+    pub fn describe_artifact(&self, path: &Path) -> Result<ArtifactMeta, YabsError> {
+        ArtifactMeta::read(&self.project.resolve(path))
+    }
+
+    // Warns when the artifact at `path` was built with a different
+    // configuration than the current one
+    pub fn warn_if_artifact_differs(&self, path: &Path) -> Result<(), YabsError> {
+        if let Ok(built) = self.describe_artifact(path) {
+            let differences = built.differences(&self.current_artifact_meta()?);
+            if !differences.is_empty() {
+                warn!("'{}' was built with {}", path.display(), differences.join("; "));
             }
         }
-        if let Some(binaries) = self.binaries.clone() {
-            for binary in binaries {
-                let bin_path = PathBuf::from(binary.name());
-                if bin_path.exists() && fs::remove_file(&bin_path).is_ok() {
-                    info!("removed binary '{}'", bin_path.display());
+        Ok(())
+    }
+
+    // The stale objects of `build_target` in the order they're compiled, each
+    // with why it's compiled
+    fn build_object_queue(&self, build_target: &dyn Buildable) -> Result<Vec<(Target, StaleReason)>, YabsError> {
+        let strategy = self.project.staleness();
+        let decisions = self.stale_objects(strategy, build_target)?;
+        if decisions.iter().any(|(_, reason)| *reason == Some(StaleReason::FlagsChanged)) {
+            info!("compile flags changed since '{}' was built, rebuilding all objects",
+                  self.output_paths(build_target)[0].display());
+        }
+        if !self.explain {
+            for (target, reason) in &decisions {
+                if let Some(StaleReason::IncludeResolution(ref reason)) = *reason {
+                    info!("'{}': {}", target.source().display(), reason);
                 }
             }
         }
-        if let Some(libraries) = self.libraries.clone() {
-            for library in libraries {
-                if library.dynamic_file_name().exists() &&
-                   fs::remove_file(library.dynamic_file_name()).is_ok() {
-                    info!("removed library '{}'",
-                          library.dynamic_file_name().display());
-                }
-                if library.static_file_name().exists() &&
-                   fs::remove_file(library.static_file_name()).is_ok() {
-                    info!("removed library '{}'", library.static_file_name().display());
+        if self.project.compare_staleness.unwrap_or(false) {
+            let other = match strategy {
+                Staleness::Legacy => Staleness::V2,
+                Staleness::V2 => Staleness::Legacy,
+            };
+            let other_decisions = self.stale_objects(other, build_target)?;
+            for ((target, reason), (_, other_reason)) in decisions.iter().zip(&other_decisions) {
+                if reason.is_some() != other_reason.is_some() {
+                    let describe = |reason: &Option<StaleReason>| match *reason {
+                        Some(ref reason) => format!("stale ({})", reason),
+                        None => String::from("up to date"),
+                    };
+                    warn!("staleness of '{}' differs: {} decides {}, {} decides {}",
+                          target.source().display(),
+                          strategy.name(),
+                          describe(reason),
+                          other.name(),
+                          describe(other_reason));
                 }
             }
         }
-        Ok(())
-    }
-}
-
-pub fn find_build_file(dir: &mut PathBuf) -> Result<BuildFile, YabsError> {
-    let original = dir.clone();
-    loop {
-        if let Some(filepath) = check_dir(dir) {
-            env::set_current_dir(&dir)?;
-            return Ok(BuildFile::from_file(&dir.join(filepath))?);
-        } else if !dir.pop() {
-            break;
+        self.forget_missing_objects(&decisions)?;
+        let objects = decisions.len();
+        let mut reasons: BTreeMap<Target, StaleReason> =
+            decisions.into_iter().filter_map(|(target, reason)| reason.map(|reason| (target, reason))).collect();
+        let mut forced = self.forced.borrow_mut();
+        reasons.retain(|target, reason| *reason != StaleReason::Forced || forced.insert(target.object()));
+        drop(forced);
+        if self.explain {
+            if reasons.is_empty() {
+                info!("all {} objects of '{}' up to date", objects, build_target.name());
+            }
+            for (target, reason) in &reasons {
+                info!("rebuilding {}: {}", target.object().display(), reason.explain());
+            }
         }
+        let queue = self.order_job_queue(reasons.keys().cloned().collect());
+        Ok(queue.into_iter()
+                .filter_map(|target| reasons.remove(&target).map(|reason| (target, reason)))
+                .collect())
     }
-    bail!(YabsErrorKind::NoAssumedToml(original.to_str().unwrap().to_owned()))
-}
 
-fn check_dir(dir: &PathBuf) -> Option<PathBuf> {
-    if let Some(assumed) = get_assumed_filename_for_dir(dir) {
-        if dir.join(&assumed).exists() {
-            return Some(dir.join(assumed));
+    // Drops the records of objects deleted since they were compiled, such as
+    // by removing the object directory by hand
+    fn forget_missing_objects(&self, decisions: &[(Target, Option<StaleReason>)]) -> Result<(), YabsError> {
+        let missing: Vec<PathBuf> = decisions.iter()
+                                              .filter(|(_, reason)| {
+                                                          *reason == Some(StaleReason::NoObject { recorded: true })
+                                                      })
+                                              .map(|(target, _)| target.object())
+                                              .collect();
+        if missing.is_empty() {
+            return Ok(());
+        }
+        let mut state = self.object_records();
+        for object in &missing {
+            state.forget(object);
         }
+        info!("{} recorded object(s) no longer exist and will be compiled again", missing.len());
+        state.flush()
     }
-    None
-}
 
-#[test]
-#[should_panic]
-fn test_empty_buildfile() {
-    let bf = BuildFile::from_file(&"test/empty.toml").unwrap();
-    assert_eq!(bf.binaries.unwrap().len(), 0);
+    // Every object of the project with the reason it has to be compiled for
+    // `build_target`, or None if it doesn't
+    fn stale_objects(&self,
+                     strategy: Staleness,
+                     build_target: &dyn Buildable)
+                     -> Result<Vec<(Target, Option<StaleReason>)>, YabsError> {
+        let target_path = self.output_paths(build_target).into_iter().next().unwrap_or_default();
+        let is_binary = build_target.kinds().first() == Some(&TargetKind::Binary);
+        let target_exists = self.project.resolve(&target_path).exists();
+        let flags_changed = match strategy {
+            Staleness::V2 => target_exists && self.compile_flags_changed(&target_path)?,
+            Staleness::Legacy => false,
+        };
+        let built = match strategy {
+            Staleness::Legacy if is_binary && target_exists => {
+                Some(fs::metadata(self.project.resolve(&target_path))?.modified()?)
+            },
+            _ => None,
+        };
+        let granularity = self.fs_caps.mtime_granularity();
+        let mut extra_deps = Vec::new();
+        for dep in self.project.extra_deps() {
+            match fs::metadata(self.project.resolve(&dep)).and_then(|meta| meta.modified()) {
+                Ok(modtime) => extra_deps.push((dep, modtime)),
+                Err(_) => warn!("extra dependency '{}' doesn't exist", dep.display()),
+            }
+        }
+        let state = self.object_records();
+        let by_hash = self.project.change_detection() == ChangeDetection::Hash;
+        let hashes = self.source_hashes();
+        let mut resolver = IncludeResolver::new(&self.context().cwd);
+        let mut decisions = Vec::new();
+        for (target, modtime) in build_target.sources(&self.project) {
+            // Objects compiled since the artifact was built, such as by an
+            // interrupted build, are judged by their own records
+            let flags_changed = match state.get(&target.object()) {
+                Some(record) if strategy == Staleness::V2 => record.command_hash != self.object_hash(target)?,
+                _ => flags_changed,
+            };
+            // The one stat of the object, a missing object is stale whatever the
+            // artifacts and records say
+            let compiled = match fs::metadata(self.project.resolve(target.object())).and_then(|meta| meta.modified()) {
+                Ok(compiled) => compiled,
+                Err(_) => {
+                    let recorded = state.get(&target.object()).is_some();
+                    decisions.push((target.clone(), Some(StaleReason::NoObject { recorded })));
+                    continue;
+                },
+            };
+            let reason = if self.state.discarded && state.get(&target.object()).is_none() {
+                Some(StaleReason::StateDiscarded)
+            } else if flags_changed {
+                Some(StaleReason::FlagsChanged)
+            } else if let Some(reason) = self.include_resolution_change(target, &state, &mut resolver)? {
+                Some(StaleReason::IncludeResolution(reason))
+            } else if by_hash && hashes.get(&target.object()) != self.project.file_hashes.get(&target.source()) {
+                Some(StaleReason::ContentChanged)
+            } else {
+                match strategy {
+                    Staleness::Legacy => {
+                        built.and_then(|built| {
+                            let is_newer = |modtime: SystemTime| modtime + granularity > built;
+                            if !by_hash && is_newer(*modtime) {
+                                Some(StaleReason::SourceNewerThanBinary {
+                                         binary: target_path.clone(),
+                                         modified: *modtime,
+                                         built,
+                                     })
+                            } else {
+                                extra_deps.iter().find(|&&(_, modtime)| is_newer(modtime)).map(|&(ref dep, modified)| {
+                                    StaleReason::DependencyChanged {
+                                        dep: dep.clone(),
+                                        modified,
+                                        built,
+                                        against: "binary",
+                                    }
+                                })
+                            }
+                        })
+                    },
+                    // Equal timestamps can't tell which came first, so they
+                    // count as stale
+                    Staleness::V2 => {
+                        let is_newer = |modtime: SystemTime| modtime + granularity >= compiled;
+                        if !by_hash && is_newer(*modtime) {
+                            Some(StaleReason::SourceNewerThanObject {
+                                     modified: *modtime,
+                                     compiled,
+                                 })
+                        } else {
+                            extra_deps.iter().find(|&&(_, modtime)| is_newer(modtime)).map(|&(ref dep, modified)| {
+                                StaleReason::DependencyChanged {
+                                    dep: dep.clone(),
+                                    modified,
+                                    built: compiled,
+                                    against: "object",
+                                }
+                            })
+                        }
+                    },
+                }
+            };
+            decisions.push((target.clone(), reason));
+        }
+        if let Some(ref graph) = self.modules {
+            self.propagate_module_staleness(graph, &mut decisions);
+        }
+        if self.force {
+            for decision in decisions.iter_mut().filter(|(_, reason)| reason.is_none()) {
+                decision.1 = Some(StaleReason::Forced);
+            }
+        }
+        Ok(decisions)
+    }
+
+    // Why one of the headers recorded for `target` would now be found
+    // somewhere else, if one would
+    fn include_resolution_change(&self,
+                                 target: &Target,
+                                 state: &StateCache,
+                                 resolver: &mut IncludeResolver)
+                                 -> Result<Option<String>, YabsError> {
+        let headers = match state.get(&target.object()).and_then(|record| record.headers.as_ref()) {
+            Some(headers) if self.project.tracks_include_resolution() => headers,
+            _ => return Ok(None),
+        };
+        let search_path = self.include_search_path(target)?;
+        Ok(headers.iter().filter_map(|header| resolver.changed(header, &search_path)).next())
+    }
+
+    // A changed interface changes every source importing it, directly or not
+    fn propagate_module_staleness(&self, graph: &ModuleGraph, decisions: &mut [(Target, Option<StaleReason>)]) {
+        for &mut (ref target, ref mut reason) in decisions.iter_mut() {
+            if reason.is_none() && graph.bmi_path(target).is_some_and(|bmi| !self.project.resolve(bmi).exists()) {
+                *reason = Some(StaleReason::NoModuleInterface);
+            }
+        }
+        loop {
+            let stale: Vec<Target> = decisions.iter()
+                                              .filter(|(_, reason)| reason.is_some())
+                                              .map(|(target, _)| target.clone())
+                                              .collect();
+            let mut changed = false;
+            for &mut (ref target, ref mut reason) in decisions.iter_mut() {
+                if reason.is_some() {
+                    continue;
+                }
+                if let Some(import) = graph.imports(target).into_iter().find(|import| stale.contains(import)) {
+                    *reason = Some(StaleReason::ModuleInterfaceChanged(import.source()));
+                    changed = true;
+                }
+            }
+            if !changed {
+                break;
+            }
+        }
+    }
+
+    // Jobs are popped off the end of the queue, so sorting by modification time
+    // compiles the most recently edited sources first and their errors surface
+    // before the rest of the queue is worked through
+    fn order_job_queue(&self, mut queue: Vec<Target>) -> Vec<Target> {
+        if self.project.schedule() == Schedule::RecentFirst {
+            queue.sort_by_key(|target| self.project.file_mod_map.get(target).cloned());
+        }
+        queue
+    }
+
+    // The stale objects of all targets, each compiled once however many
+    // targets it's linked into
+    fn compile_all_objects(&self, jobs: usize) -> Result<(), YabsError> {
+        self.check_stopped()?;
+        let mut queue: BTreeSet<Target> = BTreeSet::new();
+        for buildable in self.buildables() {
+            queue.extend(self.build_object_queue(buildable)?.into_iter().map(|(target, _)| target));
+        }
+        self.run_job_queue(self.order_job_queue(queue.into_iter().collect()), jobs)
+    }
+
+    fn build_all_binaries(&mut self, jobs: usize) -> Result<(), YabsError> {
+        for binary in self.binaries.iter().flatten().filter(|binary| binary.is_default()) {
+            self.build_target(binary, jobs)?;
+        }
+        Ok(())
+    }
+
+    // Compiles the target's stale objects and links it unless its outputs are
+    // newer than all of its objects
+    fn build_target(&self, target: &dyn Buildable, jobs: usize) -> Result<(), YabsError> {
+        self.check_stopped()?;
+        let job_queue: Vec<Target> = self.build_object_queue(target)?.into_iter().map(|(target, _)| target).collect();
+        let compiled = !job_queue.is_empty();
+        self.run_job_queue(job_queue, jobs)?;
+        self.link_target(target, compiled)
+    }
+
+    // Links the target once its objects are compiled, unless nothing was
+    // `compiled` for it and its outputs are newer than all of its objects
+    fn link_target(&self, target: &dyn Buildable, compiled: bool) -> Result<(), YabsError> {
+        if !compiled && !self.force && self.is_up_to_date(target, &self.output_paths(target))? {
+            info!("'{}' is up to date", target.name());
+            for output in self.output_paths(target) {
+                self.warn_if_artifact_differs(&output)?;
+            }
+            return self.check_size_budget(target, false);
+        }
+        if self.report.borrow().truncated {
+            self.remains(format!("link '{}'", target.name()));
+            return Ok(());
+        }
+        info!("linking '{}'", target.name());
+        let outputs: Vec<String> =
+            self.output_paths(target).iter().map(|path| path.display().to_string()).collect();
+        events::emit(|| Event::LinkStarted {
+            target: target.name(),
+            outputs: outputs.clone(),
+        });
+        let started = Instant::now();
+        let linked = self.link(target);
+        events::emit(|| Event::LinkFinished {
+            target: target.name(),
+            outputs,
+            success: linked.is_ok(),
+            duration: started.elapsed(),
+        });
+        if interrupt::interrupted() {
+            bail!(YabsErrorKind::Interrupted);
+        }
+        linked?;
+        self.report.borrow_mut().links.push(LinkTiming {
+            target: target.name(),
+            duration: started.elapsed(),
+        });
+        self.check_size_budget(target, true)
+    }
+
+    // The budget of `target` as written and in bytes, its own 'max-size' or
+    // the project's
+    fn size_budget(&self, target: &dyn Buildable) -> Result<Option<(String, u64)>, YabsError> {
+        let budget = match target.max_size().or_else(|| self.project.max_size.clone()) {
+            Some(budget) => budget,
+            None => return Ok(None),
+        };
+        match parse_size(&budget) {
+            Some(bytes) => Ok(Some((budget, bytes))),
+            None => {
+                bail!(YabsErrorKind::InvalidValue(String::from("max-size"),
+                                                  format!("'{}' of '{}' isn't a size such as \"512KB\" or \
+                                                           \"1.5MiB\"",
+                                                          budget,
+                                                          target.name())))
+            },
+        }
+    }
+
+    // Budgets are checked after the build file is read rather than after a
+    // long build
+    fn validate_size_budgets(&self) -> Result<(), YabsError> {
+        for target in self.buildables() {
+            self.size_budget(target)?;
+        }
+        if self.project.max_size_warning.is_some_and(|percent| percent == 0 || percent > 100) {
+            bail!(YabsErrorKind::InvalidValue(String::from("max-size-warning"),
+                                              String::from("expected a percentage from 1 to 100")));
+        }
+        Ok(())
+    }
+
+    // Fails the target when an output is larger than its budget, so a budget
+    // lowered since the last link is noticed too. An output over budget is
+    // removed so the next build links it again. Freshly `linked` outputs are
+    // warned about when they're close to the budget and their sizes recorded.
+    fn check_size_budget(&self, target: &dyn Buildable, linked: bool) -> Result<(), YabsError> {
+        let (budget, max_size) = match self.size_budget(target)? {
+            Some(budget) => budget,
+            None => return Ok(()),
+        };
+        let warning = self.project.max_size_warning();
+        let mut history = SizeHistory::load(&self.project.state_dir());
+        for output in self.output_paths(target) {
+            let size = fs::metadata(self.project.resolve(&output))?.len();
+            let change = describe_change(history.last(&output), size);
+            if size > max_size {
+                fs::remove_file(self.project.resolve(&output))?;
+                bail!(YabsErrorKind::SizeBudgetExceeded(output.display().to_string(),
+                                                        size,
+                                                        format!("{} by {} bytes", budget, size - max_size),
+                                                        change));
+            }
+            if !linked {
+                continue;
+            }
+            if size * 100 >= max_size * warning {
+                warn!("'{}' is {} bytes, {}% of its budget of {} ({})",
+                      output.display(),
+                      size,
+                      size * 100 / max_size.max(1),
+                      budget,
+                      change);
+            }
+            history.record(&output, size, self.context().now())?;
+        }
+        Ok(())
+    }
+
+    // Whether every artifact of `target` exists and is newer than every object
+    // it could link and its other link inputs
+    fn is_up_to_date(&self, target: &dyn Buildable, artifacts: &[PathBuf]) -> Result<bool, YabsError> {
+        for artifact in artifacts.iter().map(|artifact| self.project.resolve(artifact)) {
+            if !artifact.exists() {
+                return Ok(false);
+            }
+            let built = fs::metadata(&artifact)?.modified()?;
+            for input in target.link_inputs().into_iter().map(|input| self.project.resolve(input)) {
+                if !input.exists() || fs::metadata(&input)?.modified()? > built {
+                    return Ok(false);
+                }
+            }
+            for (target, _) in target.sources(&self.project) {
+                let object = self.project.resolve(target.object());
+                if !object.exists() || fs::metadata(&object)?.modified()? > built {
+                    return Ok(false);
+                }
+            }
+        }
+        Ok(true)
+    }
+
+    fn run_job_queue(&self, mut job_queue: Vec<Target>, jobs: usize) -> Result<(), YabsError> {
+        self.check_disk_space(&job_queue)?;
+        let jobs = self.job_count(jobs);
+        let mut progress = Progress::new(job_queue.len());
+        // Interfaces have to be compiled before the sources importing them
+        if let Some(ref graph) = self.modules {
+            for level in graph.levels(job_queue)? {
+                self.run_job_pass(self.order_job_queue(level), jobs, &mut progress)?;
+            }
+            return Ok(());
+        }
+        if let Some(window) = self.project.fast_fail_window {
+            if self.project.schedule() == Schedule::RecentFirst && window > 0 &&
+               window < job_queue.len() {
+                let split = job_queue.len() - window;
+                let recent = job_queue.split_off(split);
+                info!("compiling {} most recently modified sources first", window);
+                self.run_job_pass(recent, jobs, &mut progress)?;
+            }
+        }
+        self.run_job_pass(job_queue, jobs, &mut progress)
+    }
+
+    // Each finished object is recorded in the state cache right away, so a
+    // build that is killed or cancelled doesn't compile it again
+    fn run_job_pass(&self, job_queue: Vec<Target>, jobs: usize, progress: &mut Progress) -> Result<(), YabsError> {
+        let mut job_queue = self.job_units(job_queue)?;
+        // Units are taken from the back
+        debug!("object queue: {}",
+               job_queue.iter().rev().flatten().map(|target| target.object().display().to_string()).collect::<Vec<_>>()
+                        .join(", "));
+        let mut pass = JobPass {
+            progress,
+            running: Vec::new(),
+            state: self.object_records(),
+            hashes: self.source_hashes(),
+            cache: self.object_cache(),
+            keys: BTreeMap::new(),
+            versions: BTreeMap::new(),
+        };
+        while !job_queue.is_empty() && !self.stop_requested() && !self.report.borrow().truncated {
+            if pass.running.len() < jobs {
+                if let Some(unit) = job_queue.pop() {
+                    let unit = self.fetch_cached(unit, &mut pass)?;
+                    if unit.is_empty() {
+                        continue;
+                    }
+                    if !self.fits_deadline(&unit, &pass) {
+                        job_queue.push(unit);
+                        self.stop_at_deadline();
+                        break;
+                    }
+                    let job = Job::new(self.spawn_unit(&unit)?);
+                    let progress = pass.progress.advance(unit.len());
+                    let sources: Vec<String> =
+                        unit.iter().map(|target| target.source().display().to_string()).collect();
+                    log_command(&format!("{} {} {}", progress, compile_label(&unit[0]), sources.join(" ")),
+                                &format!("{} {}", progress, job.command()));
+                    for target in &unit {
+                        events::emit(|| Event::CompileStarted {
+                            source: target.source().display().to_string(),
+                            object: target.object().display().to_string(),
+                            command: job.command(),
+                        });
+                    }
+                    pass.running.push((job, unit));
+                }
+            } else {
+                self.finish_jobs(&mut pass)?;
+            }
+        }
+        self.finish_jobs(&mut pass)?;
+        pass.state.flush()?;
+        pass.hashes.flush()?;
+        self.check_stopped()?;
+        if self.report.borrow().truncated {
+            for target in job_queue.into_iter().rev().flatten() {
+                self.remains(format!("compile {}", target.source().display()));
+            }
+        }
+        Ok(())
+    }
+
+    // Stops starting compiles that aren't expected to finish by `deadline`,
+    // judged by how long each took last time. Running compiles are waited
+    // for, or killed once `grace` has passed since the deadline. `build` then
+    // returns a report marked as truncated instead of linking.
+    pub fn set_deadline(&mut self, deadline: Option<Deadline>, grace: Option<Duration>) {
+        self.deadline = deadline;
+        self.deadline_grace = grace;
+    }
+
+    // How long `unit` is expected to take: the time each source took when
+    // its object was last compiled, else the average of the recorded
+    // compiles, else the average of this build's compiles so far. With
+    // nothing to go by a compile is assumed to fit.
+    fn estimated_duration(&self, unit: &[Target], pass: &JobPass) -> Duration {
+        let recorded: Vec<f64> = pass.state.objects.values().map(|record| record.seconds).collect();
+        let fallback = if !recorded.is_empty() {
+            recorded.iter().sum::<f64>() / recorded.len() as f64
+        } else {
+            let report = self.report.borrow();
+            let compiled: Duration = report.compiles.iter().map(|compile| compile.duration).sum();
+            compiled.as_secs_f64() / report.compiles.len().max(1) as f64
+        };
+        let seconds: f64 = unit.iter()
+                               .map(|target| pass.state.get(&target.object()).map_or(fallback, |record| record.seconds))
+                               .sum();
+        Duration::from_secs_f64(seconds.max(0.0))
+    }
+
+    fn fits_deadline(&self, unit: &[Target], pass: &JobPass) -> bool {
+        match self.deadline_at.get() {
+            Some(deadline) => Instant::now() + self.estimated_duration(unit, pass) <= deadline,
+            None => true,
+        }
+    }
+
+    fn stop_at_deadline(&self) {
+        let mut report = self.report.borrow_mut();
+        if !report.truncated {
+            warn!("the deadline is near, no more compiles are started");
+            report.truncated = true;
+        }
+    }
+
+    // Adds `step` to what a build stopped at its deadline didn't do, objects
+    // shared between targets are listed once
+    fn remains(&self, step: String) {
+        let mut report = self.report.borrow_mut();
+        if !report.remaining.contains(&step) {
+            report.remaining.push(step);
+        }
+    }
+
+    // Ctrl-C or the cancellation token, either stops new jobs from starting
+    fn stop_requested(&self) -> bool {
+        interrupt::interrupted() || self.cancel.is_cancelled()
+    }
+
+    fn check_stopped(&self) -> Result<(), YabsError> {
+        if interrupt::interrupted() {
+            bail!(YabsErrorKind::Interrupted);
+        }
+        if self.cancel.is_cancelled() {
+            bail!(YabsErrorKind::Cancelled);
+        }
+        Ok(())
+    }
+
+    fn timed_out(&self, job: &Job) -> bool {
+        self.project.job_timeout().is_some_and(|timeout| job.duration() >= timeout)
+    }
+
+    fn grace_expired(&self) -> bool {
+        match (self.deadline_at.get(), self.deadline_grace) {
+            (Some(deadline), Some(grace)) => Instant::now() > deadline + grace,
+            _ => false,
+        }
+    }
+
+    // Waits for every running job, noticing each as soon as it finishes so its
+    // duration is right
+    fn finish_jobs(&self, pass: &mut JobPass) -> Result<(), YabsError> {
+        while !pass.running.is_empty() {
+            let mut index = 0;
+            while index < pass.running.len() {
+                let finished = match pass.running[index].0.try_yield() {
+                    Ok(false) if self.timed_out(&pass.running[index].0) => {
+                        let (ref mut job, ref unit) = pass.running[index];
+                        job.kill();
+                        let sources: Vec<String> =
+                            unit.iter().map(|target| format!("'{}'", target.source().display())).collect();
+                        let seconds = self.project.job_timeout_secs.unwrap_or_default();
+                        Err(YabsErrorKind::Timeout(format!("compiling {}", sources.join(", ")), seconds).into())
+                    },
+                    finished => finished,
+                };
+                match finished {
+                    Ok(false) => index += 1,
+                    Ok(true) => {
+                        let (job, unit) = pass.running.remove(index);
+                        self.log_diagnostics(&job, &unit);
+                        self.commit_unit(&unit)?;
+                        self.finish_unit(&job, unit, pass)?;
+                    },
+                    Err(_) if interrupt::interrupted() => {
+                        // Ctrl-C reaches the compilers too, what they leave
+                        // behind may be half written
+                        let (job, unit) = pass.running.remove(index);
+                        emit_compiles_finished(&unit, None, job.duration());
+                        self.discard_unit(&unit);
+                        for target in unit {
+                            let _ = fs::remove_file(self.project.resolve(target.object()));
+                        }
+                    },
+                    Err(error) => {
+                        // The compiler's diagnostics come right before the
+                        // error saying what failed
+                        let (status, error) = {
+                            let (ref job, ref unit) = pass.running[index];
+                            self.log_diagnostics(job, unit);
+                            match *error.kind() {
+                                YabsErrorKind::Command(_, status) => {
+                                    let sources: Vec<String> =
+                                        unit.iter().map(|target| target.source().display().to_string()).collect();
+                                    (Some(status), YabsErrorKind::CompileFailed(sources.join(", "), status).into())
+                                },
+                                _ => (None, error),
+                            }
+                        };
+                        // The build stops here, so do the other compiles
+                        for (running, (mut job, unit)) in pass.running.drain(..).enumerate() {
+                            job.kill();
+                            emit_compiles_finished(&unit, if running == index { status } else { None }, job.duration());
+                            self.discard_unit(&unit);
+                        }
+                        pass.state.flush()?;
+                        pass.hashes.flush()?;
+                        return Err(error);
+                    },
+                }
+            }
+            if !pass.running.is_empty() && interrupt::interrupted() {
+                warn!("interrupted, killing {} running compile(s)", pass.running.len());
+                for (mut job, unit) in pass.running.drain(..) {
+                    job.kill();
+                    emit_compiles_finished(&unit, None, job.duration());
+                    self.discard_unit(&unit);
+                    for target in unit {
+                        let _ = fs::remove_file(self.project.resolve(target.object()));
+                    }
+                }
+            }
+            if !pass.running.is_empty() && self.grace_expired() {
+                warn!("killing {} compile(s) still running past the deadline", pass.running.len());
+                for (mut job, unit) in pass.running.drain(..) {
+                    job.kill();
+                    emit_compiles_finished(&unit, None, job.duration());
+                    self.discard_unit(&unit);
+                    for target in unit {
+                        let _ = fs::remove_file(self.project.resolve(target.object()));
+                        self.remains(format!("compile {}", target.source().display()));
+                    }
+                }
+                self.stop_at_deadline();
+            }
+            if !pass.running.is_empty() {
+                thread::sleep(Duration::from_millis(2));
+            }
+        }
+        Ok(())
+    }
+
+    // Everything a compile wrote to stderr as one block, so the diagnostics of
+    // parallel compiles don't interleave. Its warnings and errors are counted
+    // in the report, and a batch's are told apart by the source they're about.
+    fn log_diagnostics(&self, job: &Job, unit: &[Target]) {
+        if job.diagnostics().trim().is_empty() {
+            return;
+        }
+        warn!(target: DIAGNOSTICS_TARGET, "{}", job.diagnostics().trim_end_matches('\n'));
+        let parts = match unit {
+            [_] => vec![(Some(0), job.diagnostics().to_owned())],
+            _ => {
+                let names: Vec<String> = unit.iter()
+                                             .map(|target| target.source())
+                                             .map(|source| source.file_name().unwrap().to_string_lossy().into_owned())
+                                             .collect();
+                diagnostics_by_file(job.diagnostics(), &names)
+            },
+        };
+        let mut report = self.report.borrow_mut();
+        for (index, output) in parts {
+            events::emit(|| Event::Diagnostics {
+                sources: match index {
+                    Some(index) => vec![unit[index].source().display().to_string()],
+                    None => unit.iter().map(|target| target.source().display().to_string()).collect(),
+                },
+                output: strip_ansi(&output),
+            });
+            for (file, severity) in parse_compiler_diagnostics(&output) {
+                // A batch runs in its sources' directory
+                let file = match unit {
+                    [_] => file,
+                    _ => source_dir(&unit[0]).join(file).display().to_string().trim_start_matches("./").to_owned(),
+                };
+                let counts = report.diagnostics.entry(file).or_default();
+                match severity {
+                    Severity::Warning => counts.warnings += 1,
+                    Severity::Error => counts.errors += 1,
+                }
+            }
+        }
+    }
+
+    fn finish_unit(&self, job: &Job, unit: Vec<Target>, pass: &mut JobPass) -> Result<(), YabsError> {
+        // A batched compile's time is split evenly between its sources
+        let duration = job.duration() / unit.len() as u32;
+        emit_compiles_finished(&unit, Some(0), duration);
+        for target in unit {
+            if let (Some(cache), Some(key)) = (pass.cache.as_ref(), pass.keys.remove(&target.object())) {
+                cache.store(&key, &self.project.resolve(target.object()))?;
+            }
+            self.record_object(&target, duration.as_secs_f64(), pass)?;
+            self.report.borrow_mut().compiles.push(CompileTiming {
+                source: target.source(),
+                duration,
+            });
+        }
+        Ok(())
+    }
+
+    fn record_object(&self, target: &Target, seconds: f64, pass: &mut JobPass) -> Result<(), YabsError> {
+        if let Some(hash) = self.project.file_hashes.get(&target.source()) {
+            pass.hashes.insert(&target.object(), hash.clone());
+        }
+        let source_modified = self.project
+                                  .file_mod_map
+                                  .get(target)
+                                  .and_then(|modtime| modtime.duration_since(UNIX_EPOCH).ok())
+                                  .map(|modtime| modtime.as_secs())
+                                  .unwrap_or(0);
+        let depfile = self.project.resolve(target.object().with_extension("d"));
+        let headers = if self.project.tracks_include_resolution() && depfile.exists() {
+            let mut contents = String::new();
+            File::open(&depfile)?.read_to_string(&mut contents)?;
+            Some(header_records(&parse_depfile(&contents), &self.include_search_path(target)?))
+        } else {
+            None
+        };
+        pass.state.record(&target.object(),
+                          ObjectRecord {
+                              command_hash: self.object_hash(target)?,
+                              source: target.source().display().to_string(),
+                              source_modified,
+                              seconds,
+                              headers,
+                          })
+    }
+
+    // The object cache, unless it's disabled or the compiler can't preprocess
+    // like gcc and clang
+    fn object_cache(&self) -> Option<ObjectCache> {
+        if !self.project.object_cache.unwrap_or(false) || self.project.is_msvc() || self.modules.is_some() {
+            return None;
+        }
+        let max_size = self.project.cache_max_size() * 1024 * 1024;
+        Some(ObjectCache::new(&self.state_path(CACHE_DIR), max_size))
+    }
+
+    // `name` in the state directory
+    fn state_path(&self, name: &str) -> PathBuf {
+        self.project.state_dir().join(name)
+    }
+
+    // Hash of the compiler, the compile flags and the preprocessed source, so
+    // edited headers change it too. None if the source can't be preprocessed,
+    // the compile reports why.
+    fn cache_key(&self, target: &Target, versions: &mut BTreeMap<String, String>) -> Result<Option<String>, YabsError> {
+        let flags = self.compile_flags(target)?;
+        let source = target.source();
+        let contents = if source.extension().is_some_and(|ext| ext == "s") {
+            let mut contents = String::new();
+            File::open(self.project.resolve(&source))?.read_to_string(&mut contents)?;
+            contents
+        } else {
+            let output = command_from_args(&preprocess_args(&flags, &source)).current_dir(&self.context().cwd)
+                                                                              .stderr(Stdio::null())
+                                                                              .output()?;
+            if !output.status.success() {
+                return Ok(None);
+            }
+            String::from_utf8_lossy(&output.stdout).into_owned()
+        };
+        let cwd = &self.context().cwd;
+        let version = versions.entry(flags[0].clone()).or_insert_with(|| tool_version(&flags[0], cwd)).clone();
+        let mut hashed = vec![version, contents];
+        hashed.extend(flags);
+        Ok(Some(format!("{:016x}", hash_strings(&hashed))))
+    }
+
+    // Copies the objects of `unit` found in the object cache into place,
+    // returning those that still have to be compiled
+    fn fetch_cached(&self, unit: Vec<Target>, pass: &mut JobPass) -> Result<Vec<Target>, YabsError> {
+        // A forced rebuild compiles for the diagnostics as much as the objects
+        let cache = match pass.cache {
+            Some(ref cache) if !self.force => cache.clone(),
+            _ => return Ok(unit),
+        };
+        let mut stats = self.cache_stats.get();
+        let mut remaining = Vec::new();
+        for target in unit {
+            match self.cache_key(&target, &mut pass.versions)? {
+                Some(key) => {
+                    if cache.fetch(&key, &self.project.resolve(target.object()))? {
+                        info!("{} cached {}", pass.progress.advance(1), target.object().display());
+                        stats.hits += 1;
+                        self.report.borrow_mut().cached += 1;
+                        self.record_object(&target, 0.0, pass)?;
+                        continue;
+                    }
+                    stats.misses += 1;
+                    pass.keys.insert(target.object(), key);
+                },
+                None => stats.misses += 1,
+            }
+            remaining.push(target);
+        }
+        self.cache_stats.set(stats);
+        Ok(remaining)
+    }
+
+    // Removes every object in the object cache
+    pub fn clear_cache(&self) -> Result<(), YabsError> {
+        let cache_dir = self.state_path(CACHE_DIR);
+        if cache_dir.exists() {
+            fs::remove_dir_all(&cache_dir)?;
+            info!("removed object cache '{}'", cache_dir.display());
+        }
+        Ok(())
+    }
+
+    pub fn cache_stats(&self) -> CacheStats {
+        self.cache_stats.get()
+    }
+
+    // Identifies the command compiling `target`, recorded with its object
+    fn object_hash(&self, target: &Target) -> Result<String, YabsError> {
+        Ok(format!("{:016x}", hash_strings(&self.object_command(target)?)))
+    }
+
+    // Logs why each object is compiled and when a target's objects are all up
+    // to date
+    pub fn set_explain(&mut self, explain: bool) {
+        self.explain = explain;
+    }
+
+    // Compiles every object and links every output whether or not they're up
+    // to date, bypassing the object cache. `status` and `plan` report
+    // everything as stale.
+    pub fn set_force(&mut self, force: bool) {
+        self.force = force;
+    }
+
+    // Whether builds and cleans wait for other yabs processes using the
+    // project, or fail with `ProjectLocked`. They wait by default.
+    pub fn set_lock_blocking(&mut self, block: bool) {
+        self.lock_fail_fast = !block;
+    }
+
+    // Compilers write plain diagnostics into the pipe yabs reads them from,
+    // set when yabs colors its output so they keep their colors
+    pub fn set_color_diagnostics(&mut self, color: bool) {
+        self.color_diagnostics = color;
+    }
+
+    // `-fdiagnostics-color` for the compiler of `target` when it takes it. It
+    // doesn't change the object, so it's left out of logged commands and of
+    // what decides an object is stale.
+    fn diagnostics_color_args(&self, target: &Target) -> Vec<String> {
+        self.driver_color_args(&self.project.compiler_for(Language::of(&target.source())))
+    }
+
+    // `-fdiagnostics-color` for `driver`, the compiler a compile or link runs,
+    // when it takes it
+    fn driver_color_args(&self, driver: &str) -> Vec<String> {
+        let color = match self.project.diagnostics_color {
+            Some(color) => color,
+            None if self.color_diagnostics => true,
+            // Compilers writing into a pipe are plain already
+            None => return Vec::new(),
+        };
+        let supported = *self.diagnostics_color_support
+                             .borrow_mut()
+                             .entry(driver.to_owned())
+                             .or_insert_with(|| supports_diagnostics_color(driver, &self.context().cwd));
+        match (supported, color) {
+            (true, true) => vec![String::from("-fdiagnostics-color=always")],
+            (true, false) => vec![String::from("-fdiagnostics-color=never")],
+            (false, _) => Vec::new(),
+        }
+    }
+
+    fn lock(&self) -> Result<ProjectLock, YabsError> {
+        ProjectLock::acquire(&self.project.state_dir(), !self.lock_fail_fast)
+    }
+
+    pub fn context(&self) -> &BuildContext {
+        &self.project.context
+    }
+
+    // Shared with embedders that need to stop the build, see
+    // `CancellationToken`
+    pub fn cancellation_token(&self) -> CancellationToken {
+        self.cancel.clone()
+    }
+
+    // Runs the argv built by `cmd` with the objects given inline, or through a
+    // response file next to `output` if the command would be too long, and
+    // with the `unlogged` arguments after them
+    fn run_with_objects<F>(&self,
+                           output: &Path,
+                           objects: Vec<PathBuf>,
+                           unlogged: &[String],
+                           cmd: F)
+                           -> Result<(), YabsError>
+        where F: Fn(Vec<String>) -> Vec<String>
+    {
+        let inline = cmd(objects.iter()
+                                .map(|obj| obj.to_string_lossy().into_owned())
+                                .collect());
+        let inline_len: usize = inline.iter().map(|arg| arg.len() + 1).sum();
+        let timeout = self.project.link_timeout();
+        let what = format!("linking '{}'", output.display());
+        let stderr = if inline_len <= self.context().platform.max_command_len() {
+            run_args_unlogged(&inline, &self.context().cwd, unlogged, timeout, &what)?
+        } else {
+            let rsp_path = self.project.resolve(format!("{}.rsp", output.display()));
+            let rsp = ResponseFile::create(rsp_path,
+                                           &objects,
+                                           self.project.keep_response_files.unwrap_or(false))?;
+            run_args_unlogged(&cmd(vec![rsp.arg()]), &self.context().cwd, unlogged, timeout, &what)?
+        };
+        self.check_link_output(output, &stderr)?;
+        self.current_artifact_meta()?.write(&self.project.resolve(output))
+    }
+
+    // Link and archive steps that succeed can still warn, so their output is
+    // surfaced instead of dropped
+    fn check_link_output(&self, output: &Path, stderr: &str) -> Result<(), YabsError> {
+        let suppress = link_suppress_patterns(self.project.link_warning_suppress.as_ref())?;
+        let link_output = classify_link_output(stderr, &suppress);
+        if link_output.lines.is_empty() {
+            return Ok(());
+        }
+        if link_output.warnings.is_empty() {
+            info!("{}", link_output.lines.join("\n"));
+            return Ok(());
+        }
+        warn!("'{}' produced {} warning(s):\n{}",
+              output.display(),
+              link_output.warnings.len(),
+              link_output.lines.join("\n"));
+        if self.project.link_warnings_as_errors.unwrap_or(false) {
+            bail!(YabsErrorKind::LinkWarnings(output.display().to_string(),
+                                              link_output.warnings.len()));
+        }
+        Ok(())
+    }
+
+    fn link_driver(&self, objects: &[PathBuf]) -> String {
+        let cxx = self.project
+                      .file_mod_map
+                      .keys()
+                      .any(|target| {
+                               Language::of(&target.source()) == Language::Cxx &&
+                               objects.contains(&target.object())
+                           });
+        if cxx {
+            self.project.compiler_for(Language::Cxx)
+        } else {
+            self.project.compiler_for(Language::C)
+        }
+    }
+
+    // Produces every output of `target` from its objects
+    pub fn link(&self, target: &dyn Buildable) -> Result<(), YabsError> {
+        let binaries = self.binaries.clone().unwrap_or_default();
+        let objects = target.objects(&self.project, &binaries)?;
+        let overrides = target.overrides();
+        let project = if overrides == Overrides::default() {
+            Cow::Borrowed(&self.project)
+        } else {
+            let mut project = self.project.clone();
+            project.merge_overrides(&overrides);
+            Cow::Owned(project)
+        };
+        let link = LinkContext {
+            project: &project,
+            extra_flags: &self.extra_flags,
+            lib_args: project.lib_args()?,
+            env_flags: project.env_flags("LDFLAGS")?,
+            driver: overrides.compiler.unwrap_or_else(|| self.link_driver(&objects)),
+            rpath_args: project.rpath_args(&self.origin_rpath(target)),
+        };
+        for (kind, output) in self.outputs(target) {
+            if let Some(dir) = output.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+                fs::create_dir_all(self.project.resolve(dir))?;
+            }
+            // Linked to a temporary first, so a failed link leaves the
+            // previous output in place
+            let temporary = temporary_path(&output);
+            let objects = target.objects_of(kind, &self.project, &binaries)?;
+            let objects = match kind {
+                TargetKind::Binary => self.partial_link(target, &link.driver, objects)?,
+                TargetKind::StaticLibrary => {
+                    if self.project.ar_is_explicit() && !self.project.lto_args().is_empty() {
+                        warn!("LTO is enabled but 'ar' is set to '{}', '{}' may not be usable \
+                               if it can't handle LTO objects",
+                              self.project.ar(),
+                              output.display());
+                    }
+                    match self.changed_members(&project, &output, &objects)? {
+                        Some(changed) => {
+                            info!("replacing {} of the {} member(s) of '{}'",
+                                  changed.len(),
+                                  objects.len(),
+                                  output.display());
+                            fs::copy(self.project.resolve(&output), self.project.resolve(&temporary))?;
+                            changed
+                        },
+                        None => objects,
+                    }
+                },
+                _ => objects,
+            };
+            // Links run through the compiler color its diagnostics, and those
+            // of the code LTO compiles
+            let color_args = match kind {
+                TargetKind::StaticLibrary => Vec::new(),
+                _ => self.driver_color_args(&link.driver),
+            };
+            let linked = self.run_with_objects(&output, objects, &color_args, |object_args| {
+                target.link_args(kind, &temporary, &link, object_args)
+            });
+            let linked = linked.and_then(|_| {
+                                       if kind == TargetKind::StaticLibrary {
+                                           return match project.ranlib() {
+                                               Some(ranlib) => self.ranlib(&project, &ranlib, &temporary, &output),
+                                               None => Ok(()),
+                                           };
+                                       }
+                                       if project.splits_debug_info() {
+                                           self.split_debug_info(&project, &temporary, &output)?;
+                                       }
+                                       if project.strips() {
+                                           self.strip(&project, &temporary, &output)?;
+                                       }
+                                       Ok(())
+                                   });
+            if let Err(error) = linked {
+                discard_temporary(&self.project.resolve(&output));
+                return Err(error);
+            }
+            commit_temporary(&self.project.resolve(&output))?;
+        }
+        Ok(())
+    }
+
+    // Strips the freshly linked `temporary` of `output`, logging how much
+    // smaller it got
+    fn strip(&self, project: &ProjectDesc, temporary: &Path, output: &Path) -> Result<(), YabsError> {
+        let before = fs::metadata(self.project.resolve(temporary))?.len();
+        let mut args = shell_split(&project.strip_tool())?;
+        args.push(temporary.to_string_lossy().into_owned());
+        run_args_within(&args,
+                        &self.context().cwd,
+                        project.link_timeout(),
+                        &format!("stripping '{}'", output.display()))?;
+        info!("stripped '{}' from {} to {} bytes",
+              output.display(),
+              before,
+              fs::metadata(self.project.resolve(temporary))?.len());
+        Ok(())
+    }
+
+    // Indexes the freshly written archive `temporary` of `output` with `ranlib`
+    fn ranlib(&self, project: &ProjectDesc, ranlib: &str, temporary: &Path, output: &Path) -> Result<(), YabsError> {
+        let mut args = shell_split(ranlib)?;
+        args.push(temporary.to_string_lossy().into_owned());
+        run_args_within(&args,
+                        &self.context().cwd,
+                        project.link_timeout(),
+                        &format!("indexing '{}'", output.display()))?;
+        Ok(())
+    }
+
+    // The objects to replace in a copy of the existing archive `output` when
+    // 'incremental-archive' is set, those newer than it. None when the archive
+    // has to be written from scratch: there's none yet, it's thin, or it holds
+    // members that aren't among `objects` or two `objects` share a name.
+    fn changed_members(&self,
+                       project: &ProjectDesc,
+                       output: &Path,
+                       objects: &[PathBuf])
+                       -> Result<Option<Vec<PathBuf>>, YabsError> {
+        if !project.is_incremental_archive() || project.is_thin_archive() ||
+           !self.project.resolve(output).is_file() {
+            return Ok(None);
+        }
+        let names: BTreeSet<String> = objects.iter()
+                                             .filter_map(|object| object.file_name())
+                                             .map(|name| name.to_string_lossy().into_owned())
+                                             .collect();
+        if names.len() != objects.len() {
+            return Ok(None);
+        }
+        let list = [project.ar(), String::from("t"), output.to_string_lossy().into_owned()];
+        let listed = command_from_args(&list).current_dir(&self.context().cwd).output()?;
+        let members = String::from_utf8_lossy(&listed.stdout);
+        if !listed.status.success() || members.lines().any(|member| !names.contains(member)) {
+            return Ok(None);
+        }
+        let archived = fs::metadata(self.project.resolve(output))?.modified()?;
+        let mut changed = Vec::new();
+        for object in objects {
+            if fs::metadata(self.project.resolve(object))?.modified()? >= archived {
+                changed.push(object.clone());
+            }
+        }
+        Ok(Some(changed))
+    }
+
+    // Moves the debug info of the freshly linked `temporary` into the `.debug`
+    // file of `output`, leaving a link to it debuggers follow
+    fn split_debug_info(&self, project: &ProjectDesc, temporary: &Path, output: &Path) -> Result<(), YabsError> {
+        let debug_file = self.debug_file(output);
+        if let Some(dir) = debug_file.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(self.project.resolve(dir))?;
+        }
+        let objcopy = shell_split(&project.objcopy())?;
+        let what = format!("splitting the debug info of '{}'", output.display());
+        let mut args = objcopy.clone();
+        args.push(String::from("--only-keep-debug"));
+        args.push(temporary.to_string_lossy().into_owned());
+        args.push(debug_file.to_string_lossy().into_owned());
+        run_args_within(&args, &self.context().cwd, project.link_timeout(), &what)?;
+        let mut args = objcopy;
+        args.push(String::from("--strip-debug"));
+        args.push(format!("--add-gnu-debuglink={}", debug_file.display()));
+        args.push(temporary.to_string_lossy().into_owned());
+        run_args_within(&args, &self.context().cwd, project.link_timeout(), &what)?;
+        info!("wrote the debug info of '{}' to '{}'", output.display(), debug_file.display());
+        Ok(())
+    }
+
+    // The file the debug info of `output` is split into, named after it in
+    // 'debug-dir' or next to it. The `.gnu_debuglink` only records its name,
+    // so debuggers only find it in 'debug-dir' when they're told to look there.
+    fn debug_file(&self, output: &Path) -> PathBuf {
+        let name = format!("{}.debug", output.file_name().unwrap_or_default().to_string_lossy());
+        match self.project.debug_dir {
+            Some(ref dir) => dir.join(self.project.output_dir().unwrap_or_default()).join(name),
+            None => output.with_file_name(name),
+        }
+    }
+
+    // Splits `objects` into the binary's link groups and links each group into
+    // a relocatable object with `-r` by `driver`, returning the partial links
+    // to use for the final link. Groups whose members haven't changed since
+    // their last partial link are reused. Each object's group follows from its
+    // path alone, so adding or removing a source only changes its own group.
+    fn partial_link(&self,
+                    binary: &dyn Buildable,
+                    driver: &str,
+                    objects: Vec<PathBuf>)
+                    -> Result<Vec<PathBuf>, YabsError> {
+        let groups = binary.incremental_link_groups();
+        if groups < 2 || objects.len() < 2 {
+            return Ok(objects);
+        }
+        if self.project.uses_lto() {
+            warn!("not using incremental link groups for '{}' as LTO is enabled",
+                  binary.name());
+            return Ok(objects);
+        }
+        if !self.project.supports_partial_link() {
+            warn!("'{}' can't produce partial links, linking '{}' in one step",
+                  driver,
+                  binary.name());
+            return Ok(objects);
+        }
+        let partial_dir = self.partial_link_dir(binary);
+        fs::create_dir_all(self.project.resolve(&partial_dir))?;
+        let mut members: Vec<Vec<PathBuf>> = vec![Vec::new(); groups];
+        for object in objects {
+            let index = hash_strings(&[object.display().to_string()]) % groups as u64;
+            members[index as usize].push(object);
+        }
+        let mut partials = Vec::new();
+        for (index, group) in members.iter().enumerate().filter(|(_, group)| !group.is_empty()) {
+            let partial = partial_dir.join(format!("{}.o", index));
+            let hash_path = self.project.resolve(partial_dir.join(format!("{}.hash", index)));
+            let hash = hash_files(group, &self.context().cwd)?.to_string();
+            let mut recorded = String::new();
+            if let Ok(mut file) = File::open(&hash_path) {
+                file.read_to_string(&mut recorded)?;
+            }
+            if !self.project.resolve(&partial).exists() || recorded != hash {
+                let mut args = vec![driver.to_owned(), String::from("-r"), String::from("-o")];
+                args.push(partial.to_string_lossy().into_owned());
+                args.extend(group.iter().map(|obj| obj.to_string_lossy().into_owned()));
+                let what = format!("linking '{}'", partial.display());
+                let stderr = run_args_within(&args, &self.context().cwd, self.project.link_timeout(), &what)?;
+                self.check_link_output(&partial, &stderr)?;
+                File::create(&hash_path)?.write_all(hash.as_bytes())?;
+            }
+            partials.push(partial);
+        }
+        Ok(partials)
+    }
+
+    // Jobs to run for a caller asking for `jobs`: 0 uses the project's 'jobs'
+    // or one job per logical CPU, and absurdly many are clamped with a warning
+    pub fn job_count(&self, jobs: usize) -> usize {
+        let jobs = match jobs {
+            0 => self.project.jobs.filter(|&jobs| jobs > 0).unwrap_or_else(default_jobs),
+            jobs => jobs,
+        };
+        let max = (default_jobs() * MAX_JOBS_PER_CPU).max(MIN_MAX_JOBS);
+        if jobs > max {
+            warn!("{} jobs is more than this machine can use, running {}", jobs, max);
+            return max;
+        }
+        jobs
+    }
+
+    pub fn build_library_with_name(&mut self, name: &str, jobs: usize) -> Result<(), YabsError> {
+        let _lock = self.lock()?;
+        self.preflight()?;
+        let jobs = self.job_count(jobs);
+        match self.libraries.iter().flatten().find(|lib| lib.name() == name) {
+            Some(library) => self.build_target(library, jobs),
+            None => {
+                let known = self.libraries.iter().flatten().map(|lib| lib.name()).collect();
+                bail!(YabsErrorKind::TargetNotFound("library".to_owned(), name.to_owned(), known))
+            },
+        }
+    }
+
+    pub fn build_binary_with_name(&mut self, name: &str, jobs: usize) -> Result<(), YabsError> {
+        let _lock = self.lock()?;
+        self.preflight()?;
+        let jobs = self.job_count(jobs);
+        match self.binaries.iter().flatten().find(|bin| bin.name() == name) {
+            Some(binary) => self.build_target(binary, jobs),
+            None => {
+                let known = self.binaries.iter().flatten().map(|bin| bin.name()).collect();
+                bail!(YabsErrorKind::TargetNotFound("binary".to_owned(), name.to_owned(), known))
+            },
+        }
+    }
+
+    // Builds the binaries and libraries `names` stand for together: the
+    // objects of all of them are compiled in one pass, each shared object
+    // once, before each is linked. Every name is resolved before anything is
+    // built.
+    pub fn build_targets(&mut self, names: &[&str], jobs: usize) -> Result<(), YabsError> {
+        for name in names {
+            self.find_buildable(name)?;
+        }
+        let _lock = self.lock()?;
+        self.preflight()?;
+        let mut targets: Vec<&dyn Buildable> = Vec::new();
+        for name in names {
+            let target = self.find_buildable(name)?;
+            if !targets.iter().any(|other| other.output_paths() == target.output_paths()) {
+                targets.push(target);
+            }
+        }
+        self.build_together(targets, self.job_count(jobs))
+    }
+
+    // Compiles the objects of all `targets` in one pass, then links each
+    fn build_together(&self, targets: Vec<&dyn Buildable>, jobs: usize) -> Result<(), YabsError> {
+        self.check_stopped()?;
+        let mut job_queue: Vec<Target> = Vec::new();
+        let mut queued: BTreeSet<PathBuf> = BTreeSet::new();
+        let mut compiled = Vec::new();
+        for target in &targets {
+            let queue = self.build_object_queue(*target)?;
+            compiled.push(!queue.is_empty());
+            for (object, _) in queue {
+                if queued.insert(object.object()) {
+                    job_queue.push(object);
+                }
+            }
+        }
+        self.run_job_queue(job_queue, jobs)?;
+        for (target, compiled) in targets.into_iter().zip(compiled) {
+            self.link_target(target, compiled)?;
+        }
+        Ok(())
+    }
+
+    // The binary or library `name` stands for. A binary and a library sharing
+    // a name are told apart with a "bin:" or "lib:" in front of it.
+    fn find_buildable(&self, name: &str) -> Result<&dyn Buildable, YabsError> {
+        let binary = |name: &str| self.binaries.iter().flatten().find(|binary| binary.name() == name);
+        let library = |name: &str| self.libraries.iter().flatten().find(|library| library.name() == name);
+        let found: Option<&dyn Buildable> = if let Some(name) = name.strip_prefix("bin:") {
+            binary(name).map(|binary| binary as &dyn Buildable)
+        } else if let Some(name) = name.strip_prefix("lib:") {
+            library(name).map(|library| library as &dyn Buildable)
+        } else {
+            match (binary(name), library(name)) {
+                (Some(_), Some(_)) => bail!(YabsErrorKind::AmbiguousTarget(name.to_owned())),
+                (Some(binary), None) => Some(binary),
+                (None, library) => library.map(|library| library as &dyn Buildable),
+            }
+        };
+        match found {
+            Some(found) => Ok(found),
+            None => {
+                bail!(YabsErrorKind::TargetNotFound("binary or library".to_owned(),
+                                                    name.to_owned(),
+                                                    self.buildable_names()))
+            },
+        }
+    }
+
+    // Builds the binaries and libraries of the group `name` together, including
+    // those with 'default = false'
+    pub fn build_group(&mut self, name: &str, jobs: usize) -> Result<(), YabsError> {
+        if !self.groups.contains_key(name) {
+            bail!(YabsErrorKind::TargetNotFound("group".to_owned(),
+                                                name.to_owned(),
+                                                self.groups.keys().cloned().collect()))
+        }
+        let _lock = self.lock()?;
+        self.preflight()?;
+        let targets = self.group_members(name)?;
+        self.build_together(targets, self.job_count(jobs))
+    }
+
+    // Runs the tool `template` describes over the selected sources with the
+    // flags each is compiled with, see `CompileParts::expand`. A failing run
+    // doesn't stop the others, the report tells which passed.
+    pub fn run_tool_over_sources(&mut self,
+                                 template: &[String],
+                                 jobs: usize,
+                                 selector: &SourceSelector)
+                                 -> Result<ToolReport, YabsError> {
+        let tool = match template.first() {
+            Some(tool) => tool.clone(),
+            None => bail!(YabsErrorKind::InvalidValue(String::from("tool"), String::from("no command given"))),
+        };
+        if self.context().find_program(&tool).is_none() {
+            bail!(YabsErrorKind::ToolNotFound(tool, String::from("install it or check the command")));
+        }
+        let objects = match selector.target {
+            Some(ref name) => {
+                let buildable = self.find_buildable(name)?;
+                Some(buildable.objects(&self.project, self.binaries.as_ref().unwrap_or(&vec![]))?)
+            },
+            None => None,
+        };
+        let mut commands = Vec::new();
+        for target in self.project.file_mod_map.keys() {
+            let listed = match objects {
+                Some(ref objects) => objects.contains(&target.object()),
+                None => target.owner().is_none(),
+            };
+            if !listed || !selector.matches(&target.source())? {
+                continue;
+            }
+            let parts = CompileParts::new(&self.compile_flags(target)?, &target.source(), &target.object());
+            commands.push((target.source(), parts.expand(template)));
+        }
+        self.run_over_sources(commands, jobs)
+    }
+
+    // The source `source` of the project after the preprocessor, run with the
+    // flags it's compiled with
+    pub fn preprocess(&self, source: &Path) -> Result<String, YabsError> {
+        let plain = |path: &Path| -> PathBuf {
+            path.components().filter(|component| *component != Component::CurDir).collect()
+        };
+        let target = match self.project.shared_targets().find(|target| plain(&target.source()) == plain(source)) {
+            Some(target) => target,
+            None => {
+                let wanted = plain(source).display().to_string();
+                let sources: Vec<String> =
+                    self.project.shared_targets().map(|target| plain(&target.source()).display().to_string()).collect();
+                let close = closest_matches(&wanted, sources.iter().map(String::as_str), 3);
+                bail!(YabsErrorKind::SourceNotFound(source.to_path_buf(),
+                                                    close.into_iter().map(String::from).collect()))
+            },
+        };
+        let output = command_from_args(&preprocess_args(&self.compile_flags(target)?, &target.source()))
+            .current_dir(&self.context().cwd)
+            .output()?;
+        if !output.status.success() {
+            warn!(target: DIAGNOSTICS_TARGET, "{}", String::from_utf8_lossy(&output.stderr).trim_end());
+            bail!(YabsErrorKind::CompileFailed(target.source().display().to_string(),
+                                               output.status.code().unwrap_or(-1)));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+
+    // Runs every source through the compiler with the flags it's built with
+    // and `-fsyntax-only`, or `/Zs` for MSVC, so nothing is written. Every
+    // source is checked whether or not others fail, their diagnostics are
+    // logged as they'd be by a build.
+    pub fn check_syntax(&mut self, jobs: usize) -> Result<ToolReport, YabsError> {
+        let mut commands = Vec::new();
+        for target in self.project.shared_targets() {
+            let mut args: Vec<String> = self.compile_flags(target)?.into_iter().filter(|arg| arg != "-MMD").collect();
+            args.extend(self.diagnostics_color_args(target));
+            args.push(String::from(if self.project.is_msvc() { "/Zs" } else { "-fsyntax-only" }));
+            args.push(target.source().to_string_lossy().into_owned());
+            commands.push((target.source(), args));
+        }
+        let report = self.run_over_sources(commands, jobs)?;
+        for run in report.runs.iter().filter(|run| !run.output.is_empty()) {
+            warn!(target: DIAGNOSTICS_TARGET, "{}", run.output.trim_end());
+        }
+        info!(target: SUMMARY_TARGET,
+              "syntax check: {} source(s) passed, {} failed",
+              report.passed(),
+              report.failed().len());
+        Ok(report)
+    }
+
+    // Runs `commands`, each with the source it's run over, on `jobs` threads.
+    // A failing command doesn't stop the others.
+    fn run_over_sources(&self, commands: Vec<(PathBuf, Vec<String>)>, jobs: usize) -> Result<ToolReport, YabsError> {
+        let next = AtomicUsize::new(0);
+        let progress = Mutex::new(Progress::new(commands.len()));
+        let outputs: Mutex<Vec<Option<::std::io::Result<Output>>>> =
+            Mutex::new(commands.iter().map(|_| None).collect());
+        let cancel = &self.cancel;
+        let cwd = &self.context().cwd;
+        thread::scope(|scope| {
+            for _ in 0..self.job_count(jobs).min(commands.len()) {
+                scope.spawn(|| while !cancel.is_cancelled() && !interrupt::interrupted() {
+                                let index = next.fetch_add(1, Ordering::SeqCst);
+                                let args = match commands.get(index) {
+                                    Some((_, args)) => args,
+                                    None => break,
+                                };
+                                info!("{} {}", progress.lock().unwrap().advance(1), display_args(args));
+                                let output = command_from_args(args).current_dir(cwd).output();
+                                outputs.lock().unwrap()[index] = Some(output);
+                            });
+            }
+        });
+        self.check_stopped()?;
+        let mut report = ToolReport::default();
+        for ((source, args), output) in commands.into_iter().zip(outputs.into_inner().unwrap()) {
+            if let Some(output) = output {
+                let output = output?;
+                report.runs.push(ToolRun {
+                                     source,
+                                     command: display_args(&args),
+                                     success: output.status.success(),
+                                     output: String::from_utf8_lossy(&output.stdout).into_owned() +
+                                             &String::from_utf8_lossy(&output.stderr),
+                                 });
+            }
+        }
+        Ok(report)
+    }
+
+    // The outputs of `target`, placed in its own 'bin-dir' or 'lib-out-dir',
+    // else the project's, and under that in the output directory of the
+    // target triple when cross compiling
+    fn outputs(&self, target: &dyn Buildable) -> Vec<(TargetKind, PathBuf)> {
+        let cross = self.project.output_dir().unwrap_or_default();
+        target.outputs()
+              .into_iter()
+              .map(|(kind, path)| {
+                  let project_dir = match kind {
+                      TargetKind::Binary => self.project.bin_dir.clone(),
+                      _ => self.project.lib_out_dir.clone(),
+                  };
+                  let dir = target.out_dir().or(project_dir).unwrap_or_default();
+                  (kind, dir.join(&cross).join(path))
+              })
+              .collect()
+    }
+
+    // The 'rpath' entry for the project's libraries as seen from the directory
+    // `target` is written to, `$ORIGIN/../lib` for a binary in `build/bin` and
+    // libraries in `build/lib`. Directories that can't be told apart that way
+    // are given as absolute paths.
+    fn origin_rpath(&self, target: &dyn Buildable) -> String {
+        let cross = self.project.output_dir().unwrap_or_default();
+        let lib_dir = self.project.lib_out_dir.clone().unwrap_or_default().join(&cross);
+        let output_dir = self.output_paths(target)
+                             .first()
+                             .and_then(|output| output.parent().map(Path::to_path_buf))
+                             .unwrap_or_default();
+        let normal = |path: &Path| -> Option<Vec<String>> {
+            path.components()
+                .filter(|component| *component != Component::CurDir)
+                .map(|component| match component {
+                         Component::Normal(name) => Some(name.to_string_lossy().into_owned()),
+                         _ => None,
+                     })
+                .collect()
+        };
+        match (normal(&output_dir), normal(&lib_dir)) {
+            (Some(from), Some(to)) => {
+                let common = from.iter().zip(&to).take_while(|(a, b)| a == b).count();
+                let mut rpath = vec![String::from("$ORIGIN")];
+                rpath.extend(from[common..].iter().map(|_| String::from("..")));
+                rpath.extend(to[common..].iter().cloned());
+                rpath.join("/")
+            },
+            _ => self.context().cwd.join(lib_dir).to_string_lossy().into_owned(),
+        }
+    }
+
+    fn output_paths(&self, target: &dyn Buildable) -> Vec<PathBuf> {
+        self.outputs(target).into_iter().map(|(_, path)| path).collect()
+    }
+
+    fn partial_link_dir(&self, target: &dyn Buildable) -> PathBuf {
+        self.project.output_dir().unwrap_or_default().join(target.partial_link_dir())
+    }
+
+    // Outputs more than one binary or library would be written to, each with
+    // the first target writing it and the one writing it again. Targets
+    // sharing a name are left to `check`'s error about the names.
+    fn output_collisions(&self) -> Vec<(PathBuf, String, String)> {
+        let mut writers: BTreeMap<PathBuf, String> = BTreeMap::new();
+        let mut collisions = Vec::new();
+        for buildable in self.buildables() {
+            for path in self.output_paths(buildable) {
+                match writers.get(&path) {
+                    Some(first) if *first != buildable.name() => {
+                        collisions.push((path, first.clone(), buildable.name()))
+                    },
+                    Some(_) => {},
+                    None => {
+                        writers.insert(path, buildable.name());
+                    },
+                }
+            }
+        }
+        collisions
+    }
+
+    // Fails before anything is built when two targets would overwrite each
+    // other's outputs or a program the build runs isn't there. Every build
+    // starts here, so a forced one compiles every object again.
+    fn preflight(&mut self) -> Result<(), YabsError> {
+        self.probe_fs_caps()?;
+        if self.state.has_stale_records() {
+            self.state.save()?;
+        }
+        self.forced.borrow_mut().clear();
+        self.space_needed.set(0);
+        self.artifact_meta.replace(None);
+        if let Some((path, first, second)) = self.output_collisions().into_iter().next() {
+            bail!(YabsErrorKind::OutputCollision(path, first, second));
+        }
+        self.check_link_inputs()?;
+        if !self.project.supports_split_debug_info() {
+            let split = self.linked_with(|overrides| overrides.split_debug_info, self.project.split_debug_info);
+            if let Some(buildable) = split.first() {
+                bail!(YabsErrorKind::InvalidValue(String::from("split-debug-info"),
+                                                  format!("'{}' can't have its debug info split by objcopy when \
+                                                           building for macOS or with MSVC",
+                                                          buildable.name())));
+            }
+        }
+        self.check_programs()
+    }
+
+    // Missing link inputs fail before anything is compiled rather than in the
+    // middle of the linker's output
+    fn check_link_inputs(&self) -> Result<(), YabsError> {
+        for buildable in self.buildables() {
+            for input in buildable.link_inputs() {
+                if !self.project.resolve(&input).is_file() {
+                    bail!(YabsErrorKind::LinkInputNotFound(buildable.name(), input.display().to_string()));
+                }
+            }
+        }
+        if !self.project.supports_version_scripts() {
+            for library in self.libraries.iter().flatten().filter(|library| library.version_script().is_some()) {
+                warn!("the linker doesn't take version scripts, 'version-script' of '{}' is ignored", library.name());
+            }
+        }
+        Ok(())
+    }
+
+    // The compilers, compiler launcher and ar the build runs, each with what
+    // it's run as
+    fn build_programs(&self) -> Result<Vec<(String, String)>, YabsError> {
+        let mut programs: Vec<(String, String)> = Vec::new();
+        let mut languages: Vec<Language> = Vec::new();
+        for target in self.project.file_mod_map.keys() {
+            let language = Language::of(&target.source());
+            if !languages.contains(&language) {
+                languages.push(language);
+                programs.push((format!("{} compiler", language.name()), self.project.compiler_for(language)));
+            }
+        }
+        for buildable in self.buildables() {
+            if let Some(compiler) = buildable.overrides().compiler {
+                programs.push((format!("compiler of '{}'", buildable.name()), compiler));
+            }
+        }
+        if let Some(launcher) = self.project.launcher_args()?.first() {
+            programs.push((String::from("compiler launcher"), launcher.clone()));
+        }
+        if self.libraries.iter().flatten().any(|library| library.is_static()) {
+            programs.push((String::from("ar"), self.project.ar()));
+            if let Some(ranlib) = self.project.ranlib() {
+                programs.push((String::from("ranlib"), ranlib));
+            }
+        }
+        if !self.linked_with(|overrides| overrides.split_debug_info, self.project.split_debug_info).is_empty() {
+            programs.push((String::from("objcopy"), self.project.objcopy()));
+        }
+        if !self.linked_with(|overrides| overrides.strip, self.project.strip).is_empty() {
+            programs.push((String::from("strip"), self.project.strip_tool()));
+        }
+        Ok(programs)
+    }
+
+    // The binaries and libraries with a binary or dynamic library output for
+    // which `setting` of their overrides, or else `project`, is set
+    fn linked_with<F>(&self, setting: F, project: Option<bool>) -> Vec<&dyn Buildable>
+        where F: Fn(&Overrides) -> Option<bool>
+    {
+        self.buildables()
+            .into_iter()
+            .filter(|buildable| buildable.kinds().iter().any(|&kind| kind != TargetKind::StaticLibrary))
+            .filter(|buildable| setting(&buildable.overrides()).or(project).unwrap_or(false))
+            .collect()
+    }
+
+    // Fails once for a program that isn't there rather than once for each
+    // compile, logging the version of each that is with `-v`. Programs given
+    // as a path have to be executable files.
+    fn check_programs(&self) -> Result<(), YabsError> {
+        let mut checked: BTreeSet<String> = BTreeSet::new();
+        for (role, command) in self.build_programs()? {
+            let program = command.split_whitespace().next().unwrap_or_default().to_owned();
+            if program.is_empty() || !checked.insert(program.clone()) {
+                continue;
+            }
+            match self.context().find_program(&program) {
+                Some(ref path) if is_executable(path) => {
+                    if log_enabled!(LogLevel::Debug) {
+                        debug!("{} '{}': {}",
+                               role,
+                               path.display(),
+                               tool_version(&path.to_string_lossy(), &self.context().cwd));
+                    }
+                },
+                _ => bail!(YabsErrorKind::ProgramNotFound(role, program)),
+            }
+        }
+        Ok(())
+    }
+
+    // Binaries and libraries a build of everything builds
+    fn default_buildables(&self) -> Vec<&dyn Buildable> {
+        self.buildables().into_iter().filter(|buildable| buildable.is_default()).collect()
+    }
+
+    // Binaries and libraries in the order they're built
+    fn buildables(&self) -> Vec<&dyn Buildable> {
+        let mut buildables: Vec<&dyn Buildable> = Vec::new();
+        for binary in self.binaries.iter().flatten() {
+            buildables.push(binary);
+        }
+        for library in self.libraries.iter().flatten() {
+            buildables.push(library);
+        }
+        buildables
+    }
+
+    // Every binary, library and task with what it builds and whether it's up
+    // to date
+    pub fn targets(&self) -> Vec<TargetInfo> {
+        let mut targets = Vec::new();
+        for buildable in self.buildables() {
+            let compiled = self.stale_objects(self.project.staleness(), buildable)
+                               .map(|objects| objects.iter().all(|(_, stale)| stale.is_none()))
+                               .unwrap_or(false);
+            for (kind, output) in self.outputs(buildable) {
+                let linked = self.is_up_to_date(buildable, ::std::slice::from_ref(&output)).unwrap_or(false);
+                targets.push(TargetInfo {
+                                 kind,
+                                 name: buildable.name(),
+                                 default: buildable.is_default(),
+                                 exists: self.project.resolve(&output).exists(),
+                                 up_to_date: compiled && linked,
+                                 output: Some(output),
+                             });
+            }
+        }
+        for task in self.tasks.iter().flatten() {
+            targets.push(TargetInfo {
+                             kind: TargetKind::Task,
+                             name: task.name(),
+                             output: None,
+                             default: false,
+                             exists: false,
+                             up_to_date: false,
+                         });
+        }
+        targets
+    }
+
+    // Logs `targets` as a table, or as one event each with `--json`
+    pub fn log_targets(&self) {
+        let targets = self.targets();
+        if events::enabled() {
+            for target in targets {
+                events::emit(|| {
+                    Event::Target {
+                        name: target.name,
+                        kind: target.kind.describe().to_owned(),
+                        output: target.output.map(|output| output.display().to_string()),
+                        default: target.default,
+                        exists: target.exists,
+                        up_to_date: target.up_to_date,
+                    }
+                });
+            }
+            return;
+        }
+        let name_width = targets.iter().map(|target| target.name.len()).max().unwrap_or(0).max("NAME".len());
+        let kind_width = targets.iter().map(|target| target.kind.describe().len()).max().unwrap_or(0).max("KIND".len());
+        info!("{:<name_width$}  {:<kind_width$}  {:<11}  OUTPUT",
+              "NAME",
+              "KIND",
+              "STATUS",
+              name_width = name_width,
+              kind_width = kind_width);
+        for target in &targets {
+            let status = match *target {
+                TargetInfo { kind: TargetKind::Task, .. } => "",
+                TargetInfo { up_to_date: true, .. } => "up to date",
+                TargetInfo { exists: true, .. } => "stale",
+                _ => "not built",
+            };
+            let output = match target.output {
+                Some(ref output) if !target.default => format!("{}  (not built by default)", output.display()),
+                Some(ref output) => output.display().to_string(),
+                None => String::new(),
+            };
+            let line = format!("{:<name_width$}  {:<kind_width$}  {:<11}  {}",
+                               target.name,
+                               target.kind.describe(),
+                               status,
+                               output,
+                               name_width = name_width,
+                               kind_width = kind_width);
+            info!("{}", line.trim_end());
+        }
+    }
+
+    // What a build would compile and link for each binary and library,
+    // without touching anything. Objects are decided as `build_object_queue`
+    // decides them, but missing ones are left in the state cache.
+    pub fn status(&self) -> Result<BuildStatus, YabsError> {
+        let mut status = BuildStatus::default();
+        for buildable in self.default_buildables() {
+            let decisions = self.stale_objects(self.project.staleness(), buildable)?;
+            let objects = decisions.len();
+            let stale_objects: Vec<(PathBuf, StaleReason)> =
+                decisions.into_iter()
+                         .filter_map(|(target, reason)| reason.map(|reason| (target.object(), reason)))
+                         .collect();
+            let outputs = self.output_paths(buildable);
+            let relink = self.force || !stale_objects.is_empty() || !self.is_up_to_date(buildable, &outputs)?;
+            status.targets.push(TargetStatus {
+                                    name: buildable.name(),
+                                    outputs,
+                                    stale_objects,
+                                    objects,
+                                    relink,
+                                });
+        }
+        Ok(status)
+    }
+
+    // Logs `status` with a line per target, and each stale object with why
+    // with `--verbose`
+    pub fn log_status(&self) -> Result<BuildStatus, YabsError> {
+        let status = self.status()?;
+        let paint = |colour: Colour, text: &str| {
+            if self.color_diagnostics {
+                colour.paint(text).to_string()
+            } else {
+                text.to_owned()
+            }
+        };
+        for target in &status.targets {
+            if target.is_up_to_date() {
+                info!("{}: {}", target.name, paint(Colour::Green, "up to date"));
+                continue;
+            }
+            let mut steps = Vec::new();
+            if !target.stale_objects.is_empty() {
+                steps.push(format!("{} of {} object(s) to compile", target.stale_objects.len(), target.objects));
+            }
+            if target.relink {
+                let outputs: Vec<String> = target.outputs.iter().map(|output| output.display().to_string()).collect();
+                steps.push(format!("{} to link", outputs.join(", ")));
+            }
+            info!("{}: {}", target.name, paint(Colour::Yellow, &steps.join(", ")));
+            for (object, reason) in &target.stale_objects {
+                debug!("  {}: {}", object.display(), reason);
+            }
+        }
+        Ok(status)
+    }
+
+    // The name and kind of every target, without looking at what's built
+    fn target_kinds(&self) -> Vec<(String, TargetKind)> {
+        let mut kinds = Vec::new();
+        for buildable in self.buildables() {
+            for kind in buildable.kinds() {
+                kinds.push((buildable.name(), kind));
+            }
+        }
+        for task in self.tasks.iter().flatten() {
+            kinds.push((task.name(), TargetKind::Task));
+        }
+        kinds
+    }
+
+    // Names of the binaries and libraries, in the order they're built
+    fn buildable_names(&self) -> Vec<String> {
+        self.buildables().into_iter().map(|buildable| buildable.name()).collect()
+    }
+
+    // Names of every binary, library and task, once each
+    fn target_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = Vec::new();
+        for (name, _) in self.target_kinds() {
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+        names
+    }
+
+    fn find_task(&self, name: &str) -> Option<Task> {
+        self.tasks.as_ref().and_then(|tasks| tasks.iter().find(|task| task.name() == name).cloned())
+    }
+
+    // Binaries and libraries are found by name, so no two binaries and no two
+    // libraries may share one. A binary and a library can, they're told apart
+    // by "bin:" and "lib:".
+    fn validate_names(&self) -> Result<(), YabsError> {
+        let binaries: Vec<String> = self.binaries.iter().flatten().map(|binary| binary.name()).collect();
+        let libraries: Vec<String> = self.libraries.iter().flatten().map(|library| library.name()).collect();
+        for &(table, ref names) in &[("bin", binaries), ("lib", libraries)] {
+            for (second, name) in names.iter().enumerate() {
+                if let Some(first) = names[..second].iter().position(|other| other == name) {
+                    bail!(YabsErrorKind::DuplicateTarget(table.to_owned(), name.clone(), first + 1, second + 1));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Every task dependency has to name a target, and tasks can't depend on
+    // themselves through other tasks
+    fn validate_tasks(&self) -> Result<(), YabsError> {
+        for task in self.tasks.as_ref().unwrap_or(&vec![]) {
+            for dep in task.deps() {
+                if !self.target_kinds().iter().any(|(name, _)| *name == dep) {
+                    bail!(YabsErrorKind::TargetNotFound("target".to_owned(), dep, self.target_names()));
+                }
+            }
+            self.check_task_cycle(task, &mut vec![])?;
+        }
+        Ok(())
+    }
+
+    // Every group member has to name a binary, library or group, and groups
+    // can't list themselves through other groups
+    fn validate_groups(&self) -> Result<(), YabsError> {
+        let names = self.buildable_names();
+        for name in self.groups.keys() {
+            if names.contains(name) {
+                bail!(YabsErrorKind::InvalidValue(format!("groups.{}", name),
+                                                  format!("'{}' is already the name of a binary or library", name)));
+            }
+            self.group_members(name)?;
+        }
+        Ok(())
+    }
+
+    // The binaries and libraries of the group `name`, with those of the groups
+    // it lists, in the order they're built
+    fn group_members(&self, name: &str) -> Result<Vec<&dyn Buildable>, YabsError> {
+        let mut members = Vec::new();
+        self.collect_group(name, &mut vec![], &mut members)?;
+        Ok(self.buildables()
+               .into_iter()
+               .filter(|buildable| members.iter().any(|member| ptr::addr_eq(*member, *buildable)))
+               .collect())
+    }
+
+    fn collect_group<'a>(&'a self,
+                         name: &str,
+                         path: &mut Vec<String>,
+                         members: &mut Vec<&'a dyn Buildable>)
+                         -> Result<(), YabsError> {
+        if path.iter().any(|group| group == name) {
+            path.push(name.to_owned());
+            bail!(YabsErrorKind::GroupCycle(path.join(" -> ")));
+        }
+        path.push(name.to_owned());
+        let names = self.buildable_names();
+        for member in self.groups.get(name).into_iter().flatten() {
+            if self.groups.contains_key(member) {
+                self.collect_group(member, path, members)?;
+            } else if let Some(buildable) = self.group_member(member)? {
+                members.push(buildable);
+            } else {
+                let known = names.iter().chain(self.groups.keys()).map(String::as_str);
+                let hint = match closest_match(member, known) {
+                    Some(known) => format!("; did you mean '{}'?", known),
+                    None => String::new(),
+                };
+                bail!(YabsErrorKind::InvalidValue(format!("groups.{}", name),
+                                                  format!("'{}' isn't a binary, library or group{}", member, hint)));
+            }
+        }
+        path.pop();
+        Ok(())
+    }
+
+    // The binary or library a group lists as `member`, None when nothing has
+    // that name
+    fn group_member(&self, member: &str) -> Result<Option<&dyn Buildable>, YabsError> {
+        match self.find_buildable(member) {
+            Ok(buildable) => Ok(Some(buildable)),
+            Err(YabsError(YabsErrorKind::TargetNotFound(..), _)) => Ok(None),
+            Err(error) => Err(error),
+        }
+    }
+
+    fn check_task_cycle(&self, task: &Task, path: &mut Vec<String>) -> Result<(), YabsError> {
+        if path.contains(&task.name()) {
+            path.push(task.name());
+            bail!(YabsErrorKind::TaskCycle(path.join(" -> ")));
+        }
+        path.push(task.name());
+        for dep in task.deps() {
+            if let Some(dep_task) = self.find_task(&dep) {
+                self.check_task_cycle(&dep_task, path)?;
+            }
+        }
+        path.pop();
+        Ok(())
+    }
+
+    // Builds or runs the task's dependencies, then runs the task unless its
+    // outputs are up to date
+    pub fn run_task(&mut self, name: &str, jobs: usize) -> Result<(), YabsError> {
+        let task = match self.find_task(name) {
+            Some(task) => task,
+            None => {
+                let tasks = self.tasks.iter().flatten().map(|task| task.name()).collect();
+                bail!(YabsErrorKind::TargetNotFound("task".to_owned(), name.to_owned(), tasks))
+            },
+        };
+        let _lock = self.lock()?;
+        let jobs = self.job_count(jobs);
+        for dep in task.deps() {
+            let kind = self.target_kinds().into_iter().find(|(name, _)| *name == dep).map(|(_, kind)| kind);
+            match kind {
+                Some(TargetKind::Binary) => self.build_binary_with_name(&dep, jobs)?,
+                Some(TargetKind::Task) => self.run_task(&dep, jobs)?,
+                Some(_) => self.build_library_with_name(&dep, jobs)?,
+                None => bail!(YabsErrorKind::TargetNotFound("target".to_owned(), dep, self.target_names())),
+            }
+        }
+        if task.is_up_to_date(&self.context().cwd)? {
+            info!("task '{}' is up to date", name);
+            return Ok(());
+        }
+        task.run(&self.context().cwd)
+    }
+
+    pub fn build_all_libraries(&mut self, jobs: usize) -> Result<(), YabsError> {
+        let _lock = self.lock()?;
+        let jobs = self.job_count(jobs);
+        for library in self.libraries.iter().flatten().filter(|library| library.is_default()) {
+            self.build_target(library, jobs)?;
+        }
+        Ok(())
+    }
+
+    pub fn build_id(&self) -> &str {
+        &self.build_id
+    }
+
+    // Logs which compiler and environment flags the build uses. When cross
+    // compiling the compiler has to exist, or every compile would fail.
+    pub fn check_toolchain(&self) -> Result<(), YabsError> {
+        info!("build {}", self.build_id);
+        let mut languages = vec![self.project.main_language()];
+        if languages[0] == Language::Cxx && self.project.has_language(Language::C) {
+            languages.push(Language::C);
+        }
+        for language in languages {
+            let (compiler, reason) = self.project.compiler_choice_for(language);
+            if self.project.has_language(Language::C) && self.project.has_language(Language::Cxx) {
+                info!("using {} compiler '{}' ({})", language.name(), compiler, reason);
+            } else {
+                info!("using compiler '{}' ({})", compiler, reason);
+            }
+            if let Some(ref target) = self.project.target {
+                if self.context().find_program(&compiler).is_none() {
+                    bail!(YabsErrorKind::ToolNotFound(compiler,
+                                                      format!("install a toolchain for '{}' or \
+                                                               set 'compiler'",
+                                                              target)));
+                }
+            }
+        }
+        if self.modules.is_some() {
+            let compiler = self.project.compiler_for(Language::Cxx);
+            let version = tool_version(&compiler, &self.context().cwd);
+            if !compiler.contains("clang") || clang_major_version(&version).unwrap_or(0) < 16 {
+                bail!(YabsErrorKind::InvalidValue(String::from("cxx-modules"),
+                                                  format!("'{}' doesn't support the module flags yabs \
+                                                           passes, C++ modules need clang 16 or newer",
+                                                          compiler)));
+            }
+        }
+        if let Some(launcher) = self.project.launcher_args()?.first() {
+            if self.context().find_program(launcher).is_none() {
+                bail!(YabsErrorKind::ToolNotFound(launcher.clone(),
+                                                  String::from("install it or remove \
+                                                                'compiler-launcher'")));
+            }
+            info!("using compiler launcher '{}'", launcher);
+        }
+        for var in &["CFLAGS", "CXXFLAGS", "LDFLAGS"] {
+            let flags = self.project.env_flags(var)?;
+            if !flags.is_empty() {
+                info!("using {} from the environment: {}", var, display_args(&flags));
+            }
+        }
+        Ok(())
+    }
+
+    // Every tool requirement with what declares it
+    fn tool_requirements(&self) -> Vec<(ToolRequirement, String)> {
+        let mut requirements: Vec<(ToolRequirement, String)> = self.project
+                                                                   .requires
+                                                                   .iter()
+                                                                   .flatten()
+                                                                   .map(|req| (req.clone(), String::from("[project]")))
+                                                                   .collect();
+        for task in self.tasks.iter().flatten() {
+            for requirement in task.requires() {
+                requirements.push((requirement, format!("task '{}'", task.name())));
+            }
+        }
+        requirements
+    }
+
+    // Probes each required tool once, however many declare it
+    fn probe_tools(&self) -> Result<Vec<(ToolRequirement, String, ToolProbe)>, YabsError> {
+        let mut probes: BTreeMap<(String, Option<String>), ToolProbe> = BTreeMap::new();
+        let mut probed = Vec::new();
+        for (requirement, needed_by) in self.tool_requirements() {
+            let key = (requirement.tool.clone(), requirement.version_regex.clone());
+            if !probes.contains_key(&key) {
+                let program = self.context().find_program(&requirement.tool);
+                let probe = probe_tool(program, requirement.version_regex.as_deref())?;
+                probes.insert(key.clone(), probe);
+            }
+            probed.push((requirement, needed_by, probes[&key].clone()));
+        }
+        Ok(probed)
+    }
+
+    // Fails with every missing or too old tool at once, before anything is
+    // built
+    pub fn check_tools(&self) -> Result<(), YabsError> {
+        let mut problems = Vec::new();
+        for (requirement, needed_by, probe) in self.probe_tools()? {
+            if let Some(problem) = requirement.problem(&probe)? {
+                problems.push(format!("  {} (needed by {})", problem, needed_by));
+            }
+        }
+        if !problems.is_empty() {
+            bail!(YabsErrorKind::ToolRequirements(problems.join("\n")));
+        }
+        Ok(())
+    }
+
+    // Prints what the build would use and what the filesystem can do
+    pub fn doctor(&self) -> Result<(), YabsError> {
+        self.check_toolchain()?;
+        for (requirement, needed_by, probe) in self.probe_tools()? {
+            match requirement.problem(&probe)? {
+                Some(problem) => warn!("{} (needed by {})", problem, needed_by),
+                None => {
+                    info!("tool '{}' {} at '{}' (needed by {})",
+                          requirement.tool,
+                          probe.version.as_deref().unwrap_or("of unknown version"),
+                          probe.path.as_ref().map(|path| path.display().to_string()).unwrap_or_default(),
+                          needed_by)
+                },
+            }
+        }
+        let caps = match self.state.fs_caps {
+            Some(caps) => {
+                info!("filesystem capabilities (cached in '{}'):", self.state.path().display());
+                caps
+            },
+            None => {
+                info!("filesystem capabilities (probed now, the first build caches them):");
+                FsCapabilities::probe(&self.object_root()?)?
+            },
+        };
+        info!("  symlinks: {}", caps.symlinks);
+        info!("  fine-grained modification times: {}", caps.fine_mtime);
+        info!("  permissions: {}", caps.permissions);
+        info!("  case-sensitive: {}", caps.case_sensitive);
+        for limitation in caps.limitations() {
+            warn!("{}", limitation);
+        }
+        Ok(())
+    }
+
+    // Looks for mistakes in the buildfile without building anything, each
+    // logged with the key it's in. Only fails when one of them would break
+    // the build, include and library directories that don't exist were already
+    // warned about when the buildfile was loaded.
+    pub fn check(&self) -> Result<(), YabsError> {
+        let mut errors: Vec<String> = Vec::new();
+        let mut warnings: Vec<String> = Vec::new();
+        // A binary and a library may share a name, see `find_buildable`
+        if let Err(error) = self.validate_names() {
+            errors.push(error.to_string());
+        }
+        for (path, first, second) in self.output_collisions() {
+            errors.push(format!("'output': '{}' and '{}' are both written to '{}'",
+                                first,
+                                second,
+                                path.display()));
+        }
+        for binary in self.binaries.iter().flatten() {
+            if !self.project.resolve(binary.path()).is_file() {
+                errors.push(format!("'path' of binary '{}': '{}' doesn't exist",
+                                    binary.name(),
+                                    binary.path().display()));
+            }
+        }
+        for library in self.libraries.iter().flatten() {
+            if !library.is_static() && !library.is_dynamic() {
+                errors.push(format!("'types' of library '{}': lists neither \"static\" nor \"dynamic\"",
+                                    library.name()));
+            }
+        }
+        let mut languages: BTreeSet<&'static str> = BTreeSet::new();
+        for target in self.project.file_mod_map.keys() {
+            if target.object() == target.source() {
+                errors.push(format!("'src': '{}' doesn't have one of the 'file-extensions'",
+                                    target.source().display()));
+                continue;
+            }
+            let language = Language::of(&target.source());
+            let compiler = self.project.compiler_for(language);
+            let program = compiler.split_whitespace().next().unwrap_or_default().to_owned();
+            if languages.insert(language.name()) && self.context().find_program(&program).is_none() {
+                errors.push(format!("'compiler': '{}', which compiles the {} sources, wasn't found",
+                                    program,
+                                    language.name()));
+            }
+        }
+        if self.libraries.iter().flatten().any(|library| library.is_static()) &&
+           self.context().find_program(&self.project.ar()).is_none() {
+            errors.push(format!("'ar': '{}', which archives the static libraries, wasn't found", self.project.ar()));
+        }
+        for &(key, scripts) in &[("before-script", &self.project.before_script),
+                                 ("after-script", &self.project.after_script)] {
+            for script in scripts.iter().flatten() {
+                let program = script.command().split_whitespace().next().unwrap_or_default();
+                if program.contains('/') && !self.context().cwd.join(program).is_file() {
+                    warnings.push(format!("'{}': '{}' runs '{}', which doesn't exist", key, script.command(), program));
+                }
+                if let Script::Declared { inputs: Some(ref inputs), .. } = *script {
+                    for input in inputs {
+                        let cwd = &self.context().cwd;
+                        if !expand_glob(input, cwd)?.iter().any(|path| cwd.join(path).exists()) {
+                            warnings.push(format!("'{}': input '{}' of '{}' matches no files",
+                                                  key,
+                                                  input,
+                                                  script.command()));
+                        }
+                    }
+                }
+            }
+        }
+        for warning in &warnings {
+            warn!("{}", warning);
+        }
+        for error in &errors {
+            error!("{}", error);
+        }
+        info!(target: SUMMARY_TARGET,
+              "check found {} error(s), {} warning(s)",
+              errors.len(),
+              warnings.len());
+        if !errors.is_empty() {
+            bail!(YabsErrorKind::CheckFailed(errors.len()));
+        }
+        Ok(())
+    }
+
+    // Compiles every object under strace and reports the files each target's
+    // compiles read that the build doesn't know about: anything outside the
+    // sources' directories, the include directories, 'extra-deps', the system
+    // and the toolchain. Advisory, the build itself isn't changed.
+    #[cfg(target_os = "linux")]
+    pub fn verify_inputs(&self) -> Result<(), YabsError> {
+        if self.context().find_program("strace").is_none() {
+            warn!("input verification traces compiles with strace, which wasn't found, skipping");
+            return Ok(());
+        }
+        info!("verifying inputs, every source is compiled again under strace so this is slow \
+               and its findings are advisory");
+        let cwd = self.context().cwd.clone();
+        let canonical = |path: &Path| cwd.join(path).canonicalize().unwrap_or_else(|_| cwd.join(path));
+        let mut declared_dirs: Vec<PathBuf> = self.project
+                                                  .include_args()?
+                                                  .iter()
+                                                  .filter_map(|arg| arg.strip_prefix("-I"))
+                                                  .map(|dir| canonical(Path::new(dir)))
+                                                  .collect();
+        declared_dirs.push(cwd.join(".yabs"));
+        let extra_deps: Vec<PathBuf> = self.project.extra_deps().iter().map(|dep| canonical(dep)).collect();
+        let mut reads: BTreeMap<PathBuf, Vec<PathBuf>> = BTreeMap::new();
+        let mut suggested: Vec<String> = Vec::new();
+        for buildable in self.buildables() {
+            let mut undeclared: Vec<PathBuf> = Vec::new();
+            let objects = buildable.objects(&self.project, self.binaries.as_ref().unwrap_or(&vec![]))?;
+            for target in self.project.file_mod_map.keys().filter(|target| objects.contains(&target.object())) {
+                if !reads.contains_key(&target.object()) {
+                    let compiler = self.project.compiler_for(Language::of(&target.source()));
+                    let compiler = self.context().find_program(&compiler);
+                    let toolchain = compiler.and_then(|compiler| compiler.canonicalize().ok())
+                                            .and_then(|compiler| {
+                                                          compiler.parent()
+                                                                  .and_then(|bin| bin.parent())
+                                                                  .map(|prefix| prefix.to_path_buf())
+                                                      });
+                    let source = canonical(&target.source());
+                    let scratch = target.with_object_dir(&self.state_path(VERIFY_DIR));
+                    fs::create_dir_all(scratch.object().parent().unwrap())?;
+                    let log = scratch.object().with_extension("strace");
+                    if let Err(error) = run_args(&strace_args(&log, &self.object_command(&scratch)?), &cwd) {
+                        warn!("couldn't verify '{}': {}", target.source().display(), error);
+                        reads.insert(target.object(), Vec::new());
+                        continue;
+                    }
+                    let mut buffer = String::new();
+                    File::open(&log)?.read_to_string(&mut buffer)?;
+                    let found = strace_reads(&buffer)
+                        .iter()
+                        .map(|read| canonical(read))
+                        .filter(|read| {
+                                    *read != source && read.parent() != source.parent() &&
+                                    !extra_deps.contains(read) &&
+                                    !declared_dirs.iter().any(|dir| read.starts_with(dir)) &&
+                                    !is_system_path(read, toolchain.as_deref())
+                                })
+                        .collect();
+                    reads.insert(target.object(), found);
+                }
+                for read in &reads[&target.object()] {
+                    if !undeclared.contains(read) {
+                        undeclared.push(read.clone());
+                    }
+                }
+            }
+            if undeclared.is_empty() {
+                info!("'{}' read no undeclared inputs", buildable.name());
+                continue;
+            }
+            warn!("'{}' read {} undeclared input(s):", buildable.name(), undeclared.len());
+            for read in undeclared {
+                let shown = read.strip_prefix(&cwd).unwrap_or(&read).display().to_string();
+                warn!("  {}", shown);
+                if !suggested.contains(&shown) {
+                    suggested.push(shown);
+                }
+            }
+        }
+        if !suggested.is_empty() {
+            let path = self.state_path(VERIFY_DIR).join("suggested-deps.toml");
+            let mut suggestion = BTreeMap::new();
+            suggestion.insert("extra-deps", suggested);
+            File::create(&path)?.write_all(toml::to_string(&suggestion)?.as_bytes())?;
+            info!("suggested 'extra-deps' for [project] written to '{}'", path.display());
+        }
+        Ok(())
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn verify_inputs(&self) -> Result<(), YabsError> {
+        warn!("input verification is only supported on Linux, skipping");
+        Ok(())
+    }
+
+    // Every compile and link a build would perform, with why the stale ones
+    // have to run. Nothing is built.
+    pub fn plan(&self) -> Result<BuildPlan, YabsError> {
+        let schedule = self.project.schedule();
+        let mut plan = BuildPlan {
+            schedule: schedule.name().to_owned(),
+            fast_fail_window: self.project
+                                  .fast_fail_window
+                                  .filter(|window| *window > 0 && schedule == Schedule::RecentFirst),
+            ..BuildPlan::default()
+        };
+        let strategy = self.project.staleness();
+        let mut queued = 0;
+        for buildable in self.default_buildables() {
+            let mut compiling = false;
+            // Objects are shared between targets, any target needing one
+            // makes it stale
+            let planned_stale = |plan: &BuildPlan, target: &Target| {
+                plan.steps.get(&target.object().display().to_string()).is_some_and(|step| step.stale.is_some())
+            };
+            let stale_objects = self.stale_objects(strategy, buildable)?;
+            let queue = self.order_job_queue(stale_objects.iter()
+                                                          .filter(|(target, stale)| {
+                                                                      stale.is_some() && !planned_stale(&plan, target)
+                                                                  })
+                                                          .map(|(target, _)| target.clone())
+                                                          .collect());
+            // Jobs are popped off the end of the queue
+            let mut positions = BTreeMap::new();
+            for target in queue.into_iter().rev() {
+                positions.insert(target, queued);
+                queued += 1;
+            }
+            for (target, stale) in stale_objects {
+                compiling |= stale.is_some();
+                if planned_stale(&plan, &target) {
+                    continue;
+                }
+                let id = target.object().display().to_string();
+                plan.add(PlanStep {
+                             id: id.clone(),
+                             kind: StepKind::Compile,
+                             inputs: vec![target.source().display().to_string()],
+                             outputs: vec![id],
+                             command: self.object_command(&target)?,
+                             stale: stale.map(|reason| reason.to_string()),
+                             position: positions.get(&target).cloned(),
+                         });
+            }
+            let objects = buildable.objects(&self.project, self.binaries.as_ref().unwrap_or(&vec![]))?;
+            let outputs = self.output_paths(buildable);
+            let up_to_date = self.is_up_to_date(buildable, &outputs)?;
+            for output in outputs {
+                let stale = if !self.project.resolve(&output).exists() {
+                    Some(String::from("no output"))
+                } else if self.force {
+                    Some(StaleReason::Forced.to_string())
+                } else if compiling {
+                    Some(String::from("objects are stale"))
+                } else if !up_to_date {
+                    Some(String::from("inputs newer than the output"))
+                } else {
+                    None
+                };
+                plan.add(PlanStep {
+                             id: output.display().to_string(),
+                             kind: StepKind::Link,
+                             inputs: objects.iter()
+                                            .chain(buildable.link_inputs().iter())
+                                            .map(|input| input.display().to_string())
+                                            .collect(),
+                             outputs: vec![output.display().to_string()],
+                             command: Vec::new(),
+                             stale,
+                             position: None,
+                         });
+            }
+        }
+        for task in self.tasks.iter().flatten() {
+            let outputs = task.outputs();
+            let stale = if outputs.is_empty() {
+                Some(String::from("no outputs declared"))
+            } else if outputs.iter().any(|output| !self.project.resolve(output).exists()) {
+                Some(String::from("no output"))
+            } else if !task.is_up_to_date(&self.context().cwd)? {
+                Some(String::from("inputs newer than the output"))
+            } else {
+                None
+            };
+            let mut inputs: Vec<String> = task.inputs().iter().map(|input| input.display().to_string()).collect();
+            for dep in task.deps() {
+                inputs.extend(self.dependency_ids(&dep)?);
+            }
+            plan.add(PlanStep {
+                         id: task_id(&task.name()),
+                         kind: StepKind::Task,
+                         inputs,
+                         outputs: outputs.iter().map(|output| output.display().to_string()).collect(),
+                         command: task.argv(),
+                         stale,
+                         position: None,
+                     });
+        }
+        Ok(plan)
+    }
+
+    // How a task's dependency `dep` appears in the plan and the graph, the
+    // outputs of a binary or library and the id of a task
+    fn dependency_ids(&self, dep: &str) -> Result<Vec<String>, YabsError> {
+        if self.find_task(dep).is_some() {
+            return Ok(vec![task_id(dep)]);
+        }
+        let buildable = self.find_buildable(dep)?;
+        Ok(self.output_paths(buildable).iter().map(|output| output.display().to_string()).collect())
+    }
+
+    // The sources, objects and outputs of the build as a Graphviz DOT digraph.
+    // Headers are only known from the last build's records, which are kept
+    // with 'include-resolution-tracking'.
+    pub fn export_graph(&self) -> Result<String, YabsError> {
+        let mut graph = Graph::default();
+        let state = self.object_records();
+        for target in self.project.file_mod_map.keys() {
+            let source = target.source().display().to_string();
+            let object = target.object().display().to_string();
+            graph.add_node(&source, NodeKind::Source);
+            graph.add_node(&object, NodeKind::Object);
+            graph.add_edge(&source, &object, EdgeKind::Compile);
+            let headers = state.get(&target.object()).and_then(|record| record.headers.as_ref());
+            for header in headers.into_iter().flatten() {
+                graph.add_node(&header.resolved, NodeKind::Header);
+                graph.add_edge(&header.resolved, &object, EdgeKind::Include);
+            }
+        }
+        for buildable in self.buildables() {
+            let objects = buildable.objects(&self.project, self.binaries.as_ref().unwrap_or(&vec![]))?;
+            for (kind, output) in self.outputs(buildable) {
+                let output = output.display().to_string();
+                let (node, edge) = match kind {
+                    TargetKind::StaticLibrary => (NodeKind::StaticLibrary, EdgeKind::Archive),
+                    TargetKind::DynamicLibrary => (NodeKind::DynamicLibrary, EdgeKind::Link),
+                    _ => (NodeKind::Binary, EdgeKind::Link),
+                };
+                graph.add_node(&output, node);
+                for object in &objects {
+                    let object = object.display().to_string();
+                    graph.add_node(&object, NodeKind::Object);
+                    graph.add_edge(&object, &output, edge);
+                }
+            }
+        }
+        for task in self.tasks.iter().flatten() {
+            let id = task_id(&task.name());
+            graph.add_node(&id, NodeKind::Task);
+            for dep in task.deps() {
+                for dep_id in self.dependency_ids(&dep)? {
+                    if dep_id.starts_with("task:") {
+                        graph.add_node(&dep_id, NodeKind::Task);
+                    }
+                    graph.add_edge(&dep_id, &id, EdgeKind::Depend);
+                }
+            }
+        }
+        Ok(graph.to_dot())
+    }
+
+    // Picks up sources that were added, removed or modified since the build
+    // file was loaded
+    pub fn refresh(&mut self) -> Result<(), YabsError> {
+        self.project.clear_sources();
+        self.find_objects()?;
+        self.scan_modules()
+    }
+
+    // How a freshly computed plan differs from `previous`, for callers that
+    // re-plan on every change
+    pub fn plan_delta(&mut self, previous: &BuildPlan) -> Result<PlanDelta, YabsError> {
+        self.refresh()?;
+        let delta = self.plan()?.delta_from(previous);
+        if delta.newly_stale_objects() > 0 {
+            info!("{}", delta.summary());
+        }
+        Ok(delta)
+    }
+
+    // Builds every binary and library, returning what was done and how long
+    // it took, which is also logged
+    pub fn build(&mut self, jobs: usize) -> Result<BuildReport, YabsError> {
+        self.run_build(jobs, false, true)
+    }
+
+    // Compiles the stale objects of every binary and library without linking
+    // any of them, running 'before-script' and 'after-script' only with
+    // `scripts`
+    pub fn build_objects_only(&mut self, jobs: usize, scripts: bool) -> Result<BuildReport, YabsError> {
+        self.run_build(jobs, true, scripts)
+    }
+
+    fn run_build(&mut self, jobs: usize, objects_only: bool, scripts: bool) -> Result<BuildReport, YabsError> {
+        let _lock = self.lock()?;
+        self.preflight()?;
+        let started = Instant::now();
+        let jobs = self.job_count(jobs);
+        self.report.replace(BuildReport::default());
+        self.deadline_at.set(self.deadline.map(|deadline| deadline.instant(started)));
+        if scripts {
+            self.project.remove_stale_script_stamps()?;
+            self.project.run_script(&self.project.before_script)?;
+        }
+        let built = if objects_only {
+            self.compile_all_objects(jobs)
+        } else {
+            if !self.buildables().is_empty() && self.default_buildables().is_empty() {
+                warn!("every binary and library has 'default = false', so nothing is built; \
+                       name one with --bin or --lib to build it");
+            }
+            self.build_all_binaries(jobs).and_then(|_| self.build_all_libraries(jobs))
+        };
+        let built = built.and_then(|_| self.save_state());
+        events::emit(|| Event::DiagnosticsSummary {
+            files: self.report
+                       .borrow()
+                       .diagnostics
+                       .iter()
+                       .map(|(file, counts)| (file.clone(), counts.warnings, counts.errors))
+                       .collect(),
+        });
+        if let Err(error) = built {
+            // What was compiled and the diagnostics counted so far are still
+            // worth seeing
+            self.log_report(started, objects_only);
+            return Err(self.out_of_space_error(error, self.space_needed.get()));
+        }
+        if scripts && !self.report.borrow().truncated {
+            self.project.run_script(&self.project.after_script)?;
+        }
+        if self.object_cache().is_some() {
+            let stats = self.cache_stats();
+            info!("object cache: {} hits, {} misses", stats.hits, stats.misses);
+        }
+        let report = self.log_report(started, objects_only);
+        self.report_new_sources()?;
+        if let Some(max) = self.project.max_warnings {
+            if report.warnings() > max {
+                bail!(YabsErrorKind::TooManyWarnings(report.warnings(), max));
+            }
+        }
+        Ok(report)
+    }
+
+    // Finishes the report of the build started at `started` and logs its
+    // summary
+    fn log_report(&self, started: Instant, objects_only: bool) -> BuildReport {
+        let report = {
+            let mut report = self.report.borrow_mut();
+            report.objects = self.project.file_mod_map.len();
+            report.objects_only = objects_only;
+            report.wall_time = started.elapsed();
+            report.clone()
+        };
+        for line in report.summary() {
+            info!(target: SUMMARY_TARGET, "{}", line);
+        }
+        report
+    }
+
+    // Adds the room `job_queue` takes to what the build may need, every
+    // source times 'disk-space-factor' for its object and the compiler's
+    // temporary files, and warns once the filesystem holding the objects has
+    // less free space than that
+    fn check_disk_space(&self, job_queue: &[Target]) -> Result<(), YabsError> {
+        let factor = self.project.disk_space_factor();
+        let mut needed = self.space_needed.get();
+        let queued = needed;
+        for target in job_queue {
+            let size = fs::metadata(self.project.resolve(target.source()))?.len();
+            needed = needed.saturating_add(size.saturating_mul(factor));
+        }
+        self.space_needed.set(needed);
+        let cwd = &self.context().cwd;
+        match free_space(cwd) {
+            // Only the queue that takes the build past the free space warns
+            Some(available) if available < needed && available >= queued => {
+                let mount = disk_space(cwd).map(|space| space.mount).unwrap_or_else(|| cwd.display().to_string());
+                warn!("only {} free on {}, the build may need about {}",
+                      human_size(available),
+                      mount,
+                      human_size(needed));
+            },
+            _ => {},
+        }
+        Ok(())
+    }
+
+    // A full disk fails every compile and write after it, reported as one
+    // error instead
+    fn out_of_space_error(&self, error: YabsError, needed: u64) -> YabsError {
+        if !is_out_of_space(&error) {
+            return error;
+        }
+        let mount = disk_space(&self.context().cwd)
+            .map(|space| space.mount)
+            .unwrap_or_else(|| self.context().cwd.display().to_string());
+        YabsErrorKind::OutOfDiskSpace(mount, human_size(needed)).into()
+    }
+
+    // The records of the objects compiled before, none while they belong to
+    // discarded state
+    fn object_records(&self) -> StateCache {
+        let mut records = StateCache::load(&self.project.state_dir());
+        if self.state.has_stale_records() {
+            records.objects.clear();
+        }
+        records
+    }
+
+    // Like `object_records` for the hashes objects were compiled from
+    fn source_hashes(&self) -> SourceHashes {
+        let mut hashes = SourceHashes::load(&self.project.state_dir());
+        if self.state.has_stale_records() {
+            hashes.objects.clear();
+        }
+        hashes
+    }
+
+    fn save_state(&mut self) -> Result<(), YabsError> {
+        self.state.sources = self.project.source_records.clone();
+        self.state.save()
+    }
+
+    fn report_new_sources(&self) -> Result<(), YabsError> {
+        let new_sources = self.project.find_new_sources()?;
+        if !new_sources.is_empty() {
+            info!("{} source file(s) appeared during the build; run again to include them",
+                  new_sources.len());
+        }
+        Ok(())
+    }
+
+    // Removes the notes (.gcno) and counters (.gcda) coverage builds leave next
+    // to each object
+    pub fn clean_coverage(&self) -> Result<(), YabsError> {
+        self.remove_coverage(&mut Removals::new(self.dry_run, &self.context().cwd, None));
+        Ok(())
+    }
+
+    // Prints line coverage for each source with coverage data, which exists once
+    // a binary built with 'coverage' has been run
+    pub fn coverage_report(&self) -> Result<(), YabsError> {
+        for target in self.project.file_mod_map.keys() {
+            if !self.project.resolve(target.object().with_extension("gcda")).exists() {
+                continue;
+            }
+            let mut args = self.project.coverage_tool();
+            args.push(String::from("-n"));
+            args.push(String::from("-o"));
+            args.push(target.object().to_string_lossy().into_owned());
+            args.push(target.source().to_string_lossy().into_owned());
+            let output = command_from_args(&args).current_dir(&self.context().cwd).output()?;
+            if !output.status.success() {
+                log_output(&String::from_utf8(output.stderr)?);
+                bail!(YabsErrorKind::Command(display_args(&args), output.status.code().unwrap_or(1)));
+            }
+            for (file, lines) in parse_gcov_summary(&String::from_utf8(output.stdout)?) {
+                info!("{}: {} lines", file, lines);
+            }
+        }
+        Ok(())
+    }
+
+    // Profile-guided optimization: builds instrumented binaries, runs
+    // `training_cmd` to collect a profile and rebuilds using it. The objects
+    // of both stages live under `PGO_DIR` in the state directory so a normal
+    // build never links them, and the final artifacts record different
+    // compile flags than a normal build so it will recompile its own objects
+    // before relinking them.
+    pub fn build_pgo(&mut self, jobs: usize, training_cmd: &str) -> Result<(), YabsError> {
+        let _lock = self.lock()?;
+        let jobs = self.job_count(jobs);
+        let pgo_dir = self.state_path(PGO_DIR);
+        let profile_dir = pgo_dir.join("profile");
+        if profile_dir.exists() {
+            fs::remove_dir_all(&profile_dir)?;
+        }
+        fs::create_dir_all(&profile_dir)?;
+        let mut stage = self.clone();
+        stage.project.relocate_objects(&pgo_dir.join("obj"));
+        stage.project.run_script(&stage.project.before_script)?;
+
+        info!("building instrumented binaries");
+        stage.extra_flags = self.extra_flags.clone();
+        stage.extra_flags.push(format!("-fprofile-generate={}", profile_dir.display()));
+        stage.build_all_binaries(jobs)?;
+        stage.build_all_libraries(jobs)?;
+
+        info!("running training command");
+        run_cmd(training_cmd, &self.context().cwd)?;
+        if fs::read_dir(&profile_dir)?.next().is_none() {
+            bail!(YabsErrorKind::NoProfileData(profile_dir));
+        }
+        // clang writes raw profiles that have to be merged before use
+        let profile = if self.project.is_clang() {
+            let merged = profile_dir.join("default.profdata");
+            run_cmd(&format!("llvm-profdata merge -output='{}' '{}'/*.profraw",
+                             merged.display(),
+                             profile_dir.display()),
+                    &self.context().cwd)?;
+            merged
+        } else {
+            profile_dir
+        };
+
+        info!("building optimized binaries");
+        stage.extra_flags = self.extra_flags.clone();
+        stage.extra_flags.push(format!("-fprofile-use={}", profile.display()));
+        stage.build_all_binaries(jobs)?;
+        stage.build_all_libraries(jobs)?;
+        stage.project.run_script(&stage.project.after_script)
+    }
+
+    // Writes `<prefix>/yabs-export/<name>.toml` so other projects can list
+    // this one in their 'deps'. The export points at the libraries, where
+    // they're written to, and the include directories of this project
+    // directory.
+    pub fn export(&self, prefix: &Path) -> Result<PathBuf, YabsError> {
+        let name = match self.project.name {
+            Some(ref name) => name.clone(),
+            None => {
+                bail!(YabsErrorKind::InvalidValue("name".to_owned(),
+                                                  "a project needs a name to be exported".to_owned()))
+            },
+        };
+        let cwd = self.context().cwd.clone();
+        let absolute = |entry: &String| if self.project.is_command(entry) {
+            entry.clone()
+        } else {
+            cwd.join(entry).to_string_lossy().into_owned()
+        };
+        let mut libraries: Vec<String> = Vec::new();
+        let mut lib_dir: Vec<String> = Vec::new();
+        for library in self.libraries.as_ref().unwrap_or(&vec![]) {
+            for output in self.output_paths(library) {
+                let dir = match output.parent() {
+                    Some(dir) if !dir.as_os_str().is_empty() => cwd.join(dir),
+                    _ => cwd.clone(),
+                };
+                let dir = dir.to_string_lossy().into_owned();
+                if !lib_dir.contains(&dir) {
+                    lib_dir.push(dir);
+                }
+            }
+            let library_name = library.output_name();
+            if let Some(stem) = library_name.strip_prefix("lib") {
+                libraries.push(stem.to_owned());
+            } else if library.is_dynamic() {
+                libraries.push(format!(":{}", library.dynamic_file_name().display()));
+            } else {
+                libraries.push(format!(":{}", library.static_file_name().display()));
+            }
+        }
+        libraries.extend(self.project.libs.iter().flatten().flat_map(LibEntry::names));
+        if lib_dir.is_empty() {
+            lib_dir.push(cwd.to_string_lossy().into_owned());
+        }
+        lib_dir.extend(self.project.lib_dir.as_ref().unwrap_or(&vec![]).iter().map(&absolute));
+        let package = Export {
+            name,
+            version: self.project.version.clone(),
+            include: self.project.include.as_ref().unwrap_or(&vec![]).iter().map(&absolute).collect(),
+            defines: self.project.defines.clone().unwrap_or_default(),
+            lib_dir,
+            libraries,
+            requires: self.project.deps.clone().unwrap_or_default(),
+        };
+        let path = package.write(&self.project.resolve(prefix))?;
+        info!("exported '{}' to '{}'", package.name, path.display());
+        Ok(path)
+    }
+
+    pub fn clean(&self) -> Result<Vec<PathBuf>, YabsError> {
+        let _lock = self.clean_lock()?;
+        let mut removals = Removals::new(self.dry_run, &self.context().cwd, None);
+        for target in self.project.file_mod_map.keys() {
+            self.remove_object(target, &mut removals);
+        }
+        self.remove_coverage(&mut removals);
+        removals.remove("state", "build state", self.state.path());
+        removals.remove("pgo_data", "profile-guided optimization data", &self.state_path(PGO_DIR));
+        for target in self.buildables() {
+            self.remove_outputs(target, &mut removals);
+        }
+        Ok(removals.removed)
+    }
+
+    // Removes the outputs of the binary or library `name` and its objects.
+    // Objects other targets are built from are kept unless `force` is set.
+    pub fn clean_target(&self, name: &str, force: bool) -> Result<Vec<PathBuf>, YabsError> {
+        let buildable = self.find_buildable(name)?;
+        let _lock = self.clean_lock()?;
+        let binaries = self.binaries.clone().unwrap_or_default();
+        let objects = buildable.objects(&self.project, &binaries)?;
+        // Objects compiled for the target alone are never shared, the rest are
+        // when another binary or library links them too
+        let mut shared: BTreeSet<PathBuf> = BTreeSet::new();
+        for other in self.buildables().into_iter().filter(|other| !ptr::addr_eq(*other, buildable)) {
+            shared.extend(other.objects(&self.project, &binaries)?);
+        }
+        let mut removals = Removals::new(self.dry_run, &self.context().cwd, None);
+        let mut state = self.object_records();
+        let mut kept = 0;
+        for target in self.project.file_mod_map.keys().filter(|target| objects.contains(&target.object())) {
+            if !force && target.owner().is_none() && shared.contains(&target.object()) {
+                kept += 1;
+                continue;
+            }
+            self.remove_object(target, &mut removals);
+            state.forget(&target.object());
+        }
+        if !self.dry_run {
+            state.flush()?;
+        }
+        self.remove_outputs(buildable, &mut removals);
+        if kept > 0 {
+            info!("kept {} object(s) other targets are built from, --force removes them too", kept);
+        }
+        Ok(removals.removed)
+    }
+
+    // Removes everything `clean` does and everything else yabs wrote: the
+    // state directory, dependency files, coverage data, kept response files,
+    // the export written into the project and object directories left empty.
+    // Only paths yabs derives itself are removed, and none outside the
+    // project, including through symlinked directories.
+    pub fn distclean(&self) -> Result<Vec<PathBuf>, YabsError> {
+        let lock = self.clean_lock()?;
+        let cwd = &self.context().cwd;
+        let mut removals = Removals::new(self.dry_run, cwd, Some(cwd.canonicalize()?));
+        let mut dirs: BTreeSet<PathBuf> = BTreeSet::new();
+        for target in self.project.file_mod_map.keys() {
+            let object = target.object();
+            if !removals.in_project(&object) {
+                warn!("not removing '{}', it's outside the project", object.display());
+                continue;
+            }
+            self.remove_object(target, &mut removals);
+            removals.remove("depfile", "dependency file", &object.with_extension("d"));
+            dirs.extend(object.parent().map(Path::to_path_buf));
+        }
+        self.remove_coverage(&mut removals);
+        for target in self.buildables() {
+            self.remove_outputs(target, &mut removals);
+            for output in self.output_paths(target) {
+                let rsp = PathBuf::from(format!("{}.rsp", output.display()));
+                removals.remove("response_file", "response file", &rsp);
+                dirs.extend(output.parent().map(Path::to_path_buf));
+            }
+        }
+        if let Some(ref name) = self.project.name {
+            let export = Export::path_in(Path::new(""), name);
+            removals.remove("export", "export", &export);
+            dirs.extend(export.parent().map(Path::to_path_buf));
+        }
+        let state_dir = self.project.state_dir();
+        let state_dir = state_dir.strip_prefix(cwd).map(Path::to_path_buf).unwrap_or(state_dir);
+        let mut state_dirs = vec![PathBuf::from(DEFAULT_STATE_DIR)];
+        if state_dir != state_dirs[0] {
+            state_dirs.push(state_dir.clone());
+        }
+        for dir in &state_dirs {
+            if !cwd.join(dir).exists() {
+                continue;
+            }
+            if !removals.in_project(dir) {
+                warn!("not removing '{}', it's outside the project", dir.display());
+                continue;
+            }
+            for entry in fs::read_dir(cwd.join(dir))? {
+                let path = dir.join(entry?.file_name());
+                // The lock stays held through its open file, and a yabs that
+                // locks the removed file tries again
+                removals.remove("state", "state", &path);
+            }
+            dirs.insert(dir.clone());
+        }
+        drop(lock);
+        // Deepest first, so emptied parents go as well
+        let mut dirs: Vec<PathBuf> = dirs.into_iter().collect();
+        dirs.sort_by_key(|dir| ::std::cmp::Reverse(dir.components().count()));
+        for dir in dirs {
+            for dir in dir.ancestors() {
+                let is_root = dir.components().all(|component| component == Component::CurDir);
+                if is_root || !removals.empties(dir) {
+                    break;
+                }
+                removals.remove("directory", "empty directory", dir);
+            }
+        }
+        Ok(removals.removed)
+    }
+
+    // Only says what would be removed, see `Removals`
+    pub fn set_dry_run(&mut self, dry_run: bool) {
+        self.dry_run = dry_run;
+    }
+
+    // The lock for removing files, a dry run doesn't take it so it writes
+    // nothing
+    fn clean_lock(&self) -> Result<Option<ProjectLock>, YabsError> {
+        if self.dry_run {
+            Ok(None)
+        } else {
+            self.lock().map(Some)
+        }
+    }
+
+    // Removes the object of `target` with its dependency file and module
+    // interface
+    fn remove_object(&self, target: &Target, removals: &mut Removals) {
+        removals.remove("object", "object", &target.object());
+        if self.project.tracks_include_resolution() {
+            removals.remove("depfile", "dependency file", &target.object().with_extension("d"));
+        }
+        if let Some(bmi) = self.modules.as_ref().and_then(|graph| graph.bmi_path(target)) {
+            removals.remove("module_interface", "module interface", &bmi);
+        }
+    }
+
+    // The notes (.gcno) and counters (.gcda) coverage builds leave next to
+    // each object
+    fn remove_coverage(&self, removals: &mut Removals) {
+        for target in self.project.file_mod_map.keys() {
+            for ext in &["gcno", "gcda"] {
+                removals.remove("coverage", "coverage data", &target.object().with_extension(ext));
+            }
+        }
+    }
+
+    fn remove_outputs(&self, target: &dyn Buildable, removals: &mut Removals) {
+        for (kind, output) in self.outputs(target) {
+            let what = if kind == TargetKind::Binary { "binary" } else { "library" };
+            removals.remove(what, what, &output);
+            removals.remove("metadata", "metadata", &ArtifactMeta::sidecar_path(&output));
+            if kind != TargetKind::StaticLibrary {
+                removals.remove("debug_info", "debug info", &self.debug_file(&output));
+            }
+        }
+        removals.remove("partial_links", "partial links", &self.partial_link_dir(target));
+    }
+}
+
+// Removes what the cleans remove, or with `dry_run` only logs what it would
+// remove. Both find the same paths the same way, so a dry run lists exactly
+// what a clean would remove.
+struct Removals {
+    dry_run: bool,
+    // The directory relative paths are removed from
+    dir: PathBuf,
+    // The project directory, only paths inside it are removed when it's set
+    root: Option<PathBuf>,
+    // What was removed, or would have been
+    removed: Vec<PathBuf>,
+}
+
+impl Removals {
+    fn new(dry_run: bool, dir: &Path, root: Option<PathBuf>) -> Removals {
+        Removals {
+            dry_run,
+            dir: dir.to_path_buf(),
+            root,
+            removed: Vec::new(),
+        }
+    }
+
+    // Whether `path`, relative to the project directory, is in the project.
+    // Its parent has to be inside it once symlinks are resolved, `..` isn't
+    // followed at all.
+    fn in_project(&self, path: &Path) -> bool {
+        let root = match self.root {
+            Some(ref root) => root,
+            None => return true,
+        };
+        if path.components().any(|component| component == Component::ParentDir) {
+            return false;
+        }
+        root.join(path)
+            .parent()
+            .and_then(|parent| parent.canonicalize().ok())
+            .is_some_and(|parent| parent.starts_with(root))
+    }
+
+    // Removes `path` if it exists, a symlink is removed itself and a directory
+    // with everything in it. Paths in the project are reported relative to it.
+    fn remove(&mut self, kind: &str, what: &str, path: &Path) {
+        let resolved = self.dir.join(path);
+        let path = if path.is_absolute() { path.strip_prefix(&self.dir).unwrap_or(path) } else { path };
+        let meta = match fs::symlink_metadata(&resolved) {
+            Ok(meta) => meta,
+            Err(_) => return,
+        };
+        if !self.in_project(path) {
+            warn!("not removing '{}', it's outside the project", path.display());
+            return;
+        }
+        if self.dry_run {
+            info!("would remove '{}'", path.display());
+            self.removed.push(path.to_path_buf());
+            return;
+        }
+        let removed = if meta.is_dir() {
+            fs::remove_dir_all(&resolved)
+        } else {
+            fs::remove_file(&resolved)
+        };
+        if removed.is_ok() {
+            log_removed(kind, what, path);
+            self.removed.push(path.to_path_buf());
+        }
+    }
+
+    // Whether the directory `dir` in the project is empty once everything
+    // removed so far is gone
+    fn empties(&self, dir: &Path) -> bool {
+        self.in_project(dir) &&
+        fs::read_dir(self.dir.join(dir)).map(|entries| {
+                                            entries.filter_map(|entry| entry.ok())
+                                                   .all(|entry| self.removed.contains(&dir.join(entry.file_name())))
+                                        })
+                                       .unwrap_or(false)
+    }
+}
+
+// The compile command `flags` of `source` with the preprocessor writing to
+// stdout in place of the compile
+fn preprocess_args(flags: &[String], source: &Path) -> Vec<String> {
+    let mut args: Vec<String> = flags.iter()
+                                     .filter(|arg| *arg != "-MMD")
+                                     .map(|arg| if arg == "-c" { String::from("-E") } else { arg.clone() })
+                                     .collect();
+    args.push(source.to_string_lossy().into_owned());
+    args
+}
+
+// The short name of the step compiling `target`, shown in place of its command
+fn compile_label(target: &Target) -> &'static str {
+    match Language::of(&target.source()) {
+        Language::Cxx => "CXX",
+        Language::Asm => "AS",
+        Language::C | Language::Other => "CC",
+    }
+}
+
+// The id of the task `name` in plans and graphs
+fn task_id(name: &str) -> String {
+    format!("task:{}", name)
+}
+
+// `kind` names what was removed in the event, `what` in the message
+fn log_removed(kind: &str, what: &str, path: &Path) {
+    info!("removed {} '{}'", what, path.display());
+    events::emit(|| Event::Removed {
+        kind: kind.to_owned(),
+        path: path.display().to_string(),
+    });
+}
+
+// A compile event for each source of a unit that stopped with `status`
+fn emit_compiles_finished(unit: &[Target], status: Option<i32>, duration: Duration) {
+    for target in unit {
+        events::emit(|| Event::CompileFinished {
+            source: target.source().display().to_string(),
+            object: target.object().display().to_string(),
+            status,
+            duration,
+        });
+    }
+}
+
+// Whether `compiler` takes `-fdiagnostics-color`, as gcc and clang do, by
+// preprocessing an empty file with it in `dir`
+fn supports_diagnostics_color(compiler: &str, dir: &Path) -> bool {
+    Command::new(compiler).args(["-fdiagnostics-color=always", "-x", "c", "-E", "-o", "/dev/null", "/dev/null"])
+                          .current_dir(dir)
+                          .stdout(Stdio::null())
+                          .stderr(Stdio::null())
+                          .status()
+                          .map(|status| status.success())
+                          .unwrap_or(false)
+}
+
+// The major version in `clang --version` output such as "Debian clang version
+// 16.0.6 (15)"
+fn clang_major_version(version: &str) -> Option<u32> {
+    let start = version.find("clang version ")? + "clang version ".len();
+    version[start..].split('.').next().and_then(|major| major.parse().ok())
+}
+
+// Appends a line for each of `fields` of `table`, with the value yabs resolved
+// it to or the one the buildfile gave it, and a comment for those without one
+// `key` is where `table` is in the buildfile, values found in it are from
+// the buildfile `origins` has for them
+fn write_config_section(config: &mut String,
+                        table: Option<&toml::Value>,
+                        fields: &[&str],
+                        resolved: &BTreeMap<&str, (toml::Value, String)>,
+                        origins: &Origins,
+                        key: &str) {
+    for field in fields {
+        match (resolved.get(field), table.and_then(|table| table.get(*field))) {
+            (Some((value, source)), _) => {
+                config.push_str(&format!("{} = {}  # {}\n", field, inline_toml(value), source))
+            },
+            (None, Some(value)) => {
+                let origin = origin_of(origins, &format!("{}.{}", key, field));
+                config.push_str(&format!("{} = {}  # {}\n",
+                                         field,
+                                         inline_toml(value),
+                                         origin.as_deref().unwrap_or("buildfile")))
+            },
+            (None, None) => config.push_str(&format!("# {} isn't set\n", field)),
+        }
+    }
+}
+
+// The buildfiles the value at `key` came from, those of every key under it
+// for a table
+fn origin_of(origins: &Origins, key: &str) -> Option<String> {
+    if let Some(origin) = origins.get(key) {
+        return Some(origin.clone());
+    }
+    let nested = format!("{}.", key);
+    let mut files: Vec<&str> = Vec::new();
+    for origin in origins.iter().filter(|entry| entry.0.starts_with(&nested)).map(|entry| entry.1) {
+        for file in origin.split(", ") {
+            if !files.contains(&file) {
+                files.push(file);
+            }
+        }
+    }
+    if files.is_empty() { None } else { Some(files.join(", ")) }
+}
+
+// Looked for when a directory has no build file named after it, in this order
+pub const FALLBACK_BUILD_FILES: &[&str] = &["yabs.toml", ".yabs.toml", "yabs.json"];
+
+pub fn find_build_file(dir: &Path) -> Result<BuildFile, YabsError> {
+    let mut context = BuildContext::from_env();
+    context.cwd = dir.to_path_buf();
+    find_build_file_with_context(context)
+}
+
+// Looks for a build file in `context.cwd` and its parents, see
+// `open_build_file_with_context`
+pub fn find_build_file_with_context(context: BuildContext) -> Result<BuildFile, YabsError> {
+    let path = locate_build_file(&context)?;
+    open_build_file_with_context(&path, context)
+}
+
+// The build file in `context.cwd` or the nearest of its parents with one
+pub fn locate_build_file(context: &BuildContext) -> Result<PathBuf, YabsError> {
+    let mut dir = context.cwd.clone();
+    loop {
+        if let Some(path) = check_dir(&dir) {
+            return Ok(path);
+        } else if !dir.pop() {
+            break;
+        }
+    }
+    bail!(YabsErrorKind::NoAssumedToml(context.cwd.to_str().unwrap().to_owned()))
+}
+
+// Loads the build file at `path` without looking for one. Its paths are
+// relative to the directory it's in, which becomes the build's `cwd` that
+// commands are run in and the files it names are looked up from.
+pub fn open_build_file_with_context(path: &Path, mut context: BuildContext) -> Result<BuildFile, YabsError> {
+    if context.cwd.is_relative() {
+        context.cwd = env::current_dir()?.join(&context.cwd);
+    }
+    let path = context.cwd.join(path);
+    if !path.is_file() {
+        bail!(YabsErrorKind::BuildfileNotFound(path));
+    }
+    let dir = path.parent().map(Path::to_path_buf).unwrap_or_else(|| context.cwd.clone());
+    context.cwd = dir;
+    BuildFile::from_file_with_context(&path, context)
+}
+
+fn source_dir(target: &Target) -> PathBuf {
+    match target.source().parent() {
+        Some(dir) if dir != Path::new("") => dir.to_path_buf(),
+        _ => PathBuf::from("."),
+    }
+}
+
+// The build file named after `dir`, or else one of `FALLBACK_BUILD_FILES`
+fn check_dir(dir: &Path) -> Option<PathBuf> {
+    get_assumed_filename_for_dir(dir).into_iter()
+                                     .chain(FALLBACK_BUILD_FILES.iter().map(PathBuf::from))
+                                     .map(|name| dir.join(name))
+                                     .find(|path| path.is_file())
+}
+
+#[test]
+#[should_panic]
+fn test_empty_buildfile() {
+    let bf = BuildFile::from_file(&"test/empty.toml").unwrap();
+    assert_eq!(bf.binaries.unwrap().len(), 0);
 }
 
 #[test]
@@ -320,3 +4437,301 @@ fn test_non_empty_buildfile() {
     let default_proj: ProjectDesc = Default::default();
     assert_eq!(bf.project, default_proj);
 }
+
+#[test]
+fn test_duplicate_target_names() {
+    match BuildFile::from_file(&"test/duplicates.toml") {
+        Err(YabsError(YabsErrorKind::DuplicateTarget(ref table, ref name, 1, 3), _)) => {
+            assert_eq!((table.as_str(), name.as_str()), ("bin", "app"))
+        },
+        result => panic!("{:?}", result.map(|_| ())),
+    }
+}
+
+#[test]
+fn test_order_job_queue_recent_first() {
+    use std::time::{Duration, UNIX_EPOCH};
+    let mut bf = BuildFile::default();
+    let edited = Target::new(PathBuf::from("a.c"), PathBuf::from("a.o"));
+    let stale = Target::new(PathBuf::from("b.c"), PathBuf::from("b.o"));
+    bf.project.file_mod_map.insert(edited.clone(), UNIX_EPOCH + Duration::from_secs(20));
+    bf.project.file_mod_map.insert(stale.clone(), UNIX_EPOCH + Duration::from_secs(10));
+    let mut queue = bf.order_job_queue(vec![edited.clone(), stale.clone()]);
+    assert_eq!(queue.pop(), Some(stale.clone()));
+    bf.project.fast_fail_window = Some(1);
+    let mut queue = bf.order_job_queue(vec![edited.clone(), stale.clone()]);
+    assert_eq!(queue.pop(), Some(edited.clone()));
+    bf.project.schedule = Some(Schedule::Ordered);
+    let mut queue = bf.order_job_queue(vec![edited.clone(), stale.clone()]);
+    assert_eq!(queue.pop(), Some(stale));
+}
+
+#[test]
+fn test_buildable_sources_and_link() {
+    use std::ffi::OsStr;
+    let mut bf: BuildFile = toml::from_str("[project]\nfile-extensions = [\"c\"]\n\
+                                            [[bin]]\nname = \"app\"\npath = \"main.c\"\n\
+                                            [[lib]]\nname = \"libown\"\ntypes = [\"static\"]\ndefines = [\"OWN\"]\n")
+                                .unwrap();
+    let shared = Target::new(PathBuf::from("main.c"), PathBuf::from("main.o"));
+    let owned = shared.owned_by("libown", Path::new("libown.objects"));
+    bf.project.file_mod_map.insert(shared.clone(), UNIX_EPOCH);
+    bf.project.file_mod_map.insert(shared.position_independent(), UNIX_EPOCH);
+    bf.project.file_mod_map.insert(owned.clone(), UNIX_EPOCH);
+    let binary = &bf.binaries.as_ref().unwrap()[0];
+    let library = &bf.libraries.as_ref().unwrap()[0];
+    // Each target only links the objects compiled for it
+    assert_eq!(binary.sources(&bf.project), vec![(&shared, &UNIX_EPOCH)]);
+    assert_eq!(library.sources(&bf.project), vec![(&owned, &UNIX_EPOCH)]);
+    let link = LinkContext {
+        project: &bf.project,
+        extra_flags: &[],
+        lib_args: vec![String::from("-lm")],
+        env_flags: vec![],
+        driver: String::from("cc"),
+        rpath_args: vec![],
+    };
+    let command = binary.link(TargetKind::Binary, Path::new("app"), &link, &[shared.object()]);
+    assert_eq!(command.get_program(), "cc");
+    assert_eq!(command.get_args().collect::<Vec<&OsStr>>(), ["-o", "app", "main.o", "-lm"]);
+}
+
+#[test]
+fn test_module_flags() {
+    use desc::modules::ModuleUnit;
+    let mut bf: BuildFile = toml::from_str("[project]\nfile-extensions = [\"cppm\", \"cpp\"]\n\
+                                            compiler-cxx = \"clang++\"\ncxx-modules = true\n")
+        .unwrap();
+    let interface = Target::new(PathBuf::from("obj/app.cppm"), PathBuf::from("obj/app.o"));
+    let main = Target::new(PathBuf::from("main.cpp"), PathBuf::from("main.o"));
+    let exports = ModuleUnit {
+        exports: Some(String::from("app")),
+        imports: vec![],
+    };
+    let imports = ModuleUnit {
+        exports: None,
+        imports: vec![String::from("app")],
+    };
+    bf.modules = Some(ModuleGraph::from_units(vec![(interface.clone(), exports), (main.clone(), imports)]).unwrap());
+    let flags = bf.compile_flags(&interface).unwrap();
+    assert!(flags.contains(&String::from("-fmodule-output=obj/app.pcm")), "{:?}", flags);
+    let flags = bf.compile_flags(&main).unwrap();
+    assert!(!flags.iter().any(|flag| flag.starts_with("-fmodule-output")), "{:?}", flags);
+    assert!(flags.contains(&String::from("-fprebuilt-module-path=obj")), "{:?}", flags);
+    let mut decisions = vec![(interface.clone(), Some(StaleReason::NoObject { recorded: false })),
+                             (main.clone(), None)];
+    bf.propagate_module_staleness(bf.modules.as_ref().unwrap(), &mut decisions);
+    assert_eq!(decisions[1].1.as_ref().map(|reason| reason.to_string()),
+               Some(String::from("module interface 'obj/app.cppm' changed")));
+}
+
+#[test]
+fn test_job_count() {
+    let mut bf = BuildFile::default();
+    assert_eq!(bf.job_count(0), default_jobs());
+    assert_eq!(bf.job_count(3), 3);
+    bf.project.jobs = Some(5);
+    assert_eq!(bf.job_count(0), 5);
+    assert_eq!(bf.job_count(2), 2);
+    bf.project.jobs = Some(0);
+    assert_eq!(bf.job_count(0), default_jobs());
+    assert_eq!(bf.job_count(100000), (default_jobs() * MAX_JOBS_PER_CPU).max(MIN_MAX_JOBS));
+}
+
+#[test]
+fn test_progress_counter() {
+    let mut progress = Progress::new(212);
+    assert_eq!(progress.advance(1), "[  1/212]");
+    progress.advance(35);
+    assert_eq!(progress.advance(1), "[ 37/212]");
+    assert_eq!(Progress::new(5).advance(5), "[5/5]");
+}
+
+#[test]
+fn test_clang_major_version() {
+    assert_eq!(clang_major_version("Debian clang version 16.0.6 (15)"), Some(16));
+    assert_eq!(clang_major_version("clang version 18.1.3"), Some(18));
+    assert_eq!(clang_major_version("g++ (Debian 12.2.0-14) 12.2.0"), None);
+}
+
+#[test]
+fn test_cancelled_build_spawns_nothing() {
+    let bf = BuildFile::default();
+    bf.cancellation_token().cancel();
+    let queue = vec![Target::new(PathBuf::from("missing.c"), PathBuf::from("missing.o"))];
+    match bf.run_job_pass(queue, 1, &mut Progress::new(1)) {
+        Err(YabsError(YabsErrorKind::Cancelled, _)) => {},
+        result => panic!("expected the build to be cancelled, got {:?}", result),
+    }
+}
+
+#[test]
+fn test_buildfile_to_json() {
+    let bf: BuildFile = toml::from_str("[project]\nfile-extensions = [\"c\"]\n\
+                                        [[bin]]\nname = \"app\"\npath = \"main.c\"\n\
+                                        [[task]]\nname = \"gen\"\ncommand = [\"true\"]\n")
+        .unwrap();
+    let json = bf.to_json_string().unwrap();
+    assert!(json.contains("\"bin\": [\n    {\n      \"name\": \"app\",\n      \"path\": \"main.c\"\n    }\n  ]"),
+            "{}",
+            json);
+    let again: BuildFile = parse_json(&json).unwrap().try_into().unwrap();
+    assert_eq!(again.to_json_string().unwrap(), json);
+}
+
+#[test]
+fn test_effective_config() {
+    let mut bf: BuildFile = toml::from_str("[project]\nfile-extensions = [\"c\"]\ncompiler-c = \"clang\"\n\
+                                            max-size = \"1MiB\"\n[[bin]]\nname = \"app\"\npath = \"main.c\"\n")
+        .unwrap();
+    bf.project.context = BuildContext::isolated("/tmp").with_var("AR", "llvm-ar").with_var("CFLAGS", "-O2 -g");
+    let config = bf.effective_config().unwrap();
+    for line in &["[project]\n# name isn't set\nfile-extensions = [\"c\"]  # buildfile\n",
+                  "compiler-c = \"clang\"  # set by 'compiler-c'\n",
+                  "compiler-cxx = \"g++\"  # default\n",
+                  "ar = \"llvm-ar\"  # from AR\n",
+                  "arflags = \"rcs\"  # default\n",
+                  "# CFLAGS adds -O2 -g\n",
+                  "[[bin]]\nname = \"app\"  # buildfile\npath = \"main.c\"  # buildfile\n",
+                  "max-size = \"1MiB\"  # from [project]\n# builds app\n"] {
+        assert!(config.contains(line), "{}", config);
+    }
+    // Comments aside, it's a buildfile of its own
+    assert!(config.parse::<toml::Value>().is_ok());
+    // Defaults are what the build would use
+    bf.project.thin_archive = Some(true);
+    assert!(bf.effective_config().unwrap().contains("arflags = \"rcsT\"  # default\n"));
+}
+
+#[test]
+fn test_groups() {
+    let bf: BuildFile = toml::from_str("[project]\nfile-extensions = [\"c\"]\n\
+                                        [groups]\ntools = [\"hexview\", \"dump\"]\nall = [\"tools\", \"app\"]\n\
+                                        [[bin]]\nname = \"app\"\npath = \"main.c\"\n\
+                                        [[bin]]\nname = \"dump\"\npath = \"dump.c\"\n\
+                                        [[bin]]\nname = \"hexview\"\npath = \"hexview.c\"\n")
+        .unwrap();
+    assert!(bf.validate_groups().is_ok());
+    let names = |bf: &BuildFile, group: &str| -> Vec<String> {
+        bf.group_members(group).unwrap().into_iter().map(|member| member.name()).collect()
+    };
+    assert_eq!(names(&bf, "all"), vec!["app", "dump", "hexview"]);
+    let mut typo = bf.clone();
+    typo.groups.insert(String::from("tools"), vec![String::from("hexveiw")]);
+    let error = typo.validate_groups().unwrap_err().to_string();
+    assert!(error.contains("'groups.tools'") && error.contains("did you mean 'hexview'?"), "{}", error);
+    let mut cycle = bf.clone();
+    cycle.groups.insert(String::from("tools"), vec![String::from("all")]);
+    let error = cycle.validate_groups().unwrap_err().to_string();
+    assert!(error.contains("all -> tools -> all"), "{}", error);
+    // A library named like a binary needs "bin:" or "lib:" to be told apart
+    let mut shared_name: BuildFile = toml::from_str("[project]\nfile-extensions = [\"c\"]\n\
+                                                     [groups]\nall = [\"app\"]\n\
+                                                     [[bin]]\nname = \"app\"\npath = \"main.c\"\n\
+                                                     [[lib]]\nname = \"app\"\ntypes = [\"static\"]\n")
+        .unwrap();
+    let error = shared_name.validate_groups().unwrap_err().to_string();
+    assert!(error.contains("name it 'bin:app' or 'lib:app'"), "{}", error);
+    shared_name.groups.insert(String::from("all"), vec![String::from("bin:app")]);
+    let members = shared_name.group_members("all").unwrap();
+    assert!(members.len() == 1 && members[0].kinds().contains(&TargetKind::Binary));
+}
+
+#[test]
+fn test_targets_without_binaries_or_libraries() {
+    assert!(BuildFile::default().targets().is_empty());
+    let mut bf: BuildFile = toml::from_str("[project]\nfile-extensions = [\"c\"]\n\
+                                            [[lib]]\nname = \"libnone\"\ntypes = [\"static\", \"dynamic\"]\n")
+        .unwrap();
+    // Kept out of the working directory
+    let state_dir = ::std::env::temp_dir().join(format!("yabs-targets-test-{}", ::std::process::id()));
+    bf.project.state_dir = Some(state_dir.clone());
+    let targets = bf.targets();
+    assert_eq!(targets.iter().map(|target| target.kind).collect::<Vec<_>>(),
+               vec![TargetKind::StaticLibrary, TargetKind::DynamicLibrary]);
+    assert!(targets.iter().all(|target| !target.exists && !target.up_to_date));
+    fs::remove_dir_all(&state_dir).ok();
+}
+
+#[test]
+fn test_empty_std_is_rejected() {
+    let bf: BuildFile = toml::from_str("[project]\nfile-extensions = [\"c\"]\nstd-c = \"\"\n")
+        .unwrap();
+    assert!(bf.project.validate().is_err());
+}
+
+#[test]
+fn test_warning_presets() {
+    let bf: BuildFile = toml::from_str("[project]\nfile-extensions = [\"c\"]\n\
+                                        warnings = \"all\"\nwarnings-as-errors = true\n\
+                                        compiler-flags = [\"Wno-unused-parameter\"]\n")
+        .unwrap();
+    let target = Target::new(PathBuf::from("a.c"), PathBuf::from("a.o"));
+    assert_eq!(bf.compile_flags(&target).unwrap(),
+               vec!["gcc", "-c", "-Wall", "-Wextra", "-Werror", "-Wno-unused-parameter"]);
+}
+
+#[test]
+fn test_unknown_sanitizer_is_rejected() {
+    let bf: BuildFile = toml::from_str("[project]\nfile-extensions = [\"c\"]\n\
+                                        sanitize = [\"adress\"]\n")
+        .unwrap();
+    let error = bf.project.validate().unwrap_err().to_string();
+    assert!(error.contains("'adress'") && error.contains("address, hwaddress"));
+}
+
+#[test]
+fn test_task_cycles_are_rejected() {
+    let bf: BuildFile = toml::from_str("[project]\nfile-extensions = [\"c\"]\n\
+                                        [[task]]\nname = \"a\"\ncommand = \"true\"\n\
+                                        depends = [\"b\"]\n\
+                                        [[task]]\nname = \"b\"\ncommand = [\"true\"]\n\
+                                        depends = [\"a\"]\n")
+        .unwrap();
+    assert_eq!(bf.validate_tasks().unwrap_err().to_string(),
+               "task dependencies form a cycle: a -> b -> a");
+}
+
+#[test]
+fn test_lto_selects_archiver() {
+    let bf: BuildFile = toml::from_str("[project]\nfile-extensions = [\"c\"]\nlto = true\n").unwrap();
+    assert_eq!(bf.project.lto_args(), vec!["-flto"]);
+    assert_eq!(bf.project.ar(), "gcc-ar");
+    let bf: BuildFile = toml::from_str("[project]\nfile-extensions = [\"c\"]\ncompiler = \"clang\"\n\
+                                        lto = \"thin\"\n")
+        .unwrap();
+    assert_eq!(bf.project.lto_args(), vec!["-flto=thin"]);
+    assert_eq!(bf.project.ar(), "llvm-ar");
+    let bf: BuildFile = toml::from_str("[project]\nfile-extensions = [\"c\"]\nlto = \"fat\"\n").unwrap();
+    assert!(bf.project.validate().is_err());
+}
+
+#[test]
+fn test_cross_compile_tools() {
+    let bf: BuildFile = toml::from_str("[project]\nfile-extensions = [\"c\"]\nrespect-env = false\n\
+                                        target = \"aarch64-linux-gnu\"\nsysroot = \"/opt/root\"\n")
+        .unwrap();
+    assert_eq!(bf.project.compiler(), "aarch64-linux-gnu-gcc");
+    assert_eq!(bf.project.ar(), "aarch64-linux-gnu-ar");
+    let target = Target::new(PathBuf::from("a.c"), PathBuf::from("a.o"));
+    assert_eq!(bf.compile_flags(&target).unwrap(),
+               vec!["aarch64-linux-gnu-gcc", "-c", "--sysroot=/opt/root"]);
+}
+
+#[test]
+fn test_find_build_file_keeps_working_directory() {
+    let dir = ::std::env::temp_dir().join(format!("yabs-find-test-{}", ::std::process::id()));
+    fs::create_dir_all(dir.join("src")).unwrap();
+    // Kept out of the working directory
+    fs::write(dir.join("yabs.toml"),
+              format!("[project]\nfile-extensions = [\"c\"]\nstate-dir = \"{}\"\n", dir.join(".yabs").display()))
+        .unwrap();
+    fs::write(dir.join("src/main.c"), "int main() { return 0; }\n").unwrap();
+    let cwd = ::std::env::current_dir().unwrap();
+    let bf = find_build_file(&dir.join("src")).unwrap();
+    assert_eq!(::std::env::current_dir().unwrap(), cwd);
+    assert_eq!(bf.context().cwd, dir);
+    assert_eq!(bf.project.file_mod_map.keys().map(|target| target.source()).collect::<Vec<_>>(),
+               vec![PathBuf::from("./src/main.c")]);
+    fs::remove_dir_all(&dir).unwrap();
+}
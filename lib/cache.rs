@@ -0,0 +1,109 @@
+// Copyright (c) 2015 - 2016, Alberto Corona <ac@albertocorona.com>
+// All rights reserved. This file is part of yabs, distributed under the BSD
+// 3-Clause license. For full terms please see the LICENSE file.
+
+use error::YabsError;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+// In the state directory
+pub const CACHE_DIR: &str = "cache";
+
+// Megabytes kept when 'cache-max-size' isn't set
+pub const DEFAULT_CACHE_MAX_SIZE: u64 = 1024;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: usize,
+    pub misses: usize,
+}
+
+// Compiled objects stored by a hash of everything that went into them. Entries
+// are copied rather than linked, compilers truncate their output in place.
+#[derive(Debug, Clone)]
+pub struct ObjectCache {
+    dir: PathBuf,
+    max_size: u64,
+}
+
+impl ObjectCache {
+    // `max_size` is in bytes
+    pub fn new(dir: &Path, max_size: u64) -> ObjectCache {
+        ObjectCache {
+            dir: dir.to_path_buf(),
+            max_size,
+        }
+    }
+
+    fn entry(&self, key: &str) -> PathBuf {
+        self.dir.join(key)
+    }
+
+    // Copies the object cached under `key` to `object`, returning whether
+    // there was one
+    pub fn fetch(&self, key: &str, object: &Path) -> Result<bool, YabsError> {
+        let entry = self.entry(key);
+        if !entry.is_file() {
+            return Ok(false);
+        }
+        if let Some(dir) = object.parent() {
+            if dir != Path::new("") {
+                fs::create_dir_all(dir)?;
+            }
+        }
+        fs::copy(&entry, object)?;
+        // Entries are evicted least recently used first
+        File::options().write(true).open(&entry)?.set_modified(SystemTime::now())?;
+        Ok(true)
+    }
+
+    // Caches `object` under `key`, evicting old entries past the size limit
+    pub fn store(&self, key: &str, object: &Path) -> Result<(), YabsError> {
+        fs::create_dir_all(&self.dir)?;
+        let temporary = self.dir.join(format!("{}.tmp", key));
+        fs::copy(object, &temporary)?;
+        fs::rename(&temporary, self.entry(key))?;
+        self.evict()
+    }
+
+    fn evict(&self) -> Result<(), YabsError> {
+        let mut entries: Vec<(SystemTime, u64, PathBuf)> = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let entry = entry?;
+            let meta = entry.metadata()?;
+            entries.push((meta.modified()?, meta.len(), entry.path()));
+        }
+        let mut size: u64 = entries.iter().map(|&(_, len, _)| len).sum();
+        entries.sort();
+        for (_, len, path) in entries {
+            if size <= self.max_size {
+                break;
+            }
+            fs::remove_file(path)?;
+            size -= len;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_evicts_least_recently_used() {
+    use std::time::Duration;
+    let dir = ::std::env::temp_dir().join(format!("yabs-cache-test-{}", ::std::process::id()));
+    let object = dir.join("object.o");
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(&object, [0u8; 100]).unwrap();
+    let cache = ObjectCache::new(&dir.join("cache"), 250);
+    cache.store("old", &object).unwrap();
+    cache.store("used", &object).unwrap();
+    let past = SystemTime::now() - Duration::from_secs(60);
+    File::options().write(true).open(dir.join("cache/old")).unwrap().set_modified(past).unwrap();
+    File::options().write(true).open(dir.join("cache/used")).unwrap().set_modified(past).unwrap();
+    assert!(cache.fetch("used", &dir.join("fetched.o")).unwrap());
+    cache.store("new", &object).unwrap();
+    assert!(!dir.join("cache/old").exists());
+    assert!(dir.join("cache/used").exists() && dir.join("cache/new").exists());
+    assert!(!cache.fetch("old", &dir.join("fetched.o")).unwrap());
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,197 @@
+// Copyright (c) 2015 - 2016, Alberto Corona <ac@albertocorona.com>
+// All rights reserved. This file is part of yabs, distributed under the BSD
+// 3-Clause license. For full terms please see the LICENSE file.
+
+use std::collections::BTreeMap;
+use std::env;
+use std::io::{self, IsTerminal};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+// The values of `std::env::consts::OS` a buildfile can have a `[target.<os>]`
+// section for
+pub const KNOWN_OS: &[&str] = &["android", "dragonfly", "freebsd", "ios", "linux", "macos", "netbsd", "openbsd",
+                                   "windows"];
+
+// Facts about the machine yabs runs on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Platform {
+    // As in `std::env::consts::OS`, such as "linux" or "windows"
+    pub os: String,
+}
+
+impl Platform {
+    pub fn host() -> Platform {
+        Platform { os: env::consts::OS.to_owned() }
+    }
+
+    // The platform a target triple such as "aarch64-linux-gnu" builds for,
+    // None when its OS isn't one of `KNOWN_OS`, as for bare metal targets
+    pub fn from_triple(triple: &str) -> Option<Platform> {
+        let parts: Vec<&str> = triple.split('-').collect();
+        let has = |name: &str| parts.iter().any(|part| part.starts_with(name));
+        let os = if has("android") {
+            "android"
+        } else if has("linux") {
+            "linux"
+        } else if has("darwin") || has("macos") {
+            "macos"
+        } else if has("ios") {
+            "ios"
+        } else if has("windows") || has("mingw") || has("cygwin") {
+            "windows"
+        } else {
+            KNOWN_OS.iter().cloned().find(|os| has(os))?
+        };
+        Some(Platform { os: os.to_owned() })
+    }
+
+    pub fn is_windows(&self) -> bool {
+        self.os == "windows"
+    }
+
+    // Link and archive commands longer than this go through a response file.
+    // Windows caps a command line at 32767 characters, Linux shares its
+    // `ARG_MAX` between the arguments and the environment so we stay well
+    // below it.
+    pub fn max_command_len(&self) -> usize {
+        if self.is_windows() {
+            32000
+        } else {
+            128000
+        }
+    }
+}
+
+// Everything a build reads from its environment, gathered once when yabs
+// starts. Embedders and tests can build one of their own so a build doesn't
+// depend on the variables, directory or terminal of the process running it.
+// Commands yabs runs still inherit the process' environment, except
+// pkg-config which only sees the context's variables.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BuildContext {
+    vars: BTreeMap<String, String>,
+    pub cwd: PathBuf,
+    pub platform: Platform,
+    pub stdout_is_terminal: bool,
+    pub stderr_is_terminal: bool,
+    // The time every build reads, None reads the system clock
+    pub clock: Option<SystemTime>,
+    // Unknown keys in the buildfile are errors, as with `strict = true`
+    pub strict: bool,
+}
+
+impl Default for BuildContext {
+    fn default() -> BuildContext {
+        BuildContext::from_env()
+    }
+}
+
+impl BuildContext {
+    // A snapshot of this process' environment. Variables that aren't valid
+    // unicode are left out, as `env::var` would have ignored them.
+    pub fn from_env() -> BuildContext {
+        BuildContext {
+            vars: env::vars_os().filter_map(|(name, value)| Some((name.into_string().ok()?, value.into_string().ok()?)))
+                                .collect(),
+            cwd: env::current_dir().unwrap_or_else(|_| PathBuf::from(".")),
+            platform: Platform::host(),
+            stdout_is_terminal: io::stdout().is_terminal(),
+            stderr_is_terminal: io::stderr().is_terminal(),
+            clock: None,
+            strict: false,
+        }
+    }
+
+    // No variables and no terminal, in `cwd` on this host
+    pub fn isolated<P: AsRef<Path>>(cwd: P) -> BuildContext {
+        BuildContext {
+            vars: BTreeMap::new(),
+            cwd: cwd.as_ref().to_path_buf(),
+            platform: Platform::host(),
+            stdout_is_terminal: false,
+            stderr_is_terminal: false,
+            clock: None,
+            strict: false,
+        }
+    }
+
+    pub fn with_var(mut self, name: &str, value: &str) -> BuildContext {
+        self.vars.insert(name.to_owned(), value.to_owned());
+        self
+    }
+
+    pub fn without_var(mut self, name: &str) -> BuildContext {
+        self.vars.remove(name);
+        self
+    }
+
+    pub fn with_clock(mut self, now: SystemTime) -> BuildContext {
+        self.clock = Some(now);
+        self
+    }
+
+    pub fn var(&self, name: &str) -> Option<&str> {
+        self.vars.get(name).map(String::as_str)
+    }
+
+    pub fn vars(&self) -> &BTreeMap<String, String> {
+        &self.vars
+    }
+
+    // `name` unless it's unset or only whitespace
+    pub fn non_empty_var(&self, name: &str) -> Option<&str> {
+        self.var(name).filter(|value| !value.trim().is_empty())
+    }
+
+    // The entries of a PATH-like variable, empty ones are left out
+    pub fn paths(&self, name: &str) -> Vec<PathBuf> {
+        match self.var(name) {
+            Some(paths) => env::split_paths(paths).filter(|path| !path.as_os_str().is_empty()).collect(),
+            None => Vec::new(),
+        }
+    }
+
+    pub fn now(&self) -> SystemTime {
+        self.clock.unwrap_or_else(SystemTime::now)
+    }
+
+    // Looks `program` up like a shell in `cwd` would, paths are checked as
+    // they are
+    pub fn find_program(&self, program: &str) -> Option<PathBuf> {
+        if program.contains('/') {
+            return Some(self.cwd.join(program)).filter(|path| path.is_file());
+        }
+        self.paths("PATH").into_iter().map(|dir| dir.join(program)).find(|path| path.is_file())
+    }
+}
+
+#[test]
+fn test_isolated_context() {
+    use std::time::{Duration, UNIX_EPOCH};
+    let context = BuildContext::isolated("/tmp").with_var("CC", "clang")
+                                                .with_var("CFLAGS", " ")
+                                                .with_var("PATH", "/usr/local/bin::/usr/bin")
+                                                .with_clock(UNIX_EPOCH + Duration::from_secs(60));
+    assert_eq!(context.var("CC"), Some("clang"));
+    assert_eq!(context.var("HOME"), None);
+    assert_eq!(context.non_empty_var("CFLAGS"), None);
+    assert_eq!(context.paths("PATH"), vec![PathBuf::from("/usr/local/bin"), PathBuf::from("/usr/bin")]);
+    assert_eq!(context.now(), UNIX_EPOCH + Duration::from_secs(60));
+    assert_eq!(context.clone().without_var("CC").var("CC"), None);
+    assert!(BuildContext::isolated("/tmp").find_program("sh").is_none());
+    assert!(!context.stdout_is_terminal);
+    let windows = Platform { os: String::from("windows") };
+    assert!(windows.max_command_len() < Platform { os: String::from("linux") }.max_command_len());
+}
+
+#[test]
+fn test_platform_from_triple() {
+    let os = |triple: &str| Platform::from_triple(triple).map(|platform| platform.os);
+    assert_eq!(os("aarch64-linux-gnu"), Some(String::from("linux")));
+    assert_eq!(os("aarch64-linux-android"), Some(String::from("android")));
+    assert_eq!(os("x86_64-apple-darwin"), Some(String::from("macos")));
+    assert_eq!(os("x86_64-w64-mingw32"), Some(String::from("windows")));
+    assert_eq!(os("x86_64-unknown-freebsd13"), Some(String::from("freebsd")));
+    assert_eq!(os("arm-none-eabi"), None);
+}
@@ -0,0 +1,70 @@
+// Copyright (c) 2015 - 2016, Alberto Corona <ac@albertocorona.com>
+// All rights reserved. This file is part of yabs, distributed under the BSD
+// 3-Clause license. For full terms please see the LICENSE file.
+
+use std::time::{Duration, Instant, SystemTime};
+
+// When a build has to stop starting compiles, for CI jobs with a time limit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Deadline {
+    // Counted from the start of the build
+    After(Duration),
+    At(SystemTime),
+}
+
+impl Deadline {
+    // The deadline on the monotonic clock for a build started at `started`
+    pub fn instant(&self, started: Instant) -> Instant {
+        match *self {
+            Deadline::After(duration) => started + duration,
+            Deadline::At(time) => {
+                Instant::now() + time.duration_since(SystemTime::now()).unwrap_or_default()
+            },
+        }
+    }
+}
+
+// A duration such as `90`, `45s`, `30m`, `1h30m` or `500ms`, plain numbers
+// are seconds
+pub fn parse_duration(duration: &str) -> Option<Duration> {
+    let duration = duration.trim();
+    if let Ok(seconds) = duration.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let mut total = Duration::from_secs(0);
+    let mut rest = duration;
+    while !rest.is_empty() {
+        let digits = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+        let number = rest[..digits].parse::<u64>().ok()?;
+        rest = &rest[digits..];
+        let unit = rest.find(|c: char| c.is_ascii_digit()).unwrap_or(rest.len());
+        total += match &rest[..unit] {
+            "ms" => Duration::from_millis(number),
+            "s" => Duration::from_secs(number),
+            "m" => Duration::from_secs(number * 60),
+            "h" => Duration::from_secs(number * 60 * 60),
+            _ => return None,
+        };
+        rest = &rest[unit..];
+    }
+    if duration.is_empty() {
+        None
+    } else {
+        Some(total)
+    }
+}
+
+#[test]
+fn test_parse_duration() {
+    assert_eq!(parse_duration("90"), Some(Duration::from_secs(90)));
+    assert_eq!(parse_duration("45s"), Some(Duration::from_secs(45)));
+    assert_eq!(parse_duration("30m"), Some(Duration::from_secs(1800)));
+    assert_eq!(parse_duration("1h30m"), Some(Duration::from_secs(5400)));
+    assert_eq!(parse_duration("500ms"), Some(Duration::from_millis(500)));
+    assert_eq!(parse_duration("1d"), None);
+    assert_eq!(parse_duration("m"), None);
+    assert_eq!(parse_duration(""), None);
+    let started = Instant::now();
+    assert_eq!(Deadline::After(Duration::from_secs(5)).instant(started), started + Duration::from_secs(5));
+    assert!(Deadline::At(SystemTime::now() - Duration::from_secs(5)).instant(started) >= started);
+}
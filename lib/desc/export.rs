@@ -0,0 +1,208 @@
+extern crate serde;
+extern crate toml;
+
+use context::BuildContext;
+use error::{YabsError, YabsErrorKind};
+
+use std::cmp::Ordering;
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+// Prefixes searched for export files after 'dependency-prefixes', separated
+// like PATH
+pub const PREFIX_PATH_ENV: &str = "YABS_PREFIX_PATH";
+pub const EXPORT_DIR: &str = "yabs-export";
+
+// `deps = ["A"]` or `deps = [{ name = "A", version = ">=1.2" }]`
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum Dependency {
+    Name(String),
+    Versioned {
+        name: String,
+        version: Option<String>,
+    },
+}
+
+impl Dependency {
+    pub fn name(&self) -> &str {
+        match *self {
+            Dependency::Name(ref name) |
+            Dependency::Versioned { ref name, .. } => name,
+        }
+    }
+
+    pub fn version(&self) -> Option<&str> {
+        match *self {
+            Dependency::Name(_) => None,
+            Dependency::Versioned { ref version, .. } => version.as_ref().map(|v| v.as_str()),
+        }
+    }
+}
+
+// What a project depending on an exported package needs to build against it.
+// Paths are absolute so the file can be read from anywhere.
+#[derive(Debug, Default, Deserialize, Serialize, Clone, PartialEq)]
+pub struct Export {
+    pub name: String,
+    pub version: Option<String>,
+    pub include: Vec<String>,
+    pub defines: Vec<String>,
+    #[serde(rename = "library-directories")]
+    pub lib_dir: Vec<String>,
+    pub libraries: Vec<String>,
+    // Packages the exported libraries depend on in turn
+    pub requires: Vec<Dependency>,
+}
+
+impl Export {
+    pub fn path_in(prefix: &Path, name: &str) -> PathBuf {
+        prefix.join(EXPORT_DIR).join(format!("{}.toml", name))
+    }
+
+    pub fn read(path: &Path) -> Result<Export, YabsError> {
+        let mut buffer = String::new();
+        File::open(path)?.read_to_string(&mut buffer)?;
+        Ok(toml::from_str(&buffer)?)
+    }
+
+    pub fn write(&self, prefix: &Path) -> Result<PathBuf, YabsError> {
+        let path = Export::path_in(prefix, &self.name);
+        fs::create_dir_all(prefix.join(EXPORT_DIR))?;
+        // Every requirement is written as a table, toml can't mix strings and
+        // tables in one array
+        let mut export = self.clone();
+        export.requires = self.requires
+                              .iter()
+                              .map(|dep| {
+                                       Dependency::Versioned {
+                                           name: dep.name().to_owned(),
+                                           version: dep.version().map(|v| v.to_owned()),
+                                       }
+                                   })
+                              .collect();
+        File::create(&path)?.write_all(toml::to_string(&export)?.as_bytes())?;
+        Ok(path)
+    }
+}
+
+// `configured`, relative to `context.cwd`, followed by the entries of
+// YABS_PREFIX_PATH
+pub fn search_prefixes(configured: &[String], context: &BuildContext) -> Vec<PathBuf> {
+    let mut prefixes: Vec<PathBuf> = configured.iter().map(|prefix| context.cwd.join(prefix)).collect();
+    prefixes.extend(context.paths(PREFIX_PATH_ENV));
+    prefixes
+}
+
+// Finds the export files of `deps` and of everything they require. All
+// missing packages are reported in one error.
+pub fn resolve(deps: &[Dependency], prefixes: &[PathBuf]) -> Result<Vec<Export>, YabsError> {
+    let mut resolved: Vec<Export> = Vec::new();
+    let mut missing: Vec<String> = Vec::new();
+    let mut pending: Vec<Dependency> = deps.to_vec();
+    while !pending.is_empty() {
+        let dep = pending.remove(0);
+        let export = match resolved.iter().find(|export| export.name == dep.name()) {
+            Some(export) => export.clone(),
+            None => {
+                let found = prefixes.iter()
+                                    .map(|prefix| Export::path_in(prefix, dep.name()))
+                                    .find(|path| path.is_file());
+                match found {
+                    Some(path) => {
+                        let export = Export::read(&path)?;
+                        pending.extend(export.requires.clone());
+                        resolved.push(export.clone());
+                        export
+                    },
+                    None => {
+                        if !missing.contains(&dep.name().to_owned()) {
+                            missing.push(dep.name().to_owned());
+                        }
+                        continue;
+                    },
+                }
+            },
+        };
+        if let Some(constraint) = dep.version() {
+            let version = export.version.clone().unwrap_or_default();
+            if !version_matches(&version, constraint)? {
+                bail!(YabsErrorKind::InvalidValue("deps".to_owned(),
+                                                  format!("'{}' requires version '{}' but \
+                                                           version '{}' was found",
+                                                          dep.name(),
+                                                          constraint,
+                                                          version)));
+            }
+        }
+    }
+    if !missing.is_empty() {
+        let searched: Vec<String> = prefixes.iter()
+                                            .map(|prefix| prefix.join(EXPORT_DIR).display().to_string())
+                                            .collect();
+        bail!(YabsErrorKind::PackageNotFound(missing.join(", "), searched.join(", ")));
+    }
+    Ok(resolved)
+}
+
+fn parse_version(version: &str) -> Result<Vec<u64>, YabsError> {
+    version.trim()
+           .split('.')
+           .map(|part| {
+                    part.parse::<u64>().map_err(|_| {
+                        YabsErrorKind::InvalidValue("version".to_owned(),
+                                                    format!("'{}' is not a version number",
+                                                            version))
+                                .into()
+                    })
+                })
+           .collect()
+}
+
+fn compare_versions(a: &[u64], b: &[u64]) -> Ordering {
+    for i in 0..a.len().max(b.len()) {
+        let order = a.get(i).unwrap_or(&0).cmp(b.get(i).unwrap_or(&0));
+        if order != Ordering::Equal {
+            return order;
+        }
+    }
+    Ordering::Equal
+}
+
+// `constraint` is a comma separated list of `>=`, `>`, `<=`, `<` or `=`
+// comparisons, a bare version has to match exactly
+pub fn version_matches(version: &str, constraint: &str) -> Result<bool, YabsError> {
+    let version = parse_version(version)?;
+    for part in constraint.split(',') {
+        let part = part.trim();
+        let (op, required) = ["==", ">=", "<=", ">", "<", "="]
+            .iter()
+            .find(|op| part.starts_with(*op))
+            .map(|op| (*op, &part[op.len()..]))
+            .unwrap_or(("=", part));
+        let order = compare_versions(&version, &parse_version(required)?);
+        let matches = match op {
+            ">=" => order != Ordering::Less,
+            "<=" => order != Ordering::Greater,
+            ">" => order == Ordering::Greater,
+            "<" => order == Ordering::Less,
+            _ => order == Ordering::Equal,
+        };
+        if !matches {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+#[test]
+fn test_version_matches() {
+    assert!(version_matches("1.2.3", ">=1.2").unwrap());
+    assert!(!version_matches("1.1", ">=1.2").unwrap());
+    assert!(version_matches("1.4", ">=1.2, <2").unwrap());
+    assert!(!version_matches("2.0", ">=1.2, <2").unwrap());
+    assert!(version_matches("1.2.0", "1.2").unwrap());
+    assert!(version_matches("1.2", "=1.2").unwrap());
+    assert!(version_matches("1.x", ">=1").is_err());
+}
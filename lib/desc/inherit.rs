@@ -0,0 +1,265 @@
+// Copyright (c) 2015 - 2016, Alberto Corona <ac@albertocorona.com>
+// All rights reserved. This file is part of yabs, distributed under the BSD
+// 3-Clause license. For full terms please see the LICENSE file.
+
+// `inherits = "../common.toml"`, which loads another buildfile underneath
+// this one. Its tables are merged with ours, lists such as 'include' or the
+// `[[bin]]`s get ours appended and everything else is replaced by our value.
+
+use desc::json::{is_json, parse_json};
+use error::{YabsError, YabsErrorKind};
+
+use toml::Value;
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+// How many buildfiles may inherit from one another
+const MAX_DEPTH: usize = 8;
+
+// Keys of [project], `[[bin]]`, `[[lib]]` and `[target.<triple>]` naming files
+// the build reads, which are relative to the buildfile setting them. Where
+// outputs go, such as 'bin-dir', stays relative to the project.
+const PATH_KEYS: &[&str] = &["include", "library-directories", "src", "extra-deps", "path", "version-script"];
+
+// The buildfile each key came from, by its dotted path such as
+// "project.include" or "bin.0.name". Lists appended to are from each file
+// listed, in order.
+pub type Origins = BTreeMap<String, String>;
+
+// Merges the buildfiles `buildfile` read from `path` inherits from beneath
+// it. Nothing is recorded without an `inherits`.
+pub fn inherit(path: &Path, buildfile: &mut Value) -> Result<Origins, YabsError> {
+    let mut origins = Origins::new();
+    if buildfile.get("inherits").is_none() {
+        return Ok(origins);
+    }
+    let name = path.file_name().map(PathBuf::from).unwrap_or_else(|| path.to_path_buf());
+    let mut chain = vec![(path.canonicalize()?, name.display().to_string())];
+    *buildfile = resolve(path, Path::new(""), buildfile.clone(), &mut chain, &mut origins)?;
+    Ok(origins)
+}
+
+// `buildfile` merged over what it inherits from, `dir` being where `path` is
+// relative to the first buildfile, which names are given from
+fn resolve(path: &Path,
+           dir: &Path,
+           mut buildfile: Value,
+           chain: &mut Vec<(PathBuf, String)>,
+           origins: &mut Origins)
+           -> Result<Value, YabsError> {
+    let name = chain.last().map(|entry| entry.1.clone()).unwrap_or_default();
+    let inherits = match buildfile.as_table_mut().and_then(|table| table.remove("inherits")) {
+        Some(Value::String(inherits)) => inherits,
+        Some(_) => bail!(YabsErrorKind::InvalidValue(String::from("inherits"), String::from("has to be a path"))),
+        None => {
+            record(&buildfile, "", &name, origins);
+            return Ok(buildfile);
+        },
+    };
+    let base_path = path.parent().unwrap_or_else(|| Path::new("")).join(&inherits);
+    if !base_path.is_file() {
+        bail!(YabsErrorKind::BuildfileNotFound(base_path));
+    }
+    let base_name = lexical(&dir.join(&inherits));
+    let canonical = base_path.canonicalize()?;
+    if chain.iter().any(|entry| entry.0 == canonical) {
+        let names: Vec<String> = chain.iter()
+                                      .map(|entry| entry.1.clone())
+                                      .chain(Some(base_name.display().to_string()))
+                                      .collect();
+        bail!(YabsErrorKind::InheritanceCycle(names.join(" -> ")));
+    }
+    if chain.len() >= MAX_DEPTH {
+        bail!(YabsErrorKind::InvalidValue(String::from("inherits"),
+                                          format!("buildfiles can't inherit more than {} deep", MAX_DEPTH)));
+    }
+    chain.push((canonical, base_name.display().to_string()));
+    let contents = fs::read_to_string(&base_path)?;
+    let base = if is_json(&base_path) { parse_json(&contents)? } else { contents.parse()? };
+    let mut merged = resolve(&base_path, base_name.parent().unwrap_or_else(|| Path::new("")), base, chain, origins)?;
+    rebase(&mut merged, Path::new(&inherits).parent().unwrap_or_else(|| Path::new("")));
+    chain.pop();
+    merge(&mut merged, buildfile, "", &name, origins);
+    Ok(merged)
+}
+
+// Merges `value` from the buildfile `name` over `base`, `key` being where they
+// are
+fn merge(base: &mut Value, value: Value, key: &str, name: &str, origins: &mut Origins) {
+    match (base, value) {
+        (&mut Value::Table(ref mut base), Value::Table(table)) => {
+            for (field, value) in table {
+                let path = join_key(key, &field);
+                match base.get_mut(&field) {
+                    Some(existing) => merge(existing, value, &path, name, origins),
+                    None => {
+                        record(&value, &path, name, origins);
+                        base.insert(field, value);
+                    },
+                }
+            }
+        },
+        (&mut Value::Array(ref mut base), Value::Array(values)) => {
+            if values.iter().chain(base.iter()).any(Value::is_table) {
+                for value in values {
+                    record(&value, &join_key(key, &base.len().to_string()), name, origins);
+                    base.push(value);
+                }
+            } else if !values.is_empty() {
+                let origin = match origins.get(key) {
+                    Some(origin) if origin != name => format!("{}, {}", origin, name),
+                    _ => name.to_owned(),
+                };
+                origins.insert(key.to_owned(), origin);
+                base.extend(values);
+            }
+        },
+        (base, value) => {
+            let nested = format!("{}.", key);
+            origins.retain(|path, _| !path.starts_with(&nested));
+            record(&value, key, name, origins);
+            *base = value;
+        },
+    }
+}
+
+// Makes the relative paths of the buildfile `base` relative to `dir` instead,
+// the directory it's in from the buildfile inheriting it
+fn rebase(base: &mut Value, dir: &Path) {
+    if dir.as_os_str().is_empty() {
+        return;
+    }
+    let mut tables: Vec<&mut Value> = Vec::new();
+    if let Value::Table(ref mut buildfile) = *base {
+        for (key, value) in buildfile.iter_mut() {
+            match (key.as_str(), value) {
+                ("project", value) => tables.push(value),
+                ("bin", &mut Value::Array(ref mut targets)) | ("lib", &mut Value::Array(ref mut targets)) => {
+                    tables.extend(targets.iter_mut())
+                },
+                ("target", &mut Value::Table(ref mut platforms)) => tables.extend(platforms.values_mut()),
+                _ => {},
+            }
+        }
+    }
+    for table in tables.into_iter().filter_map(Value::as_table_mut) {
+        for (_, value) in table.iter_mut().filter(|entry| PATH_KEYS.contains(&entry.0.as_str())) {
+            match *value {
+                Value::String(ref mut path) => rebase_path(path, dir),
+                Value::Array(ref mut paths) => {
+                    for path in paths.iter_mut() {
+                        if let Value::String(ref mut path) = *path {
+                            rebase_path(path, dir);
+                        }
+                    }
+                },
+                _ => {},
+            }
+        }
+    }
+}
+
+// Paths starting with a variable, such as `${env.VCPKG_ROOT}/include`, are
+// left alone as they're usually absolute once expanded
+fn rebase_path(path: &mut String, dir: &Path) {
+    if Path::new(path.as_str()).is_relative() && !path.starts_with("${") {
+        *path = lexical(&dir.join(path.as_str())).display().to_string();
+    }
+}
+
+// Records every key under `value` as coming from `name`
+fn record(value: &Value, key: &str, name: &str, origins: &mut Origins) {
+    match *value {
+        Value::Table(ref table) => {
+            for (field, value) in table {
+                record(value, &join_key(key, field), name, origins);
+            }
+        },
+        Value::Array(ref values) if values.iter().any(Value::is_table) => {
+            for (index, value) in values.iter().enumerate() {
+                record(value, &join_key(key, &index.to_string()), name, origins);
+            }
+        },
+        _ => {
+            origins.insert(key.to_owned(), name.to_owned());
+        },
+    }
+}
+
+fn join_key(key: &str, field: &str) -> String {
+    if key.is_empty() { field.to_owned() } else { format!("{}.{}", key, field) }
+}
+
+// `path` with "." and the directories ".." leaves dropped
+fn lexical(path: &Path) -> PathBuf {
+    let mut normal = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {},
+            Component::ParentDir if matches!(normal.components().next_back(), Some(Component::Normal(_))) => {
+                normal.pop();
+            },
+            component => normal.push(component),
+        }
+    }
+    normal
+}
+
+#[test]
+fn test_merge_inherited() {
+    let mut base: Value = "[project]\n\
+                           name = \"common\"\n\
+                           include = [\"include\"]\n\
+                           [[bin]]\n\
+                           name = \"a\"\n"
+        .parse()
+        .unwrap();
+    let mut origins = Origins::new();
+    record(&base, "", "common.toml", &mut origins);
+    let child: Value = "[project]\n\
+                        name = \"tool\"\n\
+                        include = [\"src\"]\n\
+                        [[bin]]\n\
+                        name = \"b\"\n"
+        .parse()
+        .unwrap();
+    merge(&mut base, child, "", "tool.toml", &mut origins);
+    let merged: Value = "[project]\nname = \"tool\"\ninclude = [\"include\", \"src\"]\n\
+                         [[bin]]\nname = \"a\"\n[[bin]]\nname = \"b\"\n"
+        .parse()
+        .unwrap();
+    assert_eq!(base, merged);
+    assert_eq!(origins["project.name"], "tool.toml");
+    assert_eq!(origins["project.include"], "common.toml, tool.toml");
+    assert_eq!(origins["bin.0.name"], "common.toml");
+    assert_eq!(origins["bin.1.name"], "tool.toml");
+    assert_eq!(lexical(Path::new("../x/./../common.toml")), PathBuf::from("../common.toml"));
+}
+
+#[test]
+fn test_rebase_inherited_paths() {
+    let mut base: Value = "[project]\n\
+                           include = [\"include\", \"/usr/include/extra\", \"${env.ROOT}/include\"]\n\
+                           bin-dir = \"build\"\n\
+                           compiler-flags = [\"Iignored\"]\n\
+                           [[bin]]\n\
+                           path = \"./tool/main.c\"\n\
+                           [target.x86_64-linux-gnu]\n\
+                           library-directories = [\"lib\"]\n"
+        .parse()
+        .unwrap();
+    rebase(&mut base, Path::new("../common"));
+    let rebased: Value = "[project]\n\
+                          include = [\"../common/include\", \"/usr/include/extra\", \"${env.ROOT}/include\"]\n\
+                          bin-dir = \"build\"\n\
+                          compiler-flags = [\"Iignored\"]\n\
+                          [[bin]]\n\
+                          path = \"../common/tool/main.c\"\n\
+                          [target.x86_64-linux-gnu]\n\
+                          library-directories = [\"../common/lib\"]\n"
+        .parse()
+        .unwrap();
+    assert_eq!(base, rebased);
+}
@@ -0,0 +1,148 @@
+// Copyright (c) 2015 - 2016, Alberto Corona <ac@albertocorona.com>
+// All rights reserved. This file is part of yabs, distributed under the BSD
+// 3-Clause license. For full terms please see the LICENSE file.
+
+// Variables in the buildfile's strings, such as `"${env.VCPKG_ROOT}/lib"`.
+// `${project.<key>}` is the value of a key of [project] and `${env.<name>}` an
+// environment variable, `$${` is a literal `${`.
+
+use context::BuildContext;
+use error::{YabsError, YabsErrorKind};
+use ext::closest_match;
+
+use toml::Value;
+use toml::value::Table;
+
+// How deep `${project.<key>}` may refer to keys which use variables of their
+// own, deeper means they refer to each other
+const MAX_DEPTH: usize = 8;
+
+// Expands the variables in every string of `buildfile`, returns whether any
+// were found
+pub fn interpolate(buildfile: &mut Value, context: &BuildContext) -> Result<bool, YabsError> {
+    let project = buildfile.get("project").and_then(Value::as_table).cloned().unwrap_or_default();
+    let vars = Vars {
+        project: &project,
+        context,
+    };
+    vars.expand_value(buildfile, "")
+}
+
+struct Vars<'a> {
+    project: &'a Table,
+    context: &'a BuildContext,
+}
+
+impl<'a> Vars<'a> {
+    fn expand_value(&self, value: &mut Value, key: &str) -> Result<bool, YabsError> {
+        let mut found = false;
+        match *value {
+            Value::String(ref mut string) if string.contains("${") => {
+                *string = self.expand(string, 0).map_err(|reason| {
+                              YabsErrorKind::Interpolation(key.to_owned(), string.clone(), reason)
+                          })?;
+                found = true;
+            },
+            Value::Array(ref mut values) => {
+                for value in values {
+                    found |= self.expand_value(value, key)?;
+                }
+            },
+            Value::Table(ref mut table) => {
+                for (name, value) in table.iter_mut() {
+                    let path = if key.is_empty() { name.clone() } else { format!("{}.{}", key, name) };
+                    found |= self.expand_value(value, &path)?;
+                }
+            },
+            _ => {},
+        }
+        Ok(found)
+    }
+
+    // `string` with its variables replaced, or why it can't be
+    fn expand(&self, string: &str, depth: usize) -> Result<String, String> {
+        if depth > MAX_DEPTH {
+            return Err(format!("variables are nested more than {} deep, a key may refer to itself", MAX_DEPTH));
+        }
+        let mut expanded = String::new();
+        let mut rest = string;
+        while let Some(start) = rest.find("${") {
+            if rest[..start].ends_with('$') {
+                expanded.push_str(&rest[..start - 1]);
+                expanded.push_str("${");
+                rest = &rest[start + 2..];
+                continue;
+            }
+            expanded.push_str(&rest[..start]);
+            let end = match rest[start..].find('}') {
+                Some(end) => start + end,
+                None => return Err(String::from("`${` isn't closed by a `}`, write `$${` for a literal `${`")),
+            };
+            expanded.push_str(&self.lookup(&rest[start + 2..end], depth)?);
+            rest = &rest[end + 1..];
+        }
+        expanded.push_str(rest);
+        Ok(expanded)
+    }
+
+    fn lookup(&self, name: &str, depth: usize) -> Result<String, String> {
+        if let Some(var) = name.strip_prefix("env.") {
+            return match self.context.var(var) {
+                Some(value) => Ok(value.to_owned()),
+                None => Err(format!("`${{{}}}` isn't set in the environment", name)),
+            };
+        }
+        let key = match name.strip_prefix("project.") {
+            Some(key) => key,
+            None => {
+                return Err(format!("unknown variable `${{{}}}`, expected `${{project.<key>}}` or `${{env.<name>}}`",
+                                   name))
+            },
+        };
+        match self.project.get(key) {
+            Some(Value::String(value)) => self.expand(value, depth + 1),
+            Some(Value::Integer(value)) => Ok(value.to_string()),
+            Some(Value::Float(value)) => Ok(value.to_string()),
+            Some(Value::Boolean(value)) => Ok(value.to_string()),
+            Some(_) => Err(format!("`${{{}}}` is a list or table, only strings, numbers and booleans can be used",
+                                   name)),
+            None => {
+                let reason = format!("unknown variable `${{{}}}`", name);
+                Err(match closest_match(key, self.project.keys().map(String::as_str)) {
+                        Some(close) => format!("{}; did you mean `${{project.{}}}`?", reason, close),
+                        None => format!("{}, [project] has no '{}'", reason, key),
+                    })
+            },
+        }
+    }
+}
+
+#[test]
+fn test_interpolate() {
+    let context = BuildContext::isolated("/tmp").with_var("VCPKG_ROOT", "/opt/vcpkg");
+    let mut buildfile: Value = "[project]\n\
+                                name = \"app\"\n\
+                                version = \"${project.major}.1\"\n\
+                                major = 2\n\
+                                library-directories = [\"${env.VCPKG_ROOT}/lib\"]\n\
+                                [[task]]\n\
+                                command = \"cp ${project.name}-${project.version} $${HOME}/dist/\"\n"
+        .parse()
+        .unwrap();
+    assert!(interpolate(&mut buildfile, &context).unwrap());
+    assert_eq!(buildfile["project"]["version"].as_str(), Some("2.1"));
+    assert_eq!(buildfile["project"]["library-directories"][0].as_str(), Some("/opt/vcpkg/lib"));
+    assert_eq!(buildfile["task"][0]["command"].as_str(), Some("cp app-2.1 ${HOME}/dist/"));
+    let failure = |toml: &str| {
+        let mut buildfile: Value = toml.parse().unwrap();
+        interpolate(&mut buildfile, &context).unwrap_err().to_string()
+    };
+    assert_eq!(failure("[project]\nname = \"${project.nme}\"\n"),
+               "could not expand '${project.nme}' of 'project.name': unknown variable `${project.nme}`; did you mean \
+                `${project.name}`?");
+    assert!(failure("[project]\ninclude = [\"${env.NOPE}\"]\n").contains("of 'project.include'"));
+    assert!(failure("[project]\na = \"${project.b}\"\nb = \"${project.a}\"\n").contains("nested more than 8 deep"));
+    assert!(failure("[project]\nname = \"${HOME\"\n").contains("isn't closed"));
+    let mut plain: Value = "[project]\nname = \"app\"\n".parse().unwrap();
+    assert!(!interpolate(&mut plain, &context).unwrap());
+}
@@ -0,0 +1,105 @@
+// Copyright (c) 2015 - 2016, Alberto Corona <ac@albertocorona.com>
+// All rights reserved. This file is part of yabs, distributed under the BSD
+// 3-Clause license. For full terms please see the LICENSE file.
+
+// JSON buildfiles. serde_json is too old for our serde, so a JSON buildfile is
+// parsed into the `toml::Value` it stands for and deserialized from that.
+
+use error::{YabsError, YabsErrorKind};
+use ext::json_string;
+
+use serde_json;
+use toml::Value;
+
+use std::path::Path;
+
+// Whether the buildfile at `path` is written in JSON, by its extension
+pub fn is_json(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "json")
+}
+
+// The buildfile `json` as TOML. Syntax errors carry their line and column,
+// null stands for a key that isn't set.
+pub fn parse_json(json: &str) -> Result<Value, YabsError> {
+    to_toml(&serde_json::from_str(json)?, "")
+}
+
+fn to_toml(json: &serde_json::Value, key: &str) -> Result<Value, YabsError> {
+    Ok(match *json {
+        serde_json::Value::Bool(value) => Value::Boolean(value),
+        serde_json::Value::I64(value) => Value::Integer(value),
+        serde_json::Value::U64(value) if value <= i64::MAX as u64 => Value::Integer(value as i64),
+        serde_json::Value::U64(_) => {
+            bail!(YabsErrorKind::InvalidValue(key.to_owned(), "integer is too large".to_owned()))
+        },
+        serde_json::Value::F64(value) => Value::Float(value),
+        serde_json::Value::String(ref value) => Value::String(value.clone()),
+        serde_json::Value::Array(ref values) => {
+            Value::Array(values.iter()
+                               .filter(|value| !value.is_null())
+                               .map(|value| to_toml(value, key))
+                               .collect::<Result<_, _>>()?)
+        },
+        serde_json::Value::Object(ref table) => {
+            let mut converted = ::toml::value::Table::new();
+            for (key, value) in table.iter().filter(|&(_, value)| !value.is_null()) {
+                converted.insert(key.clone(), to_toml(value, key)?);
+            }
+            Value::Table(converted)
+        },
+        serde_json::Value::Null => {
+            bail!(YabsErrorKind::InvalidValue(key.to_owned(), "null isn't allowed here".to_owned()))
+        },
+    })
+}
+
+// `value` as indented JSON, dates become strings. JSON has no NaN or infinity,
+// so those are written as null and read back as unset.
+pub fn to_json(value: &Value, indent: usize) -> String {
+    let inner = "  ".repeat(indent + 1);
+    match *value {
+        Value::String(ref value) => json_string(value),
+        Value::Integer(value) => value.to_string(),
+        // Debug keeps the fraction of whole floats, 1.0 rather than 1
+        Value::Float(value) if value.is_finite() => format!("{:?}", value),
+        Value::Float(_) => String::from("null"),
+        Value::Boolean(value) => value.to_string(),
+        Value::Datetime(ref value) => json_string(&value.to_string()),
+        Value::Array(ref values) if values.is_empty() => String::from("[]"),
+        Value::Array(ref values) => {
+            let values: Vec<String> = values.iter()
+                                            .map(|value| format!("{}{}", inner, to_json(value, indent + 1)))
+                                            .collect();
+            format!("[\n{}\n{}]", values.join(",\n"), "  ".repeat(indent))
+        },
+        Value::Table(ref table) if table.is_empty() => String::from("{}"),
+        Value::Table(ref table) => {
+            let entries: Vec<String> = table.iter()
+                                            .map(|(key, value)| {
+                                                     format!("{}{}: {}", inner, json_string(key), to_json(value, indent + 1))
+                                                 })
+                                            .collect();
+            format!("{{\n{}\n{}}}", entries.join(",\n"), "  ".repeat(indent))
+        },
+    }
+}
+
+#[test]
+fn test_json_buildfile() {
+    let value = parse_json("{\"project\": {\"name\": \"app\", \"jobs\": 4, \"version\": null, \
+                            \"file-extensions\": [\"c\"]}}")
+        .unwrap();
+    assert_eq!(value, "[project]\nname = \"app\"\njobs = 4\nfile-extensions = [\"c\"]\n".parse::<Value>().unwrap());
+    assert_eq!(to_json(&value, 0),
+               "{\n  \"project\": {\n    \"file-extensions\": [\n      \"c\"\n    ],\n    \"jobs\": 4,\n    \
+                \"name\": \"app\"\n  }\n}");
+    assert_eq!(parse_json(&to_json(&value, 0)).unwrap(), value);
+    // Floats stay floats
+    let floats = "[project]\nratio = 1.0\nsmall = 1e-7\n".parse::<Value>().unwrap();
+    assert_eq!(to_json(&floats, 0), "{\n  \"project\": {\n    \"ratio\": 1.0,\n    \"small\": 1e-7\n  }\n}");
+    assert_eq!(parse_json(&to_json(&floats, 0)).unwrap(), floats);
+    assert_eq!(to_json(&Value::Float(f64::NAN), 0), "null");
+    assert_eq!(to_json(&Value::Float(f64::NEG_INFINITY), 0), "null");
+    let error = parse_json("{\n  \"project\": {\n    \"name\": \"app\",\n  }\n}").unwrap_err();
+    assert!(error.to_string().contains("at line 4 column 3"), "{}", error);
+}
@@ -0,0 +1,124 @@
+// Copyright (c) 2015 - 2016, Alberto Corona <ac@albertocorona.com>
+// All rights reserved. This file is part of yabs, distributed under the BSD
+// 3-Clause license. For full terms please see the LICENSE file.
+
+// Finds the keys of a buildfile its tables don't know about, which serde
+// drops without a word, so a typo such as `complier` doesn't go unnoticed.
+
+use ext::closest_match;
+
+use serde::de::{self, Deserialize, Deserializer, Visitor};
+use toml::Value;
+
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownKey {
+    // Of the table the key is in, such as "project" or "bin", empty at the top
+    // level
+    pub table: String,
+    pub key: String,
+    // Whether the key holds a table or an array of tables
+    pub is_table: bool,
+    // The known key it's most likely a typo of
+    pub suggestion: Option<String>,
+}
+
+impl fmt::Display for UnknownKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_table {
+            let path = if self.table.is_empty() {
+                self.key.clone()
+            } else {
+                format!("{}.{}", self.table, self.key)
+            };
+            write!(f, "unknown table [{}]", path)?;
+        } else if self.table.is_empty() {
+            write!(f, "unknown key `{}` at the top level", self.key)?;
+        } else {
+            write!(f, "unknown key `{}` in [{}]", self.key, self.table)?;
+        }
+        if let Some(ref suggestion) = self.suggestion {
+            write!(f, "; did you mean `{}`?", suggestion)?;
+        }
+        Ok(())
+    }
+}
+
+// The keys `T` reads, told by its derived `Deserialize` to the deserializer
+// as the fields of a struct. Empty for anything that isn't a struct.
+pub fn field_names<T>() -> &'static [&'static str]
+    where T: for<'de> Deserialize<'de>
+{
+    let mut names = FieldNames(&[]);
+    let _ = T::deserialize(&mut names);
+    names.0
+}
+
+// The keys of `value` that aren't in `fields`, `table` being where it is in
+// the buildfile. Keys in the array of tables `value` may be are found in each
+// of them, and reported once.
+pub fn unknown_keys(value: &Value, table: &str, fields: &[&str]) -> Vec<UnknownKey> {
+    let mut unknown: Vec<UnknownKey> = Vec::new();
+    let tables = match *value {
+        Value::Array(ref entries) => entries.iter().filter_map(Value::as_table).collect(),
+        Value::Table(ref table) => vec![table],
+        _ => Vec::new(),
+    };
+    for (key, value) in tables.into_iter().flat_map(|table| table.iter()) {
+        if fields.contains(&key.as_str()) {
+            continue;
+        }
+        let found = UnknownKey {
+            table: table.to_owned(),
+            key: key.clone(),
+            is_table: value.is_table() || value.as_array().is_some_and(|entries| entries.iter().any(Value::is_table)),
+            suggestion: closest_match(key, fields.iter().cloned()).map(str::to_owned),
+        };
+        if !unknown.contains(&found) {
+            unknown.push(found);
+        }
+    }
+    unknown
+}
+
+struct FieldNames(&'static [&'static str]);
+
+impl<'de> Deserializer<'de> for &mut FieldNames {
+    type Error = de::value::Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, _: V) -> Result<V::Value, Self::Error> {
+        Err(de::Error::custom("not a struct"))
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(self,
+                                          _: &'static str,
+                                          fields: &'static [&'static str],
+                                          _: V)
+                                          -> Result<V::Value, Self::Error> {
+        self.0 = fields;
+        Err(de::Error::custom("only the fields are wanted"))
+    }
+
+    ::serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf option unit
+        unit_struct newtype_struct seq tuple tuple_struct map enum identifier ignored_any
+    }
+}
+
+#[test]
+fn test_unknown_keys() {
+    use desc::project::{Binary, ProjectDesc};
+    assert!(field_names::<ProjectDesc>().contains(&"compiler"));
+    assert!(field_names::<String>().is_empty());
+    let buildfile: Value = "[project]\ncomplier = \"clang\"\ninclude = [\"src\"]\n[project.extra]\nflag = 1\n\
+                            [[bin]]\nname = \"a\"\npth = \"a.c\"\n[[bin]]\nname = \"b\"\npth = \"b.c\"\n"
+                               .parse()
+                               .unwrap();
+    let unknown = unknown_keys(&buildfile["project"], "project", field_names::<ProjectDesc>());
+    assert_eq!(unknown.iter().map(|key| key.to_string()).collect::<Vec<_>>(),
+               vec!["unknown key `complier` in [project]; did you mean `compiler`?", "unknown table [project.extra]"]);
+    let unknown = unknown_keys(&buildfile["bin"], "bin", field_names::<Binary>());
+    assert_eq!(unknown.iter().map(|key| key.to_string()).collect::<Vec<_>>(),
+               vec!["unknown key `pth` in [bin]; did you mean `path`?"]);
+}
@@ -1,4 +1,12 @@
 extern crate serde;
 extern crate serde_json;
 
+pub mod export;
+pub mod inherit;
+pub mod interpolate;
+pub mod json;
+pub mod keys;
+pub mod modules;
 pub mod project;
+pub mod task;
+pub mod tools;
@@ -0,0 +1,201 @@
+extern crate regex;
+
+use desc::project::{Language, Target};
+use error::{YabsError, YabsErrorKind};
+
+use regex::Regex;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+// What a C++ source declares about modules
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ModuleUnit {
+    // Module or partition the source is the interface of, such as "app" or
+    // "app:util"
+    pub exports: Option<String>,
+    // Named modules and partitions imported, partitions are qualified with
+    // their module
+    pub imports: Vec<String>,
+}
+
+impl ModuleUnit {
+    // Scans for `export module` and `import` declarations. Header units such
+    // as `import <vector>;` aren't supported and are skipped.
+    pub fn scan(contents: &str) -> Result<ModuleUnit, YabsError> {
+        let module_decl = Regex::new(r"^\s*(export\s+)?module\s+([\w.]+(?::[\w.]+)?)\s*;")?;
+        let import_decl = Regex::new(r"^\s*(?:export\s+)?import\s+([\w.]*(?::[\w.]+)?)\s*;")?;
+        let mut unit = ModuleUnit::default();
+        let mut module = None;
+        for line in contents.lines() {
+            if let Some(captures) = module_decl.captures(line) {
+                let name = captures[2].to_owned();
+                if captures.get(1).is_some() {
+                    unit.exports = Some(name.clone());
+                } else if !name.contains(':') {
+                    // Implementation units implicitly import their interface
+                    unit.imports.push(name.clone());
+                }
+                module = Some(name.split(':').next().unwrap().to_owned());
+            } else if let Some(captures) = import_decl.captures(line) {
+                let name = &captures[1];
+                let name = match (name.strip_prefix(':'), module.as_ref()) {
+                    (Some(partition), Some(module)) => format!("{}:{}", module, partition),
+                    _ => name.to_owned(),
+                };
+                if !unit.imports.contains(&name) {
+                    unit.imports.push(name);
+                }
+            }
+        }
+        Ok(unit)
+    }
+}
+
+// How the C++ sources of a project depend on each other through modules
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ModuleGraph {
+    units: BTreeMap<Target, ModuleUnit>,
+    interfaces: BTreeMap<String, Target>,
+}
+
+impl ModuleGraph {
+    // Scans every C++ source among `targets`, their paths are relative to
+    // `dir`
+    pub fn scan<'a, I: Iterator<Item = &'a Target>>(dir: &Path, targets: I) -> Result<ModuleGraph, YabsError> {
+        let mut units = Vec::new();
+        for target in targets.filter(|target| Language::of(&target.source()) == Language::Cxx) {
+            let mut contents = String::new();
+            File::open(dir.join(target.source()))?.read_to_string(&mut contents)?;
+            units.push((target.clone(), ModuleUnit::scan(&contents)?));
+        }
+        ModuleGraph::from_units(units)
+    }
+
+    pub fn from_units(units: Vec<(Target, ModuleUnit)>) -> Result<ModuleGraph, YabsError> {
+        let mut graph = ModuleGraph::default();
+        for (target, unit) in units {
+            if let Some(ref name) = unit.exports {
+                graph.interfaces.insert(name.clone(), target.clone());
+            }
+            graph.units.insert(target, unit);
+        }
+        for (target, unit) in &graph.units {
+            for import in &unit.imports {
+                if !graph.interfaces.contains_key(import) {
+                    bail!(YabsErrorKind::ModuleNotFound(import.clone(), target.source().display().to_string()));
+                }
+            }
+        }
+        Ok(graph)
+    }
+
+    // Compiled module interface of `target`, next to its object and named
+    // after the module so `-fprebuilt-module-path` finds it
+    pub fn bmi_path(&self, target: &Target) -> Option<PathBuf> {
+        self.units.get(target).and_then(|unit| unit.exports.as_ref()).map(|name| {
+            target.object().with_file_name(format!("{}.pcm", name.replace(':', "-")))
+        })
+    }
+
+    // Directories holding every compiled module interface
+    pub fn bmi_dirs(&self) -> Vec<PathBuf> {
+        let dirs: BTreeSet<PathBuf> = self.interfaces
+                                          .values()
+                                          .filter_map(|target| self.bmi_path(target))
+                                          .map(|bmi| bmi.parent().map(|dir| dir.to_path_buf()).unwrap_or_default())
+                                          .collect();
+        dirs.into_iter().collect()
+    }
+
+    // Interfaces `target` imports directly
+    pub fn imports(&self, target: &Target) -> Vec<Target> {
+        self.units
+            .get(target)
+            .map(|unit| unit.imports.iter().filter_map(|name| self.interfaces.get(name)).cloned().collect())
+            .unwrap_or_default()
+    }
+
+    // Splits `targets` into groups that can compile in parallel, each only
+    // importing interfaces from earlier groups or compiled before
+    pub fn levels(&self, targets: Vec<Target>) -> Result<Vec<Vec<Target>>, YabsError> {
+        let mut depths: BTreeMap<Target, usize> = BTreeMap::new();
+        for target in &targets {
+            self.depth(target, &mut depths, &mut Vec::new())?;
+        }
+        let mut levels: Vec<Vec<Target>> = Vec::new();
+        for target in targets {
+            let depth = depths[&target];
+            while levels.len() <= depth {
+                levels.push(Vec::new());
+            }
+            levels[depth].push(target);
+        }
+        Ok(levels.into_iter().filter(|level| !level.is_empty()).collect())
+    }
+
+    fn depth(&self,
+             target: &Target,
+             depths: &mut BTreeMap<Target, usize>,
+             path: &mut Vec<Target>)
+             -> Result<usize, YabsError> {
+        if let Some(depth) = depths.get(target) {
+            return Ok(*depth);
+        }
+        if path.contains(target) {
+            path.push(target.clone());
+            let names: Vec<String> = path.iter().map(|target| target.source().display().to_string()).collect();
+            bail!(YabsErrorKind::ModuleCycle(names.join(" -> ")));
+        }
+        path.push(target.clone());
+        let mut depth = 0;
+        for import in self.imports(target) {
+            depth = depth.max(self.depth(&import, depths, path)? + 1);
+        }
+        path.pop();
+        depths.insert(target.clone(), depth);
+        Ok(depth)
+    }
+}
+
+#[cfg(test)]
+fn unit(name: &str, exports: Option<&str>, imports: &[&str]) -> (Target, ModuleUnit) {
+    (Target::new(PathBuf::from(format!("{}.cppm", name)), PathBuf::from(format!("{}.o", name))),
+     ModuleUnit {
+         exports: exports.map(|name| name.to_owned()),
+         imports: imports.iter().map(|name| name.to_string()).collect(),
+     })
+}
+
+#[test]
+fn test_scan_module_unit() {
+    let unit = ModuleUnit::scan("module;\n#include <cstdio>\nexport module app:util;\n\
+                                 import base;\nexport import :io;\nimport <vector>;\n")
+        .unwrap();
+    assert_eq!(unit.exports, Some(String::from("app:util")));
+    assert_eq!(unit.imports, vec!["base", "app:io"]);
+    let unit = ModuleUnit::scan("module app;\nimport :util;\nint main() {}\n").unwrap();
+    assert_eq!(unit.exports, None);
+    assert_eq!(unit.imports, vec!["app", "app:util"]);
+}
+
+#[test]
+fn test_module_levels() {
+    let graph = ModuleGraph::from_units(vec![unit("main", None, &["app"]),
+                                             unit("app", Some("app"), &["base"]),
+                                             unit("base", Some("base"), &[]),
+                                             unit("other", None, &[])])
+        .unwrap();
+    let targets: Vec<Target> = graph.units.keys().cloned().collect();
+    let levels = graph.levels(targets).unwrap();
+    let names: Vec<Vec<String>> = levels.iter()
+                                        .map(|level| level.iter().map(|t| t.source().display().to_string()).collect())
+                                        .collect();
+    assert_eq!(names, vec![vec!["base.cppm", "other.cppm"], vec!["app.cppm"], vec!["main.cppm"]]);
+    assert_eq!(graph.bmi_path(&unit("app", None, &[]).0), Some(PathBuf::from("app.pcm")));
+    assert!(ModuleGraph::from_units(vec![unit("main", None, &["missing"])]).is_err());
+    let cycle = ModuleGraph::from_units(vec![unit("a", Some("a"), &["b"]), unit("b", Some("b"), &["a"])]).unwrap();
+    let targets: Vec<Target> = cycle.units.keys().cloned().collect();
+    assert!(cycle.levels(targets).is_err());
+}
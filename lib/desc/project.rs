@@ -3,14 +3,24 @@ extern crate toml;
 extern crate walkdir;
 extern crate regex;
 
-use error::YabsError;
+use budget::DEFAULT_SIZE_WARNING;
+use cache::DEFAULT_CACHE_MAX_SIZE;
+use context::{BuildContext, Platform};
+use error::{YabsError, YabsErrorKind};
+use desc::export::{self, Dependency, Export};
+use desc::task::Script;
+use desc::tools::ToolRequirement;
+use space::DEFAULT_DISK_SPACE_FACTOR;
+use state::{DEFAULT_STATE_DIR, SourceRecord};
 use ext::*;
 use regex::Regex;
 use std::collections::{BTreeMap, BTreeSet};
 
+use std::fmt;
 use std::fs::metadata;
-use std::path::PathBuf;
-use std::time::SystemTime;
+use std::path::{Component, Path, PathBuf};
+use std::process::Command;
+use std::time::{Duration, SystemTime};
 use walkdir::{DirEntry, WalkDir, WalkDirIterator};
 
 #[derive(Debug, Default, Deserialize, Serialize, Clone, PartialEq)]
@@ -19,6 +29,37 @@ pub struct Binary {
     name: String,
     // Path to entry point
     path: PathBuf,
+    // Laid over the project's settings for the binary's own objects and link
+    compiler: Option<String>,
+    #[serde(rename = "compiler-flags")]
+    compiler_flags: Option<Vec<String>>,
+    #[serde(rename = "linker-flags")]
+    lflags: Option<Vec<String>>,
+    #[serde(rename = "libraries")]
+    libs: Option<Vec<LibEntry>>,
+    #[serde(rename = "library-directories")]
+    lib_dir: Option<Vec<String>>,
+    include: Option<Vec<String>>,
+    defines: Option<Vec<String>>,
+    rpath: Option<Vec<String>>,
+    // Directory the binary is written to instead of the project's 'bin-dir'
+    #[serde(rename = "bin-dir")]
+    bin_dir: Option<PathBuf>,
+    // File name of the binary when it isn't named after the target
+    output: Option<String>,
+    // Whether a plain build builds the binary, otherwise it's only built
+    // when it's named
+    default: Option<bool>,
+    // Replaces the project's 'strip' for the binary
+    strip: Option<bool>,
+    #[serde(rename = "split-debug-info")]
+    split_debug_info: Option<bool>,
+    // Number of partial links to split the objects into before the final link
+    #[serde(rename = "incremental-link-groups")]
+    incremental_link_groups: Option<usize>,
+    // Largest the linked binary may be, such as "512KiB"
+    #[serde(rename = "max-size")]
+    max_size: Option<String>,
 }
 
 impl Binary {
@@ -29,12 +70,59 @@ impl Binary {
     pub fn path(&self) -> PathBuf {
         self.path.clone()
     }
+
+    pub fn incremental_link_groups(&self) -> usize {
+        self.incremental_link_groups.unwrap_or(0)
+    }
+
+    pub fn bin_dir(&self) -> Option<PathBuf> {
+        self.bin_dir.clone()
+    }
+
+    // File name of the built binary, 'output' or else its name
+    pub fn output_name(&self) -> String {
+        self.output.clone().unwrap_or_else(|| self.name())
+    }
+
+    pub fn is_default(&self) -> bool {
+        self.default.unwrap_or(true)
+    }
+
+    pub fn max_size(&self) -> Option<String> {
+        self.max_size.clone()
+    }
+
+    pub fn overrides(&self) -> Overrides {
+        Overrides {
+            compiler: self.compiler.clone(),
+            ar: None,
+            compiler_flags: self.compiler_flags.clone(),
+            lflags: self.lflags.clone(),
+            libs: self.libs.clone(),
+            lib_dir: self.lib_dir.clone(),
+            include: self.include.clone(),
+            defines: self.defines.clone(),
+            rpath: self.rpath.clone(),
+            visibility: None,
+            strip: self.strip,
+            strip_tool: None,
+            split_debug_info: self.split_debug_info,
+            objcopy: None,
+            ranlib: None,
+        }
+    }
 }
 
 #[derive(Debug, Default, Clone, PartialEq, PartialOrd, Eq, Ord)]
 pub struct Target {
     source: PathBuf,
     object: PathBuf,
+    // The binary or library the object is compiled for when it compiles its
+    // objects with settings of its own, None for objects shared by the rest
+    owner: Option<String>,
+    // Whether the object is compiled with `-fPIC` for dynamic libraries, next
+    // to the one the rest link
+    pic: bool,
 }
 
 impl Target {
@@ -42,6 +130,8 @@ impl Target {
         Target {
             source: src,
             object: obj,
+            owner: None,
+            pic: false,
         }
     }
 
@@ -52,6 +142,46 @@ impl Target {
     pub fn object(&self) -> PathBuf {
         self.object.clone()
     }
+
+    pub fn owner(&self) -> Option<&str> {
+        self.owner.as_deref()
+    }
+
+    pub fn is_pic(&self) -> bool {
+        self.pic
+    }
+
+    // The same target with its object moved under `dir`
+    pub fn with_object_dir(&self, dir: &Path) -> Target {
+        let relative: PathBuf = self.object
+                                    .components()
+                                    .filter(|component| matches!(component, Component::Normal(_)))
+                                    .collect();
+        Target {
+            source: self.source(),
+            object: dir.join(relative),
+            owner: self.owner.clone(),
+            pic: self.pic,
+        }
+    }
+
+    // The position independent object of the same source, `main.o` becomes
+    // `main.pic.o`
+    pub fn position_independent(&self) -> Target {
+        Target {
+            object: self.object.with_extension("pic.o"),
+            pic: true,
+            ..self.clone()
+        }
+    }
+
+    // The object compiled for `owner` alone, under `dir`
+    pub fn owned_by(&self, owner: &str, dir: &Path) -> Target {
+        Target {
+            owner: Some(owner.to_owned()),
+            ..self.with_object_dir(dir)
+        }
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Ord, Eq, PartialOrd)]
@@ -62,14 +192,226 @@ enum LibType {
     Dynamic,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Language {
+    C,
+    Cxx,
+    // `.S` is run through the C preprocessor by the compiler driver, `.s`
+    // goes straight to the assembler
+    Asm,
+    Other,
+}
+
+impl Language {
+    pub fn of(path: &Path) -> Language {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("c") => Language::C,
+            Some("cpp") | Some("cc") | Some("cxx") | Some("c++") | Some("C") | Some("cppm") => {
+                Language::Cxx
+            },
+            Some("s") | Some("S") => Language::Asm,
+            _ => Language::Other,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Language::C => "C",
+            Language::Cxx => "C++",
+            Language::Asm => "assembly",
+            Language::Other => "other",
+        }
+    }
+}
+
+// Warning presets, applied before 'compiler-flags' so they can be fine tuned
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+pub enum Warnings {
+    #[serde(rename = "none")]
+    None,
+    #[serde(rename = "default")]
+    Default,
+    #[serde(rename = "all")]
+    All,
+    #[serde(rename = "strict")]
+    Strict,
+}
+
+impl Warnings {
+    pub fn flags(&self, msvc: bool) -> Vec<&'static str> {
+        match (*self, msvc) {
+            (Warnings::None, false) => vec!["-w"],
+            (Warnings::None, true) => vec!["/W0"],
+            (Warnings::Default, _) => vec![],
+            (Warnings::All, false) => vec!["-Wall", "-Wextra"],
+            (Warnings::All, true) => vec!["/W4"],
+            (Warnings::Strict, false) => vec!["-Wall", "-Wextra", "-Wpedantic", "-Wshadow"],
+            (Warnings::Strict, true) => vec!["/W4", "/permissive-"],
+        }
+    }
+}
+
+// `lto = true` or `lto = "thin"`, thin LTO is only understood by clang
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum Lto {
+    Enabled(bool),
+    Mode(String),
+}
+
+// Runs `pkg-config <query> <package>` with the variables of `context`, the
+// package may carry a version constraint like "zlib >= 1.2"
+fn pkg_config_flags(package: &str, query: &str, context: &BuildContext) -> Result<Vec<String>, YabsError> {
+    let output = Command::new("pkg-config").arg(query)
+                                           .arg(package)
+                                           .env_clear()
+                                           .envs(context.vars())
+                                           .current_dir(&context.cwd)
+                                           .output();
+    let output = match output {
+        Ok(output) => output,
+        Err(error) => bail!(YabsErrorKind::PkgConfig(package.to_owned(), error.to_string())),
+    };
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let reason = stderr.trim();
+        bail!(YabsErrorKind::PkgConfig(package.to_owned(),
+                                       if reason.is_empty() {
+                                           format!("exited with {}", output.status)
+                                       } else {
+                                           reason.to_owned()
+                                       }));
+    }
+    shell_split(&String::from_utf8(output.stdout)?)
+}
+
+// The value of `flag` when it's the option `name`, given in the same word or
+// the next
+fn option_value<I: Iterator<Item = String>>(name: &str, flag: &str, rest: &mut I) -> Option<String> {
+    match flag.strip_prefix(name) {
+        Some("") => rest.next(),
+        Some(value) => Some(value.to_owned()),
+        None => None,
+    }
+}
+
+pub const SANITIZERS: &[&str] = &["address", "hwaddress", "leak", "memory", "thread", "undefined"];
+
+pub const VISIBILITIES: &[&str] = &["default", "hidden", "protected", "internal"];
+
+// Fails for a 'visibility' `-fvisibility` doesn't take
+pub fn validate_visibility(visibility: &Option<String>) -> Result<(), YabsError> {
+    match *visibility {
+        Some(ref visibility) if !VISIBILITIES.contains(&visibility.as_str()) => {
+            bail!(YabsErrorKind::InvalidValue("visibility".to_owned(),
+                                              format!("expected one of {}, found \"{}\"",
+                                                      VISIBILITIES.join(", "),
+                                                      visibility)))
+        },
+        _ => Ok(()),
+    }
+}
+
+// How stale objects are handed to the compiler
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+pub enum Pipeline {
+    // One compiler process per source
+    #[serde(rename = "per-file")]
+    PerFile,
+    // One compiler process per batch of sources sharing a directory and flags
+    #[serde(rename = "batched")]
+    Batched,
+}
+
+// How stale objects are found. `legacy` compares sources against the
+// target's binary and never against libraries, `v2` compares each source
+// against its own object and also rebuilds when the compile flags change.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+pub enum Staleness {
+    #[serde(rename = "legacy")]
+    Legacy,
+    #[serde(rename = "v2")]
+    V2,
+}
+
+impl Staleness {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Staleness::Legacy => "legacy",
+            Staleness::V2 => "v2",
+        }
+    }
+}
+
+// What tells that a source changed, its modification time or its contents.
+// Hashing survives checkouts that touch every file and skewed clocks.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeDetection {
+    #[serde(rename = "mtime")]
+    Mtime,
+    #[serde(rename = "hash")]
+    Hash,
+}
+
+// Order in which stale objects are compiled
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+pub enum Schedule {
+    // Most recently modified sources first
+    #[serde(rename = "recent-first")]
+    RecentFirst,
+    // Strictly by source path
+    #[serde(rename = "ordered")]
+    Ordered,
+}
+
+impl Schedule {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Schedule::RecentFirst => "recent-first",
+            Schedule::Ordered => "ordered",
+        }
+    }
+}
+
 #[derive(Debug, Default, Deserialize, Serialize, Clone, PartialEq)]
 pub struct Library {
     name: String,
-    #[serde(skip_serializing)]
-    #[serde(skip_deserializing)]
-    path: PathBuf,
     #[serde(rename = "types")]
     lib_types: BTreeSet<LibType>,
+    // Laid over the project's settings for the library's own objects and link
+    compiler: Option<String>,
+    #[serde(rename = "compiler-flags")]
+    compiler_flags: Option<Vec<String>>,
+    #[serde(rename = "linker-flags")]
+    lflags: Option<Vec<String>>,
+    #[serde(rename = "libraries")]
+    libs: Option<Vec<LibEntry>>,
+    #[serde(rename = "library-directories")]
+    lib_dir: Option<Vec<String>>,
+    include: Option<Vec<String>>,
+    defines: Option<Vec<String>>,
+    // Directory the library's files are written to instead of the project's
+    // 'lib-out-dir'
+    #[serde(rename = "lib-out-dir")]
+    lib_out_dir: Option<PathBuf>,
+    // Name of the library's files, without their extension, when they aren't
+    // named after the target
+    output: Option<String>,
+    // Whether a plain build builds the library
+    default: Option<bool>,
+    // Replaces the project's 'strip' for the dynamic library
+    strip: Option<bool>,
+    #[serde(rename = "split-debug-info")]
+    split_debug_info: Option<bool>,
+    // Replaces the project's 'visibility' for the library's objects
+    visibility: Option<String>,
+    // Linker version script of the dynamic library, deciding which symbols it
+    // exports
+    #[serde(rename = "version-script")]
+    version_script: Option<PathBuf>,
+    // Largest each of the library's files may be
+    #[serde(rename = "max-size")]
+    max_size: Option<String>,
 }
 
 impl Library {
@@ -77,8 +419,46 @@ impl Library {
         self.name.clone()
     }
 
-    pub fn path(&self) -> PathBuf {
-        self.path.clone()
+    pub fn max_size(&self) -> Option<String> {
+        self.max_size.clone()
+    }
+
+    pub fn lib_out_dir(&self) -> Option<PathBuf> {
+        self.lib_out_dir.clone()
+    }
+
+    pub fn version_script(&self) -> Option<PathBuf> {
+        self.version_script.clone()
+    }
+
+    // What the library's files are called before their extension, 'output'
+    // or else its name
+    pub fn output_name(&self) -> String {
+        self.output.clone().unwrap_or_else(|| self.name())
+    }
+
+    pub fn is_default(&self) -> bool {
+        self.default.unwrap_or(true)
+    }
+
+    pub fn overrides(&self) -> Overrides {
+        Overrides {
+            compiler: self.compiler.clone(),
+            ar: None,
+            compiler_flags: self.compiler_flags.clone(),
+            lflags: self.lflags.clone(),
+            libs: self.libs.clone(),
+            lib_dir: self.lib_dir.clone(),
+            include: self.include.clone(),
+            defines: self.defines.clone(),
+            rpath: None,
+            visibility: self.visibility.clone(),
+            strip: self.strip,
+            strip_tool: None,
+            split_debug_info: self.split_debug_info,
+            objcopy: None,
+            ranlib: None,
+        }
     }
 
     pub fn is_static(&self) -> bool {
@@ -91,22 +471,100 @@ impl Library {
 
     #[cfg(target_os = "windows")]
     pub fn static_file_name(&self) -> PathBuf {
-        PathBuf::from(self.name() + ".lib")
+        PathBuf::from(self.output_name() + ".lib")
     }
 
     #[cfg(not(target_os = "windows"))]
     pub fn static_file_name(&self) -> PathBuf {
-        PathBuf::from(self.name() + ".a")
+        PathBuf::from(self.output_name() + ".a")
     }
 
     #[cfg(target_os = "windows")]
     pub fn dynamic_file_name(&self) -> PathBuf {
-        PathBuf::from(self.name() + ".dll")
+        PathBuf::from(self.output_name() + ".dll")
     }
 
     #[cfg(not(target_os = "windows"))]
     pub fn dynamic_file_name(&self) -> PathBuf {
-        PathBuf::from(self.name() + ".so")
+        PathBuf::from(self.output_name() + ".so")
+    }
+}
+
+// An entry of 'libraries'. A name is a library, a command in backticks
+// whose output is linked with, or a flag such as `-Wl,--as-needed` passed on
+// as it is. `{ group = [...] }` links its members between `--start-group` and
+// `--end-group` so archives depending on each other resolve, and
+// `{ name = "...", whole-archive = true }` links every member of an archive.
+// TOML arrays can't mix strings and tables, so names are written as
+// `{ name = "..." }` next to tables.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum LibEntry {
+    Name(String),
+    Group {
+        group: Vec<String>,
+    },
+    Library {
+        name: String,
+        #[serde(rename = "whole-archive", default)]
+        whole_archive: bool,
+    },
+}
+
+impl LibEntry {
+    // The libraries of the entry without how they're linked, for exports
+    pub fn names(&self) -> Vec<String> {
+        match *self {
+            LibEntry::Name(ref name) | LibEntry::Library { ref name, .. } => vec![name.clone()],
+            LibEntry::Group { ref group } => group.clone(),
+        }
+    }
+}
+
+impl fmt::Display for LibEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LibEntry::Name(ref name) => write!(f, "{}", name),
+            LibEntry::Group { ref group } => write!(f, "group({})", group.join(", ")),
+            LibEntry::Library { ref name, whole_archive: true } => write!(f, "whole-archive({})", name),
+            LibEntry::Library { ref name, .. } => write!(f, "{}", name),
+        }
+    }
+}
+
+// Settings laid over the project's, by a `[target.<os>]` section when
+// building for that OS or by a `[[bin]]` or `[[lib]]` for itself. Lists are
+// appended to the project's, other values replace its own.
+#[derive(Debug, Default, Deserialize, Serialize, Clone, PartialEq)]
+pub struct Overrides {
+    pub compiler: Option<String>,
+    pub ar: Option<String>,
+    #[serde(rename = "compiler-flags")]
+    pub compiler_flags: Option<Vec<String>>,
+    #[serde(rename = "linker-flags")]
+    pub lflags: Option<Vec<String>>,
+    #[serde(rename = "libraries")]
+    pub libs: Option<Vec<LibEntry>>,
+    #[serde(rename = "library-directories")]
+    pub lib_dir: Option<Vec<String>>,
+    pub include: Option<Vec<String>>,
+    pub defines: Option<Vec<String>>,
+    pub rpath: Option<Vec<String>>,
+    pub visibility: Option<String>,
+    pub strip: Option<bool>,
+    #[serde(rename = "strip-tool")]
+    pub strip_tool: Option<String>,
+    #[serde(rename = "split-debug-info")]
+    pub split_debug_info: Option<bool>,
+    pub objcopy: Option<String>,
+    pub ranlib: Option<String>,
+}
+
+impl Overrides {
+    // Whether objects compiled with these differ from the project's
+    pub fn changes_compiles(&self) -> bool {
+        self.compiler.is_some() || self.compiler_flags.is_some() || self.include.is_some() ||
+        self.defines.is_some() || self.visibility.is_some()
     }
 }
 
@@ -117,25 +575,178 @@ pub struct ProjectDesc {
     pub file_exts: Vec<String>,
     pub version: Option<String>,
     pub compiler: Option<String>,
+    #[serde(rename = "compiler-c")]
+    pub compiler_c: Option<String>,
+    // Prefixed to compile commands only, such as "ccache" or "sccache --foo"
+    #[serde(rename = "compiler-launcher")]
+    pub compiler_launcher: Option<String>,
+    #[serde(rename = "compiler-cxx")]
+    pub compiler_cxx: Option<String>,
+    // Target triple to cross compile for, such as "aarch64-linux-gnu"
+    pub target: Option<String>,
+    pub sysroot: Option<String>,
+    // Whether CC, CXX and AR are used when 'compiler' or 'ar' aren't set
+    #[serde(rename = "respect-env")]
+    pub respect_env: Option<bool>,
+    // Whether CFLAGS, CXXFLAGS and LDFLAGS are appended to yabs' own flags
+    #[serde(rename = "use-env-flags")]
+    pub use_env_flags: Option<bool>,
     pub src: Option<Vec<PathBuf>>,
     #[serde(rename = "libraries")]
-    pub libs: Option<Vec<String>>,
+    pub libs: Option<Vec<LibEntry>>,
     #[serde(rename = "library-directories")]
     pub lib_dir: Option<Vec<String>>,
     pub include: Option<Vec<String>>,
+    pub defines: Option<Vec<String>>,
+    pub warnings: Option<Warnings>,
+    pub sanitize: Option<Vec<String>>,
+    pub coverage: Option<bool>,
+    pub lto: Option<Lto>,
+    // Packages exported by other yabs projects, see `yabs export`
+    pub deps: Option<Vec<Dependency>>,
+    #[serde(rename = "dependency-prefixes")]
+    pub dep_prefixes: Option<Vec<String>>,
+    // Packages such as "zlib >= 1.2" whose flags are asked from pkg-config
+    #[serde(rename = "pkg-config")]
+    pub pkg_config: Option<Vec<String>>,
+    // Tools the scripts and tasks run, checked before anything is built
+    pub requires: Option<Vec<ToolRequirement>>,
+    #[serde(rename = "coverage-tool")]
+    pub coverage_tool: Option<String>,
+    #[serde(rename = "warnings-as-errors")]
+    pub warnings_as_errors: Option<bool>,
+    // Language standard, `std-c` and `std-cxx` take precedence for their
+    // language
+    pub std: Option<String>,
+    #[serde(rename = "std-c")]
+    pub std_c: Option<String>,
+    #[serde(rename = "std-cxx")]
+    pub std_cxx: Option<String>,
     #[serde(rename = "compiler-flags")]
     pub compiler_flags: Option<Vec<String>>,
+    // Like 'compiler-flags' but only for C or C++ sources
+    pub cflags: Option<Vec<String>>,
+    pub cxxflags: Option<Vec<String>>,
     #[serde(rename = "explicit-cflags")]
     pub explicit_cflags: Option<String>,
     #[serde(rename = "linker-flags")]
     pub lflags: Option<Vec<String>>,
+    // Default visibility of symbols, "hidden" to export only those marked
+    // with `__attribute__((visibility("default")))`
+    pub visibility: Option<String>,
+    // Where binaries look for shared libraries at run time, `$ORIGIN` being
+    // the binary's own directory
+    pub rpath: Option<Vec<String>>,
+    // Adds the directory of the project's libraries, as seen from that of
+    // each binary, to 'rpath'
+    #[serde(rename = "rpath-origin")]
+    pub rpath_origin: Option<bool>,
     pub ignore: Option<Vec<String>>,
     #[serde(rename = "before-script")]
-    pub before_script: Option<Vec<String>>,
+    pub before_script: Option<Vec<Script>>,
     #[serde(rename = "after-script")]
-    pub after_script: Option<Vec<String>>,
+    pub after_script: Option<Vec<Script>>,
     pub ar: Option<String>,
     pub arflags: Option<String>,
+    // Archives holding the paths of their objects rather than copies of them
+    #[serde(rename = "thin-archive")]
+    pub thin_archive: Option<bool>,
+    // Run on archives once they're written, see `ProjectDesc::ranlib`
+    pub ranlib: Option<String>,
+    // Replace the changed members of an existing archive instead of writing
+    // it again
+    #[serde(rename = "incremental-archive")]
+    pub incremental_archive: Option<bool>,
+    // Strips binaries and dynamic libraries once they're linked, with
+    // 'strip-tool' or else `strip`
+    pub strip: Option<bool>,
+    #[serde(rename = "strip-tool")]
+    pub strip_tool: Option<String>,
+    // Moves the debug info of binaries and dynamic libraries into a `.debug`
+    // file next to them, or in 'debug-dir', with `objcopy`
+    #[serde(rename = "split-debug-info")]
+    pub split_debug_info: Option<bool>,
+    #[serde(rename = "debug-dir")]
+    pub debug_dir: Option<PathBuf>,
+    pub objcopy: Option<String>,
+    #[serde(rename = "keep-response-files")]
+    pub keep_response_files: Option<bool>,
+    // Reuse objects compiled from identical preprocessed sources and flags
+    #[serde(rename = "object-cache")]
+    pub object_cache: Option<bool>,
+    // Experimental C++20 modules, interfaces are compiled before the sources
+    // importing them
+    #[serde(rename = "cxx-modules")]
+    pub cxx_modules: Option<bool>,
+    // Megabytes the object cache may take before the least recently used
+    // objects are evicted
+    #[serde(rename = "cache-max-size")]
+    pub cache_max_size: Option<u64>,
+    #[serde(rename = "link-warning-suppress")]
+    pub link_warning_suppress: Option<Vec<String>>,
+    #[serde(rename = "link-warnings-as-errors")]
+    pub link_warnings_as_errors: Option<bool>,
+    pub pipeline: Option<Pipeline>,
+    #[serde(rename = "batch-size")]
+    pub batch_size: Option<usize>,
+    pub schedule: Option<Schedule>,
+    pub staleness: Option<Staleness>,
+    // Also decide staleness with the other strategy and report where it
+    // disagrees
+    #[serde(rename = "compare-staleness")]
+    pub compare_staleness: Option<bool>,
+    // Files every object depends on that aren't found through the include
+    // directories, such as a configuration header included by a relative path
+    #[serde(rename = "extra-deps")]
+    pub extra_deps: Option<Vec<String>>,
+    // Directory holding the state kept between builds, for out-of-tree
+    // builds. Defaults to '.yabs'.
+    #[serde(rename = "state-dir")]
+    pub state_dir: Option<PathBuf>,
+    // Directories binaries and libraries are written to, relative to the
+    // buildfile's directory. Default to it.
+    #[serde(rename = "bin-dir")]
+    pub bin_dir: Option<PathBuf>,
+    #[serde(rename = "lib-out-dir")]
+    pub lib_out_dir: Option<PathBuf>,
+    // Record where each header was found and rebuild objects whose headers
+    // would now be found elsewhere in the include path
+    #[serde(rename = "include-resolution-tracking")]
+    pub include_resolution_tracking: Option<bool>,
+    #[serde(rename = "change-detection")]
+    pub change_detection: Option<ChangeDetection>,
+    // Bytes of objects and temporary files expected per byte of stale source
+    // when checking for free disk space
+    #[serde(rename = "disk-space-factor")]
+    pub disk_space_factor: Option<u64>,
+    // Size budget of every binary and library without a 'max-size' of its own
+    #[serde(rename = "max-size")]
+    pub max_size: Option<String>,
+    // Percentage of its budget an artifact may reach before a warning
+    #[serde(rename = "max-size-warning")]
+    pub max_size_warning: Option<u64>,
+    #[serde(rename = "fast-fail-window")]
+    pub fast_fail_window: Option<usize>,
+    // Jobs to run when the caller passes 0, such as when `yabs build` isn't
+    // given `--jobs`
+    pub jobs: Option<usize>,
+    // Seconds a compile, a link or a before or after script may run before
+    // it's killed and the build fails
+    #[serde(rename = "job-timeout-secs")]
+    pub job_timeout_secs: Option<u64>,
+    #[serde(rename = "link-timeout-secs")]
+    pub link_timeout_secs: Option<u64>,
+    #[serde(rename = "script-timeout-secs")]
+    pub script_timeout_secs: Option<u64>,
+    // Overrides whether compilers are asked for colored diagnostics, which
+    // they otherwise are when yabs colors its own output
+    #[serde(rename = "diagnostics-color")]
+    pub diagnostics_color: Option<bool>,
+    // Fails a build whose compiles produce more warnings than this
+    #[serde(rename = "max-warnings")]
+    pub max_warnings: Option<usize>,
+    // Unknown keys in the buildfile are errors instead of warnings
+    pub strict: Option<bool>,
 
     // Serde, please don't touch this, ty
     #[serde(skip_serializing)]
@@ -145,14 +756,112 @@ pub struct ProjectDesc {
     #[serde(skip_serializing)]
     #[serde(skip_deserializing)]
     pub file_mod_map: BTreeMap<Target, SystemTime>,
+
+    // Content hash of every source, only with `change-detection = "hash"`
+    #[serde(skip_serializing)]
+    #[serde(skip_deserializing)]
+    pub file_hashes: BTreeMap<PathBuf, String>,
+
+    // What is known about each source, loaded from the build state and
+    // updated by `find_source_files`
+    #[serde(skip_serializing)]
+    #[serde(skip_deserializing)]
+    pub source_records: BTreeMap<String, SourceRecord>,
+
+    // Whether `src` was found by walking the current directory
+    #[serde(skip_serializing)]
+    #[serde(skip_deserializing)]
+    pub sources_walked: bool,
+
+    // What pkg-config gave besides include directories, defines and
+    // libraries, passed on as they are
+    #[serde(skip_serializing)]
+    #[serde(skip_deserializing)]
+    pub pkg_config_cflags: Vec<String>,
+
+    #[serde(skip_serializing)]
+    #[serde(skip_deserializing)]
+    pub pkg_config_libs: Vec<String>,
+
+    // Where environment variables are read from
+    #[serde(skip_serializing)]
+    #[serde(skip_deserializing)]
+    pub context: BuildContext,
 }
 
 impl ProjectDesc {
+    pub fn pipeline(&self) -> Pipeline {
+        self.pipeline.unwrap_or(Pipeline::PerFile)
+    }
+
+    pub fn batch_size(&self) -> usize {
+        self.batch_size.unwrap_or(16).max(1)
+    }
+
+    pub fn respects_env(&self) -> bool {
+        self.respect_env.unwrap_or(true)
+    }
+
+    pub fn uses_env_flags(&self) -> bool {
+        self.use_env_flags.unwrap_or(true)
+    }
+
+    // In megabytes
+    pub fn cache_max_size(&self) -> u64 {
+        self.cache_max_size.unwrap_or(DEFAULT_CACHE_MAX_SIZE)
+    }
+
+    pub fn disk_space_factor(&self) -> u64 {
+        self.disk_space_factor.unwrap_or(DEFAULT_DISK_SPACE_FACTOR)
+    }
+
+    pub fn max_size_warning(&self) -> u64 {
+        self.max_size_warning.unwrap_or(DEFAULT_SIZE_WARNING)
+    }
+
+    pub fn staleness(&self) -> Staleness {
+        self.staleness.unwrap_or(Staleness::V2)
+    }
+
+    // `path` as yabs itself opens it. Paths in the buildfile and those yabs
+    // derives from them are relative to the project's directory, not to the
+    // working directory of the process.
+    pub fn resolve<P: AsRef<Path>>(&self, path: P) -> PathBuf {
+        self.context.cwd.join(path)
+    }
+
+    pub fn state_dir(&self) -> PathBuf {
+        self.resolve(self.state_dir.clone().unwrap_or_else(|| PathBuf::from(DEFAULT_STATE_DIR)))
+    }
+
+    // Relies on `-MMD`, which MSVC doesn't understand
+    pub fn tracks_include_resolution(&self) -> bool {
+        self.include_resolution_tracking.unwrap_or(false) && !self.is_msvc()
+    }
+
+    pub fn change_detection(&self) -> ChangeDetection {
+        self.change_detection.unwrap_or(ChangeDetection::Mtime)
+    }
+
+    pub fn extra_deps(&self) -> Vec<PathBuf> {
+        self.extra_deps.iter().flatten().map(PathBuf::from).collect()
+    }
+
+    // Compiles go by source path unless 'fast-fail-window' asks for the most
+    // recently modified sources first
+    pub fn schedule(&self) -> Schedule {
+        self.schedule.unwrap_or(if self.fast_fail_window.is_some() {
+                                    Schedule::RecentFirst
+                                } else {
+                                    Schedule::Ordered
+                                })
+    }
+
     // TODO: Maybe have some kind of regex here to allow '*' and friends?
-    pub fn is_in_ignore(&self, entry: &DirEntry) -> bool {
+    pub fn is_in_ignore(&self, entry: &Path) -> bool {
         if let Some(ignore) = self.ignore.as_ref() {
             for path in ignore {
-                if let Some(entry_str) = entry.path().to_str() {
+                if let Some(entry_str) = entry.to_str() {
                     if entry_str.find(path).is_some() {
                         return true;
                     }
@@ -162,44 +871,585 @@ impl ProjectDesc {
         false
     }
 
-    // We have to do this as we regard entries that start with '`' as commands
-    // and don't want to prepend them
-    pub fn libs_as_string(&self) -> String {
-        let mut lib_list: Vec<String> = Vec::new();
-        let mut cmd_list: Vec<String> = Vec::new();
-        if let Some(libs) = self.libs.as_ref() {
-            for lib in libs {
-                if self.is_command(lib) {
-                    cmd_list.push(lib.clone());
+    pub fn compiler(&self) -> String {
+        self.compiler_choice().0
+    }
+
+    // The compiler for the project's main language and where it came from
+    pub fn compiler_choice(&self) -> (String, String) {
+        self.compiler_choice_for(self.main_language())
+    }
+
+    // C++ when any of the file extensions is a C++ one
+    pub fn main_language(&self) -> Language {
+        if self.has_language(Language::Cxx) {
+            Language::Cxx
+        } else {
+            Language::C
+        }
+    }
+
+    pub fn has_language(&self, language: Language) -> bool {
+        self.file_exts
+            .iter()
+            .any(|ext| Language::of(&Path::new("a").with_extension(ext)) == language)
+    }
+
+    pub fn compiler_for(&self, language: Language) -> String {
+        self.compiler_choice_for(language).0
+    }
+
+    // 'compiler-c' or 'compiler-cxx' win over 'compiler', which wins over CC or
+    // CXX unless 'respect-env' is false
+    pub fn compiler_choice_for(&self, language: Language) -> (String, String) {
+        let (key, explicit, var, default) = if language == Language::Cxx {
+            ("compiler-cxx", &self.compiler_cxx, "CXX", "g++")
+        } else {
+            ("compiler-c", &self.compiler_c, "CC", "gcc")
+        };
+        if let Some(ref compiler) = *explicit {
+            return (compiler.clone(), format!("set by '{}'", key));
+        }
+        if let Some(ref compiler) = self.compiler {
+            return (compiler.clone(), String::from("set by 'compiler'"));
+        }
+        if let Some(compiler) = self.env_tool(var) {
+            return (compiler, format!("from {}", var));
+        }
+        match self.target {
+            Some(ref target) => (format!("{}-{}", target, default), format!("for target '{}'", target)),
+            None => (String::from(default), String::from("default")),
+        }
+    }
+
+    // Flags only given to compiles of `language`
+    pub fn language_flags(&self, language: Language) -> Vec<String> {
+        let flags = match language {
+            Language::C => &self.cflags,
+            Language::Cxx => &self.cxxflags,
+            _ => return Vec::new(),
+        };
+        flags.as_ref().unwrap_or(&vec![]).prepend_each("-")
+    }
+
+    // clang cross compiles itself given `--target`, gcc needs a compiler
+    // built for the target which the triple prefix selects
+    pub fn target_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        if let Some(ref target) = self.target {
+            if self.is_clang() {
+                args.push(format!("--target={}", target));
+            }
+        }
+        if let Some(ref sysroot) = self.sysroot {
+            args.push(format!("--sysroot={}", sysroot));
+        }
+        args
+    }
+
+    // Objects of a cross build go to `obj/<triple>`, its binaries and
+    // libraries to `<triple>`, so switching targets doesn't need a clean
+    pub fn object_dir(&self) -> Option<PathBuf> {
+        self.target.as_ref().map(|target| Path::new("obj").join(target))
+    }
+
+    pub fn output_dir(&self) -> Option<PathBuf> {
+        self.target.as_ref().map(PathBuf::from)
+    }
+
+    // The OS being built for, that of 'target' when cross compiling
+    pub fn target_platform(&self) -> Option<Platform> {
+        match self.target {
+            Some(ref triple) => Platform::from_triple(triple),
+            None => Some(self.context.platform.clone()),
+        }
+    }
+
+    pub fn merge_overrides(&mut self, overrides: &Overrides) {
+        fn append<T: Clone>(list: &mut Option<Vec<T>>, extra: &Option<Vec<T>>) {
+            if let Some(ref extra) = *extra {
+                list.get_or_insert_with(Vec::new).extend(extra.iter().cloned());
+            }
+        }
+        if overrides.compiler.is_some() {
+            self.compiler = overrides.compiler.clone();
+        }
+        if overrides.ar.is_some() {
+            self.ar = overrides.ar.clone();
+        }
+        append(&mut self.compiler_flags, &overrides.compiler_flags);
+        append(&mut self.lflags, &overrides.lflags);
+        append(&mut self.libs, &overrides.libs);
+        append(&mut self.lib_dir, &overrides.lib_dir);
+        append(&mut self.include, &overrides.include);
+        append(&mut self.defines, &overrides.defines);
+        append(&mut self.rpath, &overrides.rpath);
+        if overrides.visibility.is_some() {
+            self.visibility = overrides.visibility.clone();
+        }
+        if overrides.strip.is_some() {
+            self.strip = overrides.strip;
+        }
+        if overrides.strip_tool.is_some() {
+            self.strip_tool = overrides.strip_tool.clone();
+        }
+        if overrides.split_debug_info.is_some() {
+            self.split_debug_info = overrides.split_debug_info;
+        }
+        if overrides.objcopy.is_some() {
+            self.objcopy = overrides.objcopy.clone();
+        }
+        if overrides.ranlib.is_some() {
+            self.ranlib = overrides.ranlib.clone();
+        }
+    }
+
+    // Non-empty `var` from the environment, unless 'respect-env' is false
+    fn env_tool(&self, var: &str) -> Option<String> {
+        if !self.respects_env() {
+            return None;
+        }
+        self.context.non_empty_var(var).map(String::from)
+    }
+
+    // The launcher executable followed by its leading arguments
+    pub fn launcher_args(&self) -> Result<Vec<String>, YabsError> {
+        match self.compiler_launcher {
+            Some(ref launcher) => shell_split(launcher),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    // Words of the environment variable `var`, unless 'use-env-flags' is false
+    pub fn env_flags(&self, var: &str) -> Result<Vec<String>, YabsError> {
+        if !self.uses_env_flags() {
+            return Ok(Vec::new());
+        }
+        match self.context.var(var) {
+            Some(value) => shell_split(value),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    // CXXFLAGS for C++ sources, CFLAGS for everything else
+    pub fn env_compile_flags(&self, source: &Path) -> Result<Vec<String>, YabsError> {
+        if Language::of(source) == Language::Cxx {
+            self.env_flags("CXXFLAGS")
+        } else {
+            self.env_flags("CFLAGS")
+        }
+    }
+
+    pub fn ar_is_explicit(&self) -> bool {
+        self.ar.is_some() || self.env_tool("AR").is_some()
+    }
+
+    // Plain `ar` can't index LTO bitcode, so the compiler's wrapper is used
+    // when LTO is enabled
+    pub fn ar(&self) -> String {
+        let prefix = match self.target {
+            Some(ref target) if !self.is_clang() => format!("{}-", target),
+            _ => String::new(),
+        };
+        match self.ar.clone().or_else(|| self.env_tool("AR")) {
+            Some(ar) => ar,
+            None if self.lto_args().is_empty() => prefix + "ar",
+            None if self.is_clang() => String::from("llvm-ar"),
+            None => prefix + "gcc-ar",
+        }
+    }
+
+    pub fn strips(&self) -> bool {
+        self.strip.unwrap_or(false)
+    }
+
+    pub fn splits_debug_info(&self) -> bool {
+        self.split_debug_info.unwrap_or(false)
+    }
+
+    pub fn strip_tool(&self) -> String {
+        self.strip_tool.clone().or_else(|| self.env_tool("STRIP")).unwrap_or_else(|| self.binutil("strip"))
+    }
+
+    pub fn objcopy(&self) -> String {
+        self.objcopy.clone().or_else(|| self.env_tool("OBJCOPY")).unwrap_or_else(|| self.binutil("objcopy"))
+    }
+
+    // The binutils `tool` for the target. The host's doesn't know the objects
+    // of another target, clang toolchains come with one that knows them all.
+    fn binutil(&self, tool: &str) -> String {
+        match self.target {
+            Some(_) if self.is_clang() => format!("llvm-{}", tool),
+            Some(ref target) => format!("{}-{}", target, tool),
+            None => tool.to_owned(),
+        }
+    }
+
+    // Neither Mach-O nor MSVC's outputs carry ELF debug sections objcopy can
+    // move
+    pub fn supports_split_debug_info(&self) -> bool {
+        !self.is_msvc() && self.target_platform().is_none_or(|platform| platform.os != "macos")
+    }
+
+    pub fn is_thin_archive(&self) -> bool {
+        self.thin_archive.unwrap_or(false)
+    }
+
+    pub fn is_incremental_archive(&self) -> bool {
+        self.incremental_archive.unwrap_or(false)
+    }
+
+    // 'arflags' split into arguments, with the `T` of a thin archive added to
+    // the operation
+    pub fn arflags(&self) -> Vec<String> {
+        let mut flags: Vec<String> = self.arflags
+                                         .as_deref()
+                                         .unwrap_or("rcs")
+                                         .split_whitespace()
+                                         .map(|flag| flag.to_owned())
+                                         .collect();
+        if self.is_thin_archive() {
+            match flags.first_mut() {
+                Some(operation) if !operation.contains('T') => operation.push('T'),
+                Some(_) => {},
+                None => flags.push(String::from("rcsT")),
+            }
+        }
+        flags
+    }
+
+    // The ranlib indexing archives once they're written, when 'ranlib' is set
+    // or 'arflags' doesn't have ar index them itself with `s`
+    pub fn ranlib(&self) -> Option<String> {
+        let indexed = self.arflags().first().is_some_and(|operation| operation.contains('s'));
+        if self.ranlib.is_none() && indexed {
+            return None;
+        }
+        Some(self.ranlib.clone().or_else(|| self.env_tool("RANLIB")).unwrap_or_else(|| self.binutil("ranlib")))
+    }
+
+    pub fn lto_args(&self) -> Vec<String> {
+        match self.lto {
+            Some(Lto::Enabled(true)) => vec![String::from("-flto")],
+            Some(Lto::Mode(ref mode)) if mode == "thin" && self.is_clang() => {
+                vec![String::from("-flto=thin")]
+            },
+            Some(Lto::Mode(_)) => vec![String::from("-flto")],
+            _ => vec![],
+        }
+    }
+
+    // Entries that are commands are expanded in place of the flag, which lets
+    // "`pkg-config --cflags sdl2`" be listed among the include directories
+    pub fn include_args(&self) -> Result<Vec<String>, YabsError> {
+        self.include_args_of(self.include.as_ref().unwrap_or(&vec![]))
+    }
+
+    // `-I` for each of `include`, entries in backticks are run for their flags
+    pub fn include_args_of(&self, include: &[String]) -> Result<Vec<String>, YabsError> {
+        let mut args = Vec::new();
+        for dir in include {
+            if self.is_command(dir) {
+                args.extend(command_output_args(dir, &self.context.cwd)?);
+            } else {
+                args.push(format!("-I{}", dir));
+            }
+        }
+        Ok(args)
+    }
+
+    // Include and library directories that don't exist are most likely typos,
+    // but the compiler and linker ignore them
+    pub fn warn_missing_dirs(&self) {
+        for &(key, dirs) in &[("include", &self.include), ("library-directories", &self.lib_dir)] {
+            for dir in dirs.as_ref().unwrap_or(&vec![]) {
+                if !self.is_command(dir) && !self.context.cwd.join(dir).is_dir() {
+                    warn!("'{}' lists '{}', which is not a directory", key, dir);
+                }
+            }
+        }
+    }
+
+    // LTO defers code generation to the final link, so grouping objects with
+    // `-r` beforehand either loses the optimization or produces broken objects
+    pub fn uses_lto(&self) -> bool {
+        !self.lto_args().is_empty() ||
+        self.compiler_flags
+            .iter()
+            .chain(self.lflags.iter())
+            .flat_map(|flags| flags.iter())
+            .any(|flag| flag.trim_start_matches('-').starts_with("flto"))
+    }
+
+    pub fn is_msvc(&self) -> bool {
+        let compiler = self.compiler();
+        let stem = Path::new(&compiler).file_stem().and_then(|stem| stem.to_str());
+        matches!(stem, Some("cl") | Some("clang-cl"))
+    }
+
+    // MSVC's linker can't produce relocatable objects
+    pub fn supports_partial_link(&self) -> bool {
+        !self.is_msvc()
+    }
+
+    pub fn warning_args(&self) -> Vec<String> {
+        let msvc = self.is_msvc();
+        let mut args: Vec<String> = self.warnings
+                                        .unwrap_or(Warnings::Default)
+                                        .flags(msvc)
+                                        .into_iter()
+                                        .map(|flag| flag.to_owned())
+                                        .collect();
+        if self.warnings_as_errors.unwrap_or(false) {
+            args.push(String::from(if msvc { "/WX" } else { "-Werror" }));
+        }
+        args
+    }
+
+    // Unknown standards are passed through so new ones work without a release
+    pub fn std_args(&self, source: &Path) -> Vec<String> {
+        let std = match Language::of(source) {
+            Language::C => self.std_c.as_ref().or(self.std.as_ref()),
+            Language::Cxx => self.std_cxx.as_ref().or(self.std.as_ref()),
+            Language::Asm | Language::Other => None,
+        };
+        std.map(|std| vec![format!("-std={}", std)]).unwrap_or_default()
+    }
+
+    // Sanitizers need their runtime linked in as well, see
+    // `sanitize_link_args`
+    pub fn sanitize_args(&self) -> Vec<String> {
+        let mut args = self.sanitize_link_args();
+        if !args.is_empty() {
+            args.push(String::from("-fno-omit-frame-pointer"));
+        }
+        args
+    }
+
+    pub fn sanitize_link_args(&self) -> Vec<String> {
+        match self.sanitize.as_ref() {
+            Some(sanitizers) if !sanitizers.is_empty() => {
+                vec![format!("-fsanitize={}", sanitizers.join(","))]
+            },
+            _ => vec![],
+        }
+    }
+
+    // Added to both compile and link commands
+    pub fn coverage_args(&self) -> Vec<String> {
+        if self.coverage.unwrap_or(false) {
+            vec![String::from("--coverage")]
+        } else {
+            vec![]
+        }
+    }
+
+    // clang's notes are read by `llvm-cov gcov` rather than gcc's `gcov`
+    pub fn coverage_tool(&self) -> Vec<String> {
+        match self.coverage_tool {
+            Some(ref tool) => tool.split_whitespace().map(|arg| arg.to_owned()).collect(),
+            None if self.compiler().contains("clang") => {
+                vec![String::from("llvm-cov"), String::from("gcov")]
+            },
+            None => vec![String::from("gcov")],
+        }
+    }
+
+    // Checks for values that deserialize fine but can't be used
+    pub fn validate(&self) -> Result<(), YabsError> {
+        for sanitizer in self.sanitize.as_ref().unwrap_or(&vec![]) {
+            if !SANITIZERS.contains(&sanitizer.as_str()) {
+                bail!(YabsErrorKind::InvalidValue("sanitize".to_owned(),
+                                                  format!("unknown sanitizer '{}', supported \
+                                                           sanitizers are: {}",
+                                                          sanitizer,
+                                                          SANITIZERS.join(", "))));
+            }
+        }
+        if let Some(Lto::Mode(ref mode)) = self.lto {
+            if mode != "thin" {
+                bail!(YabsErrorKind::InvalidValue("lto".to_owned(),
+                                                  format!("expected true, false or \"thin\", \
+                                                           found \"{}\"",
+                                                          mode)));
+            }
+        }
+        validate_visibility(&self.visibility)?;
+        for &(key, value) in &[("std", &self.std), ("std-c", &self.std_c), ("std-cxx", &self.std_cxx)] {
+            if value.as_ref().is_some_and(|value| value.trim().is_empty()) {
+                bail!(YabsErrorKind::InvalidValue(key.to_owned(), "must not be empty".to_owned()));
+            }
+        }
+        for &(key, value) in &[("job-timeout-secs", self.job_timeout_secs),
+                               ("link-timeout-secs", self.link_timeout_secs),
+                               ("script-timeout-secs", self.script_timeout_secs)] {
+            if value == Some(0) {
+                bail!(YabsErrorKind::InvalidValue(key.to_owned(), "must be at least 1 second".to_owned()));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn job_timeout(&self) -> Option<Duration> {
+        self.job_timeout_secs.map(Duration::from_secs)
+    }
+
+    pub fn link_timeout(&self) -> Option<Duration> {
+        self.link_timeout_secs.map(Duration::from_secs)
+    }
+
+    // Adds the usage requirements of every package in 'deps'
+    pub fn resolve_deps(&mut self) -> Result<(), YabsError> {
+        let deps = match self.deps.as_ref() {
+            Some(deps) if !deps.is_empty() => deps.clone(),
+            _ => return Ok(()),
+        };
+        let prefixes = export::search_prefixes(self.dep_prefixes.as_ref().unwrap_or(&vec![]), &self.context);
+        for package in export::resolve(&deps, &prefixes)? {
+            self.apply_export(package);
+        }
+        Ok(())
+    }
+
+    // Sorts the flags pkg-config gives for each 'pkg-config' entry into the
+    // include directories, defines and libraries, the remaining flags are kept
+    // as they are in the order given. pkg-config reads PKG_CONFIG_PATH from
+    // the context's variables.
+    pub fn resolve_pkg_config(&mut self) -> Result<(), YabsError> {
+        for package in self.pkg_config.clone().unwrap_or_default() {
+            let mut flags = pkg_config_flags(&package, "--cflags", &self.context)?.into_iter();
+            while let Some(flag) = flags.next() {
+                if let Some(dir) = option_value("-I", &flag, &mut flags) {
+                    self.include.get_or_insert_with(Vec::new).push(dir);
+                } else if let Some(define) = option_value("-D", &flag, &mut flags) {
+                    self.defines.get_or_insert_with(Vec::new).push(define);
+                } else {
+                    self.pkg_config_cflags.push(flag);
+                }
+            }
+            let mut flags = pkg_config_flags(&package, "--libs", &self.context)?.into_iter();
+            while let Some(flag) = flags.next() {
+                if let Some(dir) = option_value("-L", &flag, &mut flags) {
+                    self.lib_dir.get_or_insert_with(Vec::new).push(dir);
+                } else if let Some(lib) = option_value("-l", &flag, &mut flags) {
+                    self.libs.get_or_insert_with(Vec::new).push(LibEntry::Name(lib));
                 } else {
-                    lib_list.push(lib.clone());
+                    self.pkg_config_libs.push(flag);
                 }
             }
         }
-        lib_list = lib_list.prepend_each("-l");
-        lib_list.extend(cmd_list);
-        lib_list.join(" ")
+        Ok(())
+    }
+
+    fn apply_export(&mut self, package: Export) {
+        self.include.get_or_insert_with(Vec::new).extend(package.include);
+        self.defines.get_or_insert_with(Vec::new).extend(package.defines);
+        self.lib_dir.get_or_insert_with(Vec::new).extend(package.lib_dir);
+        self.libs.get_or_insert_with(Vec::new).extend(package.libraries.into_iter().map(LibEntry::Name));
+    }
+
+    pub fn define_args(&self) -> Vec<String> {
+        self.defines.as_ref().unwrap_or(&vec![]).prepend_each("-D")
+    }
+
+    // `-Wl,-rpath` for each 'rpath' entry and `origin`, the entry
+    // 'rpath-origin' adds. macOS calls `$ORIGIN` `@loader_path`.
+    pub fn rpath_args(&self, origin: &str) -> Vec<String> {
+        let mut rpath = self.rpath.clone().unwrap_or_default();
+        if self.rpath_origin.unwrap_or(false) {
+            rpath.push(origin.to_owned());
+        }
+        let macos = self.target_platform().is_some_and(|platform| platform.os == "macos");
+        rpath.into_iter()
+             .map(|entry| if macos { entry.replace("$ORIGIN", "@loader_path") } else { entry })
+             .map(|entry| format!("-Wl,-rpath,{}", entry))
+             .collect()
     }
 
-    // We don't care if a path is invalid UTF-8.
-    // ! Potentially spoopy !
-    // We can optionally exclude an object with source path `exclude`
-    pub fn object_list_as_string(&self, exclude: Option<Vec<Binary>>) -> Result<String, YabsError> {
-        let mut obj_str_list: Vec<String> = Vec::new();
-        for target in self.file_mod_map.keys() {
+    // `-fvisibility` for the objects of a target with `overrides`, MSVC hides
+    // everything not exported already
+    pub fn visibility_args(&self, overrides: &Overrides) -> Vec<String> {
+        match overrides.visibility.as_ref().or(self.visibility.as_ref()) {
+            Some(visibility) if !self.is_msvc() => vec![format!("-fvisibility={}", visibility)],
+            _ => vec![],
+        }
+    }
+
+    // Whether the linker takes GNU version scripts, which neither Apple's
+    // linker nor MSVC's do
+    pub fn supports_version_scripts(&self) -> bool {
+        !self.is_msvc() && self.target_platform().is_none_or(|platform| platform.os != "macos")
+    }
+
+    pub fn lib_dir_args(&self) -> Vec<String> {
+        self.lib_dir.as_ref().unwrap_or(&vec![]).prepend_each("-L")
+    }
+
+    // We have to do this as we regard entries that start with '`' as commands
+    // and don't want to prepend them. Entries are linked in the order they're
+    // listed.
+    pub fn lib_args(&self) -> Result<Vec<String>, YabsError> {
+        let mut args: Vec<String> = Vec::new();
+        for entry in self.libs.iter().flatten() {
+            match *entry {
+                LibEntry::Name(ref lib) => args.extend(self.lib_entry_args(lib)?),
+                LibEntry::Group { ref group } => {
+                    args.push(String::from("-Wl,--start-group"));
+                    for lib in group {
+                        args.extend(self.lib_entry_args(lib)?);
+                    }
+                    args.push(String::from("-Wl,--end-group"));
+                },
+                LibEntry::Library { ref name, whole_archive } => {
+                    if whole_archive {
+                        args.push(String::from("-Wl,--whole-archive"));
+                    }
+                    args.extend(self.lib_entry_args(name)?);
+                    if whole_archive {
+                        args.push(String::from("-Wl,--no-whole-archive"));
+                    }
+                },
+            }
+        }
+        Ok(args)
+    }
+
+    // `-l<lib>` for a library, the words of a command's output, or a flag
+    // such as `-Wl,--as-needed` as it is
+    fn lib_entry_args(&self, lib: &str) -> Result<Vec<String>, YabsError> {
+        if self.is_command(lib) {
+            command_output_args(lib, &self.context.cwd)
+        } else if lib.starts_with('-') {
+            Ok(vec![lib.to_owned()])
+        } else {
+            Ok(vec![format!("-l{}", lib)])
+        }
+    }
+
+    // The objects compiled for `owner`, or the shared ones without one. We can
+    // optionally exclude the objects of the entry points in `exclude`
+    pub fn object_list(&self,
+                       owner: Option<&str>,
+                       pic: bool,
+                       exclude: Option<Vec<Binary>>)
+                       -> Result<Vec<PathBuf>, YabsError> {
+        let mut obj_list = Vec::new();
+        for (target, _) in self.targets_of(owner, pic) {
             if let Some(ref exclude) = exclude {
+                let mut excluded = false;
                 for bin in exclude {
-                    if target.source.canonicalize()? != bin.path().canonicalize()? {
-                        if let Some(obj_str) = target.object.to_str() {
-                            obj_str_list.push(format!("\"{}\"", obj_str.to_owned()));
-                        }
+                    if self.context.cwd.join(&target.source).canonicalize()? ==
+                       self.context.cwd.join(bin.path()).canonicalize()? {
+                        excluded = true;
                     }
                 }
-            } else if let Some(obj_str) = target.object.to_str() {
-                obj_str_list.push(format!("\"{}\"", obj_str.to_owned()));
+                if excluded {
+                    continue;
+                }
             }
+            obj_list.push(target.object());
         }
-        Ok(obj_str_list.join(" "))
+        Ok(obj_list)
     }
 
     // Sources are found with a regular expression "(.*)\.[EXTENSIONS]+$"
@@ -208,57 +1458,353 @@ impl ProjectDesc {
     pub fn find_source_files(&mut self) -> Result<(), YabsError> {
         // If sources are listed don't walk the current directory for files.
         if self.src.is_some() {
-            let regex = Regex::new(&format!("(.*)\\.[{}]+$", self.file_exts.join("|")))?;
+            let regex = self.source_regex()?;
             for entry in self.src.clone().unwrap() {
                 if let Some(src_str) = entry.clone().to_str() {
                     self.file_mod_map
                          .insert(Target::new(entry.clone(),
                                              PathBuf::from(String::from(regex.replace(src_str, "${1}.o")))),
-                                 metadata(&entry)?.modified()?);
+                                 metadata(self.context.cwd.join(&entry)).map_err(|_| {
+                                                     YabsErrorKind::InvalidValue("src".to_owned(),
+                                                                                 format!("'{}' doesn't exist",
+                                                                                         entry.display()))
+                                                 })?
+                                                 .modified()?);
                 }
             }
         } else {
             self.walk_current_dir()?;
         }
+        self.record_sources()
+    }
+
+    // Objects mirror the paths of their sources, but `a.c` and `a.cpp`, or a
+    // source listed both as `./a.c` and `a.c`, would still be compiled into
+    // the same object with one overwriting the other. So would `a.pic.c` and
+    // the position independent object of `a.c`, so this is checked once all
+    // objects are known.
+    pub fn check_object_collisions(&self) -> Result<(), YabsError> {
+        let mut sources: BTreeMap<PathBuf, PathBuf> = BTreeMap::new();
+        for target in self.file_mod_map.keys() {
+            let object: PathBuf = target.object().components().filter(|c| *c != Component::CurDir).collect();
+            if let Some(first) = sources.insert(object.clone(), target.source()) {
+                bail!(YabsErrorKind::ObjectCollision(object, first, target.source()));
+            }
+        }
+        Ok(())
+    }
+
+    // Records the sources found, hashing those whose modification time or
+    // size changed since they were last hashed
+    pub fn record_sources(&mut self) -> Result<(), YabsError> {
+        let by_hash = self.change_detection() == ChangeDetection::Hash;
+        let mut records = BTreeMap::new();
+        self.file_hashes = BTreeMap::new();
+        for (target, modtime) in &self.file_mod_map {
+            let source = target.source();
+            let key = source.display().to_string();
+            let size = metadata(self.context.cwd.join(&source))?.len();
+            let hash = match self.source_records.get(&key) {
+                _ if !by_hash => None,
+                Some(record) if record.hash.is_some() && record.matches(*modtime, size) => record.hash.clone(),
+                _ => Some(hash_file(&self.context.cwd.join(&source))?),
+            };
+            if let Some(ref hash) = hash {
+                self.file_hashes.insert(source, hash.clone());
+            }
+            records.insert(key, SourceRecord::new(*modtime, size, hash));
+        }
+        self.source_records = records;
         Ok(())
     }
 
+    // Matches sources by their extension, capturing everything before it.
+    // Extensions are case-sensitive so `.s` and `.S` can be told apart.
+    fn source_regex(&self) -> Result<Regex, YabsError> {
+        let exts: Vec<String> = self.file_exts.iter().map(|ext| ::regex::escape(ext)).collect();
+        Ok(Regex::new(&format!("(.*)\\.(?:{})$", exts.join("|")))?)
+    }
+
     // Same regex used as `find_source_files`
-    fn walk_current_dir(&mut self) -> Result<(), YabsError> {
-        let regex = Regex::new(&format!("(.*)\\.[{}]+$", self.file_exts.join("|")))?;
-        let mut sources = Vec::new();
+    fn scan_current_dir(&self) -> Result<BTreeMap<Target, SystemTime>, YabsError> {
+        let regex = self.source_regex()?;
         let mut file_mod_map = BTreeMap::new();
-        let walk_dir = WalkDir::new(".").into_iter();
-        for entry in walk_dir.filter_entry(|e| !&self.is_in_ignore(e)) {
+        for path in self.walk_files()? {
+            if let Some(filename_str) = path.to_str() {
+                if regex.is_match(filename_str) {
+                    let object = PathBuf::from(String::from(regex.replace(filename_str, "${1}.o")));
+                    let modified = metadata(self.context.cwd.join(&path))?.modified()?;
+                    file_mod_map.insert(Target::new(path.clone(), object), modified);
+                }
+            }
+        }
+        Ok(file_mod_map)
+    }
+
+    // Every file under the project's directory, as "./"-relative paths. Hidden
+    // directories such as `.git`, the state directory and the 'ignore' entries
+    // aren't walked into.
+    pub fn walk_files(&self) -> Result<Vec<PathBuf>, YabsError> {
+        let root = &self.context.cwd;
+        let state_dir = self.state_dir();
+        let relative = |path: &Path| Path::new(".").join(path.strip_prefix(root).unwrap_or(path));
+        let skipped = |entry: &DirEntry| {
+            let hidden = entry.depth() > 0 && entry.file_name().to_string_lossy().starts_with('.');
+            (entry.file_type().is_dir() && (hidden || entry.path() == state_dir)) ||
+            self.is_in_ignore(&relative(entry.path()))
+        };
+        let mut files = Vec::new();
+        for entry in WalkDir::new(root).into_iter().filter_entry(|entry| !skipped(entry)) {
             let entry = entry?;
             if entry.path().is_file() {
-                if let Some(filename_str) = entry.path().to_str() {
-                    if regex.is_match(filename_str) {
-                        file_mod_map.insert(Target::new(entry.path().to_path_buf(),
-                                                        PathBuf::from(String::from(regex.replace(filename_str, "${1}.o")))),
-                                            metadata(entry.path())?.modified()?);
-                    }
-                }
+                files.push(relative(entry.path()));
             }
         }
-        sources.sort();
-        self.src = Some(sources);
+        Ok(files)
+    }
+
+    // The sources a project without 'src' would be built from
+    pub fn scan_sources(&self) -> Result<Vec<PathBuf>, YabsError> {
+        Ok(self.scan_current_dir()?.keys().map(|target| target.source()).collect())
+    }
+
+    fn walk_current_dir(&mut self) -> Result<(), YabsError> {
+        let file_mod_map = self.scan_current_dir()?;
+        self.src = Some(file_mod_map.keys().map(|target| target.source()).collect());
         self.file_mod_map = file_mod_map;
+        self.sources_walked = true;
         Ok(())
     }
 
+    // Forgets the sources found, so `find_source_files` finds them again with
+    // their current modification times. Walked sources are walked for again so
+    // new and deleted files are noticed.
+    pub fn clear_sources(&mut self) {
+        self.file_mod_map.clear();
+        if self.sources_walked {
+            self.src = None;
+        }
+    }
+
+    // Moves every object under `dir`, keeping them apart from the objects of a
+    // normal build
+    pub fn relocate_objects(&mut self, dir: &Path) {
+        self.file_mod_map = self.file_mod_map
+                                .iter()
+                                .map(|(target, modtime)| (target.with_object_dir(dir), *modtime))
+                                .collect();
+    }
+
+    // The sources with the objects compiled for `owner`, or their shared
+    // objects without one
+    pub fn targets_of<'a>(&'a self,
+                          owner: Option<&'a str>,
+                          pic: bool)
+                          -> impl Iterator<Item = (&'a Target, &'a SystemTime)> {
+        self.file_mod_map.iter().filter(move |(target, _)| target.owner() == owner && target.is_pic() == pic)
+    }
+
+    // The sources of the project once each, with their shared objects
+    pub fn shared_targets(&self) -> impl Iterator<Item = &Target> {
+        self.targets_of(None, false).map(|(target, _)| target)
+    }
+
+    // Whether a dynamic library with `overrides` needs position independent
+    // objects of its own. It doesn't when its objects are compiled with
+    // `-fPIC` already, or for macOS and MSVC where any object can be linked
+    // into one.
+    pub fn needs_pic_objects(&self, overrides: &Overrides) -> bool {
+        let macos = self.target_platform().is_some_and(|platform| platform.os == "macos");
+        let pic = self.compiler_flags
+                      .iter()
+                      .chain(overrides.compiler_flags.iter())
+                      .flatten()
+                      .any(|flag| matches!(flag.trim_start_matches('-'), "fPIC" | "fpic"));
+        !pic && !macos && !self.is_msvc()
+    }
+
+    pub fn is_clang(&self) -> bool {
+        self.compiler().contains("clang")
+    }
+
+    // A build works on the sources found when it started. Sources that have
+    // appeared since, such as those dropped in by generators while the build
+    // was running, are found by walking the current directory again.
+    pub fn find_new_sources(&self) -> Result<Vec<PathBuf>, YabsError> {
+        if !self.sources_walked {
+            return Ok(Vec::new());
+        }
+        Ok(self.scan_current_dir()?
+               .keys()
+               .filter(|target| !self.file_mod_map.contains_key(target))
+               .map(|target| target.source())
+               .collect())
+    }
+
     // Any string that starts and ends with the character "`" is regarded as a
     // command
     pub fn is_command(&self, string: &str) -> bool {
         string.starts_with('`') && string.ends_with('`')
     }
 
-    pub fn run_script(&self, script: &Option<Vec<String>>) -> Result<(), YabsError> {
+    // Forgets the scripts no longer in 'before-script' or 'after-script'
+    pub fn remove_stale_script_stamps(&self) -> Result<(), YabsError> {
+        let scripts: Vec<&Script> = self.before_script
+                                        .iter()
+                                        .chain(self.after_script.iter())
+                                        .flatten()
+                                        .collect();
+        Script::remove_stale_stamps(&scripts, &self.context.cwd)
+    }
+
+    pub fn run_script(&self, script: &Option<Vec<Script>>) -> Result<(), YabsError> {
         if let Some(script) = script.as_ref() {
-            for cmd in script {
-                run_cmd(cmd)?;
+            for entry in script {
+                entry.run_within(&self.context.cwd, self.script_timeout_secs.map(Duration::from_secs))?;
             }
         }
         Ok(())
     }
 }
+
+#[test]
+fn test_source_regex() {
+    let project = ProjectDesc {
+        file_exts: vec![String::from("cpp"), String::from("S")],
+        ..Default::default()
+    };
+    let regex = project.source_regex().unwrap();
+    assert_eq!(regex.replace("./src/main.cpp", "${1}.o"), "./src/main.o");
+    assert!(regex.is_match("boot.S"));
+    assert!(!regex.is_match("boot.s"));
+    assert!(!regex.is_match("main.c"));
+    assert!(!regex.is_match("main.pc"));
+}
+
+#[test]
+fn test_object_collisions() {
+    let mut project = ProjectDesc::default();
+    for &(source, object) in &[("./src/net/init.c", "./src/net/init.o"), ("./src/db/init.c", "./src/db/init.o")] {
+        project.file_mod_map.insert(Target::new(PathBuf::from(source), PathBuf::from(object)), SystemTime::now());
+    }
+    assert!(project.check_object_collisions().is_ok());
+    project.file_mod_map.insert(Target::new(PathBuf::from("src/db/init.cpp"), PathBuf::from("src/db/init.o")),
+                                SystemTime::now());
+    let error = project.check_object_collisions().unwrap_err().to_string();
+    assert!(error.contains("'./src/db/init.c' and 'src/db/init.cpp' would both be compiled into 'src/db/init.o'"),
+            "{}",
+            error);
+}
+
+#[test]
+fn test_rpath_args() {
+    let mut project = ProjectDesc {
+        rpath: Some(vec![String::from("$ORIGIN/plugins"), String::from("/opt/lib")]),
+        rpath_origin: Some(true),
+        target: Some(String::from("x86_64-linux-gnu")),
+        ..Default::default()
+    };
+    assert_eq!(project.rpath_args("$ORIGIN/../lib"),
+               vec!["-Wl,-rpath,$ORIGIN/plugins", "-Wl,-rpath,/opt/lib", "-Wl,-rpath,$ORIGIN/../lib"]);
+    project.target = Some(String::from("aarch64-apple-darwin"));
+    assert_eq!(project.rpath_args("$ORIGIN")[0], "-Wl,-rpath,@loader_path/plugins");
+    assert_eq!(project.rpath_args("$ORIGIN")[2], "-Wl,-rpath,@loader_path");
+}
+
+#[test]
+fn test_visibility_args() {
+    let mut project = ProjectDesc {
+        visibility: Some(String::from("hidden")),
+        compiler: Some(String::from("gcc")),
+        target: Some(String::from("x86_64-linux-gnu")),
+        ..Default::default()
+    };
+    assert_eq!(project.visibility_args(&Overrides::default()), vec!["-fvisibility=hidden"]);
+    let overrides = Overrides {
+        visibility: Some(String::from("protected")),
+        ..Default::default()
+    };
+    assert_eq!(project.visibility_args(&overrides), vec!["-fvisibility=protected"]);
+    assert!(project.supports_version_scripts());
+    project.target = Some(String::from("aarch64-apple-darwin"));
+    assert!(!project.supports_version_scripts());
+    assert!(validate_visibility(&Some(String::from("secret"))).is_err());
+}
+
+#[test]
+fn test_strip_tool() {
+    let mut project = ProjectDesc {
+        compiler: Some(String::from("gcc")),
+        respect_env: Some(false),
+        ..Default::default()
+    };
+    assert_eq!(project.strip_tool(), "strip");
+    project.target = Some(String::from("aarch64-linux-gnu"));
+    assert_eq!(project.strip_tool(), "aarch64-linux-gnu-strip");
+    project.compiler = Some(String::from("clang"));
+    assert_eq!(project.strip_tool(), "llvm-strip");
+    assert_eq!(project.objcopy(), "llvm-objcopy");
+    project.strip_tool = Some(String::from("/opt/cross/bin/strip"));
+    assert_eq!(project.strip_tool(), "/opt/cross/bin/strip");
+}
+
+#[test]
+fn test_archive_flags() {
+    let mut project = ProjectDesc {
+        compiler: Some(String::from("gcc")),
+        respect_env: Some(false),
+        thin_archive: Some(true),
+        ..Default::default()
+    };
+    assert_eq!(project.arflags(), vec!["rcsT"]);
+    assert_eq!(project.ranlib(), None);
+    project.arflags = Some(String::from("rc --plugin x.so"));
+    assert_eq!(project.arflags(), vec!["rcT", "--plugin", "x.so"]);
+    assert_eq!(project.ranlib(), Some(String::from("ranlib")));
+    project.arflags = None;
+    project.ranlib = Some(String::from("aarch64-apple-darwin-ranlib"));
+    assert_eq!(project.ranlib(), Some(String::from("aarch64-apple-darwin-ranlib")));
+}
+
+#[test]
+fn test_pic_objects() {
+    let target = Target::new(PathBuf::from("src/net/init.c"), PathBuf::from("src/net/init.o"));
+    let pic = target.position_independent();
+    assert_eq!(pic.object(), PathBuf::from("src/net/init.pic.o"));
+    assert!(pic.is_pic() && !target.is_pic());
+    assert!(pic.with_object_dir(Path::new("build")).is_pic());
+    let mut project = ProjectDesc {
+        compiler: Some(String::from("gcc")),
+        target: Some(String::from("x86_64-linux-gnu")),
+        ..Default::default()
+    };
+    assert!(project.needs_pic_objects(&Overrides::default()));
+    let overrides = Overrides {
+        compiler_flags: Some(vec![String::from("fPIC")]),
+        ..Default::default()
+    };
+    assert!(!project.needs_pic_objects(&overrides));
+    project.target = Some(String::from("aarch64-apple-darwin"));
+    assert!(!project.needs_pic_objects(&Overrides::default()));
+}
+
+#[test]
+fn test_lib_args_keep_their_order() {
+    let project: ProjectDesc = toml::from_str("file-extensions = [\"c\"]\n\
+                                               libraries = [{ name = \"m\" }, { name = \"-Wl,--as-needed\" }, \
+                                                            { group = [\"a\", \"b\"] }, \
+                                                            { name = \"plugins\", whole-archive = true }, \
+                                                            { name = \"z\" }]\n")
+        .unwrap();
+    assert_eq!(project.lib_args().unwrap(),
+               vec!["-lm",
+                    "-Wl,--as-needed",
+                    "-Wl,--start-group",
+                    "-la",
+                    "-lb",
+                    "-Wl,--end-group",
+                    "-Wl,--whole-archive",
+                    "-lplugins",
+                    "-Wl,--no-whole-archive",
+                    "-lz"]);
+}
@@ -0,0 +1,180 @@
+extern crate serde;
+
+use error::YabsError;
+use desc::tools::ToolRequirement;
+use log::LogLevel;
+use ext::{display_args, expand_glob, hash_strings, log_output, outputs_up_to_date, run_args, run_cmd, run_cmd_within};
+
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+// Stamps for scripts that declare outputs, named by a hash of the command
+const SCRIPT_STATE_DIR: &str = ".yabs/scripts";
+
+// A task's command is either run through the shell or spawned directly
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum TaskCommand {
+    Shell(String),
+    Argv(Vec<String>),
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct Task {
+    name: String,
+    command: TaskCommand,
+    // Binaries, libraries or tasks to build or run first
+    #[serde(rename = "depends")]
+    deps: Option<Vec<String>>,
+    inputs: Option<Vec<PathBuf>>,
+    // Tasks declaring outputs are skipped when every output is newer than
+    // every input, tasks without outputs always run
+    outputs: Option<Vec<PathBuf>>,
+    // Tools the command runs, checked before anything is built
+    requires: Option<Vec<ToolRequirement>>,
+}
+
+impl Task {
+    pub fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    pub fn deps(&self) -> Vec<String> {
+        self.deps.clone().unwrap_or_default()
+    }
+
+    pub fn requires(&self) -> Vec<ToolRequirement> {
+        self.requires.clone().unwrap_or_default()
+    }
+
+    pub fn inputs(&self) -> Vec<PathBuf> {
+        self.inputs.clone().unwrap_or_default()
+    }
+
+    pub fn outputs(&self) -> Vec<PathBuf> {
+        self.outputs.clone().unwrap_or_default()
+    }
+
+    // What `run` spawns, shell commands through `sh -c`
+    pub fn argv(&self) -> Vec<String> {
+        match self.command {
+            TaskCommand::Shell(ref cmd) => vec![String::from("sh"), String::from("-c"), cmd.clone()],
+            TaskCommand::Argv(ref args) => args.clone(),
+        }
+    }
+
+    // Inputs and outputs are found in `dir`, the project's directory
+    pub fn is_up_to_date(&self, dir: &Path) -> Result<bool, YabsError> {
+        outputs_up_to_date(self.inputs.as_ref().unwrap_or(&vec![]),
+                           self.outputs.as_ref().unwrap_or(&vec![]),
+                           dir)
+    }
+
+    // Runs the command in `dir`, the project's directory
+    pub fn run(&self, dir: &Path) -> Result<(), YabsError> {
+        match self.command {
+            TaskCommand::Shell(ref cmd) => run_cmd(cmd, dir),
+            TaskCommand::Argv(ref args) => {
+                // `run_args` logs the command itself in verbose mode
+                if !log_enabled!(LogLevel::Debug) {
+                    info!("{}", display_args(args));
+                }
+                let stderr = run_args(args, dir)?;
+                log_output(&stderr);
+                Ok(())
+            },
+        }
+    }
+}
+
+// A before-script or after-script entry. Plain strings always run, entries
+// declaring outputs are skipped while their outputs are newer than their
+// inputs and the command text hasn't changed since the last run.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum Script {
+    Command(String),
+    Declared {
+        command: String,
+        inputs: Option<Vec<String>>,
+        outputs: Option<Vec<String>>,
+    },
+}
+
+impl Script {
+    pub fn command(&self) -> &str {
+        match *self {
+            Script::Command(ref command) |
+            Script::Declared { ref command, .. } => command,
+        }
+    }
+
+    fn stamp_path(&self, dir: &Path) -> PathBuf {
+        dir.join(SCRIPT_STATE_DIR).join(format!("{:016x}",
+                                                 hash_strings(&[self.command().to_owned()])))
+    }
+
+    // Removes the stamps of scripts that aren't among `scripts` anymore, so a
+    // script removed from the buildfile and added back later runs again
+    pub fn remove_stale_stamps(scripts: &[&Script], dir: &Path) -> Result<(), YabsError> {
+        let entries = match fs::read_dir(dir.join(SCRIPT_STATE_DIR)) {
+            Ok(entries) => entries,
+            Err(_) => return Ok(()),
+        };
+        let stamps: Vec<PathBuf> = scripts.iter().map(|script| script.stamp_path(dir)).collect();
+        for entry in entries {
+            let path = entry?.path();
+            if !stamps.contains(&path) {
+                fs::remove_file(&path)?;
+            }
+        }
+        Ok(())
+    }
+
+    // Inputs and outputs are found in `dir`, the project's directory
+    pub fn is_up_to_date(&self, dir: &Path) -> Result<bool, YabsError> {
+        match *self {
+            Script::Command(_) => Ok(false),
+            Script::Declared { ref inputs, ref outputs, .. } => {
+                if !self.stamp_path(dir).exists() {
+                    return Ok(false);
+                }
+                outputs_up_to_date(&expand_globs(inputs, dir)?, &expand_globs(outputs, dir)?, dir)
+            },
+        }
+    }
+
+    // Runs the script in `dir`, the project's directory
+    pub fn run(&self, dir: &Path) -> Result<(), YabsError> {
+        self.run_within(dir, None)
+    }
+
+    // Like `run`, killing the script once it has run for `timeout`
+    pub fn run_within(&self, dir: &Path, timeout: Option<Duration>) -> Result<(), YabsError> {
+        if self.is_up_to_date(dir)? {
+            info!("script '{}' is up to date", self.command());
+            return Ok(());
+        }
+        run_cmd_within(self.command(), dir, timeout)?;
+        if let Script::Declared { outputs: Some(ref outputs), .. } = *self {
+            for output in outputs {
+                let produced = expand_glob(output, dir)?;
+                if produced.is_empty() || produced.iter().any(|path| !dir.join(path).exists()) {
+                    warn!("script '{}' didn't produce declared output '{}'", self.command(), output);
+                }
+            }
+            fs::create_dir_all(dir.join(SCRIPT_STATE_DIR))?;
+            File::create(self.stamp_path(dir))?;
+        }
+        Ok(())
+    }
+}
+
+fn expand_globs(patterns: &Option<Vec<String>>, dir: &Path) -> Result<Vec<PathBuf>, YabsError> {
+    let mut paths = Vec::new();
+    for pattern in patterns.as_ref().unwrap_or(&vec![]) {
+        paths.extend(expand_glob(pattern, dir)?);
+    }
+    Ok(paths)
+}
@@ -0,0 +1,138 @@
+extern crate regex;
+
+use desc::export::version_matches;
+use error::YabsError;
+
+use regex::Regex;
+use std::path::PathBuf;
+use std::process::Command;
+
+// `requires = [{ tool = "protoc", min-version = "3.15" }]`, `version-regex`
+// picks the version out of `<tool> --version` with its first group when the
+// usual formats don't fit
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct ToolRequirement {
+    pub tool: String,
+    #[serde(rename = "min-version")]
+    pub min_version: Option<String>,
+    #[serde(rename = "version-regex")]
+    pub version_regex: Option<String>,
+}
+
+// What probing a tool found, a missing tool has no path
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToolProbe {
+    pub path: Option<PathBuf>,
+    pub version: Option<String>,
+}
+
+impl ToolRequirement {
+    // Why the probed tool doesn't satisfy the requirement, if it doesn't
+    pub fn problem(&self, probe: &ToolProbe) -> Result<Option<String>, YabsError> {
+        if probe.path.is_none() {
+            return Ok(Some(format!("'{}' was not found", self.tool)));
+        }
+        let min_version = match self.min_version {
+            Some(ref min_version) => min_version,
+            None => return Ok(None),
+        };
+        Ok(match probe.version {
+               Some(ref version) if version_matches(version, &format!(">={}", min_version))? => None,
+               Some(ref version) => {
+                   Some(format!("'{}' is version {}, {} or newer is required",
+                                self.tool,
+                                version,
+                                min_version))
+               },
+               None => {
+                   Some(format!("couldn't read the version of '{}' from `{} --version`, set \
+                                 'version-regex'",
+                                self.tool,
+                                self.tool))
+               },
+           })
+    }
+}
+
+// Runs `<tool> --version` for the tool found at `path`, some tools print their
+// version to stderr
+pub fn probe_tool(path: Option<PathBuf>, version_regex: Option<&str>) -> Result<ToolProbe, YabsError> {
+    let version = match path {
+        Some(ref path) => {
+            match Command::new(path).arg("--version").output() {
+                Ok(output) => {
+                    let text = format!("{}\n{}",
+                                       String::from_utf8_lossy(&output.stdout),
+                                       String::from_utf8_lossy(&output.stderr));
+                    parse_tool_version(&text, version_regex)?
+                },
+                Err(_) => None,
+            }
+        },
+        None => None,
+    };
+    Ok(ToolProbe { path, version })
+}
+
+// The first dotted version number in `output`, or the first group of
+// `version_regex`. A leading `v` is dropped.
+pub fn parse_tool_version(output: &str, version_regex: Option<&str>) -> Result<Option<String>, YabsError> {
+    if let Some(pattern) = version_regex {
+        let regex = Regex::new(pattern)?;
+        return Ok(regex.captures(output)
+                       .and_then(|captures| captures.get(1).or_else(|| captures.get(0)))
+                       .map(|version| version.as_str().trim_start_matches('v').to_owned()));
+    }
+    let dotted = Regex::new(r"\b[vV]?(\d+(?:\.\d+)+)")?;
+    let plain = Regex::new(r"\b[vV]?(\d+)\b")?;
+    Ok(dotted.captures(output)
+             .or_else(|| plain.captures(output))
+             .map(|captures| captures[1].to_owned()))
+}
+
+#[test]
+fn test_parse_tool_version() {
+    let captured = [("libprotoc 3.21.12", "3.21.12"),
+                    ("cmake version 3.25.1\n\nCMake suite maintained and supported by Kitware", "3.25.1"),
+                    ("Python 3.11.2", "3.11.2"),
+                    ("GNU Make 4.3\nBuilt for x86_64-pc-linux-gnu", "4.3"),
+                    ("bison (GNU Bison) 3.8.2", "3.8.2"),
+                    ("moc 5.15.8", "5.15.8"),
+                    ("g++ (Debian 12.2.0-14+deb12u1) 12.2.0", "12.2.0"),
+                    ("Debian clang version 16.0.6 (15)", "16.0.6"),
+                    ("protoc-gen-go v1.28.1", "1.28.1"),
+                    ("NASM version 2.16.01 compiled on Jan  1 2023", "2.16.01"),
+                    ("1.11.1", "1.11.1"),
+                    ("swig 4", "4")];
+    for &(output, version) in &captured {
+        assert_eq!(parse_tool_version(output, None).unwrap(), Some(version.to_owned()), "{}", output);
+    }
+    assert_eq!(parse_tool_version("no version here", None).unwrap(), None);
+    assert_eq!(parse_tool_version("tool 12 (build 2023.1.5)", Some(r"build (\S+)\)")).unwrap(),
+               Some(String::from("2023.1.5")));
+}
+
+#[test]
+fn test_tool_requirement_problem() {
+    let requirement = ToolRequirement {
+        tool: String::from("protoc"),
+        min_version: Some(String::from("3.15")),
+        version_regex: None,
+    };
+    let probe = |version: Option<&str>| {
+        ToolProbe {
+            path: Some(PathBuf::from("/usr/bin/protoc")),
+            version: version.map(|version| version.to_owned()),
+        }
+    };
+    assert_eq!(requirement.problem(&probe(Some("3.21.12"))).unwrap(), None);
+    assert_eq!(requirement.problem(&probe(Some("3.15"))).unwrap(), None);
+    assert_eq!(requirement.problem(&probe(Some("3.6.1"))).unwrap(),
+               Some(String::from("'protoc' is version 3.6.1, 3.15 or newer is required")));
+    assert!(requirement.problem(&probe(None)).unwrap().unwrap().contains("version-regex"));
+    let missing = ToolProbe {
+        path: None,
+        version: None,
+    };
+    assert_eq!(requirement.problem(&missing).unwrap(), Some(String::from("'protoc' was not found")));
+}
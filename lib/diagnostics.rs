@@ -0,0 +1,210 @@
+// Copyright (c) 2015 - 2016, Alberto Corona <ac@albertocorona.com>
+// All rights reserved. This file is part of yabs, distributed under the BSD
+// 3-Clause license. For full terms please see the LICENSE file.
+
+extern crate regex;
+
+use error::YabsError;
+use ext::strip_ansi;
+use regex::Regex;
+use std::sync::LazyLock;
+
+// Notes ar and ld print on success that aren't worth a warning. Projects can
+// add their own patterns with 'link-warning-suppress'.
+pub const DEFAULT_LINK_SUPPRESS: &[&str] = &[r"^ar: creating ",
+                                             r"^ar: `u' modifier ignored since `D' is the default"];
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct LinkOutput {
+    // Every line that wasn't suppressed, kept for context
+    pub lines: Vec<String>,
+    // Lines that are actual warnings
+    pub warnings: Vec<String>,
+}
+
+pub fn link_suppress_patterns(extra: Option<&Vec<String>>) -> Result<Vec<Regex>, YabsError> {
+    let mut patterns = Vec::new();
+    for pattern in DEFAULT_LINK_SUPPRESS {
+        patterns.push(Regex::new(pattern)?);
+    }
+    if let Some(extra) = extra {
+        for pattern in extra {
+            patterns.push(Regex::new(pattern)?);
+        }
+    }
+    Ok(patterns)
+}
+
+// Sorts the stderr of a successful link or archive step into lines worth
+// showing and lines that are warnings. Lines are matched without their colors
+// and kept with them.
+pub fn classify_link_output(stderr: &str, suppress: &[Regex]) -> LinkOutput {
+    let mut output = LinkOutput::default();
+    for line in stderr.lines() {
+        let plain = strip_ansi(line);
+        if plain.trim().is_empty() || suppress.iter().any(|regex| regex.is_match(&plain)) {
+            continue;
+        }
+        if plain.to_lowercase().contains("warning:") {
+            output.warnings.push(line.to_owned());
+        }
+        output.lines.push(line.to_owned());
+    }
+    output
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+// A `file:line:col: warning:` or `error:` line, the column is optional
+static DIAGNOSTIC_LINE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^([^:\s][^:]*):\d+:(?:\d+:)? (?:fatal )?(warning|error):").unwrap());
+
+// The file and severity of every `file:line:col: warning:` or `error:` line a
+// gcc or clang compile wrote, `fatal error:` counts as an error. Other lines,
+// such as notes and context, are skipped.
+pub fn parse_compiler_diagnostics(stderr: &str) -> Vec<(String, Severity)> {
+    let mut diagnostics = Vec::new();
+    for captures in strip_ansi(stderr).lines().filter_map(|line| DIAGNOSTIC_LINE.captures(line)) {
+        let severity = if &captures[2] == "warning" { Severity::Warning } else { Severity::Error };
+        diagnostics.push((captures[1].trim_start_matches("./").to_owned(), severity));
+    }
+    diagnostics
+}
+
+// Splits what a compile of several `files` wrote to stderr into the part
+// about each of them, as indexes into `files`, by the file a line starts with.
+// Lines naming other files, such as headers, and context lines stay with the
+// file before them. Lines before any of `files` is named belong to none.
+pub fn diagnostics_by_file(stderr: &str, files: &[String]) -> Vec<(Option<usize>, String)> {
+    let mut parts: Vec<(Option<usize>, String)> = Vec::new();
+    let mut current = None;
+    for line in stderr.lines() {
+        let plain = strip_ansi(line);
+        let plain = plain.strip_prefix("In file included from ").unwrap_or(&plain);
+        if let Some(index) = files.iter().position(|file| plain.starts_with(&format!("{}:", file))) {
+            current = Some(index);
+        }
+        match parts.last_mut() {
+            Some(&mut (index, ref mut part)) if index == current => part.push_str(line),
+            _ => parts.push((current, line.to_owned())),
+        }
+        parts.last_mut().unwrap().1.push('\n');
+    }
+    parts
+}
+
+// Pairs of file and line coverage, such as ("src/main.c", "80.00% of 10"),
+// from the summary `gcov` prints for each file
+pub fn parse_gcov_summary(stdout: &str) -> Vec<(String, String)> {
+    let mut summary = Vec::new();
+    let mut file = None;
+    for line in stdout.lines() {
+        if line.starts_with("File '") && line.ends_with('\'') {
+            file = Some(line["File '".len()..line.len() - 1].to_owned());
+        } else if let Some(lines) = line.strip_prefix("Lines executed:") {
+            if let Some(file) = file.take() {
+                summary.push((file, lines.to_owned()));
+            }
+        } else {
+            file = None;
+        }
+    }
+    summary
+}
+
+#[cfg(test)]
+fn link_fixture(name: &str) -> String {
+    use std::fs::File;
+    use std::io::Read;
+    let mut buffer = String::new();
+    File::open(format!("test/link_output/{}", name))
+        .unwrap()
+        .read_to_string(&mut buffer)
+        .unwrap();
+    buffer
+}
+
+#[test]
+fn test_ar_notes_are_suppressed() {
+    let output = classify_link_output(&link_fixture("ar_deterministic.txt"),
+                                      &link_suppress_patterns(None).unwrap());
+    assert_eq!(output, LinkOutput::default());
+}
+
+#[test]
+fn test_ld_warnings_are_kept() {
+    let suppress = link_suppress_patterns(None).unwrap();
+    let output = classify_link_output(&link_fixture("ld_gets.txt"), &suppress);
+    assert_eq!(output.lines.len(), 2);
+    assert_eq!(output.warnings.len(), 1);
+    let output = classify_link_output(&link_fixture("ld_textrel.txt"), &suppress);
+    assert_eq!(output.lines.len(), 4);
+    assert_eq!(output.warnings.len(), 3);
+}
+
+#[test]
+fn test_extra_suppress_patterns() {
+    let extra = vec![String::from("executable stack"), String::from("NOTE: ")];
+    let output = classify_link_output(&link_fixture("ld_textrel.txt"),
+                                      &link_suppress_patterns(Some(&extra)).unwrap());
+    assert_eq!(output.warnings.len(), 2);
+}
+
+#[test]
+fn test_colored_link_output() {
+    let stderr = "\x1b[01m\x1b[Kar: creating libfoo.a\x1b[m\n\
+                  \x1b[01m\x1b[Ka.c:3:5:\x1b[m \x1b[01;35m\x1b[Kwarning:\x1b[m 'gets' is deprecated\n";
+    let output = classify_link_output(stderr, &link_suppress_patterns(None).unwrap());
+    assert_eq!(output.lines.len(), 1);
+    assert_eq!(output.warnings, vec![stderr.lines().nth(1).unwrap().to_owned()]);
+}
+
+#[test]
+fn test_parse_compiler_diagnostics() {
+    let stderr = "./src/a.c: In function 'main':\n\
+                  ./src/a.c:3:9: warning: unused variable 'x' [-Wunused-variable]\n\
+                  \x20   3 |     int x;\n\
+                  src/b.h:1:1: \x1b[01;31m\x1b[Kerror: \x1b[m\x1b[Kunknown type name 'foo'\n\
+                  src/c.c:10: warning: no column\n\
+                  src/d.c:1:10: fatal error: missing.h: No such file or directory\n\
+                  src/a.c:5:1: note: declared here\n\
+                  cc1: warning: command-line option not valid\n";
+    assert_eq!(parse_compiler_diagnostics(stderr),
+               vec![(String::from("src/a.c"), Severity::Warning),
+                    (String::from("src/b.h"), Severity::Error),
+                    (String::from("src/c.c"), Severity::Warning),
+                    (String::from("src/d.c"), Severity::Error)]);
+}
+
+#[test]
+fn test_diagnostics_by_file() {
+    let stderr = "cc1: note: run in a batch\n\
+                  a.c: In function 'main':\n\
+                  a.c:3:9: warning: unused variable 'x'\n\
+                  In file included from \x1b[01m\x1b[Kb.c:1\x1b[m:\n\
+                  b.h:1:1: error: unknown type name 'foo'\n\
+                  ab.c:2:1: warning: no newline\n\
+                  a.c:4:1: warning: again\n";
+    let files = vec![String::from("a.c"), String::from("b.c"), String::from("ab.c")];
+    assert_eq!(diagnostics_by_file(stderr, &files),
+               vec![(None, String::from("cc1: note: run in a batch\n")),
+                    (Some(0), String::from("a.c: In function 'main':\na.c:3:9: warning: unused variable 'x'\n")),
+                    (Some(1),
+                     String::from("In file included from \x1b[01m\x1b[Kb.c:1\x1b[m:\n\
+                                   b.h:1:1: error: unknown type name 'foo'\n")),
+                    (Some(2), String::from("ab.c:2:1: warning: no newline\n")),
+                    (Some(0), String::from("a.c:4:1: warning: again\n"))]);
+}
+
+#[test]
+fn test_parse_gcov_summary() {
+    let stdout = "File 'src/main.c'\nLines executed:80.00% of 10\n\n\
+                  File '/usr/include/stdio.h'\nNo executable lines\n\
+                  Lines executed:80.00% of 10\n";
+    assert_eq!(parse_gcov_summary(stdout),
+               vec![(String::from("src/main.c"), String::from("80.00% of 10"))]);
+}
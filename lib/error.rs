@@ -2,6 +2,9 @@
 // All rights reserved. This file is part of yabs, distributed under the BSD
 // 3-Clause license. For full terms please see the LICENSE file.
 
+// error_chain 0.10 still implements `description` and `cause`
+#![allow(deprecated)]
+
 extern crate error_chain;
 extern crate toml;
 extern crate walkdir;
@@ -18,6 +21,8 @@ error_chain! {
     foreign_links {
         Io(::std::io::Error);
         TomlDe(::toml::de::Error);
+        TomlSer(::toml::ser::Error);
+        JsonDe(::serde_json::Error);
         WalkDir(::walkdir::Error);
         SetLog(::log::SetLoggerError);
         UTF8(::std::string::FromUtf8Error);
@@ -33,13 +38,163 @@ error_chain! {
             description("command exited unsuccessfully")
                 display("command '{}' exited with status '{}'", cmd, status)
         }
+        BuildfileNotFound(path: ::std::path::PathBuf) {
+            description("buildfile not found")
+                display("buildfile '{}' doesn't exist", path.display())
+        }
+        BuildfileExists(path: ::std::path::PathBuf) {
+            description("buildfile already exists")
+                display("'{}' already exists, replacing it has to be forced", path.display())
+        }
         DirExists(path: ::std::path::PathBuf) {
             description("directory already exists")
                 display("directory '{}' already exists", path.display())
         }
-        TargetNotFound(ttype: String, name: String) {
+        InvalidValue(key: String, reason: String) {
+            description("invalid value in buildfile")
+                display("invalid value for '{}': {}", key, reason)
+        }
+        InheritanceCycle(files: String) {
+            description("buildfiles inherit from each other")
+                display("buildfiles inherit from each other: {}", files)
+        }
+        Interpolation(key: String, value: String, reason: String) {
+            description("variable could not be expanded")
+                display("could not expand '{}' of '{}': {}", value, key, reason)
+        }
+        OutputCollision(path: ::std::path::PathBuf, first: String, second: String) {
+            description("targets are written to the same file")
+                display("'{}' and '{}' are both written to '{}', give one of them another 'output'", first, second, path.display())
+        }
+        LinkWarnings(target: String, count: usize) {
+            description("link warnings treated as errors")
+                display("'{}' was linked with {} warning(s) and 'link-warnings-as-errors' is set", target, count)
+        }
+        SizeBudgetExceeded(artifact: String, size: u64, budget: String, change: String) {
+            description("artifact over its size budget")
+                display("'{}' is {} bytes, over its budget of {} ({})", artifact, size, budget, change)
+        }
+        NoProfileData(dir: ::std::path::PathBuf) {
+            description("training run produced no profile data")
+                display("the training run produced no profile data in '{}', make sure it runs the binaries built by yabs", dir.display())
+        }
+        PkgConfig(package: String, reason: String) {
+            description("pkg-config failed")
+                display("pkg-config could not provide '{}': {}", package, reason)
+        }
+        PackageNotFound(names: String, searched: String) {
+            description("dependency not found")
+                display("could not find an export file for {}, searched: {}", names, searched)
+        }
+        TaskCycle(path: String) {
+            description("cycle between tasks")
+                display("task dependencies form a cycle: {}", path)
+        }
+        GroupCycle(path: String) {
+            description("cycle between groups")
+                display("groups list each other: {}", path)
+        }
+        ToolNotFound(tool: String, hint: String) {
+            description("tool not found")
+                display("'{}' was not found, {}", tool, hint)
+        }
+        ProgramNotFound(role: String, program: String) {
+            description("program not found")
+                display("{} `{}` {}", role, program, if program.contains('/') {
+                    "doesn't exist or isn't executable"
+                } else {
+                    "not found in PATH"
+                })
+        }
+        LinkInputNotFound(target: String, path: String) {
+            description("link input not found")
+                display("'{}' is linked with '{}', which doesn't exist", target, path)
+        }
+        TargetNotFound(ttype: String, name: String, known: Vec<String>) {
             description("target not found")
-                display("no {} with name '{}' found", ttype, name)
+                display("no {} with name '{}' found{}", ttype, name, if known.is_empty() {
+                    String::new()
+                } else if let Some(close) = ::ext::closest_match(name, known.iter().map(String::as_str)) {
+                    format!("; did you mean `{}`? Expected one of {}", close, known.join(", "))
+                } else {
+                    format!(", expected one of {}", known.join(", "))
+                })
+        }
+        ObjectCollision(object: ::std::path::PathBuf, first: ::std::path::PathBuf, second: ::std::path::PathBuf) {
+            description("sources are compiled into the same object")
+                display("'{}' and '{}' would both be compiled into '{}', rename one of them", first.display(), second.display(), object.display())
+        }
+        DuplicateTarget(table: String, name: String, first: usize, second: usize) {
+            description("targets share a name")
+                display("[[{0}]] number {2} and number {3} are both named '{1}', names of [[{0}]] have to be unique", table, name, first, second)
+        }
+        AmbiguousTarget(name: String) {
+            description("a binary and a library share a name")
+                display("'{0}' is both a binary and a library, name it 'bin:{0}' or 'lib:{0}'", name)
+        }
+        SourceNotFound(path: ::std::path::PathBuf, close: Vec<String>) {
+            description("not a source of the project")
+                display("'{}' isn't a source of the project{}", path.display(), if close.is_empty() {
+                    String::new()
+                } else {
+                    format!(", did you mean {}?", close.join(", "))
+                })
+        }
+        ModuleNotFound(module: String, importer: String) {
+            description("imported module not found")
+                display("'{}' imports module '{}' but no source exports it", importer, module)
+        }
+        ModuleCycle(path: String) {
+            description("cycle between modules")
+                display("modules import each other: {}", path)
+        }
+        ToolRequirements(report: String) {
+            description("required tools are missing or too old")
+                display("required tools are missing or too old:\n{}", report)
+        }
+        NoSpace(cmd: String) {
+            description("command ran out of disk space")
+                display("command '{}' ran out of disk space", cmd)
+        }
+        OutOfDiskSpace(mount: String, needed: String) {
+            description("out of disk space")
+                display("out of disk space on {}, needed approximately {}", mount, needed)
+        }
+        Cancelled {
+            description("build cancelled")
+                display("the build was cancelled, objects compiled so far are kept")
+        }
+        Interrupted {
+            description("build interrupted")
+                display("the build was interrupted, objects compiled so far are kept")
+        }
+        Timeout(what: String, seconds: u64) {
+            description("command timed out")
+                display("{} timed out after {}s and was killed", what, seconds)
+        }
+        ProjectLocked(pid: u32) {
+            description("project in use by another yabs process")
+                display("another yabs process (pid {}) is using this project", pid)
+        }
+        CompileFailed(sources: String, status: i32) {
+            description("compile failed")
+                display("failed to compile {} (exit status {})", sources, status)
+        }
+        TooManyWarnings(warnings: usize, max: usize) {
+            description("too many warnings")
+                display("the build produced {} warning(s), more than 'max-warnings' allows ({})", warnings, max)
+        }
+        UnknownKeys(count: usize) {
+            description("unknown keys in the buildfile")
+                display("found {} unknown key(s) in the buildfile, which 'strict' doesn't allow", count)
+        }
+        CheckFailed(errors: usize) {
+            description("the buildfile has errors")
+                display("found {} error(s) in the buildfile", errors)
+        }
+        LogFile(path: ::std::path::PathBuf, reason: String) {
+            description("log file could not be opened")
+                display("could not open log file '{}': {}", path.display(), reason)
         }
     }
 }
@@ -0,0 +1,198 @@
+// Copyright (c) 2015 - 2016, Alberto Corona <ac@albertocorona.com>
+// All rights reserved. This file is part of yabs, distributed under the BSD
+// 3-Clause license. For full terms please see the LICENSE file.
+
+// Build events for `yabs --json`, written by `logger::JsonLogger` as one JSON
+// object per line on stdout. Every object has a "type":
+//
+//   {"type":"compile_started","source":"./main.c","object":"./main.o","command":"gcc -c -o ./main.o ./main.c"}
+//   {"type":"compile_finished","source":"./main.c","object":"./main.o","success":true,"status":0,
+//    "duration":0.052}
+//   {"type":"diagnostics","sources":["./main.c"],"output":"./main.c:1:1: warning: ..."}
+//   {"type":"link_started","target":"app","outputs":["app"]}
+//   {"type":"link_finished","target":"app","outputs":["app"],"success":true,"duration":0.031}
+//   {"type":"diagnostics_summary","errors":0,"warnings":2,"files":[{"path":"src/a.c","warnings":2,"errors":0}]}
+//   {"type":"removed","kind":"object","path":"./main.o"}
+//   {"type":"target","name":"app","kind":"binary","output":"app","default":true,"exists":true,
+//    "up_to_date":false}
+//   {"type":"message","level":"info","message":"linking 'app'"}
+//   {"type":"error","message":"'gcc -c -o ./main.o ./main.c' failed with 1"}
+//
+// "status" is the exit status, null when the compile was killed or its
+// status is unknown. "duration" is in seconds. Messages carry everything yabs
+// logs otherwise, "level" is "info" or "warning". New types and fields may be
+// added, so readers should skip ones they don't know.
+
+use ext::{json_array, json_string};
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+// The log target events are sent with, which the human readable logger skips
+pub const EVENT_TARGET: &str = "yabs::event";
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    CompileStarted {
+        source: String,
+        object: String,
+        command: String,
+    },
+    CompileFinished {
+        source: String,
+        object: String,
+        status: Option<i32>,
+        duration: Duration,
+    },
+    // What the compile of `sources` wrote to stderr
+    Diagnostics {
+        sources: Vec<String>,
+        output: String,
+    },
+    LinkStarted {
+        target: String,
+        outputs: Vec<String>,
+    },
+    LinkFinished {
+        target: String,
+        outputs: Vec<String>,
+        success: bool,
+        duration: Duration,
+    },
+    // Warnings and errors of the build's compiles by file, sent once the build
+    // finishes or fails
+    DiagnosticsSummary {
+        files: Vec<(String, usize, usize)>,
+    },
+    // One of the targets `yabs targets` lists, `output` is None for tasks
+    Target {
+        name: String,
+        kind: String,
+        output: Option<String>,
+        default: bool,
+        exists: bool,
+        up_to_date: bool,
+    },
+    // `kind` says what was removed, such as "object" or "binary"
+    Removed {
+        kind: String,
+        path: String,
+    },
+    Message {
+        level: String,
+        message: String,
+    },
+    Error {
+        message: String,
+    },
+}
+
+impl Event {
+    pub fn to_json(&self) -> String {
+        match *self {
+            Event::CompileStarted { ref source, ref object, ref command } => {
+                format!("{{\"type\":\"compile_started\",\"source\":{},\"object\":{},\"command\":{}}}",
+                        json_string(source),
+                        json_string(object),
+                        json_string(command))
+            },
+            Event::CompileFinished { ref source, ref object, status, duration } => {
+                format!("{{\"type\":\"compile_finished\",\"source\":{},\"object\":{},\"success\":{},\
+                         \"status\":{},\"duration\":{:.3}}}",
+                        json_string(source),
+                        json_string(object),
+                        status == Some(0),
+                        status.map(|status| status.to_string()).unwrap_or_else(|| String::from("null")),
+                        duration.as_secs_f64())
+            },
+            Event::Diagnostics { ref sources, ref output } => {
+                format!("{{\"type\":\"diagnostics\",\"sources\":{},\"output\":{}}}",
+                        json_array(sources),
+                        json_string(output))
+            },
+            Event::LinkStarted { ref target, ref outputs } => {
+                format!("{{\"type\":\"link_started\",\"target\":{},\"outputs\":{}}}",
+                        json_string(target),
+                        json_array(outputs))
+            },
+            Event::LinkFinished { ref target, ref outputs, success, duration } => {
+                format!("{{\"type\":\"link_finished\",\"target\":{},\"outputs\":{},\"success\":{},\
+                         \"duration\":{:.3}}}",
+                        json_string(target),
+                        json_array(outputs),
+                        success,
+                        duration.as_secs_f64())
+            },
+            Event::DiagnosticsSummary { ref files } => {
+                let objects: Vec<String> = files.iter()
+                                                .map(|&(ref path, warnings, errors)| {
+                                                         format!("{{\"path\":{},\"warnings\":{},\"errors\":{}}}",
+                                                                 json_string(path),
+                                                                 warnings,
+                                                                 errors)
+                                                     })
+                                                .collect();
+                format!("{{\"type\":\"diagnostics_summary\",\"errors\":{},\"warnings\":{},\"files\":[{}]}}",
+                        files.iter().map(|file| file.2).sum::<usize>(),
+                        files.iter().map(|file| file.1).sum::<usize>(),
+                        objects.join(","))
+            },
+            Event::Target { ref name, ref kind, ref output, default, exists, up_to_date } => {
+                format!("{{\"type\":\"target\",\"name\":{},\"kind\":{},\"output\":{},\"default\":{},\
+                         \"exists\":{},\"up_to_date\":{}}}",
+                        json_string(name),
+                        json_string(kind),
+                        output.as_ref().map(|output| json_string(output)).unwrap_or_else(|| String::from("null")),
+                        default,
+                        exists,
+                        up_to_date)
+            },
+            Event::Removed { ref kind, ref path } => {
+                format!("{{\"type\":\"removed\",\"kind\":{},\"path\":{}}}", json_string(kind), json_string(path))
+            },
+            Event::Message { ref level, ref message } => {
+                format!("{{\"type\":\"message\",\"level\":{},\"message\":{}}}",
+                        json_string(level),
+                        json_string(message))
+            },
+            Event::Error { ref message } => format!("{{\"type\":\"error\",\"message\":{}}}", json_string(message)),
+        }
+    }
+}
+
+// Called by `JsonLogger::init`, events aren't built otherwise
+pub fn enable() {
+    ENABLED.store(true, Ordering::SeqCst);
+}
+
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::SeqCst)
+}
+
+// Sends the event built by `event` to the logger when events are enabled
+pub fn emit<F: FnOnce() -> Event>(event: F) {
+    if enabled() {
+        info!(target: EVENT_TARGET, "{}", event().to_json());
+    }
+}
+
+#[test]
+fn test_event_json() {
+    let event = Event::CompileFinished {
+        source: String::from("./main.c"),
+        object: String::from("./main.o"),
+        status: Some(1),
+        duration: Duration::from_millis(52),
+    };
+    assert_eq!(event.to_json(),
+               "{\"type\":\"compile_finished\",\"source\":\"./main.c\",\"object\":\"./main.o\",\"success\":false,\
+                \"status\":1,\"duration\":0.052}");
+    let event = Event::Message {
+        level: String::from("warning"),
+        message: String::from("\"quoted\"\nand more"),
+    };
+    assert_eq!(event.to_json(),
+               "{\"type\":\"message\",\"level\":\"warning\",\"message\":\"\\\"quoted\\\"\\nand more\"}");
+}
@@ -4,23 +4,49 @@
 
 extern crate toml;
 
+use context::BuildContext;
 use error::{YabsError, YabsErrorKind};
-use std::env;
-use std::fs::File;
-use std::io::Read;
+use std::collections::hash_map::{DefaultHasher, RandomState};
+use std::hash::{BuildHasher, Hash, Hasher};
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
 use std::path::{Path, PathBuf};
-use std::process::{Child, Command};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant, UNIX_EPOCH};
+use regex::Regex;
+use walkdir::WalkDir;
 
 pub struct Job {
     process: Child,
     command: String,
+    spawned: Instant,
+    finished: Option<Instant>,
+    // Reads everything the job writes to a piped stderr, so a full pipe never
+    // blocks it
+    stderr: Option<JoinHandle<String>>,
+    diagnostics: String,
 }
 
 impl Job {
     pub fn new(raw: (String, Child)) -> Job {
+        let (command, mut process) = raw;
+        let stderr = process.stderr.take().map(|mut stderr| {
+            thread::spawn(move || {
+                let mut captured = Vec::new();
+                let _ = stderr.read_to_end(&mut captured);
+                String::from_utf8_lossy(&captured).into_owned()
+            })
+        });
         Job {
-            command: raw.0,
-            process: raw.1,
+            command,
+            process,
+            spawned: Instant::now(),
+            finished: None,
+            stderr,
+            diagnostics: String::new(),
         }
     }
 
@@ -28,13 +54,49 @@ impl Job {
         self.command.clone()
     }
 
+    // What the job wrote to stderr, once it has finished
+    pub fn diagnostics(&self) -> &str {
+        &self.diagnostics
+    }
+
+    // From spawning the job until it was seen to finish
+    pub fn duration(&self) -> Duration {
+        self.finished.unwrap_or_else(Instant::now).duration_since(self.spawned)
+    }
+
     pub fn yield_self(&mut self) -> Result<(), YabsError> {
         let status = self.process.wait()?;
+        self.finished = Some(Instant::now());
+        self.check(status)
+    }
+
+    // Kills the job and waits for it to exit, for a build that has run out of
+    // time. Its stderr isn't waited for, a process the job started may still
+    // hold it open.
+    pub fn kill(&mut self) {
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+        self.finished = Some(Instant::now());
+        self.stderr = None;
+    }
+
+    // Whether the job has finished, without waiting for it
+    pub fn try_yield(&mut self) -> Result<bool, YabsError> {
+        match self.process.try_wait()? {
+            Some(status) => {
+                self.finished = Some(Instant::now());
+                self.check(status)?;
+                Ok(true)
+            },
+            None => Ok(false),
+        }
+    }
+
+    fn check(&mut self, status: ExitStatus) -> Result<(), YabsError> {
+        self.diagnostics = self.stderr.take().and_then(|stderr| stderr.join().ok()).unwrap_or_default();
         if !status.success() {
-            if let Some(ref mut stderr) = self.process.stderr {
-                let mut buffer = String::new();
-                stderr.read_to_string(&mut buffer)?;
-                info!("{}", buffer);
+            if self.diagnostics.contains(NO_SPACE_MESSAGE) {
+                bail!(YabsErrorKind::NoSpace(self.command()));
             }
             bail!(YabsErrorKind::Command(self.command(), status.code().unwrap_or(1)));
         }
@@ -42,6 +104,35 @@ impl Job {
     }
 }
 
+// How tools report ENOSPC
+const NO_SPACE_MESSAGE: &str = "No space left on device";
+
+// Whether `error` is a job or a write that failed because the disk is full
+pub fn is_out_of_space(error: &YabsError) -> bool {
+    match *error.kind() {
+        YabsErrorKind::NoSpace(_) => true,
+        YabsErrorKind::Io(ref error) => error.kind() == ::std::io::ErrorKind::StorageFull,
+        _ => false,
+    }
+}
+
+// Shared with an embedder to stop a build. The build stops dispatching jobs,
+// waits for running ones and returns `Cancelled`.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+}
+
 pub fn parse_toml_file<T: AsRef<Path> + Clone>(file: T) -> Result<String, YabsError> {
     let mut buff = String::new();
     let mut file = File::open(&file)?;
@@ -49,37 +140,449 @@ pub fn parse_toml_file<T: AsRef<Path> + Clone>(file: T) -> Result<String, YabsEr
     Ok(buff)
 }
 
-pub fn get_assumed_filename() -> Option<String> {
-    if let Ok(current_dir) = env::current_dir() {
-        if let Some(file_stem) = current_dir.components().last() {
-            let mut file_name = file_stem.as_ref().to_string_lossy().into_owned();
-            file_name.push_str(".toml");
-            return Some(file_name);
-        }
+// The build file named after the directory of `context`
+pub fn get_assumed_filename(context: &BuildContext) -> Option<String> {
+    if let Some(file_stem) = context.cwd.components().next_back() {
+        let mut file_name = file_stem.as_os_str().to_string_lossy().into_owned();
+        file_name.push_str(".toml");
+        return Some(file_name);
     }
     None
 }
 
-pub fn get_assumed_filename_for_dir(dir: &PathBuf) -> Option<PathBuf> {
+pub fn get_assumed_filename_for_dir(dir: &Path) -> Option<PathBuf> {
     if let Some(file_stem) = dir.file_stem() {
         return Some(PathBuf::from(file_stem.to_string_lossy().into_owned() + ".toml"));
     }
     None
 }
 
-pub fn run_cmd(cmd: &str) -> Result<(), YabsError> {
-    let command = Command::new("sh").arg("-c").arg(&cmd).spawn()?.wait_with_output()?;
-    println!("{}", &cmd);
-    if !command.status.success() {
-        print!("{}", String::from_utf8(command.stderr)?);
-        bail!(YabsErrorKind::Command(cmd.to_owned(), command.status.code().unwrap_or(1)));
+// Scripts are run through a shell, compile and link commands are not, see
+// `run_args`. Commands run in `dir`, usually the project's directory.
+pub fn run_cmd(cmd: &str, dir: &Path) -> Result<(), YabsError> {
+    run_cmd_within(cmd, dir, None)
+}
+
+// Like `run_cmd`, killing the command once it has run for `timeout`
+pub fn run_cmd_within(cmd: &str, dir: &Path, timeout: Option<Duration>) -> Result<(), YabsError> {
+    let mut child = Command::new("sh").arg("-c").arg(cmd).current_dir(dir).spawn()?;
+    let status = wait_within(&mut child, timeout)?;
+    info!("{}", &cmd);
+    let status = match status {
+        Some(status) => status,
+        None => bail!(YabsErrorKind::Timeout(format!("script '{}'", cmd), timeout.unwrap_or_default().as_secs())),
+    };
+    if !status.success() {
+        bail!(YabsErrorKind::Command(cmd.to_owned(), status.code().unwrap_or(1)));
     }
-    print!("{}", String::from_utf8(command.stdout)?);
     Ok(())
 }
 
-pub fn spawn_cmd(cmd: &str) -> Result<Child, YabsError> {
-    Ok(Command::new("sh").arg("-c").arg(&cmd).spawn()?)
+// Waits for `child`, or kills it once it has run for `timeout` and returns
+// None
+fn wait_within(child: &mut Child, timeout: Option<Duration>) -> io::Result<Option<ExitStatus>> {
+    let timeout = match timeout {
+        Some(timeout) => timeout,
+        None => return child.wait().map(Some),
+    };
+    let started = Instant::now();
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(Some(status));
+        }
+        if started.elapsed() >= timeout {
+            let _ = child.kill();
+            child.wait()?;
+            return Ok(None);
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+// Where a compile or link writes before its output is renamed into place,
+// next to `path` so the rename stays on one filesystem
+pub fn temporary_path(path: &Path) -> PathBuf {
+    PathBuf::from(format!("{}.tmp.{}", path.display(), ::std::process::id()))
+}
+
+// Renames the output written to `temporary_path(path)` into place. A command
+// that succeeded without writing it leaves `path` as it was.
+pub fn commit_temporary(path: &Path) -> Result<(), YabsError> {
+    match fs::rename(temporary_path(path), path) {
+        Err(ref error) if error.kind() == io::ErrorKind::NotFound => Ok(()),
+        result => Ok(result?),
+    }
+}
+
+pub fn discard_temporary(path: &Path) {
+    let _ = fs::remove_file(temporary_path(path));
+}
+
+// Output a command wrote, passed on through the logger
+pub fn log_output(output: &str) {
+    if !output.is_empty() {
+        info!("{}", output.trim_end_matches('\n'));
+    }
+}
+
+// `text` without the ANSI escape sequences terminals color it with
+pub fn strip_ansi(text: &str) -> String {
+    let mut stripped = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            // A CSI sequence such as "\x1b[01;31m" ends with a letter
+            if chars.next() == Some('[') {
+                for c in chars.by_ref() {
+                    if c.is_ascii_alphabetic() {
+                        break;
+                    }
+                }
+            }
+        } else {
+            stripped.push(c);
+        }
+    }
+    stripped
+}
+
+// Builds a `Command` from an argv with the program as its first element
+pub fn command_from_args(args: &[String]) -> Command {
+    let mut command = Command::new(&args[0]);
+    command.args(&args[1..]);
+    command
+}
+
+// Renders an argv for logging, quoting any argument the shell would split or
+// expand so the line can be pasted back into a terminal
+pub fn display_args(args: &[String]) -> String {
+    args.iter()
+        .map(|arg| if arg.is_empty() ||
+                      arg.chars().any(|c| !(c.is_alphanumeric() || "-_=+./,:@%^".contains(c))) {
+                 format!("'{}'", arg.replace('\'', "'\\''"))
+             } else {
+                 arg.clone()
+             })
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+// Returns what the command wrote to stderr, since linkers and archivers can
+// warn without failing
+pub fn run_args(args: &[String], dir: &Path) -> Result<String, YabsError> {
+    run_args_within(args, dir, None, "")
+}
+
+// Like `run_args`, killing the command once it has run for `timeout`. The
+// `Timeout` error says it was `what` that took too long.
+pub fn run_args_within(args: &[String],
+                       dir: &Path,
+                       timeout: Option<Duration>,
+                       what: &str)
+                       -> Result<String, YabsError> {
+    run_args_unlogged(args, dir, &[], timeout, what)
+}
+
+// Like `run_args_within`, also passing `unlogged`, arguments such as
+// `-fdiagnostics-color` that are left out of the logged command
+pub fn run_args_unlogged(args: &[String],
+                         dir: &Path,
+                         unlogged: &[String],
+                         timeout: Option<Duration>,
+                         what: &str)
+                         -> Result<String, YabsError> {
+    let cmd = display_args(args);
+    debug!("{}", &cmd);
+    let mut child = command_from_args(args).args(unlogged).current_dir(dir).stderr(Stdio::piped()).spawn()?;
+    let stderr = child.stderr.take().map(|mut stderr| {
+        thread::spawn(move || {
+            let mut captured = Vec::new();
+            let _ = stderr.read_to_end(&mut captured);
+            captured
+        })
+    });
+    let status = match wait_within(&mut child, timeout)? {
+        Some(status) => status,
+        // A process the command started may still hold stderr open
+        None => bail!(YabsErrorKind::Timeout(what.to_owned(), timeout.unwrap_or_default().as_secs())),
+    };
+    let stderr = String::from_utf8(stderr.and_then(|stderr| stderr.join().ok()).unwrap_or_default())?;
+    if !status.success() {
+        log_output(&stderr);
+        bail!(YabsErrorKind::Command(cmd, status.code().unwrap_or(1)));
+    }
+    Ok(stderr)
+}
+
+// Stderr is piped so `Job` can tell why the command failed
+pub fn spawn_args(args: &[String], dir: &Path) -> Result<(String, Child), YabsError> {
+    Ok((display_args(args), command_from_args(args).current_dir(dir).stderr(Stdio::piped()).spawn()?))
+}
+
+// Entries such as "`pkg-config --libs sdl2`" are run through the shell once and
+// their output split into separate arguments
+pub fn command_output_args(cmd: &str, dir: &Path) -> Result<Vec<String>, YabsError> {
+    let cmd = cmd.trim_matches('`');
+    let output = Command::new("sh").arg("-c").arg(cmd).current_dir(dir).output()?;
+    if !output.status.success() {
+        log_output(&String::from_utf8(output.stderr)?);
+        bail!(YabsErrorKind::Command(cmd.to_owned(), output.status.code().unwrap_or(1)));
+    }
+    Ok(String::from_utf8(output.stdout)?
+           .split_whitespace()
+           .map(|arg| arg.to_owned())
+           .collect())
+}
+
+// Hashes the paths and contents of `files`, relative ones are read from
+// `dir`. The hash only needs to be stable between runs of the same yabs
+// binary, a mismatch just redoes some work.
+pub fn hash_files(files: &[PathBuf], dir: &Path) -> Result<u64, YabsError> {
+    let mut hasher = DefaultHasher::new();
+    for path in files {
+        let mut contents = Vec::new();
+        File::open(dir.join(path))?.read_to_end(&mut contents)?;
+        path.hash(&mut hasher);
+        contents.hash(&mut hasher);
+    }
+    Ok(hasher.finish())
+}
+
+// Hash of the contents of `file`, for telling whether it changed
+pub fn hash_file(file: &Path) -> Result<String, YabsError> {
+    let mut hasher = DefaultHasher::new();
+    fs::read(file)?.hash(&mut hasher);
+    Ok(format!("{:016x}", hasher.finish()))
+}
+
+pub fn hash_strings(strings: &[String]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    strings.hash(&mut hasher);
+    hasher.finish()
+}
+
+// One job per logical CPU, or a single job if they can't be counted
+pub fn default_jobs() -> usize {
+    ::num_cpus::get().max(1)
+}
+
+// Identifies one run of yabs, `YABS_BUILD_ID` lets CI use its own. Otherwise
+// it's the start time in seconds and a random suffix. It must never be part of
+// a command or a hash.
+pub fn new_build_id(context: &BuildContext) -> String {
+    if let Some(id) = context.non_empty_var("YABS_BUILD_ID") {
+        return id.to_owned();
+    }
+    let now = context.now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    // RandomState is seeded randomly per process
+    let random = RandomState::new().hash_one(now.subsec_nanos());
+    format!("{}-{:06x}", now.as_secs(), random & 0xff_ffff)
+}
+
+// Whether `path` is a file that can be run
+#[cfg(unix)]
+pub fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    path.metadata().map(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0).unwrap_or(false)
+}
+
+#[cfg(not(unix))]
+pub fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+// First line of `<tool> --version` run in `dir`, or "unknown" if the tool
+// can't tell us
+pub fn tool_version(tool: &str, dir: &Path) -> String {
+    Command::new(tool)
+        .arg("--version")
+        .current_dir(dir)
+        .output()
+        .ok()
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .and_then(|stdout| stdout.lines().next().map(|line| line.trim().to_owned()))
+        .unwrap_or_else(|| String::from("unknown"))
+}
+
+// Expands `*`, `?` and `**` in `pattern` by walking from the part of the path
+// before the first wildcard. A pattern without wildcards is returned as is,
+// whether or not the file exists. Relative patterns are walked in `dir` and
+// their matches stay relative to it.
+pub fn expand_glob(pattern: &str, dir: &Path) -> Result<Vec<PathBuf>, YabsError> {
+    let pattern = pattern.trim_start_matches("./");
+    if !pattern.contains('*') && !pattern.contains('?') {
+        return Ok(vec![PathBuf::from(pattern)]);
+    }
+    let mut root = PathBuf::new();
+    for part in pattern.split('/') {
+        if part.contains('*') || part.contains('?') {
+            break;
+        }
+        root.push(part);
+    }
+    let regex = Regex::new(&glob_regex(pattern))?;
+    let mut matches = Vec::new();
+    if !root.as_os_str().is_empty() && !dir.join(&root).is_dir() {
+        return Ok(matches);
+    }
+    for entry in WalkDir::new(dir.join(&root)) {
+        let entry = entry?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let path = entry.path().strip_prefix(dir).unwrap_or(entry.path()).to_path_buf();
+        if regex.is_match(&path.to_string_lossy()) {
+            matches.push(path);
+        }
+    }
+    matches.sort();
+    Ok(matches)
+}
+
+// Whether `path` matches `pattern` the way `expand_glob` would find it
+pub fn glob_matches(pattern: &str, path: &Path) -> Result<bool, YabsError> {
+    let regex = Regex::new(&glob_regex(pattern.trim_start_matches("./")))?;
+    let path = path.strip_prefix(".").unwrap_or(path);
+    Ok(regex.is_match(&path.to_string_lossy()))
+}
+
+fn glob_regex(pattern: &str) -> String {
+    let mut regex = String::from("^");
+    let mut chars = pattern.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                if chars.peek() == Some(&'/') {
+                    chars.next();
+                    regex.push_str("(.*/)?");
+                } else {
+                    regex.push_str(".*");
+                }
+            },
+            '*' => regex.push_str("[^/]*"),
+            '?' => regex.push_str("[^/]"),
+            c => regex.push_str(&::regex::escape(&c.to_string())),
+        }
+    }
+    regex.push('$');
+    regex
+}
+
+// True when every output exists and is newer than every input, relative
+// paths are found in `dir`. An empty output list is never up to date, and
+// neither is one with a missing input.
+pub fn outputs_up_to_date(inputs: &[PathBuf], outputs: &[PathBuf], dir: &Path) -> Result<bool, YabsError> {
+    if outputs.is_empty() {
+        return Ok(false);
+    }
+    let mut oldest_output = None;
+    for output in outputs {
+        let output = dir.join(output);
+        if !output.exists() {
+            return Ok(false);
+        }
+        let modified = fs::metadata(output)?.modified()?;
+        if oldest_output.is_none_or(|oldest| modified < oldest) {
+            oldest_output = Some(modified);
+        }
+    }
+    for input in inputs {
+        let input = dir.join(input);
+        if !input.exists() || Some(fs::metadata(&input)?.modified()?) > oldest_output {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+// Splits `line` into words the way sh would, honoring single and double
+// quotes and backslash escapes but without any expansion
+pub fn shell_split(line: &str) -> Result<Vec<String>, YabsError> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => {
+                in_word = true;
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => word.push(c),
+                        None => bail!(YabsErrorKind::InvalidValue(line.to_owned(),
+                                                                  "unterminated single quote".to_owned())),
+                    }
+                }
+            },
+            '"' => {
+                in_word = true;
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => {
+                            match chars.next() {
+                                Some(c) if c == '"' || c == '\\' || c == '$' || c == '`' => word.push(c),
+                                Some(c) => {
+                                    word.push('\\');
+                                    word.push(c);
+                                },
+                                None => break,
+                            }
+                        },
+                        Some(c) => word.push(c),
+                        None => bail!(YabsErrorKind::InvalidValue(line.to_owned(),
+                                                                  "unterminated double quote".to_owned())),
+                    }
+                }
+            },
+            '\\' => {
+                in_word = true;
+                if let Some(c) = chars.next() {
+                    word.push(c);
+                }
+            },
+            c if c.is_whitespace() => {
+                if in_word {
+                    words.push(word.clone());
+                    word.clear();
+                    in_word = false;
+                }
+            },
+            c => {
+                in_word = true;
+                word.push(c);
+            },
+        }
+    }
+    if in_word {
+        words.push(word);
+    }
+    Ok(words)
+}
+
+// `value` as a quoted JSON string. serde_json is too old for our serde, so
+// JSON output is written by hand.
+pub fn json_string(value: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+pub fn json_array(values: &[String]) -> String {
+    format!("[{}]", values.iter().map(|value| json_string(value)).collect::<Vec<_>>().join(","))
 }
 
 pub trait PrependEach<T> {
@@ -96,3 +599,199 @@ impl PrependEach<String> for Vec<String> {
         clone
     }
 }
+
+// Response files pass arguments to gcc and ar through `@file` when a command
+// would otherwise exceed the OS command-line length limit. The file is removed
+// when dropped unless `keep` is set.
+pub struct ResponseFile {
+    path: PathBuf,
+    keep: bool,
+}
+
+impl ResponseFile {
+    pub fn create(path: PathBuf, args: &[PathBuf], keep: bool) -> Result<ResponseFile, YabsError> {
+        let mut file = File::create(&path)?;
+        for arg in args {
+            writeln!(file, "{}", quote_response_arg(&arg.to_string_lossy()))?;
+        }
+        Ok(ResponseFile { path, keep })
+    }
+
+    pub fn arg(&self) -> String {
+        format!("@{}", self.path.display())
+    }
+}
+
+impl Drop for ResponseFile {
+    fn drop(&mut self) {
+        if self.keep {
+            info!("kept response file '{}'", self.path.display());
+        } else {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+// `value` as written on one line of a TOML file
+pub fn inline_toml(value: &::toml::Value) -> String {
+    match *value {
+        ::toml::Value::Array(ref values) => {
+            format!("[{}]", values.iter().map(inline_toml).collect::<Vec<_>>().join(", "))
+        },
+        ::toml::Value::Table(ref table) if table.is_empty() => String::from("{}"),
+        ::toml::Value::Table(ref table) => {
+            let mut entries = Vec::new();
+            for (key, value) in table {
+                let bare = !key.is_empty() && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+                let key = if bare { key.clone() } else { ::toml::Value::String(key.clone()).to_string() };
+                entries.push(format!("{} = {}", key, inline_toml(value)));
+            }
+            format!("{{ {} }}", entries.join(", "))
+        },
+        ref value => value.to_string(),
+    }
+}
+
+// How many characters have to be inserted, removed or replaced to turn `a`
+// into `b`
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..b.len() + 1).collect();
+    for (i, a) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, b) in b.iter().enumerate() {
+            let replaced = diagonal + if a == *b { 0 } else { 1 };
+            diagonal = row[j + 1];
+            row[j + 1] = replaced.min(row[j] + 1).min(row[j + 1] + 1);
+        }
+    }
+    row[b.len()]
+}
+
+// The candidate `name` is most likely a typo of, if any is close enough
+pub fn closest_match<'a, I: IntoIterator<Item = &'a str>>(name: &str, candidates: I) -> Option<&'a str> {
+    closest_matches(name, candidates, 1).pop()
+}
+
+// Up to `count` of the candidates close enough to `name`, the closest first
+pub fn closest_matches<'a, I: IntoIterator<Item = &'a str>>(name: &str, candidates: I, count: usize) -> Vec<&'a str> {
+    let allowed = name.chars().count().max(3) / 3;
+    let mut close: Vec<(usize, &'a str)> = candidates.into_iter()
+                                                     .map(|candidate| (edit_distance(name, candidate), candidate))
+                                                     .filter(|&(distance, _)| distance <= allowed)
+                                                     .collect();
+    close.sort_by_key(|&(distance, _)| distance);
+    close.into_iter().take(count).map(|(_, candidate)| candidate).collect()
+}
+
+// Response files are split on whitespace, so each argument is double quoted
+// with `\` and `"` escaped, which is what gcc and GNU ar expect
+pub fn quote_response_arg(arg: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in arg.chars() {
+        if c == '\\' || c == '"' {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[test]
+fn test_display_args() {
+    let args = vec!["gcc".to_owned(),
+                    "-DNAME=\"yabs\"".to_owned(),
+                    "dir with spaces/main.c".to_owned(),
+                    "-Wl,-rpath,$ORIGIN".to_owned()];
+    assert_eq!(display_args(&args),
+               "gcc '-DNAME=\"yabs\"' 'dir with spaces/main.c' '-Wl,-rpath,$ORIGIN'");
+}
+
+#[test]
+fn test_strip_ansi() {
+    assert_eq!(strip_ansi("\x1b[01m\x1b[Kmain.c:1:1:\x1b[m\x1b[K \x1b[01;31merror:\x1b[m plain"),
+               "main.c:1:1: error: plain");
+    assert_eq!(strip_ansi("no escapes"), "no escapes");
+}
+
+#[test]
+fn test_closest_match() {
+    assert_eq!(edit_distance("complier", "compiler"), 2);
+    assert_eq!(edit_distance("", "ar"), 2);
+    assert_eq!(closest_match("complier", vec!["compiler", "compiler-flags"]), Some("compiler"));
+    assert_eq!(closest_match("incldue", vec!["include", "defines"]), Some("include"));
+    assert_eq!(closest_match("x", vec!["ar"]), None);
+    assert_eq!(closest_matches("src/mian.c", vec!["src/util.c", "src/main.c", "src/man.c", "src/lib.c"], 2),
+               vec!["src/man.c", "src/main.c"]);
+}
+
+#[test]
+fn test_inline_toml() {
+    let value: ::toml::Value = "deps = [{ name = \"a\" }, { name = \"b\", version = \">=1\" }]\n\"odd key\" = {}\n"
+                                   .parse()
+                                   .unwrap();
+    assert_eq!(inline_toml(&value["deps"]), "[{ name = \"a\" }, { name = \"b\", version = \">=1\" }]");
+    assert_eq!(inline_toml(&value),
+               "{ deps = [{ name = \"a\" }, { name = \"b\", version = \">=1\" }], \"odd key\" = {} }");
+}
+
+#[test]
+fn test_quote_response_arg() {
+    assert_eq!(quote_response_arg("src/main.o"), "\"src/main.o\"");
+    assert_eq!(quote_response_arg("dir with spaces/a.o"), "\"dir with spaces/a.o\"");
+    assert_eq!(quote_response_arg("C:\\obj\"s\\a.o"), "\"C:\\\\obj\\\"s\\\\a.o\"");
+}
+
+#[test]
+fn test_glob_regex() {
+    let regex = Regex::new(&glob_regex("gen/**/*.h")).unwrap();
+    assert!(regex.is_match("gen/table.h"));
+    assert!(regex.is_match("gen/a/b/table.h"));
+    assert!(!regex.is_match("gen/table.c"));
+    assert!(!regex.is_match("src/gen/table.h"));
+    let regex = Regex::new(&glob_regex("data/?.csv")).unwrap();
+    assert!(regex.is_match("data/a.csv"));
+    assert!(!regex.is_match("data/ab.csv"));
+}
+
+#[test]
+fn test_shell_split() {
+    assert_eq!(shell_split("  -O2 -g\t-pipe ").unwrap(), vec!["-O2", "-g", "-pipe"]);
+    assert_eq!(shell_split("-DNAME='a b' \"-I/opt/my dir\" -DQ=\\\"x\\\"").unwrap(),
+               vec!["-DNAME=a b", "-I/opt/my dir", "-DQ=\"x\""]);
+    assert_eq!(shell_split("a\\ b ''").unwrap(), vec!["a b", ""]);
+    assert!(shell_split("-DNAME='oops").is_err());
+}
+
+#[test]
+fn test_is_out_of_space() {
+    let error = File::options().write(true).open("/dev/full").and_then(|mut full| full.write_all(b"object"));
+    if let Err(error) = error {
+        assert!(is_out_of_space(&YabsError::from(error)));
+    }
+    assert!(is_out_of_space(&YabsErrorKind::NoSpace(String::from("gcc -c main.c")).into()));
+    assert!(!is_out_of_space(&YabsErrorKind::Command(String::from("gcc -c main.c"), 1).into()));
+}
+
+#[test]
+fn test_json_string() {
+    assert_eq!(json_string("a \"b\"\\c\n\u{1}"), "\"a \\\"b\\\"\\\\c\\n\\u0001\"");
+    assert_eq!(json_array(&["x".to_owned(), "y".to_owned()]), "[\"x\",\"y\"]");
+}
+
+#[test]
+fn test_outputs_up_to_date() {
+    let dir = ::std::env::temp_dir().join(format!("yabs-outputs-test-{}", ::std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let (inputs, outputs) = (vec![PathBuf::from("input")], vec![dir.join("output")]);
+    fs::write(dir.join("input"), "").unwrap();
+    fs::write(&outputs[0], "").unwrap();
+    assert!(outputs_up_to_date(&inputs, &outputs, &dir).unwrap());
+    assert!(!outputs_up_to_date(&inputs, &[], &dir).unwrap());
+    // A missing input or output makes them stale instead of failing
+    assert!(!outputs_up_to_date(&[dir.join("missing")], &outputs, &dir).unwrap());
+    assert!(!outputs_up_to_date(&inputs, &[PathBuf::from("missing")], &dir).unwrap());
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,139 @@
+// Copyright (c) 2015 - 2016, Alberto Corona <ac@albertocorona.com>
+// All rights reserved. This file is part of yabs, distributed under the BSD
+// 3-Clause license. For full terms please see the LICENSE file.
+
+use error::YabsError;
+use std::fs::{self, File};
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// What the filesystem holding the project can do. FAT and some network shares
+// lack symlinks and permissions, keep modification times to 2 seconds and
+// ignore case.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FsCapabilities {
+    pub symlinks: bool,
+    #[serde(rename = "fine-mtime")]
+    pub fine_mtime: bool,
+    pub permissions: bool,
+    #[serde(rename = "case-sensitive")]
+    pub case_sensitive: bool,
+}
+
+impl Default for FsCapabilities {
+    fn default() -> FsCapabilities {
+        FsCapabilities {
+            symlinks: true,
+            fine_mtime: true,
+            permissions: true,
+            case_sensitive: true,
+        }
+    }
+}
+
+impl FsCapabilities {
+    // Tries each feature on scratch files in `dir`
+    pub fn probe(dir: &Path) -> Result<FsCapabilities, YabsError> {
+        let probe = dir.join("yabs-probe");
+        File::create(&probe)?;
+        let caps = FsCapabilities {
+            symlinks: probe_symlinks(&probe, &dir.join("yabs-probe-link")),
+            fine_mtime: probe_fine_mtime(&probe),
+            permissions: probe_permissions(&probe),
+            case_sensitive: !dir.join("YABS-PROBE").exists(),
+        };
+        fs::remove_file(&probe)?;
+        Ok(caps)
+    }
+
+    // How much newer a source has to be than an artifact to be seen as
+    // changed after it
+    pub fn mtime_granularity(&self) -> Duration {
+        if self.fine_mtime {
+            Duration::from_secs(0)
+        } else {
+            Duration::from_secs(2)
+        }
+    }
+
+    pub fn limitations(&self) -> Vec<&'static str> {
+        let mut limitations = Vec::new();
+        if !self.symlinks {
+            limitations.push("the filesystem doesn't support symlinks, scripts making them will fail");
+        }
+        if !self.fine_mtime {
+            limitations.push("the filesystem keeps coarse modification times, sources are compared by \
+                              their contents unless 'change-detection' is set");
+        }
+        if !self.permissions {
+            limitations.push("the filesystem doesn't keep permissions, binaries linked on it may not be \
+                              executable");
+        }
+        if !self.case_sensitive {
+            limitations.push("the filesystem ignores case, sources whose names only differ in case \
+                              will overwrite each other's objects");
+        }
+        limitations
+    }
+}
+
+#[cfg(unix)]
+fn symlink(target: &Path, link: &Path) -> Result<(), YabsError> {
+    Ok(::std::os::unix::fs::symlink(target, link)?)
+}
+
+#[cfg(not(unix))]
+fn symlink(target: &Path, link: &Path) -> Result<(), YabsError> {
+    Ok(::std::os::windows::fs::symlink_file(target, link)?)
+}
+
+#[cfg(unix)]
+fn set_mode(path: &Path, mode: u32) -> Result<(), YabsError> {
+    use std::os::unix::fs::PermissionsExt;
+    Ok(fs::set_permissions(path, fs::Permissions::from_mode(mode))?)
+}
+
+#[cfg(not(unix))]
+fn set_mode(_: &Path, _: u32) -> Result<(), YabsError> {
+    Ok(())
+}
+
+fn probe_symlinks(probe: &Path, link: &Path) -> bool {
+    let _ = fs::remove_file(link);
+    let works = symlink(Path::new(probe.file_name().unwrap()), link).is_ok() &&
+                fs::read_link(link).is_ok();
+    let _ = fs::remove_file(link);
+    works
+}
+
+fn probe_fine_mtime(probe: &Path) -> bool {
+    let requested = UNIX_EPOCH + Duration::new(1_000_000_001, 500_000_000);
+    let set = File::options().write(true).open(probe).and_then(|file| file.set_modified(requested));
+    let modified: Option<SystemTime> = fs::metadata(probe).and_then(|meta| meta.modified()).ok();
+    set.is_ok() && modified == Some(requested)
+}
+
+#[cfg(unix)]
+fn probe_permissions(probe: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    set_mode(probe, 0o751).is_ok() &&
+    fs::metadata(probe).map(|meta| meta.permissions().mode() & 0o777 == 0o751).unwrap_or(false)
+}
+
+#[cfg(not(unix))]
+fn probe_permissions(_: &Path) -> bool {
+    false
+}
+
+#[test]
+fn test_limitations() {
+    assert!(FsCapabilities::default().limitations().is_empty());
+    let fat = FsCapabilities {
+        symlinks: false,
+        fine_mtime: false,
+        permissions: false,
+        case_sensitive: false,
+    };
+    assert_eq!(fat.limitations().len(), 4);
+    assert_eq!(fat.mtime_granularity(), Duration::from_secs(2));
+}
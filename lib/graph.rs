@@ -0,0 +1,130 @@
+// Copyright (c) 2015 - 2016, Alberto Corona <ac@albertocorona.com>
+// All rights reserved. This file is part of yabs, distributed under the BSD
+// 3-Clause license. For full terms please see the LICENSE file.
+
+// The files of a build and what produces each, written as Graphviz DOT by
+// `BuildFile::export_graph`
+
+use std::collections::{BTreeMap, BTreeSet};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum NodeKind {
+    Source,
+    Header,
+    Object,
+    StaticLibrary,
+    DynamicLibrary,
+    Binary,
+    // Named "task:" and the task's name
+    Task,
+}
+
+impl NodeKind {
+    // Shape and fill color of the node
+    fn style(&self) -> (&'static str, &'static str) {
+        match *self {
+            NodeKind::Source => ("note", "lightyellow"),
+            NodeKind::Header => ("note", "khaki"),
+            NodeKind::Object => ("ellipse", "lightgrey"),
+            NodeKind::StaticLibrary => ("box3d", "lightblue"),
+            NodeKind::DynamicLibrary => ("component", "lightblue"),
+            NodeKind::Binary => ("doubleoctagon", "palegreen"),
+            NodeKind::Task => ("cds", "lightsalmon"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum EdgeKind {
+    // Source to object
+    Compile,
+    // Header to the object of a source including it
+    Include,
+    // Object to the binary or dynamic library it's linked into
+    Link,
+    // Object to the static library it's a member of
+    Archive,
+    // Binary, library or task to a task depending on it
+    Depend,
+}
+
+impl EdgeKind {
+    fn label(&self) -> &'static str {
+        match *self {
+            EdgeKind::Compile => "compile",
+            EdgeKind::Include => "include",
+            EdgeKind::Link => "link",
+            EdgeKind::Archive => "archive",
+            EdgeKind::Depend => "depends",
+        }
+    }
+}
+
+// Nodes are identified by their path. A file added as more than one kind
+// keeps the first.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Graph {
+    nodes: BTreeMap<String, NodeKind>,
+    edges: BTreeSet<(String, String, EdgeKind)>,
+}
+
+impl Graph {
+    pub fn add_node(&mut self, path: &str, kind: NodeKind) {
+        self.nodes.entry(path.to_owned()).or_insert(kind);
+    }
+
+    // Adds the edge from `from` to `to`, which have to have been added as
+    // nodes
+    pub fn add_edge(&mut self, from: &str, to: &str, kind: EdgeKind) {
+        self.edges.insert((from.to_owned(), to.to_owned(), kind));
+    }
+
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph yabs {\n    rankdir = LR;\n    node [style = filled];\n");
+        for (path, kind) in &self.nodes {
+            let (shape, color) = kind.style();
+            dot.push_str(&format!("    {} [shape = {}, fillcolor = {}];\n", dot_string(path), shape, color));
+        }
+        for (from, to, kind) in &self.edges {
+            dot.push_str(&format!("    {} -> {} [label = {}];\n", dot_string(from), dot_string(to), kind.label()));
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+// `value` as a quoted DOT ID. Backslashes are escaped as well as quotes, so a
+// path ending in one doesn't escape the closing quote, and newlines are
+// written as `\n`.
+fn dot_string(value: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => {},
+            _ => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[test]
+fn test_graph_dot() {
+    let mut graph = Graph::default();
+    graph.add_node("./a \"b\".c", NodeKind::Source);
+    graph.add_node("./a \"b\".o", NodeKind::Object);
+    graph.add_node("./a \"b\".o", NodeKind::Header);
+    graph.add_node("dir\\app", NodeKind::Binary);
+    graph.add_edge("./a \"b\".c", "./a \"b\".o", EdgeKind::Compile);
+    graph.add_edge("./a \"b\".o", "dir\\app", EdgeKind::Link);
+    assert_eq!(graph.to_dot(),
+               "digraph yabs {\n    rankdir = LR;\n    node [style = filled];\n    \
+                \"./a \\\"b\\\".c\" [shape = note, fillcolor = lightyellow];\n    \
+                \"./a \\\"b\\\".o\" [shape = ellipse, fillcolor = lightgrey];\n    \
+                \"dir\\\\app\" [shape = doubleoctagon, fillcolor = palegreen];\n    \
+                \"./a \\\"b\\\".c\" -> \"./a \\\"b\\\".o\" [label = compile];\n    \
+                \"./a \\\"b\\\".o\" -> \"dir\\\\app\" [label = link];\n}\n");
+}
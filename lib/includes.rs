@@ -0,0 +1,176 @@
+// Copyright (c) 2015 - 2016, Alberto Corona <ac@albertocorona.com>
+// All rights reserved. This file is part of yabs, distributed under the BSD
+// 3-Clause license. For full terms please see the LICENSE file.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+// Start of the reason an object is stale when one of its headers would now be
+// found somewhere else
+pub const INCLUDE_RESOLUTION_CHANGED: &str = "include resolution changed";
+
+// A header an object included, by the name it was found under in an include
+// directory and where it was found
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HeaderRecord {
+    pub name: String,
+    pub resolved: String,
+}
+
+// Splits the prerequisites of a make rule written by `-MMD`, skipping the
+// source itself
+pub fn parse_depfile(contents: &str) -> Vec<PathBuf> {
+    let joined = contents.replace("\\\r\n", " ").replace("\\\n", " ");
+    let rule = match joined.find(": ") {
+        Some(index) => &joined[index + 2..],
+        None => return Vec::new(),
+    };
+    let rule = rule.lines().next().unwrap_or("");
+    let mut prerequisites = Vec::new();
+    let mut current = String::new();
+    let mut chars = rule.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if chars.peek() == Some(&' ') => {
+                current.push(' ');
+                chars.next();
+            },
+            ' ' | '\t' => {
+                if !current.is_empty() {
+                    prerequisites.push(PathBuf::from(current.clone()));
+                    current.clear();
+                }
+            },
+            _ => current.push(c),
+        }
+    }
+    if !current.is_empty() {
+        prerequisites.push(PathBuf::from(current));
+    }
+    prerequisites.into_iter().skip(1).collect()
+}
+
+// The headers in `prerequisites` found in one of `search_path`, named
+// relative to the first directory they're in. Headers found next to the file
+// including them are recorded by their path alone.
+pub fn header_records(prerequisites: &[PathBuf], search_path: &[PathBuf]) -> Vec<HeaderRecord> {
+    prerequisites.iter()
+                 .map(|header| {
+                          let header = normalize(header);
+                          let name = search_path.iter()
+                                                .filter_map(|dir| header.strip_prefix(normalize(dir)).ok())
+                                                .next()
+                                                .unwrap_or(&header);
+                          HeaderRecord {
+                              name: name.display().to_string(),
+                              resolved: header.display().to_string(),
+                          }
+                      })
+                 .collect()
+}
+
+fn normalize(path: &Path) -> PathBuf {
+    path.components().filter(|component| *component != Component::CurDir).collect()
+}
+
+// Finds headers through the include directories the way the compiler would,
+// listing each directory only once. Relative directories are listed in
+// `root`, the directory the compiler runs in.
+#[derive(Debug, Default)]
+pub struct IncludeResolver {
+    root: PathBuf,
+    listings: BTreeMap<PathBuf, BTreeSet<OsString>>,
+}
+
+impl IncludeResolver {
+    pub fn new(root: &Path) -> IncludeResolver {
+        IncludeResolver {
+            root: root.to_path_buf(),
+            listings: BTreeMap::new(),
+        }
+    }
+
+    fn exists(&mut self, path: &Path) -> bool {
+        let (dir, file) = match (path.parent(), path.file_name()) {
+            (Some(dir), Some(file)) => (dir.to_path_buf(), file.to_owned()),
+            _ => return false,
+        };
+        let root = &self.root;
+        self.listings
+            .entry(dir.clone())
+            .or_insert_with(|| {
+                                let dir = if dir == Path::new("") { Path::new(".") } else { dir.as_path() };
+                                fs::read_dir(root.join(dir)).map(|entries| {
+                                                          entries.filter_map(|entry| entry.ok())
+                                                                 .map(|entry| entry.file_name())
+                                                                 .collect()
+                                                      })
+                                                 .unwrap_or_default()
+                            })
+            .contains(&file)
+    }
+
+    // Where `name` is found first in `search_path`
+    pub fn resolve(&mut self, name: &str, search_path: &[PathBuf]) -> Option<PathBuf> {
+        search_path.iter().map(|dir| normalize(&dir.join(name))).find(|path| self.exists(path))
+    }
+
+    // Why `header` would now be found elsewhere or not at all, if it would
+    pub fn changed(&mut self, header: &HeaderRecord, search_path: &[PathBuf]) -> Option<String> {
+        let resolved = if header.name == header.resolved {
+            Some(PathBuf::from(&header.resolved)).filter(|path| self.exists(path))
+        } else {
+            self.resolve(&header.name, search_path)
+        };
+        match resolved {
+            Some(ref path) if *path == Path::new(&header.resolved) => None,
+            Some(path) => {
+                Some(format!("{}: {} now resolves to {}",
+                             INCLUDE_RESOLUTION_CHANGED,
+                             header.name,
+                             path.display()))
+            },
+            None => Some(format!("{}: {} no longer resolves", INCLUDE_RESOLUTION_CHANGED, header.name)),
+        }
+    }
+}
+
+#[test]
+fn test_parse_depfile() {
+    let depfile = "obj/main.o: src/main.c include/foo.h \\\n  ./vendor/dir\\ with\\ spaces/bar.h \\\n \
+                   include/sys/baz.h\n";
+    assert_eq!(parse_depfile(depfile),
+               vec![PathBuf::from("include/foo.h"),
+                    PathBuf::from("./vendor/dir with spaces/bar.h"),
+                    PathBuf::from("include/sys/baz.h")]);
+    let search_path = vec![PathBuf::from("vendor/dir with spaces"), PathBuf::from("./include")];
+    let records = header_records(&parse_depfile(depfile), &search_path);
+    let names: Vec<&str> = records.iter().map(|record| record.name.as_str()).collect();
+    assert_eq!(names, vec!["foo.h", "bar.h", "sys/baz.h"]);
+    assert_eq!(records[1].resolved, "vendor/dir with spaces/bar.h");
+}
+
+#[test]
+fn test_include_resolution_changed() {
+    let dir = ::std::env::temp_dir().join(format!("yabs-includes-test-{}", ::std::process::id()));
+    fs::create_dir_all(dir.join("include")).unwrap();
+    fs::create_dir_all(dir.join("vendor")).unwrap();
+    fs::write(dir.join("include/foo.h"), "").unwrap();
+    let search_path = vec![dir.join("vendor"), dir.join("include")];
+    let header = HeaderRecord {
+        name: String::from("foo.h"),
+        resolved: dir.join("include/foo.h").display().to_string(),
+    };
+    assert_eq!(IncludeResolver::default().changed(&header, &search_path), None);
+    fs::write(dir.join("vendor/foo.h"), "").unwrap();
+    assert_eq!(IncludeResolver::default().changed(&header, &search_path),
+               Some(format!("include resolution changed: foo.h now resolves to {}",
+                            dir.join("vendor/foo.h").display())));
+    fs::remove_file(dir.join("vendor/foo.h")).unwrap();
+    fs::remove_file(dir.join("include/foo.h")).unwrap();
+    assert_eq!(IncludeResolver::default().changed(&header, &search_path),
+               Some(String::from("include resolution changed: foo.h no longer resolves")));
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,32 @@
+// Copyright (c) 2015 - 2016, Alberto Corona <ac@albertocorona.com>
+// All rights reserved. This file is part of yabs, distributed under the BSD
+// 3-Clause license. For full terms please see the LICENSE file.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// Exit status of a process killed by SIGINT, as shells report it
+pub const INTERRUPTED_STATUS: i32 = 130;
+
+static INTERRUPTS: AtomicUsize = AtomicUsize::new(0);
+
+// Catches Ctrl-C so a build can stop starting jobs, kill the running ones
+// and remove what they left half written. A second Ctrl-C exits right away.
+#[cfg(unix)]
+pub fn install() {
+    extern "C" fn handle(_: ::libc::c_int) {
+        if INTERRUPTS.fetch_add(1, Ordering::SeqCst) > 0 {
+            unsafe { ::libc::_exit(INTERRUPTED_STATUS) };
+        }
+    }
+    unsafe {
+        ::libc::signal(::libc::SIGINT, handle as extern "C" fn(::libc::c_int) as ::libc::sighandler_t);
+    }
+}
+
+#[cfg(not(unix))]
+pub fn install() {}
+
+// Whether Ctrl-C was pressed since `install`
+pub fn interrupted() -> bool {
+    INTERRUPTS.load(Ordering::SeqCst) > 0
+}
@@ -14,12 +14,35 @@ extern crate serde;
 #[macro_use]
 extern crate error_chain;
 extern crate serde_json;
+extern crate num_cpus;
+extern crate libc;
 #[macro_use]
 extern crate log;
 
+pub mod analysis;
+pub mod artifact;
+pub mod budget;
 pub mod build;
+pub mod cache;
+pub mod context;
+pub mod deadline;
+pub mod diagnostics;
 pub mod error;
+pub mod events;
 pub mod ext;
+pub mod fscaps;
+pub mod graph;
+pub mod includes;
+pub mod interrupt;
+pub mod lock;
 pub mod new;
+pub mod plan;
+pub mod report;
+pub mod space;
+pub mod stale;
+pub mod state;
+#[cfg(all(feature = "testing", unix))]
+pub mod testing;
 pub mod logger;
+pub mod verify;
 mod desc;
@@ -0,0 +1,185 @@
+// Copyright (c) 2015 - 2016, Alberto Corona <ac@albertocorona.com>
+// All rights reserved. This file is part of yabs, distributed under the BSD
+// 3-Clause license. For full terms please see the LICENSE file.
+
+use error::{YabsError, YabsErrorKind};
+use interrupt;
+
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Kept in the state directory, locked with `flock` by the yabs process using
+// the project and holding its pid for the others to report
+pub const LOCK_FILE: &str = "lock";
+
+// How often a waiting yabs says what it's waiting for
+const WAIT_LOG_INTERVAL: Duration = Duration::from_secs(5);
+
+// The locks this process holds by path, with how many `ProjectLock`s hold
+// each. The kernel releases a lock once its file is closed, so dropping the
+// last one or the process dying releases it.
+static HELD: Mutex<BTreeMap<PathBuf, (File, usize)>> = Mutex::new(BTreeMap::new());
+
+// Keeps other yabs processes from building or cleaning the project until it's
+// dropped, which also happens when a build panics or is interrupted. A lock
+// this process already holds is taken again without waiting, for builds that
+// run other builds.
+#[derive(Debug)]
+pub struct ProjectLock {
+    path: PathBuf,
+}
+
+impl ProjectLock {
+    // Waits for another process holding the lock when `block` is set,
+    // otherwise fails with `ProjectLocked`
+    pub fn acquire(state_dir: &Path, block: bool) -> Result<ProjectLock, YabsError> {
+        fs::create_dir_all(state_dir)?;
+        let path = state_dir.canonicalize()?.join(LOCK_FILE);
+        let mut logged: Option<Instant> = None;
+        loop {
+            let mut held = HELD.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            if let Some(&mut (_, ref mut count)) = held.get_mut(&path) {
+                *count += 1;
+                return Ok(ProjectLock { path });
+            }
+            let mut file = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(&path)?;
+            if try_lock(&file)? {
+                // Removed by `distclean` while its holder had it locked
+                if !is_same_file(&file, &path) {
+                    continue;
+                }
+                // A holder releasing the lock empties the file, so a pid left
+                // in it is of a process that died holding it
+                let holder = read_holder(&path);
+                if holder != 0 && !is_running(holder) {
+                    warn!("breaking stale lock left by pid {}", holder);
+                }
+                file.set_len(0)?;
+                file.seek(SeekFrom::Start(0))?;
+                file.write_all(format!("{}\n", process::id()).as_bytes())?;
+                held.insert(path.clone(), (file, 1));
+                return Ok(ProjectLock { path });
+            }
+            drop(held);
+            // Not written yet by a holder that just took it
+            let holder = read_holder(&path);
+            if !block {
+                bail!(YabsErrorKind::ProjectLocked(holder));
+            }
+            if interrupt::interrupted() {
+                bail!(YabsErrorKind::Interrupted);
+            }
+            if logged.is_none_or(|logged| logged.elapsed() >= WAIT_LOG_INTERVAL) {
+                info!("waiting for other yabs process (pid {})", holder);
+                logged = Some(Instant::now());
+            }
+            thread::sleep(Duration::from_millis(100));
+        }
+    }
+}
+
+impl Drop for ProjectLock {
+    fn drop(&mut self) {
+        let mut held = HELD.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let released = match held.get_mut(&self.path) {
+            Some(&mut (_, ref mut count)) => {
+                *count -= 1;
+                *count == 0
+            },
+            None => false,
+        };
+        if released {
+            if let Some((file, _)) = held.remove(&self.path) {
+                let _ = file.set_len(0);
+            }
+        }
+    }
+}
+
+// The pid written to the lock file at `path`, 0 when there's none
+fn read_holder(path: &Path) -> u32 {
+    fs::read_to_string(path).ok().and_then(|pid| pid.trim().parse::<u32>().ok()).unwrap_or(0)
+}
+
+// Whether the process `pid` still exists, signal 0 only checks that it could
+// be signalled
+#[cfg(unix)]
+fn is_running(pid: u32) -> bool {
+    if unsafe { ::libc::kill(pid as ::libc::pid_t, 0) } == 0 {
+        return true;
+    }
+    io::Error::last_os_error().raw_os_error() != Some(::libc::ESRCH)
+}
+
+#[cfg(not(unix))]
+fn is_running(_: u32) -> bool {
+    true
+}
+
+// Takes the lock on `file` without waiting, false when another process has it
+#[cfg(unix)]
+fn try_lock(file: &File) -> io::Result<bool> {
+    use std::os::unix::io::AsRawFd;
+    if unsafe { ::libc::flock(file.as_raw_fd(), ::libc::LOCK_EX | ::libc::LOCK_NB) } == 0 {
+        return Ok(true);
+    }
+    let error = io::Error::last_os_error();
+    if error.raw_os_error() == Some(::libc::EWOULDBLOCK) {
+        Ok(false)
+    } else {
+        Err(error)
+    }
+}
+
+// Without `flock` the project isn't locked
+#[cfg(not(unix))]
+fn try_lock(_: &File) -> io::Result<bool> {
+    Ok(true)
+}
+
+// Whether `file` is still the file at `path`
+#[cfg(unix)]
+fn is_same_file(file: &File, path: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    match (file.metadata(), fs::metadata(path)) {
+        (Ok(locked), Ok(current)) => locked.dev() == current.dev() && locked.ino() == current.ino(),
+        _ => false,
+    }
+}
+
+#[cfg(not(unix))]
+fn is_same_file(_: &File, path: &Path) -> bool {
+    path.exists()
+}
+
+#[test]
+fn test_project_lock() {
+    let dir = ::std::env::temp_dir().join(format!("yabs-lock-test-{}", process::id()));
+    let lock = ProjectLock::acquire(&dir, false).unwrap();
+    assert_eq!(fs::read_to_string(dir.join(LOCK_FILE)).unwrap(), format!("{}\n", process::id()));
+    // Taken again by the same process, and only released by its last holder
+    drop(ProjectLock::acquire(&dir, false).unwrap());
+    assert!(HELD.lock().unwrap().contains_key(&dir.canonicalize().unwrap().join(LOCK_FILE)));
+    drop(lock);
+    assert!(!HELD.lock().unwrap().contains_key(&dir.canonicalize().unwrap().join(LOCK_FILE)));
+    // A pid left in the file by a process that's gone doesn't hold the lock
+    fs::write(dir.join(LOCK_FILE), "2000000000\n").unwrap();
+    drop(ProjectLock::acquire(&dir, false).unwrap());
+    // Locked through a file of its own, as another process would
+    let other = File::open(dir.join(LOCK_FILE)).unwrap();
+    assert!(try_lock(&other).unwrap());
+    fs::write(dir.join(LOCK_FILE), "1\n").unwrap();
+    match ProjectLock::acquire(&dir, false) {
+        Err(YabsError(YabsErrorKind::ProjectLocked(1), _)) => {},
+        result => panic!("expected the project to be locked, got {:?}", result),
+    }
+    drop(other);
+    drop(ProjectLock::acquire(&dir, false).unwrap());
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -3,36 +3,341 @@ extern crate ansi_term;
 
 use ansi_term::Colour;
 
-use error::YabsError;
+use context::BuildContext;
+use error::{YabsError, YabsErrorKind};
+use ext::strip_ansi;
+use events::{self, EVENT_TARGET, Event};
 use log::{LogLevel, LogLevelFilter, LogMetadata, LogRecord};
+use stale::utc_timestamp;
 
-pub struct Logger;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime};
+
+// A copy of the log without colors, flushed after every record so a crash
+// doesn't lose the end of it
+pub struct LogFile(Mutex<File>);
+
+impl LogFile {
+    // Appends to the file at `path` when `append` is set, otherwise truncates it
+    pub fn open(path: &Path, append: bool) -> Result<LogFile, YabsError> {
+        match OpenOptions::new().create(true).write(true).append(append).truncate(!append).open(path) {
+            Ok(file) => Ok(LogFile(Mutex::new(file))),
+            Err(error) => bail!(YabsErrorKind::LogFile(path.to_path_buf(), error.to_string())),
+        }
+    }
+
+    // Colored compiler diagnostics are written without their colors too
+    fn write_line(&self, line: &str) {
+        if let Ok(mut file) = self.0.lock() {
+            let _ = writeln!(file, "{}", strip_ansi(line));
+            let _ = file.flush();
+        }
+    }
+}
+
+// The log target of the lines ending a build, which quiet mode still shows
+pub const SUMMARY_TARGET: &str = "yabs::summary";
+
+// The log target of what a compiler wrote to stderr, logged as a warning so
+// quiet mode shows it and written to stderr as it is
+pub const DIAGNOSTICS_TARGET: &str = "yabs::diagnostics";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verbosity {
+    // Warnings, errors and the summary
+    Quiet,
+    // A short line for each compile, such as "CC ./src/foo.c"
+    Normal,
+    // Complete commands and debug details
+    Verbose,
+}
+
+impl Verbosity {
+    // Quiet mode needs info for the summary, everything else it leaves out is
+    // filtered by the logger
+    fn max_level(&self) -> LogLevelFilter {
+        match *self {
+            Verbosity::Quiet | Verbosity::Normal => LogLevelFilter::Info,
+            Verbosity::Verbose => LogLevelFilter::Debug,
+        }
+    }
+}
+
+// Whether the `error:` and `warning:` prefixes are colored
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorChoice {
+    Always,
+    // When stderr is a terminal and NO_COLOR isn't set
+    Auto,
+    Never,
+}
+
+impl ColorChoice {
+    // "always", "auto" or "never"
+    pub fn parse(choice: &str) -> Option<ColorChoice> {
+        match choice {
+            "always" => Some(ColorChoice::Always),
+            "auto" => Some(ColorChoice::Auto),
+            "never" => Some(ColorChoice::Never),
+            _ => None,
+        }
+    }
+
+    pub fn enabled(&self, context: &BuildContext) -> bool {
+        match *self {
+            ColorChoice::Always => true,
+            // See https://no-color.org, an empty NO_COLOR counts as unset
+            ColorChoice::Auto => context.stderr_is_terminal && context.non_empty_var("NO_COLOR").is_none(),
+            ColorChoice::Never => false,
+        }
+    }
+}
+
+// Logs `command` in verbose mode and the shorter `status` otherwise
+pub fn log_command(status: &str, command: &str) {
+    if log_enabled!(LogLevel::Debug) {
+        info!("{}", command);
+    } else {
+        info!("{}", status);
+    }
+}
+
+// How each record is prefixed with the time it was logged
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Timestamps {
+    Off,
+    // The time of day in UTC, such as `2024-05-03T12:01:07`
+    Wall,
+    // Since the logger was set up, such as `[+83.2s]`
+    Elapsed,
+}
+
+impl Timestamps {
+    // "off", "wall" or "elapsed"
+    pub fn parse(timestamps: &str) -> Option<Timestamps> {
+        match timestamps {
+            "off" => Some(Timestamps::Off),
+            "wall" => Some(Timestamps::Wall),
+            "elapsed" => Some(Timestamps::Elapsed),
+            _ => None,
+        }
+    }
+
+    fn prefix(&self, now: SystemTime, elapsed: Duration) -> String {
+        match *self {
+            Timestamps::Off => String::new(),
+            Timestamps::Wall => {
+                let (date, time) = utc_timestamp(now);
+                format!("{}T{} ", date, time)
+            },
+            Timestamps::Elapsed => format!("[+{:.1}s] ", elapsed.as_secs_f64()),
+        }
+    }
+
+    // The same time as a field of a JSON object, with its trailing comma
+    fn field(&self, now: SystemTime, elapsed: Duration) -> String {
+        match *self {
+            Timestamps::Off => String::new(),
+            Timestamps::Wall => {
+                let (date, time) = utc_timestamp(now);
+                format!("\"time\":\"{}T{}\",", date, time)
+            },
+            Timestamps::Elapsed => format!("\"elapsed\":{:.3},", elapsed.as_secs_f64()),
+        }
+    }
+}
+
+// How the log is written, each is chosen independently of the others
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LogOptions {
+    pub verbosity: Verbosity,
+    // Decided with `ColorChoice::enabled`
+    pub color: bool,
+    pub timestamps: Timestamps,
+}
+
+impl Default for LogOptions {
+    fn default() -> LogOptions {
+        LogOptions {
+            verbosity: Verbosity::Normal,
+            color: false,
+            timestamps: Timestamps::Off,
+        }
+    }
+}
+
+pub struct Logger {
+    options: LogOptions,
+    started: Instant,
+    file: Option<LogFile>,
+}
 
 impl Logger {
-    pub fn init() -> Result<(), YabsError> {
+    pub fn init(options: LogOptions) -> Result<(), YabsError> {
+        Logger::install(options, None)
+    }
+
+    // Logs to the console and to the file at `path`, see `LogFile::open`
+    pub fn init_with_file(options: LogOptions, path: &Path, append: bool) -> Result<(), YabsError> {
+        Logger::install(options, Some(LogFile::open(path, append)?))
+    }
+
+    fn install(options: LogOptions, file: Option<LogFile>) -> Result<(), YabsError> {
         Ok(log::set_logger(|max_log_level| {
-                               max_log_level.set(LogLevelFilter::Info);
-                               Box::new(Logger)
+                               max_log_level.set(options.verbosity.max_level());
+                               Box::new(Logger {
+                                   options,
+                                   started: Instant::now(),
+                                   file,
+                               })
                            })?)
     }
+
+    fn prefix(&self, colour: Colour, prefix: &str) -> String {
+        if self.options.color {
+            colour.bold().paint(prefix).to_string()
+        } else {
+            prefix.to_owned()
+        }
+    }
+
+    fn tee(&self, line: &str) {
+        if let Some(ref file) = self.file {
+            file.write_line(line);
+        }
+    }
 }
 
 impl log::Log for Logger {
+    fn enabled(&self, metadata: &LogMetadata) -> bool {
+        match self.options.verbosity {
+            Verbosity::Quiet => metadata.level() <= LogLevel::Warn || metadata.target() == SUMMARY_TARGET,
+            Verbosity::Normal => metadata.level() <= LogLevel::Info,
+            Verbosity::Verbose => metadata.level() <= LogLevel::Debug,
+        }
+    }
+
+    fn log(&self, record: &LogRecord) {
+        if !self.enabled(record.metadata()) || record.target() == EVENT_TARGET {
+            return;
+        }
+        if record.target() == DIAGNOSTICS_TARGET {
+            eprintln!("{}", record.args());
+            self.tee(&record.args().to_string());
+            return;
+        }
+        let time = self.options.timestamps.prefix(SystemTime::now(), self.started.elapsed());
+        match record.level() {
+            // Kept out of stdout so piped build output doesn't mix them in
+            LogLevel::Error => {
+                eprintln!("{}{}: {}", time, self.prefix(Colour::Red, "error"), record.args());
+                self.tee(&format!("{}error: {}", time, record.args()));
+            },
+            LogLevel::Warn => {
+                eprintln!("{}{}: {}", time, self.prefix(Colour::Yellow, "warning"), record.args());
+                self.tee(&format!("{}warning: {}", time, record.args()));
+            },
+            LogLevel::Info | LogLevel::Debug => {
+                println!("{}{}", time, record.args());
+                self.tee(&format!("{}{}", time, record.args()));
+            },
+            _ => {},
+        };
+    }
+}
+
+// Writes everything as JSON, one object per line, see `events`. Messages and
+// errors become "message" and "error" events. Only the timestamps of the
+// options are used, as a "time" or "elapsed" field of each object.
+pub struct JsonLogger {
+    timestamps: Timestamps,
+    started: Instant,
+    file: Option<LogFile>,
+}
+
+impl JsonLogger {
+    pub fn init(options: LogOptions) -> Result<(), YabsError> {
+        JsonLogger::install(options, None)
+    }
+
+    // Writes the same lines to the file at `path`, see `LogFile::open`
+    pub fn init_with_file(options: LogOptions, path: &Path, append: bool) -> Result<(), YabsError> {
+        JsonLogger::install(options, Some(LogFile::open(path, append)?))
+    }
+
+    fn install(options: LogOptions, file: Option<LogFile>) -> Result<(), YabsError> {
+        events::enable();
+        Ok(log::set_logger(|max_log_level| {
+                               max_log_level.set(LogLevelFilter::Info);
+                               Box::new(JsonLogger {
+                                   timestamps: options.timestamps,
+                                   started: Instant::now(),
+                                   file,
+                               })
+                           })?)
+    }
+
+    fn write_object(&self, object: &str) {
+        let field = self.timestamps.field(SystemTime::now(), self.started.elapsed());
+        let line = format!("{{{}{}", field, &object[1..]);
+        println!("{}", line);
+        if let Some(ref file) = self.file {
+            file.write_line(&line);
+        }
+    }
+}
+
+impl log::Log for JsonLogger {
     fn enabled(&self, metadata: &LogMetadata) -> bool {
         metadata.level() <= LogLevel::Info
     }
 
     fn log(&self, record: &LogRecord) {
-        if self.enabled(record.metadata()) {
-            match record.level() {
-                LogLevel::Error => {
-                    println!("{}: {}", Colour::Red.bold().paint("error"), record.args());
-                },
-                LogLevel::Info => {
-                    println!("{}", record.args());
-                },
-                _ => {},
-            };
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        if record.target() == EVENT_TARGET {
+            self.write_object(&record.args().to_string());
+            return;
         }
+        // Sent as "diagnostics" events
+        if record.target() == DIAGNOSTICS_TARGET {
+            return;
+        }
+        let message = record.args().to_string();
+        let event = match record.level() {
+            LogLevel::Error => Event::Error { message },
+            LogLevel::Warn => Event::Message { level: String::from("warning"), message },
+            _ => Event::Message { level: String::from("info"), message },
+        };
+        self.write_object(&event.to_json());
     }
 }
+
+#[test]
+fn test_color_choice() {
+    let mut terminal = BuildContext::isolated("/tmp");
+    terminal.stderr_is_terminal = true;
+    assert!(ColorChoice::Auto.enabled(&terminal));
+    assert!(!ColorChoice::Auto.enabled(&terminal.clone().with_var("NO_COLOR", "1")));
+    assert!(ColorChoice::Auto.enabled(&terminal.clone().with_var("NO_COLOR", "")));
+    assert!(ColorChoice::Always.enabled(&terminal.clone().with_var("NO_COLOR", "1")));
+    assert!(!ColorChoice::Auto.enabled(&BuildContext::isolated("/tmp")));
+    assert!(!ColorChoice::Never.enabled(&terminal));
+    assert_eq!(ColorChoice::parse("sometimes"), None);
+}
+
+#[test]
+fn test_timestamps() {
+    use std::time::UNIX_EPOCH;
+    let now = UNIX_EPOCH + Duration::from_secs(1714737667);
+    let elapsed = Duration::from_millis(83240);
+    assert_eq!(Timestamps::Wall.prefix(now, elapsed), "2024-05-03T12:01:07 ");
+    assert_eq!(Timestamps::Elapsed.prefix(now, elapsed), "[+83.2s] ");
+    assert_eq!(Timestamps::Off.prefix(now, elapsed), "");
+    assert_eq!(Timestamps::Wall.field(now, elapsed), "\"time\":\"2024-05-03T12:01:07\",");
+    assert_eq!(Timestamps::Elapsed.field(now, elapsed), "\"elapsed\":83.240,");
+}
@@ -1,55 +1,282 @@
+// Copyright (c) 2015 - 2016, Alberto Corona <ac@albertocorona.com>
+// All rights reserved. This file is part of yabs, distributed under the BSD
+// 3-Clause license. For full terms please see the LICENSE file.
+
+use desc::project::ProjectDesc;
 use error::{YabsError, YabsErrorKind};
 
+use regex::Regex;
+use toml::Value;
+
+use std::collections::BTreeSet;
 use std::fs;
-use std::io::Write;
 use std::path::{Path, PathBuf};
 
-pub fn new_project(name: &str, lib: bool) -> Result<(), YabsError> {
+// Extensions `init` looks for sources with, in the order they're listed in
+// 'file-extensions'
+const INIT_SOURCE_EXTENSIONS: &[&str] = &["c", "cpp", "cc", "cxx", "S", "s"];
+const INIT_HEADER_EXTENSIONS: &[&str] = &["h", "hh", "hpp", "hxx"];
+
+// What `new_project` lays out
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProjectKind {
+    // `src/main.c`
+    Binary,
+    // `src/lib.c` and its header in `include`
+    Library,
+    // Both, with the binary calling into the library
+    BinaryAndLibrary,
+}
+
+impl ProjectKind {
+    fn has_binary(&self) -> bool {
+        *self != ProjectKind::Library
+    }
+
+    fn has_library(&self) -> bool {
+        *self != ProjectKind::Binary
+    }
+}
+
+// Creates the project in the directory `name`, named after its last
+// component. An empty directory is filled in, one with anything in it is left
+// alone.
+pub fn new_project(name: &str, kind: ProjectKind) -> Result<(), YabsError> {
     let dir = Path::new(name);
-    if dir.exists() {
+    if dir.exists() && (!dir.is_dir() || fs::read_dir(dir)?.next().is_some()) {
         bail!(YabsErrorKind::DirExists(dir.to_path_buf()));
     }
-    fs::create_dir_all(dir)?;
+    let name = match dir.file_name().and_then(|name| name.to_str()) {
+        Some(name) => name,
+        None => bail!(YabsErrorKind::DirExists(dir.to_path_buf())),
+    };
     fs::create_dir_all(dir.join("src"))?;
-    if lib {
-        create_bin_files(&dir.to_path_buf(), name)?;
-    }
-    Ok(create_bin_files(&dir.to_path_buf(), name)?)
-}
-
-pub fn create_bin_files(path: &PathBuf, name: &str) -> Result<(), YabsError> {
-    fs::File::create(path.join(format!("{}.toml", name)))
-        .and_then(|mut file| {
-            Ok(file.write_all(format!("[project]\n\
-                name = \"{0}\"\n\
-                version = \"0.0.1\"\n\
-                target = [\"{0}\"]\n\
-                file-extensions = [\"cpp\"]\n\
-                compiler = \"g++\"\n\
-                compiler-flags = []\n\
-                src = [\n\
-                \t\"src/main.cpp\"\n\
-                ]\n\
-                libraries = []\n\
-                library-directories = []\n\
-                include = []\n\
-                linker-flags = []\n\
-                ignore = []\n\
-                before-script = []\n\
-                after-script = []\n\n\
-                [[bin]]\n\
-                name = \"{0}\"\n\
-                path = \"./src/main.cpp\"
-                ",
-                                      name)
-                                  .as_bytes())?)
-        })?;
-    fs::File::create(path.join("src/main.cpp"))
-        .and_then(|mut src| {
-                      Ok(src.write_all(b"#include <iostream>\n\n\
-            int main() {\n\
-            \treturn 0;\n\
-            }")?)
-                  })?;
+    fs::write(dir.join(format!("{}.toml", name)), buildfile(name, kind))?;
+    fs::write(dir.join(".gitignore"), gitignore(name, kind))?;
+    let ident = c_identifier(name);
+    if kind.has_library() {
+        fs::create_dir_all(dir.join("include"))?;
+        fs::write(dir.join("include").join(format!("{}.h", name)),
+                  format!("#ifndef {0}_H\n\
+                           #define {0}_H\n\n\
+                           const char *{1}_greeting(void);\n\n\
+                           #endif\n",
+                          ident.to_uppercase(),
+                          ident))?;
+        fs::write(dir.join("src/lib.c"),
+                  format!("#include \"{}.h\"\n\n\
+                           const char *{}_greeting(void) {{\n\
+                           \x20   return \"Hello, world!\";\n\
+                           }}\n",
+                          name,
+                          ident))?;
+    }
+    match kind {
+        ProjectKind::Binary => {
+            fs::write(dir.join("src/main.c"),
+                      "#include <stdio.h>\n\n\
+                       int main(void) {\n\
+                       \x20   printf(\"Hello, world!\\n\");\n\
+                       \x20   return 0;\n\
+                       }\n")?
+        },
+        ProjectKind::BinaryAndLibrary => {
+            fs::write(dir.join("src/main.c"),
+                      format!("#include <stdio.h>\n\n\
+                               #include \"{}.h\"\n\n\
+                               int main(void) {{\n\
+                               \x20   printf(\"%s\\n\", {}_greeting());\n\
+                               \x20   return 0;\n\
+                               }}\n",
+                              name,
+                              ident))?
+        },
+        ProjectKind::Library => {},
+    }
     Ok(())
 }
+
+// Writes a buildfile for the sources already in `dir`. Sources are found like
+// a project without 'src' finds them, each directory with headers becomes an
+// include directory and each source defining `main()` a binary, named after
+// the directory when there's only one. Without any the sources make up a
+// static library. An existing buildfile is only replaced when `force` is set.
+pub fn init(dir: &Path, force: bool) -> Result<PathBuf, YabsError> {
+    let dir = dir.canonicalize()?;
+    let name = match dir.file_name().and_then(|name| name.to_str()) {
+        Some(name) => name.to_owned(),
+        None => bail!(YabsErrorKind::NoAssumedToml(dir.display().to_string())),
+    };
+    let path = dir.join(format!("{}.toml", name));
+    if path.exists() && !force {
+        bail!(YabsErrorKind::BuildfileExists(path));
+    }
+    let mut project = ProjectDesc {
+        file_exts: INIT_SOURCE_EXTENSIONS.iter().map(|ext| ext.to_string()).collect(),
+        ..ProjectDesc::default()
+    };
+    project.context.cwd = dir.clone();
+    let sources: Vec<PathBuf> = project.scan_sources()?.iter().map(|source| relative(source)).collect();
+    let used: BTreeSet<String> = sources.iter()
+                                        .filter_map(|source| source.extension())
+                                        .map(|ext| ext.to_string_lossy().into_owned())
+                                        .collect();
+    let exts: Vec<&str> = INIT_SOURCE_EXTENSIONS.iter().cloned().filter(|ext| used.contains(*ext)).collect();
+    let mut include: BTreeSet<PathBuf> = BTreeSet::new();
+    for path in project.walk_files()? {
+        let is_header = path.extension()
+                            .and_then(|ext| ext.to_str())
+                            .is_some_and(|ext| INIT_HEADER_EXTENSIONS.contains(&ext));
+        if is_header {
+            let dir = relative(path.parent().unwrap_or_else(|| Path::new(".")));
+            include.insert(if dir.as_os_str().is_empty() { PathBuf::from(".") } else { dir });
+        }
+    }
+    let main = Regex::new(r"(?m)^\s*(?:int|void)\s+main\s*\(")?;
+    let mut mains = Vec::new();
+    for source in &sources {
+        if main.is_match(&String::from_utf8_lossy(&fs::read(dir.join(source))?)) {
+            mains.push(source.clone());
+        }
+    }
+    info!("found {} source(s){}", sources.len(), listed(&sources));
+    info!("found {} include director(ies){}", include.len(), listed(&include));
+    if sources.is_empty() {
+        warn!("no sources with one of the extensions {} were found", INIT_SOURCE_EXTENSIONS.join(", "));
+    }
+    if mains.len() > 1 {
+        warn!("main() is defined in {} files, writing a [[bin]] for each{}", mains.len(), listed(&mains));
+    } else if mains.is_empty() && !sources.is_empty() {
+        warn!("main() isn't defined in any source, writing a static [[lib]] instead");
+    }
+    let quoted = |values: Vec<String>| -> String {
+        values.into_iter().map(|value| Value::String(value).to_string()).collect::<Vec<_>>().join(", ")
+    };
+    let mut buildfile = format!("[project]\n\
+                                 name = {}\n\
+                                 version = \"0.0.1\"\n\
+                                 file-extensions = [{}]\n",
+                                Value::String(name.clone()),
+                                quoted(exts.iter().map(|ext| ext.to_string()).collect()));
+    if !include.is_empty() {
+        buildfile.push_str(&format!("include = [{}]\n",
+                                    quoted(include.iter().map(|dir| dir.display().to_string()).collect())));
+    }
+    let mut bin_names: BTreeSet<String> = BTreeSet::new();
+    for source in &mains {
+        let bin_name = if mains.len() == 1 {
+            name.clone()
+        } else {
+            let stem = source.file_stem().unwrap_or_default().to_string_lossy().into_owned();
+            if bin_names.contains(&stem) {
+                source.with_extension("").display().to_string().replace('/', "-")
+            } else {
+                stem
+            }
+        };
+        buildfile.push_str(&format!("\n[[bin]]\nname = {}\npath = {}\n",
+                                    Value::String(bin_name.clone()),
+                                    Value::String(source.display().to_string())));
+        bin_names.insert(bin_name);
+    }
+    if mains.is_empty() {
+        buildfile.push_str(&format!("\n[[lib]]\nname = {}\ntypes = [\"static\"]\n",
+                                    Value::String(format!("lib{}", name))));
+    }
+    fs::write(&path, buildfile)?;
+    info!("wrote '{}'", path.display());
+    Ok(path)
+}
+
+// `path` found walking ".", without the "./" in front
+fn relative(path: &Path) -> PathBuf {
+    path.strip_prefix(".").unwrap_or(path).to_path_buf()
+}
+
+// ": a, b" for the paths, or nothing without any
+fn listed<'a, I: IntoIterator<Item = &'a PathBuf>>(paths: I) -> String {
+    let paths: Vec<String> = paths.into_iter().map(|path| path.display().to_string()).collect();
+    if paths.is_empty() {
+        String::new()
+    } else {
+        format!(": {}", paths.join(", "))
+    }
+}
+
+fn buildfile(name: &str, kind: ProjectKind) -> String {
+    let mut sources = Vec::new();
+    if kind.has_binary() {
+        sources.push("\"src/main.c\"");
+    }
+    if kind.has_library() {
+        sources.push("\"src/lib.c\"");
+    }
+    let mut buildfile = format!("[project]\n\
+                                 name = \"{}\"\n\
+                                 version = \"0.0.1\"\n\
+                                 file-extensions = [\"c\"]\n\
+                                 src = [{}]\n",
+                                name,
+                                sources.join(", "));
+    if kind.has_library() {
+        buildfile.push_str("include = [\"include\"]\n");
+    }
+    if kind.has_binary() {
+        buildfile.push_str(&format!("\n[[bin]]\n\
+                                     name = \"{}\"\n\
+                                     path = \"src/main.c\"\n",
+                                    name));
+    }
+    if kind.has_library() {
+        buildfile.push_str(&format!("\n[[lib]]\n\
+                                     name = \"lib{}\"\n\
+                                     types = [\"static\", \"dynamic\"]\n",
+                                    name));
+    }
+    buildfile
+}
+
+fn gitignore(name: &str, kind: ProjectKind) -> String {
+    let mut ignored = String::from("*.o\n*.d\n.yabs/\n");
+    if kind.has_binary() {
+        ignored.push_str(&format!("/{}\n", name));
+    }
+    if kind.has_library() {
+        ignored.push_str("*.a\n*.so\n");
+    }
+    ignored
+}
+
+// `name` as it can be used in C, its header guard and functions are named
+// after it
+fn c_identifier(name: &str) -> String {
+    let ident: String = name.chars().map(|c| if c.is_ascii_alphanumeric() { c } else { '_' }).collect();
+    if ident.starts_with(|c: char| c.is_ascii_digit()) {
+        format!("_{}", ident)
+    } else {
+        ident
+    }
+}
+
+#[test]
+fn test_new_project_buildfile() {
+    assert_eq!(buildfile("hello", ProjectKind::Binary),
+               "[project]\nname = \"hello\"\nversion = \"0.0.1\"\nfile-extensions = [\"c\"]\nsrc = [\"src/main.c\"]\n\n\
+                [[bin]]\nname = \"hello\"\npath = \"src/main.c\"\n");
+    assert!(buildfile("hello", ProjectKind::BinaryAndLibrary).contains("src = [\"src/main.c\", \"src/lib.c\"]\n"));
+    assert!(!buildfile("hello", ProjectKind::Library).contains("[[bin]]"));
+    assert_eq!(c_identifier("my-lib"), "my_lib");
+    assert_eq!(c_identifier("2d"), "_2d");
+}
+
+#[test]
+fn test_init_keeps_working_directory() {
+    let dir = ::std::env::temp_dir().join(format!("yabs-init-test-{}", ::std::process::id())).join("kept");
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("main.c"), "int main(void) { return 0; }\n").unwrap();
+    let cwd = ::std::env::current_dir().unwrap();
+    assert_eq!(init(&dir, false).unwrap(), dir.canonicalize().unwrap().join("kept.toml"));
+    assert_eq!(::std::env::current_dir().unwrap(), cwd);
+    assert!(fs::read_to_string(dir.join("kept.toml")).unwrap().contains("path = \"main.c\"\n"));
+    fs::remove_dir_all(dir.parent().unwrap()).unwrap();
+}
@@ -0,0 +1,204 @@
+// Copyright (c) 2015 - 2016, Alberto Corona <ac@albertocorona.com>
+// All rights reserved. This file is part of yabs, distributed under the BSD
+// 3-Clause license. For full terms please see the LICENSE file.
+
+use ext::{json_array, json_string};
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepKind {
+    Compile,
+    Link,
+    Task,
+}
+
+impl StepKind {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            StepKind::Compile => "compile",
+            StepKind::Link => "link",
+            StepKind::Task => "task",
+        }
+    }
+}
+
+// One compile, link or task. Compiles and links are identified by the path
+// they produce and tasks by "task:" and their name, which stay the same
+// between runs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlanStep {
+    pub id: String,
+    pub kind: StepKind,
+    pub inputs: Vec<String>,
+    pub outputs: Vec<String>,
+    // Empty for links, whose libraries can come from shell commands
+    pub command: Vec<String>,
+    // Why the step has to run, None if its output is up to date
+    pub stale: Option<String>,
+    // Where a stale compile is in the order compiles are started, from 0
+    pub position: Option<usize>,
+}
+
+impl PlanStep {
+    pub fn to_json(&self) -> String {
+        format!("{{\"id\":{},\"kind\":{},\"inputs\":{},\"outputs\":{},\"command\":{},\"stale\":{},\"position\":{}}}",
+                json_string(&self.id),
+                json_string(self.kind.name()),
+                json_array(&self.inputs),
+                json_array(&self.outputs),
+                json_array(&self.command),
+                self.stale.as_ref().map(|reason| json_string(reason)).unwrap_or_else(|| String::from("null")),
+                self.position.map(|position| position.to_string()).unwrap_or_else(|| String::from("null")))
+    }
+
+    // How `self` differs from the same step in an earlier plan
+    fn changes_since(&self, previous: &PlanStep) -> Vec<String> {
+        let mut reasons = Vec::new();
+        match (&previous.stale, &self.stale) {
+            (&None, Some(reason)) => reasons.push(format!("became stale: {}", reason)),
+            (Some(_), None) => reasons.push(String::from("became up to date")),
+            (Some(old), Some(new)) if old != new => {
+                reasons.push(format!("stale because {}", new))
+            },
+            _ => {},
+        }
+        if self.command != previous.command {
+            reasons.push(String::from("command changed"));
+        }
+        if self.inputs != previous.inputs {
+            reasons.push(String::from("inputs changed"));
+        }
+        if self.outputs != previous.outputs {
+            reasons.push(String::from("outputs changed"));
+        }
+        reasons
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BuildPlan {
+    pub steps: BTreeMap<String, PlanStep>,
+    // The 'schedule' stale objects are compiled in, such as "recent-first"
+    pub schedule: String,
+    // How many of the most recently modified sources are compiled before the
+    // rest, None when they aren't
+    pub fast_fail_window: Option<usize>,
+}
+
+impl BuildPlan {
+    pub fn add(&mut self, step: PlanStep) {
+        self.steps.insert(step.id.clone(), step);
+    }
+
+    pub fn stale_steps(&self) -> Vec<&PlanStep> {
+        self.steps.values().filter(|step| step.stale.is_some()).collect()
+    }
+
+    pub fn delta_from(&self, previous: &BuildPlan) -> PlanDelta {
+        let mut delta = PlanDelta::default();
+        for (id, step) in &self.steps {
+            match previous.steps.get(id) {
+                None => delta.added.push(step.clone()),
+                Some(old) => {
+                    let reasons = step.changes_since(old);
+                    if !reasons.is_empty() {
+                        delta.changed.push((step.clone(), reasons));
+                    }
+                },
+            }
+        }
+        delta.removed = previous.steps.keys().filter(|id| !self.steps.contains_key(*id)).cloned().collect();
+        delta
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PlanDelta {
+    pub added: Vec<PlanStep>,
+    pub removed: Vec<String>,
+    // Each changed step as it is now, with what changed
+    pub changed: Vec<(PlanStep, Vec<String>)>,
+}
+
+impl PlanDelta {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+
+    // Compile steps that weren't stale in the previous plan and are now
+    pub fn newly_stale_objects(&self) -> usize {
+        let added = self.added.iter().filter(|step| step.stale.is_some());
+        let changed = self.changed
+                          .iter()
+                          .filter(|(_, reasons)| reasons.iter().any(|reason| reason.starts_with("became stale")))
+                          .map(|(step, _)| step);
+        added.chain(changed).filter(|step| step.kind == StepKind::Compile).count()
+    }
+
+    // A one line description such as "3 objects newly stale since last build"
+    pub fn summary(&self) -> String {
+        match self.newly_stale_objects() {
+            1 => String::from("1 object newly stale since last build"),
+            count => format!("{} objects newly stale since last build", count),
+        }
+    }
+
+    // The delta as a JSON patch (RFC 6902) of `{"steps": {<id>: <step>}}`.
+    // Replacements also carry the reasons for the change.
+    pub fn to_json(&self) -> String {
+        let mut ops = Vec::new();
+        for step in &self.added {
+            ops.push(format!("{{\"op\":\"add\",\"path\":{},\"value\":{}}}",
+                             json_string(&pointer(&step.id)),
+                             step.to_json()));
+        }
+        for id in &self.removed {
+            ops.push(format!("{{\"op\":\"remove\",\"path\":{}}}", json_string(&pointer(id))));
+        }
+        for (step, reasons) in &self.changed {
+            ops.push(format!("{{\"op\":\"replace\",\"path\":{},\"value\":{},\"reasons\":{}}}",
+                             json_string(&pointer(&step.id)),
+                             step.to_json(),
+                             json_array(reasons)));
+        }
+        format!("[{}]", ops.join(","))
+    }
+}
+
+// JSON pointer to the step `id`, `~` and `/` are escaped as the RFC requires
+fn pointer(id: &str) -> String {
+    format!("/steps/{}", id.replace('~', "~0").replace('/', "~1"))
+}
+
+#[cfg(test)]
+fn step(id: &str, stale: Option<&str>) -> PlanStep {
+    PlanStep {
+        id: id.to_owned(),
+        kind: StepKind::Compile,
+        inputs: vec![id.replace(".o", ".c")],
+        outputs: vec![id.to_owned()],
+        command: vec![String::from("gcc")],
+        stale: stale.map(|reason| reason.to_owned()),
+        position: None,
+    }
+}
+
+#[test]
+fn test_plan_delta() {
+    let mut previous = BuildPlan::default();
+    previous.add(step("src/a.o", None));
+    previous.add(step("src/b.o", None));
+    previous.add(step("src/gone.o", None));
+    let mut current = BuildPlan::default();
+    current.add(step("src/a.o", Some("source newer than its object")));
+    current.add(step("src/b.o", None));
+    current.add(step("src/new.o", Some("no object")));
+    let delta = current.delta_from(&previous);
+    assert_eq!(delta.added, vec![step("src/new.o", Some("no object"))]);
+    assert_eq!(delta.removed, vec!["src/gone.o"]);
+    assert_eq!(delta.changed.len(), 1);
+    assert_eq!(delta.changed[0].1, vec!["became stale: source newer than its object"]);
+    assert_eq!(delta.summary(), "2 objects newly stale since last build");
+    assert!(delta.to_json().starts_with("[{\"op\":\"add\",\"path\":\"/steps/src~1new.o\""));
+    assert!(current.delta_from(&current).is_empty());
+}
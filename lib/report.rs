@@ -0,0 +1,232 @@
+// Copyright (c) 2015 - 2016, Alberto Corona <ac@albertocorona.com>
+// All rights reserved. This file is part of yabs, distributed under the BSD
+// 3-Clause license. For full terms please see the LICENSE file.
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::time::Duration;
+
+// How many of the slowest compiles the summary lists
+pub const SLOWEST_SHOWN: usize = 5;
+
+// How many of the files with the most warnings the summary lists
+pub const MOST_WARNINGS_SHOWN: usize = 5;
+
+// What the compiles of a build reported about one file
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DiagnosticCounts {
+    pub warnings: usize,
+    pub errors: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompileTiming {
+    pub source: PathBuf,
+    pub duration: Duration,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LinkTiming {
+    pub target: String,
+    pub duration: Duration,
+}
+
+// What `BuildFile::build` did and how long it took. Durations are measured
+// with a monotonic clock.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BuildReport {
+    pub wall_time: Duration,
+    // Objects of every source, compiled or not
+    pub objects: usize,
+    pub compiles: Vec<CompileTiming>,
+    // Objects copied from the object cache
+    pub cached: usize,
+    pub links: Vec<LinkTiming>,
+    // Set when the build stopped at its deadline, `remaining` lists the
+    // compiles and links it didn't get to
+    pub truncated: bool,
+    pub remaining: Vec<String>,
+    // Warnings and errors of the compiles that ran, by the file they're in
+    pub diagnostics: BTreeMap<String, DiagnosticCounts>,
+    // Set by `BuildFile::build_objects_only`, which links nothing
+    pub objects_only: bool,
+}
+
+impl BuildReport {
+    pub fn compiled(&self) -> usize {
+        self.compiles.len()
+    }
+
+    // Objects that were already up to date
+    pub fn skipped(&self) -> usize {
+        self.objects.saturating_sub(self.compiled() + self.cached)
+    }
+
+    pub fn warnings(&self) -> usize {
+        self.diagnostics.values().map(|counts| counts.warnings).sum()
+    }
+
+    pub fn errors(&self) -> usize {
+        self.diagnostics.values().map(|counts| counts.errors).sum()
+    }
+
+    // Files with warnings, most first
+    pub fn most_warnings(&self, count: usize) -> Vec<(&str, usize)> {
+        let mut files: Vec<(&str, usize)> = self.diagnostics
+                                                .iter()
+                                                .filter(|&(_, counts)| counts.warnings > 0)
+                                                .map(|(file, counts)| (file.as_str(), counts.warnings))
+                                                .collect();
+        files.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+        files.truncate(count);
+        files
+    }
+
+    // Slowest first
+    pub fn slowest(&self, count: usize) -> Vec<&CompileTiming> {
+        let mut compiles: Vec<&CompileTiming> = self.compiles.iter().collect();
+        compiles.sort_by(|a, b| b.duration.cmp(&a.duration).then_with(|| a.source.cmp(&b.source)));
+        compiles.truncate(count);
+        compiles
+    }
+
+    pub fn summary(&self) -> Vec<String> {
+        let linked = if self.objects_only {
+            String::from("nothing linked, only objects were built")
+        } else {
+            format!("{} target(s) linked", self.links.len())
+        };
+        let mut lines = vec![format!("finished in {}: {} object(s) compiled, {} cached, {} unchanged, {}",
+                                     seconds(self.wall_time),
+                                     self.compiled(),
+                                     self.cached,
+                                     self.skipped(),
+                                     linked)];
+        let slowest = self.slowest(SLOWEST_SHOWN);
+        if !slowest.is_empty() {
+            lines.push(String::from("slowest compiles:"));
+            for compile in slowest {
+                lines.push(format!("  {} {}", seconds(compile.duration), compile.source.display()));
+            }
+        }
+        for link in &self.links {
+            lines.push(format!("linked '{}' in {}", link.target, seconds(link.duration)));
+        }
+        if !self.diagnostics.is_empty() {
+            lines.push(format!("build finished: {} error(s), {} warning(s) in {} file(s)",
+                               self.errors(),
+                               self.warnings(),
+                               self.diagnostics.len()));
+            let most = self.most_warnings(MOST_WARNINGS_SHOWN);
+            if !most.is_empty() {
+                lines.push(String::from("most warnings:"));
+                for (file, warnings) in most {
+                    lines.push(format!("  {} {}", warnings, file));
+                }
+            }
+        }
+        if self.truncated {
+            lines.push(format!("stopped at the deadline, {} step(s) remain:", self.remaining.len()));
+            for step in &self.remaining {
+                lines.push(format!("  {}", step));
+            }
+        }
+        lines
+    }
+}
+
+// One run of a tool over a source by `BuildFile::run_tool_over_sources`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ToolRun {
+    pub source: PathBuf,
+    pub command: String,
+    pub success: bool,
+    // Standard output followed by standard error
+    pub output: String,
+}
+
+// Runs in the order the sources were given
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ToolReport {
+    pub runs: Vec<ToolRun>,
+}
+
+impl ToolReport {
+    pub fn passed(&self) -> usize {
+        self.runs.iter().filter(|run| run.success).count()
+    }
+
+    pub fn failed(&self) -> Vec<&ToolRun> {
+        self.runs.iter().filter(|run| !run.success).collect()
+    }
+
+    pub fn summary(&self) -> String {
+        format!("{} source(s) passed, {} failed", self.passed(), self.failed().len())
+    }
+}
+
+fn seconds(duration: Duration) -> String {
+    format!("{:.2}s", duration.as_secs_f64())
+}
+
+#[test]
+fn test_build_report_summary() {
+    let compile = |source: &str, millis: u64| {
+        CompileTiming {
+            source: PathBuf::from(source),
+            duration: Duration::from_millis(millis),
+        }
+    };
+    let report = BuildReport {
+        wall_time: Duration::from_millis(3250),
+        objects: 10,
+        compiles: vec![compile("a.c", 100),
+                       compile("b.c", 900),
+                       compile("c.c", 300),
+                       compile("d.c", 50),
+                       compile("e.c", 700),
+                       compile("f.c", 10)],
+        cached: 1,
+        links: vec![LinkTiming {
+                        target: String::from("app"),
+                        duration: Duration::from_millis(420),
+                    }],
+        ..BuildReport::default()
+    };
+    assert_eq!(report.skipped(), 3);
+    assert_eq!(report.summary(),
+               vec!["finished in 3.25s: 6 object(s) compiled, 1 cached, 3 unchanged, 1 target(s) linked",
+                    "slowest compiles:",
+                    "  0.90s b.c",
+                    "  0.70s e.c",
+                    "  0.30s c.c",
+                    "  0.10s a.c",
+                    "  0.05s d.c",
+                    "linked 'app' in 0.42s"]);
+    let truncated = BuildReport {
+        truncated: true,
+        remaining: vec![String::from("compile ./c.c"), String::from("link 'app'")],
+        ..report
+    };
+    assert_eq!(truncated.summary()[8..],
+               ["stopped at the deadline, 2 step(s) remain:", "  compile ./c.c", "  link 'app'"]);
+    let objects_only = BuildReport {
+        objects_only: true,
+        links: Vec::new(),
+        ..truncated
+    };
+    assert_eq!(objects_only.summary()[0],
+               "finished in 3.25s: 6 object(s) compiled, 1 cached, 3 unchanged, nothing linked, only objects were \
+                built");
+}
+
+#[test]
+fn test_diagnostics_summary() {
+    let mut report = BuildReport::default();
+    for &(file, warnings, errors) in &[("a.c", 1, 0), ("b.c", 5, 0), ("c.h", 0, 2)] {
+        report.diagnostics.insert(String::from(file), DiagnosticCounts { warnings, errors });
+    }
+    assert_eq!((report.warnings(), report.errors()), (6, 2));
+    assert_eq!(report.summary()[1..],
+               ["build finished: 2 error(s), 6 warning(s) in 3 file(s)", "most warnings:", "  5 b.c", "  1 a.c"]);
+}
@@ -0,0 +1,88 @@
+// Copyright (c) 2015 - 2016, Alberto Corona <ac@albertocorona.com>
+// All rights reserved. This file is part of yabs, distributed under the BSD
+// 3-Clause license. For full terms please see the LICENSE file.
+
+use std::path::Path;
+use std::process::Command;
+
+// Bytes of objects and temporary files assumed per byte of stale source when
+// 'disk-space-factor' isn't set
+pub const DEFAULT_DISK_SPACE_FACTOR: u64 = 4;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiskSpace {
+    pub mount: String,
+    pub available: u64,
+}
+
+// Bytes free on the filesystem holding `dir`, without spawning `df`. None
+// where it can't be told.
+#[cfg(unix)]
+pub fn free_space(dir: &Path) -> Option<u64> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+    let path = CString::new(dir.as_os_str().as_bytes()).ok()?;
+    let mut stat: ::libc::statvfs = unsafe { ::std::mem::zeroed() };
+    if unsafe { ::libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        return None;
+    }
+    Some(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+#[cfg(not(unix))]
+pub fn free_space(dir: &Path) -> Option<u64> {
+    disk_space(dir).map(|space| space.available)
+}
+
+// Free space on the filesystem holding `dir` and where it's mounted, asked
+// from `df`. None where there's no `df` or it can't tell.
+pub fn disk_space(dir: &Path) -> Option<DiskSpace> {
+    let output = Command::new("df").arg("-Pk").arg(dir).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    parse_df(&String::from_utf8_lossy(&output.stdout))
+}
+
+// The filesystem line of `df -Pk` output, the mount point comes last and may
+// contain spaces
+pub fn parse_df(output: &str) -> Option<DiskSpace> {
+    let line = output.lines().nth(1)?;
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    if fields.len() < 6 {
+        return None;
+    }
+    Some(DiskSpace {
+             mount: fields[5..].join(" "),
+             available: fields[3].parse::<u64>().ok()? * 1024,
+         })
+}
+
+pub fn human_size(bytes: u64) -> String {
+    let units = ["bytes", "KB", "MB", "GB", "TB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < units.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, units[0])
+    } else {
+        format!("{:.1} {}", size, units[unit])
+    }
+}
+
+#[test]
+fn test_parse_df() {
+    let output = "Filesystem     1024-blocks      Used Available Capacity Mounted on\n\
+                  /dev/nvme0n1p2   479151816 401256528  53485720      89% /home/me/My Projects\n";
+    assert_eq!(parse_df(output),
+               Some(DiskSpace {
+                        mount: String::from("/home/me/My Projects"),
+                        available: 53485720 * 1024,
+                    }));
+    assert_eq!(parse_df("Filesystem 1024-blocks Used Available Capacity Mounted on\n"), None);
+    assert_eq!(human_size(512), "512 bytes");
+    assert_eq!(human_size(3 * 1024 * 1024 / 2), "1.5 MB");
+}
@@ -0,0 +1,160 @@
+// Copyright (c) 2015 - 2016, Alberto Corona <ac@albertocorona.com>
+// All rights reserved. This file is part of yabs, distributed under the BSD
+// 3-Clause license. For full terms please see the LICENSE file.
+
+use std::fmt;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Why an object has to be compiled. Displayed the way build plans record it,
+// `explain` adds the timestamps that were compared.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StaleReason {
+    // `recorded` when the state cache has a record of compiling it
+    NoObject {
+        recorded: bool,
+    },
+    StateDiscarded,
+    FlagsChanged,
+    // One of the object's headers would now be found elsewhere, described by
+    // `IncludeResolver::changed`
+    IncludeResolution(String),
+    ContentChanged,
+    SourceNewerThanBinary {
+        binary: PathBuf,
+        modified: SystemTime,
+        built: SystemTime,
+    },
+    SourceNewerThanObject {
+        modified: SystemTime,
+        compiled: SystemTime,
+    },
+    // `against` names what the dependency was compared with, the binary or
+    // the object
+    DependencyChanged {
+        dep: PathBuf,
+        modified: SystemTime,
+        built: SystemTime,
+        against: &'static str,
+    },
+    NoModuleInterface,
+    ModuleInterfaceChanged(PathBuf),
+    // `BuildFile::set_force` rebuilds everything
+    Forced,
+}
+
+impl StaleReason {
+    // The reason for `yabs build --explain`
+    pub fn explain(&self) -> String {
+        match *self {
+            StaleReason::NoObject { recorded: false } => String::from("object missing"),
+            StaleReason::NoObject { recorded: true } => String::from("object missing (state cache said up to date)"),
+            StaleReason::SourceNewerThanBinary { ref binary, modified, built } => {
+                compare_times("source", modified, &format!("binary '{}'", binary.display()), built)
+            },
+            StaleReason::SourceNewerThanObject { modified, compiled } => {
+                compare_times("source", modified, "object", compiled)
+            },
+            StaleReason::DependencyChanged { ref dep, modified, built, against } => {
+                compare_times(&format!("'{}'", dep.display()), modified, against, built)
+            },
+            _ => self.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for StaleReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            StaleReason::NoObject { .. } => write!(f, "no object"),
+            StaleReason::StateDiscarded => write!(f, "build state was discarded"),
+            StaleReason::FlagsChanged => write!(f, "compile flags changed"),
+            StaleReason::IncludeResolution(ref reason) => write!(f, "{}", reason),
+            StaleReason::ContentChanged => write!(f, "source content changed"),
+            StaleReason::SourceNewerThanBinary { ref binary, .. } => {
+                write!(f, "source newer than '{}'", binary.display())
+            },
+            StaleReason::SourceNewerThanObject { .. } => write!(f, "source newer than its object"),
+            StaleReason::DependencyChanged { ref dep, .. } => write!(f, "'{}' changed", dep.display()),
+            StaleReason::NoModuleInterface => write!(f, "no module interface"),
+            StaleReason::ModuleInterfaceChanged(ref source) => {
+                write!(f, "module interface '{}' changed", source.display())
+            },
+            StaleReason::Forced => write!(f, "rebuild forced"),
+        }
+    }
+}
+
+// Like `source modified 2024-05-03 12:01:07 > binary 'app' 11:58:02`, the
+// second date is left out when it's the same day. Timestamps within the
+// filesystem's granularity of each other can't be ordered, so they're only
+// said to be too close.
+fn compare_times(what: &str, modified: SystemTime, other: &str, built: SystemTime) -> String {
+    let newer = modified > built;
+    let (modified_date, modified_time) = utc_timestamp(modified);
+    let (built_date, built_time) = utc_timestamp(built);
+    let built = if built_date == modified_date {
+        built_time
+    } else {
+        format!("{} {}", built_date, built_time)
+    };
+    if newer {
+        format!("{} modified {} {} > {} {}", what, modified_date, modified_time, other, built)
+    } else {
+        format!("{} modified {} {}, too close to {} {} to tell which is newer",
+                what,
+                modified_date,
+                modified_time,
+                other,
+                built)
+    }
+}
+
+// The UTC date and time of day of `time`, such as `2024-05-03` and `12:01:07`
+pub fn utc_timestamp(time: SystemTime) -> (String, String) {
+    let secs = time.duration_since(UNIX_EPOCH).map(|since| since.as_secs()).unwrap_or(0);
+    let (days, secs) = (secs / 86400, secs % 86400);
+    // Days to a civil date, after Howard Hinnant's `civil_from_days`
+    let z = days as i64 + 719468;
+    let era = z / 146097;
+    let day_of_era = z - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (format!("{:04}-{:02}-{:02}", year, month, day),
+     format!("{:02}:{:02}:{:02}", secs / 3600, secs % 3600 / 60, secs % 60))
+}
+
+#[test]
+fn test_explain_stale_reason() {
+    use std::time::Duration;
+    let at = |secs: u64| UNIX_EPOCH + Duration::from_secs(secs);
+    assert_eq!(utc_timestamp(at(1714737667)), (String::from("2024-05-03"), String::from("12:01:07")));
+    assert_eq!(utc_timestamp(at(951782400)), (String::from("2000-02-29"), String::from("00:00:00")));
+    let reason = StaleReason::SourceNewerThanBinary {
+        binary: PathBuf::from("app"),
+        modified: at(1714737667),
+        built: at(1714737482),
+    };
+    assert_eq!(reason.to_string(), "source newer than 'app'");
+    assert_eq!(reason.explain(), "source modified 2024-05-03 12:01:07 > binary 'app' 11:58:02");
+    let reason = StaleReason::SourceNewerThanObject {
+        modified: at(1714737667),
+        compiled: at(1714737667),
+    };
+    assert_eq!(reason.explain(),
+               "source modified 2024-05-03 12:01:07, too close to object 12:01:07 to tell which is newer");
+    let reason = StaleReason::DependencyChanged {
+        dep: PathBuf::from("config.h"),
+        modified: at(1714737667),
+        built: at(1714600000),
+        against: "object",
+    };
+    assert_eq!(reason.explain(), "'config.h' modified 2024-05-03 12:01:07 > object 2024-05-01 21:46:40");
+    assert_eq!(StaleReason::NoObject { recorded: false }.explain(), "object missing");
+    assert_eq!(StaleReason::NoObject { recorded: true }.explain(),
+               "object missing (state cache said up to date)");
+}
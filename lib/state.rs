@@ -0,0 +1,329 @@
+// Copyright (c) 2015 - 2016, Alberto Corona <ac@albertocorona.com>
+// All rights reserved. This file is part of yabs, distributed under the BSD
+// 3-Clause license. For full terms please see the LICENSE file.
+
+extern crate toml;
+
+use error::YabsError;
+use fscaps::FsCapabilities;
+use includes::HeaderRecord;
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+// Where build state is kept unless 'state-dir' says otherwise
+pub const DEFAULT_STATE_DIR: &str = ".yabs";
+
+pub const BUILD_STATE_FILE: &str = "state";
+
+pub const STATE_CACHE_FILE: &str = "objects.toml";
+
+pub const SOURCE_HASHES_FILE: &str = "hashes";
+
+pub const SIZE_HISTORY_FILE: &str = "sizes.toml";
+
+// Sizes kept per artifact, the oldest are dropped first
+const SIZE_HISTORY_LEN: usize = 100;
+
+// Bumped whenever `BuildState` changes incompatibly, older state is discarded
+pub const STATE_VERSION: u32 = 1;
+
+// Completions arriving within this long of the last flush wait for the next
+// one, so a build of many small objects doesn't sync the cache for each
+const FLUSH_INTERVAL: Duration = Duration::from_millis(250);
+
+// How an object was last compiled successfully
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ObjectRecord {
+    #[serde(rename = "command-hash")]
+    pub command_hash: String,
+    pub source: String,
+    // Modification time of the source when it was compiled, in seconds since
+    // the epoch
+    #[serde(rename = "source-modified")]
+    pub source_modified: u64,
+    pub seconds: f64,
+    // Headers the compile included, with 'include-resolution-tracking'
+    pub headers: Option<Vec<HeaderRecord>>,
+}
+
+// Records of every compiled object, written as the build goes so objects
+// finished by an interrupted build are recognized by the next one
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct StateCache {
+    pub objects: BTreeMap<String, ObjectRecord>,
+    #[serde(skip)]
+    path: PathBuf,
+    #[serde(skip)]
+    pending: usize,
+    #[serde(skip)]
+    flushed: Option<Instant>,
+}
+
+impl StateCache {
+    // An empty cache if there's none yet or it can't be read, it only saves
+    // work
+    pub fn load(state_dir: &Path) -> StateCache {
+        let path = state_dir.join(STATE_CACHE_FILE);
+        StateCache { path: path.clone(), ..read_toml(&path).unwrap_or_default() }
+    }
+
+    pub fn get(&self, object: &Path) -> Option<&ObjectRecord> {
+        self.objects.get(&object.display().to_string())
+    }
+
+    // Stores `record`, flushing to disk unless the last flush was very recent
+    pub fn record(&mut self, object: &Path, record: ObjectRecord) -> Result<(), YabsError> {
+        self.objects.insert(object.display().to_string(), record);
+        self.pending += 1;
+        if self.flushed.is_none_or(|flushed| flushed.elapsed() >= FLUSH_INTERVAL) {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    // Drops the record of `object`, written with the next flush
+    pub fn forget(&mut self, object: &Path) {
+        if self.objects.remove(&object.display().to_string()).is_some() {
+            self.pending += 1;
+        }
+    }
+
+    // Writes pending records
+    pub fn flush(&mut self) -> Result<(), YabsError> {
+        if self.pending == 0 {
+            return Ok(());
+        }
+        write_toml(&self.path, &toml::to_string(self)?)?;
+        self.pending = 0;
+        self.flushed = Some(Instant::now());
+        Ok(())
+    }
+}
+
+// Content hash of the source each object was last compiled from, kept for
+// `change-detection = "hash"`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SourceHashes {
+    pub objects: BTreeMap<String, String>,
+    #[serde(skip)]
+    path: PathBuf,
+    #[serde(skip)]
+    changed: bool,
+}
+
+impl SourceHashes {
+    // Empty if there are none yet, which rebuilds every object once
+    pub fn load(state_dir: &Path) -> SourceHashes {
+        let path = state_dir.join(SOURCE_HASHES_FILE);
+        SourceHashes { path: path.clone(), ..read_toml(&path).unwrap_or_default() }
+    }
+
+    pub fn get(&self, object: &Path) -> Option<&String> {
+        self.objects.get(&object.display().to_string())
+    }
+
+    pub fn insert(&mut self, object: &Path, hash: String) {
+        self.objects.insert(object.display().to_string(), hash);
+        self.changed = true;
+    }
+
+    pub fn flush(&mut self) -> Result<(), YabsError> {
+        if !self.changed {
+            return Ok(());
+        }
+        write_toml(&self.path, &toml::to_string(self)?)?;
+        self.changed = false;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SizeRecord {
+    pub size: u64,
+    // Seconds since the Unix epoch
+    pub recorded: u64,
+}
+
+// Size of every artifact linked within its 'max-size' budget, oldest first
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SizeHistory {
+    pub artifacts: BTreeMap<String, Vec<SizeRecord>>,
+    #[serde(skip)]
+    path: PathBuf,
+}
+
+impl SizeHistory {
+    pub fn load(state_dir: &Path) -> SizeHistory {
+        let path = state_dir.join(SIZE_HISTORY_FILE);
+        SizeHistory { path: path.clone(), ..read_toml(&path).unwrap_or_default() }
+    }
+
+    pub fn last(&self, artifact: &Path) -> Option<u64> {
+        self.artifacts.get(&artifact.display().to_string()).and_then(|sizes| sizes.last()).map(|record| record.size)
+    }
+
+    pub fn record(&mut self, artifact: &Path, size: u64, now: SystemTime) -> Result<(), YabsError> {
+        let recorded = now.duration_since(UNIX_EPOCH).map(|since| since.as_secs()).unwrap_or(0);
+        let sizes = self.artifacts.entry(artifact.display().to_string()).or_default();
+        sizes.push(SizeRecord { size, recorded });
+        if sizes.len() > SIZE_HISTORY_LEN {
+            let excess = sizes.len() - SIZE_HISTORY_LEN;
+            sizes.drain(..excess);
+        }
+        write_toml(&self.path, &toml::to_string(self)?)
+    }
+}
+
+// What the last build found out about a source
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SourceRecord {
+    // Nanoseconds since the epoch
+    pub modified: u64,
+    pub size: u64,
+    // Only with `change-detection = "hash"`
+    pub hash: Option<String>,
+}
+
+impl SourceRecord {
+    pub fn new(modified: SystemTime, size: u64, hash: Option<String>) -> SourceRecord {
+        SourceRecord {
+            modified: modified.duration_since(UNIX_EPOCH).map(|since| since.as_nanos() as u64).unwrap_or(0),
+            size,
+            hash,
+        }
+    }
+
+    // Whether the source still looks like it did, so its hash can be reused
+    pub fn matches(&self, modified: SystemTime, size: u64) -> bool {
+        let record = SourceRecord::new(modified, size, None);
+        record.modified == self.modified && record.size == self.size
+    }
+}
+
+// Versioned state kept between runs in '<state-dir>/state', loaded by
+// `BuildFile::from_file` and saved after every build
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BuildState {
+    pub version: u32,
+    pub sources: BTreeMap<String, SourceRecord>,
+    // Probed by the first build, see `FsCapabilities`
+    #[serde(rename = "fs-caps")]
+    pub fs_caps: Option<FsCapabilities>,
+    #[serde(skip)]
+    path: PathBuf,
+    // Set when the state on disk couldn't be used and was dropped
+    #[serde(skip)]
+    pub discarded: bool,
+    // The object records and hashes next to discarded state, removed by the
+    // next save
+    #[serde(skip)]
+    stale_records: bool,
+}
+
+impl BuildState {
+    // Unreadable state or state of another version is discarded with a
+    // warning instead of failing the build, together with the object records
+    // and hashes next to it so everything is rebuilt. Loading leaves them on
+    // disk until a build saves the state, see `has_stale_records`.
+    pub fn load(state_dir: &Path) -> BuildState {
+        let path = state_dir.join(BUILD_STATE_FILE);
+        let mut state = BuildState {
+            version: STATE_VERSION,
+            path: path.clone(),
+            ..BuildState::default()
+        };
+        let mut buffer = String::new();
+        if File::open(&path).and_then(|mut file| file.read_to_string(&mut buffer)).is_err() {
+            return state;
+        }
+        let problem = match toml::from_str::<BuildState>(&buffer) {
+            Ok(loaded) if loaded.version == STATE_VERSION => {
+                state.sources = loaded.sources;
+                state.fs_caps = loaded.fs_caps;
+                return state;
+            },
+            Ok(loaded) => format!("it is version {}, this yabs uses version {}", loaded.version, STATE_VERSION),
+            Err(error) => error.to_string(),
+        };
+        warn!("discarding build state '{}' and rebuilding everything: {}", path.display(), problem);
+        state.discarded = true;
+        state.stale_records = true;
+        state
+    }
+
+    // Writes the state, first removing the records left by discarded state
+    pub fn save(&mut self) -> Result<(), YabsError> {
+        if self.stale_records {
+            if let Some(dir) = self.path.parent() {
+                for file in &[STATE_CACHE_FILE, SOURCE_HASHES_FILE] {
+                    let _ = fs::remove_file(dir.join(file));
+                }
+            }
+            self.stale_records = false;
+        }
+        write_toml(&self.path, &toml::to_string(self)?)
+    }
+
+    // Whether the object records and hashes on disk belong to discarded state
+    // and are to be ignored
+    pub fn has_stale_records(&self) -> bool {
+        self.stale_records
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+fn read_toml<T: ::serde::de::DeserializeOwned>(path: &Path) -> Option<T> {
+    let mut buffer = String::new();
+    File::open(path)
+        .and_then(|mut file| file.read_to_string(&mut buffer))
+        .ok()
+        .and_then(|_| toml::from_str(&buffer).ok())
+}
+
+// Writes through a temporary file so a kill mid-write leaves the previous
+// contents intact
+fn write_toml(path: &Path, contents: &str) -> Result<(), YabsError> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let temporary = path.with_extension("tmp");
+    let written = File::create(&temporary).and_then(|mut file| {
+                                              file.write_all(contents.as_bytes())?;
+                                              file.sync_all()
+                                          });
+    if let Err(error) = written {
+        // A full disk leaves the previous file as it was
+        let _ = fs::remove_file(&temporary);
+        return Err(error.into());
+    }
+    fs::rename(&temporary, path)?;
+    Ok(())
+}
+
+#[test]
+fn test_build_state_is_discarded_when_unusable() {
+    let dir = ::std::env::temp_dir().join(format!("yabs-state-test-{}", ::std::process::id()));
+    let mut state = BuildState::load(&dir);
+    assert!(!state.discarded && state.sources.is_empty());
+    state.sources.insert(String::from("main.c"), SourceRecord::new(SystemTime::now(), 10, None));
+    state.save().unwrap();
+    assert_eq!(BuildState::load(&dir).sources, state.sources);
+    let stale = |contents: &str| {
+        fs::write(dir.join(BUILD_STATE_FILE), contents).unwrap();
+        fs::write(dir.join(STATE_CACHE_FILE), "").unwrap();
+        let mut state = BuildState::load(&dir);
+        assert!(state.discarded && state.sources.is_empty() && state.has_stale_records());
+        assert!(dir.join(STATE_CACHE_FILE).exists());
+        state.save().unwrap();
+        assert!(!state.has_stale_records() && !dir.join(STATE_CACHE_FILE).exists());
+    };
+    stale("version = 0\n[sources]\n");
+    stale("version = \"\0garbage");
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,265 @@
+// Copyright (c) 2015 - 2016, Alberto Corona <ac@albertocorona.com>
+// All rights reserved. This file is part of yabs, distributed under the BSD
+// 3-Clause license. For full terms please see the LICENSE file.
+
+//! Helpers for testing projects, scripts and tasks against yabs, enabled with
+//! the `testing` feature.
+//!
+//! A `TestProject` is written to a scratch directory, built in-process with a
+//! fake compiler that records its invocations instead of compiling, and
+//! removed again when it is dropped unless the test panicked.
+//!
+//! ```
+//! use util::testing::TestProject;
+//!
+//! let project = TestProject::new("example",
+//!                                "[project]\n\
+//!                                 file-extensions = [\"c\"]\n\
+//!                                 compiler = \"./yabs-fake-cc\"\n\
+//!                                 [[bin]]\n\
+//!                                 name = \"example\"\n\
+//!                                 path = \"main.c\"\n",
+//!                                &[("main.c", "int main() { return 0; }\n")]);
+//! let report = project.build().unwrap();
+//! assert_eq!((report.compiled(), report.links.len()), (1, 1));
+//! project.assert_compiled(&["./main.c"]);
+//! assert!(project.path("example").exists());
+//! assert!(project.state().sources.contains_key("./main.c"));
+//!
+//! // Nothing changed, so nothing is compiled or linked again
+//! project.clear_invocations();
+//! project.build().unwrap();
+//! assert!(project.invocations().is_empty());
+//! assert!(project.plan().unwrap().stale_steps().is_empty());
+//! ```
+
+use build::{BuildFile, open_build_file_with_context};
+use context::BuildContext;
+use error::YabsError;
+use plan::{BuildPlan, PlanDelta};
+use report::BuildReport;
+use state::{BuildState, DEFAULT_STATE_DIR, StateCache};
+
+use std::env;
+use std::fs;
+use std::ops::Deref;
+#[cfg(unix)]
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+// Written into every test project, set `compiler` (and `ar` for libraries) to
+// it
+pub const FAKE_COMPILER: &str = "./yabs-fake-cc";
+
+const INVOCATIONS_LOG: &str = "yabs-fake-cc.log";
+
+// Separates the arguments of an invocation in the log, so arguments may hold
+// spaces
+const ARG_SEPARATOR: char = '\u{1f}';
+
+// A directory of its own for every call, tests run in parallel
+pub fn scratch_dir(name: &str) -> PathBuf {
+    static COUNT: AtomicUsize = AtomicUsize::new(0);
+    let dest = env::temp_dir()
+        .join(format!("yabs-test-{}-{}", ::std::process::id(), COUNT.fetch_add(1, Ordering::SeqCst)))
+        .join(name);
+    if dest.exists() {
+        fs::remove_dir_all(&dest).unwrap();
+    }
+    dest
+}
+
+// Writes each file under `dir`, creating the directories they're in
+pub fn write_files<S: AsRef<str>>(dir: &Path, files: &[(&str, S)]) {
+    for &(path, ref contents) in files {
+        let path = dir.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents.as_ref()).unwrap();
+    }
+}
+
+// Copies the files and directories under `src` into `dest`
+pub fn copy_dir(src: &Path, dest: &Path) {
+    fs::create_dir_all(dest).unwrap();
+    for entry in fs::read_dir(src).unwrap() {
+        let entry = entry.unwrap();
+        if entry.path().is_dir() {
+            copy_dir(&entry.path(), &dest.join(entry.file_name()));
+        } else {
+            fs::copy(entry.path(), dest.join(entry.file_name())).unwrap();
+        }
+    }
+}
+
+// A project in a scratch directory, named after the project so its buildfile
+// `<name>.toml` is found. It derefs to its directory.
+pub struct TestProject {
+    dir: PathBuf,
+    name: String,
+}
+
+impl TestProject {
+    pub fn new<S: AsRef<str>>(name: &str, buildfile: &str, files: &[(&str, S)]) -> TestProject {
+        let project = TestProject::from_files(name, files);
+        project.write(&format!("{}.toml", name), buildfile);
+        project.write_fake_compiler();
+        project
+    }
+
+    // A project of `files` alone, its buildfile among them, without the fake
+    // compiler
+    pub fn from_files<S: AsRef<str>>(name: &str, files: &[(&str, S)]) -> TestProject {
+        let dir = scratch_dir(name);
+        fs::create_dir_all(&dir).unwrap();
+        write_files(&dir, files);
+        TestProject {
+            dir,
+            name: name.to_owned(),
+        }
+    }
+
+    // A copy of the project in `src`, such as a fixture checked into a
+    // repository, so builds leave nothing behind in it
+    pub fn copied(name: &str, src: &Path) -> TestProject {
+        let project = TestProject::from_files::<&str>(name, &[]);
+        copy_dir(src, &project.dir);
+        project
+    }
+
+    // Records the arguments of every invocation except `--version` and creates
+    // the file given with `-o`, or the archive given to an `ar` style command
+    fn write_fake_compiler(&self) {
+        let script = format!("#!/bin/sh\n\
+                              [ \"$1\" = \"--version\" ] && {{ echo \"yabs-fake-cc 1.0\"; exit 0; }}\n\
+                              for arg in \"$@\"; do printf '%s\\037' \"$arg\"; done >> '{log}'\n\
+                              echo >> '{log}'\n\
+                              case \"$1\" in -*) ;; *) [ $# -ge 2 ] && : > \"$2\";; esac\n\
+                              while [ $# -gt 0 ]; do\n\
+                              \x20   [ \"$1\" = \"-o\" ] && [ $# -ge 2 ] && : > \"$2\"\n\
+                              \x20   shift\n\
+                              done\n",
+                             log = self.path(INVOCATIONS_LOG).display());
+        let path = self.path(FAKE_COMPILER);
+        fs::write(&path, script).unwrap();
+        #[cfg(unix)]
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn path<P: AsRef<Path>>(&self, path: P) -> PathBuf {
+        self.dir.join(path)
+    }
+
+    pub fn write(&self, path: &str, contents: &str) {
+        write_files(&self.dir, &[(path, contents)]);
+    }
+
+    // Loads the project's buildfile with `context`, without changing the
+    // process' working directory
+    pub fn load(&self) -> Result<BuildFile, YabsError> {
+        self.load_with_context(self.context())
+    }
+
+    fn load_with_context(&self, context: BuildContext) -> Result<BuildFile, YabsError> {
+        open_build_file_with_context(&self.path(format!("{}.toml", self.name)), context)
+    }
+
+    // Builds everything like `yabs build` with one job, so invocations are
+    // recorded in a predictable order
+    pub fn build(&self) -> Result<BuildReport, YabsError> {
+        self.load()?.build(1)
+    }
+
+    // No environment variables besides the host's PATH, so a build sees
+    // only what the test adds with `BuildContext::with_var`
+    pub fn context(&self) -> BuildContext {
+        let context = BuildContext::isolated(&self.dir);
+        match env::var("PATH") {
+            Ok(path) => context.with_var("PATH", &path),
+            Err(_) => context,
+        }
+    }
+
+    // Like `build`, with environment variables and the clock read from
+    // `context`
+    pub fn build_with_context(&self, context: BuildContext) -> Result<BuildReport, YabsError> {
+        self.load_with_context(context)?.build(1)
+    }
+
+    pub fn plan(&self) -> Result<BuildPlan, YabsError> {
+        self.load()?.plan()
+    }
+
+    // What changed since `previous`, see `PlanDelta::summary`
+    pub fn plan_delta(&self, previous: &BuildPlan) -> Result<PlanDelta, YabsError> {
+        self.load()?.plan_delta(previous)
+    }
+
+    // Arguments of every recorded invocation of the fake compiler, oldest first
+    pub fn invocations(&self) -> Vec<Vec<String>> {
+        fs::read_to_string(self.path(INVOCATIONS_LOG))
+            .unwrap_or_default()
+            .lines()
+            .map(|line| line.split(ARG_SEPARATOR).filter(|arg| !arg.is_empty()).map(String::from).collect())
+            .collect()
+    }
+
+    pub fn clear_invocations(&self) {
+        let _ = fs::remove_file(self.path(INVOCATIONS_LOG));
+    }
+
+    // Sources compiled by the recorded invocations, in the order they were
+    // compiled
+    pub fn compiled(&self) -> Vec<String> {
+        self.invocations()
+            .into_iter()
+            .filter(|args| args.iter().any(|arg| arg == "-c"))
+            .filter_map(|args| args.last().cloned())
+            .collect()
+    }
+
+    pub fn assert_compiled(&self, sources: &[&str]) {
+        let mut compiled = self.compiled();
+        compiled.sort();
+        let mut expected: Vec<String> = sources.iter().map(|source| source.to_string()).collect();
+        expected.sort();
+        assert_eq!(compiled, expected, "invocations: {:?}", self.invocations());
+    }
+
+    // The build state in the default state directory
+    pub fn state(&self) -> BuildState {
+        BuildState::load(&self.path(DEFAULT_STATE_DIR))
+    }
+
+    pub fn object_records(&self) -> StateCache {
+        StateCache::load(&self.path(DEFAULT_STATE_DIR))
+    }
+}
+
+impl Deref for TestProject {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.dir
+    }
+}
+
+impl AsRef<Path> for TestProject {
+    fn as_ref(&self) -> &Path {
+        &self.dir
+    }
+}
+
+// Kept for inspection when the test failed
+impl Drop for TestProject {
+    fn drop(&mut self) {
+        if !thread::panicking() {
+            let _ = fs::remove_dir_all(self.dir.parent().unwrap());
+        }
+    }
+}
@@ -0,0 +1,124 @@
+// Copyright (c) 2015 - 2016, Alberto Corona <ac@albertocorona.com>
+// All rights reserved. This file is part of yabs, distributed under the BSD
+// 3-Clause license. For full terms please see the LICENSE file.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+// Scratch objects, strace logs and suggestions of `yabs verify`, in the state
+// directory
+pub const VERIFY_DIR: &str = "verify";
+
+// Files anything on the system may read, such as the dynamic loader's cache,
+// the standard headers and the compiler's own files
+const SYSTEM_DIRS: &[&str] = &["/usr", "/lib", "/lib32", "/lib64", "/etc", "/proc", "/sys", "/dev"];
+
+// The argv tracing `command`, logging every file it and its children open into
+// `log`
+pub fn strace_args(log: &Path, command: &[String]) -> Vec<String> {
+    let mut args: Vec<String> = ["strace", "-f", "-qq", "-e", "trace=open,openat,execve", "-o"]
+        .iter()
+        .map(|arg| arg.to_string())
+        .collect();
+    args.push(log.to_string_lossy().into_owned());
+    args.push(String::from("--"));
+    args.extend(command.iter().cloned());
+    args
+}
+
+// Files successfully opened for reading or executed in an strace log written
+// with `-f`, except those the traced processes wrote themselves such as the
+// compiler's temporary files. Calls interrupted by another process are logged
+// in two halves, `<unfinished ...>` and `<... openat resumed>`.
+pub fn strace_reads(log: &str) -> Vec<PathBuf> {
+    let mut reads = Vec::new();
+    let mut written = Vec::new();
+    let mut pending: BTreeMap<String, Option<PathBuf>> = BTreeMap::new();
+    for line in log.lines() {
+        let (pid, call) = match line.find(' ') {
+            Some(index) if line[..index].chars().all(|c| c.is_ascii_digit()) => {
+                (&line[..index], line[index + 1..].trim())
+            },
+            _ => ("", line.trim()),
+        };
+        if let Some(path) = written_path(call) {
+            written.push(path);
+            continue;
+        }
+        let path = if call.starts_with("<...") {
+            match pending.remove(pid) {
+                Some(path) => path,
+                None => continue,
+            }
+        } else {
+            opened_path(call)
+        };
+        if call.ends_with("<unfinished ...>") {
+            pending.insert(pid.to_owned(), path);
+            continue;
+        }
+        let succeeded = call.rsplit(" = ").next().is_some_and(|result| !result.starts_with('-'));
+        if let Some(path) = path {
+            if succeeded && !reads.contains(&path) {
+                reads.push(path);
+            }
+        }
+    }
+    reads.retain(|path| !written.contains(path));
+    reads
+}
+
+// The path of an open or exec call and the flags following it
+fn call_path(call: &str) -> Option<(PathBuf, &str)> {
+    let name = call.split('(').next().unwrap_or("");
+    if !["open", "openat", "execve"].contains(&name) {
+        return None;
+    }
+    let start = call.find('"')? + 1;
+    let end = start + call[start..].find('"')?;
+    Some((PathBuf::from(&call[start..end]), &call[end..]))
+}
+
+// The file read by an open or exec call, None for other calls and for files
+// opened for writing or directories
+fn opened_path(call: &str) -> Option<PathBuf> {
+    call_path(call).and_then(|(path, flags)| if flags.contains("O_DIRECTORY") { None } else { Some(path) })
+}
+
+fn written_path(call: &str) -> Option<PathBuf> {
+    call_path(call).and_then(|(path, flags)| {
+                                 if ["O_WRONLY", "O_CREAT"].iter().any(|flag| flags.contains(flag)) {
+                                     Some(path)
+                                 } else {
+                                     None
+                                 }
+                             })
+}
+
+// Whether `path` belongs to the system or to the toolchain installed under
+// `toolchain_prefix`
+pub fn is_system_path(path: &Path, toolchain_prefix: Option<&Path>) -> bool {
+    SYSTEM_DIRS.iter().any(|dir| path.starts_with(dir)) ||
+    toolchain_prefix.is_some_and(|prefix| path.starts_with(prefix))
+}
+
+#[test]
+fn test_strace_reads() {
+    let log = "100 execve(\"/usr/bin/gcc\", [\"gcc\", \"-c\"], 0x7ffd /* 20 vars */) = 0\n\
+               100 openat(AT_FDCWD, \"main.c\", O_RDONLY|O_NOCTTY) = 3\n\
+               100 openat(AT_FDCWD, \"missing.h\", O_RDONLY|O_NOCTTY) = -1 ENOENT (No such file)\n\
+               100 openat(AT_FDCWD, \"/tmp/cc1.s\", O_RDWR|O_CREAT|O_EXCL, 0600) = 4\n\
+               102 openat(AT_FDCWD, \"/tmp/cc1.s\", O_RDONLY) = 3\n\
+               100 openat(AT_FDCWD, \"include\", O_RDONLY|O_DIRECTORY) = 5\n\
+               101 openat(AT_FDCWD, \"/opt/vendor/config.h\", O_RDONLY <unfinished ...>\n\
+               100 openat(AT_FDCWD, \"main.c\", O_RDONLY|O_NOCTTY) = 3\n\
+               101 <... openat resumed>) = 6\n\
+               101 +++ exited with 0 +++";
+    assert_eq!(strace_reads(log),
+               vec![PathBuf::from("/usr/bin/gcc"),
+                    PathBuf::from("main.c"),
+                    PathBuf::from("/opt/vendor/config.h")]);
+    assert!(is_system_path(Path::new("/usr/include/stdio.h"), None));
+    assert!(!is_system_path(Path::new("/opt/vendor/config.h"), None));
+    assert!(is_system_path(Path::new("/opt/cross/lib/gcc/crt1.o"), Some(Path::new("/opt/cross"))));
+}
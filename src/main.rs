@@ -3,15 +3,15 @@
 // 3-Clause license. For full terms please see the LICENSE file.
 
 extern crate util;
-extern crate num_cpus;
 #[macro_use]
 extern crate clap;
 #[macro_use]
 extern crate log;
 
 use clap::App;
-use std::env;
 use std::fmt::Display;
+use std::fs;
+use std::path::Path;
 use std::process::exit;
 use util::*;
 
@@ -20,58 +20,270 @@ enum ExitStatus {
     GeneralError,
     OptionError,
     BuildError,
+    // `yabs status` found something to build
+    Dirty,
 }
 
 fn check_error<T, E: Display>(result: Result<T, E>, status: ExitStatus) -> ExitStatus {
     if let Err(error) = result {
         error!("{}", error.to_string());
-        return status;
+        status
     } else {
-        return ExitStatus::Success;
+        ExitStatus::Success
+    }
+}
+
+// The `--jobs` of a subcommand, 0 when it isn't given
+fn parse_jobs(matches: &clap::ArgMatches) -> Result<usize, ExitStatus> {
+    match matches.value_of("jobs").map(|jobs| jobs.parse::<usize>()) {
+        None => Ok(0),
+        Some(Ok(jobs)) => Ok(jobs),
+        Some(Err(error)) => {
+            error!("{}", error.to_string());
+            Err(ExitStatus::OptionError)
+        },
     }
 }
 
 fn run() -> ExitStatus {
     let yaml = load_yaml!("cli.yaml");
-    if let Err(error) = logger::Logger::init() {
-        error!("{}", error.to_string());
+    let matches = App::from_yaml(yaml).get_matches();
+    let append = matches.is_present("append-log");
+    let verbosity = if matches.is_present("quiet") {
+        logger::Verbosity::Quiet
+    } else if matches.is_present("verbose") {
+        logger::Verbosity::Verbose
+    } else {
+        logger::Verbosity::Normal
+    };
+    let mut context = context::BuildContext::from_env();
+    context.strict = matches.is_present("strict");
+    let color = matches.value_of("color")
+                       .and_then(logger::ColorChoice::parse)
+                       .unwrap_or(logger::ColorChoice::Auto)
+                       .enabled(&context);
+    let options = logger::LogOptions {
+        verbosity,
+        color,
+        timestamps: matches.value_of("timestamps")
+                           .and_then(logger::Timestamps::parse)
+                           .unwrap_or(logger::Timestamps::Off),
+    };
+    let logger = match (matches.is_present("json"), matches.value_of("log-file")) {
+        (true, Some(path)) => logger::JsonLogger::init_with_file(options, Path::new(path), append),
+        (true, None) => logger::JsonLogger::init(options),
+        (false, Some(path)) => logger::Logger::init_with_file(options, Path::new(path), append),
+        (false, None) => logger::Logger::init(options),
+    };
+    if let Err(error) = logger {
+        // There is no logger to report it through
+        eprintln!("error: {}", error);
         return ExitStatus::OptionError;
     };
-    let matches = App::from_yaml(yaml).get_matches();
     if let Some(matches) = matches.subcommand_matches("new") {
-        if matches.is_present("bin") {
-            if let Some(value) = matches.value_of("bin") {
-                if let Err(error) = new::new_project(&value.to_owned(), false) {
+        for &(arg, kind) in &[("bin", new::ProjectKind::Binary),
+                              ("lib", new::ProjectKind::Library),
+                              ("bin-lib", new::ProjectKind::BinaryAndLibrary)] {
+            if let Some(value) = matches.value_of(arg) {
+                if let Err(error) = new::new_project(value, kind) {
                     error!("{}", error.to_string());
                     return ExitStatus::OptionError;
                 }
             }
         }
-    } else if let Ok(ref mut cwd) = env::current_dir() {
-        match build::find_build_file(cwd) {
+    } else if let Some(matches) = matches.subcommand_matches("init") {
+        if let Err(error) = new::init(&context.cwd, matches.is_present("force")) {
+            error!("{}", error.to_string());
+            return ExitStatus::OptionError;
+        }
+    } else {
+        let path = match matches.value_of("file") {
+            Some(path) => Ok(context.cwd.join(path)),
+            None => build::locate_build_file(&context),
+        };
+        let build_file = path.and_then(|path| build::open_build_file_with_context(&path, context));
+        match build_file {
             Ok(mut build_file) => {
+                // JSON events carry diagnostics without their colors anyway
+                build_file.set_color_diagnostics(color && !matches.is_present("json"));
+                let compiles = ["build", "task", "pgo", "verify", "check-syntax"];
+                if compiles.contains(&matches.subcommand_name().unwrap_or("")) {
+                    if let Err(error) = build_file.check_toolchain().and_then(|_| build_file.check_tools()) {
+                        error!("{}", error.to_string());
+                        return ExitStatus::OptionError;
+                    }
+                }
                 if let Some(matches) = matches.subcommand_matches("build") {
-                    let mut jobs = num_cpus::get();
-                    if let Some(jobs_given) = matches.value_of("jobs") {
-                        match jobs_given.parse::<usize>() {
-                            Ok(j) => jobs = j,
-                            Err(error) => {
-                                error!("{}", error.to_string());
-                                return ExitStatus::OptionError;
-                            },
+                    let jobs = match parse_jobs(matches) {
+                        Ok(jobs) => jobs,
+                        Err(status) => return status,
+                    };
+                    build_file.set_explain(matches.is_present("explain"));
+                    build_file.set_force(matches.is_present("force"));
+                    build_file.set_lock_blocking(!matches.is_present("no-wait"));
+                    let mut durations = Vec::new();
+                    for option in &["deadline", "deadline-grace"] {
+                        let value = matches.value_of(option);
+                        let duration = value.and_then(deadline::parse_duration);
+                        if let (Some(value), None) = (value, duration) {
+                            error!("invalid duration '{}' for '--{}', expected one such as 90s, 25m or 1h30m",
+                                   value,
+                                   option);
+                            return ExitStatus::OptionError;
                         }
+                        durations.push(duration);
                     }
+                    build_file.set_deadline(durations[0].map(deadline::Deadline::After), durations[1]);
                     if let Some(bin_name) = matches.value_of("bin") {
                         return check_error(build_file.build_binary_with_name(bin_name, jobs),
                                            ExitStatus::BuildError);
                     } else if let Some(lib_name) = matches.value_of("lib") {
                         return check_error(build_file.build_library_with_name(lib_name, jobs),
                                            ExitStatus::BuildError);
+                    } else if let Some(group) = matches.value_of("group") {
+                        return check_error(build_file.build_group(group, jobs), ExitStatus::BuildError);
+                    } else if let Some(names) = matches.values_of("targets") {
+                        let names: Vec<&str> = names.collect();
+                        return check_error(build_file.build_targets(&names, jobs), ExitStatus::BuildError);
+                    } else {
+                        let built = if matches.is_present("objects-only") {
+                            build_file.build_objects_only(jobs, !matches.is_present("no-scripts"))
+                        } else {
+                            build_file.build(jobs)
+                        };
+                        match built {
+                            Ok(ref report) if report.truncated => {
+                                error!("the build stopped at its deadline");
+                                return ExitStatus::BuildError;
+                            },
+                            result => return check_error(result, ExitStatus::BuildError),
+                        }
+                    }
+                } else if let Some(matches) = matches.subcommand_matches("task") {
+                    let jobs = match parse_jobs(matches) {
+                        Ok(jobs) => jobs,
+                        Err(status) => return status,
+                    };
+                    if let Some(name) = matches.value_of("name") {
+                        return check_error(build_file.run_task(name, jobs), ExitStatus::BuildError);
+                    }
+                } else if let Some(matches) = matches.subcommand_matches("pgo") {
+                    let jobs = match parse_jobs(matches) {
+                        Ok(jobs) => jobs,
+                        Err(status) => return status,
+                    };
+                    if let Some(training) = matches.value_of("training") {
+                        return check_error(build_file.build_pgo(jobs, training),
+                                           ExitStatus::BuildError);
+                    }
+                } else if let Some(matches) = matches.subcommand_matches("tool") {
+                    let jobs = match parse_jobs(matches) {
+                        Ok(jobs) => jobs,
+                        Err(status) => return status,
+                    };
+                    let selector = analysis::SourceSelector {
+                        target: matches.value_of("target").map(String::from),
+                        patterns: matches.values_of("glob").into_iter().flatten().map(String::from).collect(),
+                    };
+                    let template: Vec<String> =
+                        matches.values_of("command").into_iter().flatten().map(String::from).collect();
+                    match build_file.run_tool_over_sources(&template, jobs, &selector) {
+                        Ok(report) => {
+                            for run in &report.runs {
+                                if !run.output.is_empty() {
+                                    info!("{}:\n{}", run.source.display(), run.output.trim_end());
+                                }
+                            }
+                            info!(target: logger::SUMMARY_TARGET, "{}", report.summary());
+                            if !report.failed().is_empty() {
+                                return ExitStatus::BuildError;
+                            }
+                        },
+                        Err(error) => {
+                            error!("{}", error.to_string());
+                            return ExitStatus::BuildError;
+                        },
+                    }
+                } else if let Some(matches) = matches.subcommand_matches("preprocess") {
+                    let source = Path::new(matches.value_of("source").unwrap_or_default());
+                    let text = match build_file.preprocess(source) {
+                        Ok(text) => text,
+                        Err(error) => {
+                            error!("{}", error.to_string());
+                            return ExitStatus::BuildError;
+                        },
+                    };
+                    match matches.value_of("output") {
+                        Some(path) => return check_error(fs::write(path, text), ExitStatus::GeneralError),
+                        None => print!("{}", text),
+                    }
+                } else if let Some(matches) = matches.subcommand_matches("check-syntax") {
+                    let jobs = match parse_jobs(matches) {
+                        Ok(jobs) => jobs,
+                        Err(status) => return status,
+                    };
+                    match build_file.check_syntax(jobs) {
+                        Ok(ref report) if !report.failed().is_empty() => return ExitStatus::BuildError,
+                        result => return check_error(result, ExitStatus::BuildError),
+                    }
+                } else if let Some(matches) = matches.subcommand_matches("export") {
+                    if let Some(prefix) = matches.value_of("prefix") {
+                        return check_error(build_file.export(Path::new(prefix)),
+                                           ExitStatus::GeneralError);
+                    }
+                } else if matches.subcommand_matches("verify").is_some() {
+                    return check_error(build_file.verify_inputs(), ExitStatus::GeneralError);
+                } else if let Some(matches) = matches.subcommand_matches("status") {
+                    build_file.set_force(matches.is_present("force"));
+                    return match build_file.log_status() {
+                        Ok(ref status) if status.is_dirty() => ExitStatus::Dirty,
+                        result => check_error(result, ExitStatus::GeneralError),
+                    };
+                } else if matches.subcommand_matches("graph").is_some() {
+                    return check_error(build_file.export_graph().map(|graph| print!("{}", graph)),
+                                       ExitStatus::GeneralError);
+                } else if let Some(matches) = matches.subcommand_matches("sources") {
+                    let target = matches.value_of("target");
+                    // Printed as they are so they can be piped into other tools
+                    let sources = if matches.value_of("format") == Some("json") {
+                        build_file.sources_json(target).map(|json| println!("{}", json))
                     } else {
-                        return check_error(build_file.build(jobs), ExitStatus::BuildError);
+                        build_file.sources_for(target).map(|sources| {
+                            for info in sources {
+                                println!("{}", info.source.display());
+                            }
+                        })
+                    };
+                    return check_error(sources, ExitStatus::GeneralError);
+                } else if matches.subcommand_matches("targets").is_some() {
+                    build_file.log_targets();
+                } else if matches.subcommand_matches("print-config").is_some() {
+                    // Printed as it is so it can be redirected and diffed
+                    match build_file.effective_config() {
+                        Ok(config) => print!("{}", config),
+                        Err(error) => {
+                            error!("{}", error.to_string());
+                            return ExitStatus::GeneralError;
+                        },
                     }
-                } else if matches.subcommand_matches("clean").is_some() {
-                    return check_error(build_file.clean(), ExitStatus::GeneralError);
+                } else if matches.subcommand_matches("check").is_some() {
+                    return check_error(build_file.check(), ExitStatus::GeneralError);
+                } else if matches.subcommand_matches("doctor").is_some() {
+                    return check_error(build_file.doctor(), ExitStatus::GeneralError);
+                } else if matches.subcommand_matches("coverage").is_some() {
+                    return check_error(build_file.coverage_report(), ExitStatus::GeneralError);
+                } else if let Some(matches) = matches.subcommand_matches("distclean") {
+                    build_file.set_lock_blocking(!matches.is_present("no-wait"));
+                    build_file.set_dry_run(matches.is_present("dry-run"));
+                    return check_error(build_file.distclean(), ExitStatus::GeneralError);
+                } else if let Some(matches) = matches.subcommand_matches("clean") {
+                    build_file.set_lock_blocking(!matches.is_present("no-wait"));
+                    build_file.set_dry_run(matches.is_present("dry-run"));
+                    let cleaned = match matches.value_of("target") {
+                        Some(target) => build_file.clean_target(target, matches.is_present("force")),
+                        None => build_file.clean(),
+                    };
+                    return check_error(cleaned, ExitStatus::GeneralError);
                 }
                 if matches.is_present("sources") {
                     build_file.print_sources();
@@ -87,7 +299,10 @@ fn run() -> ExitStatus {
 }
 
 fn main() {
-    match run() {
-        error => exit(error as i32),
+    interrupt::install();
+    let status = run();
+    if interrupt::interrupted() {
+        exit(interrupt::INTERRUPTED_STATUS);
     }
+    exit(status as i32)
 }
@@ -0,0 +1,5 @@
+#include "greet.h"
+
+int greet() {
+	return sizeof(GREETING) == 12 ? ANSWER : 0;
+}
@@ -0,0 +1 @@
+int greet();
@@ -0,0 +1,5 @@
+#include "greet.h"
+
+int main() {
+	return greet() == 42 ? 0 : 1;
+}
@@ -0,0 +1,3205 @@
+// Copyright (c) 2015 - 2016, Alberto Corona <ac@albertocorona.com>
+// All rights reserved. This file is part of yabs, distributed under the BSD
+// 3-Clause license. For full terms please see the LICENSE file.
+
+extern crate libc;
+extern crate serde_json;
+extern crate util;
+
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Output};
+use std::time::{Duration, SystemTime};
+use util::fscaps::FsCapabilities;
+use util::plan::{BuildPlan, StepKind};
+use util::testing::TestProject;
+
+// Copies a project from `test/`, keeping its directory name so its buildfile
+// is still found
+fn fixture(name: &str) -> TestProject {
+    TestProject::copied(name, &Path::new(env!("CARGO_MANIFEST_DIR")).join("test").join(name))
+}
+
+// A project described by its files, removed once the test passes
+fn project(name: &str, files: &[(&str, String)]) -> TestProject {
+    TestProject::from_files(name, files)
+}
+
+fn yabs(dir: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_yabs")).args(args).current_dir(dir).output().unwrap()
+}
+
+// Everything yabs logged, warnings and errors go to stderr
+fn logged(output: &Output) -> String {
+    format!("{}{}", String::from_utf8_lossy(&output.stdout), String::from_utf8_lossy(&output.stderr))
+}
+
+#[test]
+fn test_build_dir_with_spaces() {
+    let dir = fixture("spaces");
+    let output = yabs(&dir, &["build"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stdout));
+    assert!(dir.join("dir with spaces/main.o").exists());
+    assert!(Command::new(dir.join("spaces")).status().unwrap().success());
+}
+
+#[test]
+fn test_incremental_link_groups() {
+    let mut files = vec![("many_objects.toml",
+                          String::from("[project]\n\
+                                        file-extensions = [\"c\"]\n\
+                                        [[bin]]\n\
+                                        name = \"many\"\n\
+                                        path = \"./src/main.c\"\n\
+                                        incremental-link-groups = 3\n")),
+                         ("src/main.c",
+                          String::from("int sum();\nint main() { return sum() == 78 ? 0 : 1; }\n"))];
+    let mut sum = String::from("int sum() { return 0");
+    let names: Vec<String> = (0..12).map(|i| format!("src/f{}.c", i)).collect();
+    for (i, name) in names.iter().enumerate() {
+        files.push((name, format!("int f{}() {{ return {}; }}\n", i, i + 1)));
+        sum = format!("int f{}();\n{} + f{}()", i, sum, i);
+    }
+    files.push(("src/sum.c", sum + "; }\n"));
+    let dir = project("many_objects", &files);
+    let output = yabs(&dir, &["build"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stdout));
+    assert!(Command::new(dir.join("many")).status().unwrap().success());
+    for group in 0..3 {
+        assert!(dir.join(format!("many.partial/{}.o", group)).exists());
+    }
+    std::thread::sleep(std::time::Duration::from_millis(20));
+    fs::write(dir.join("src/f0.c"), "int f0() { return 1; }\nint unused() { return 0; }\n").unwrap();
+    let output = yabs(&dir, &["build", "-v"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{}", stdout);
+    assert_eq!(stdout.lines().filter(|line| line.contains(" -r -o ")).count(), 1);
+    assert!(Command::new(dir.join("many")).status().unwrap().success());
+    // A new source only joins one group, the others stay as they were
+    fs::write(dir.join("src/extra.c"), "int extra() { return 0; }\n").unwrap();
+    let output = yabs(&dir, &["build", "-v"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{}", stdout);
+    assert_eq!(stdout.lines().filter(|line| line.contains(" -r -o ")).count(), 1, "{}", stdout);
+}
+
+// Runs yabs in `dir` like `yabs`, with how long it took and the peak resident
+// size in KiB of it and the processes it waited for, such as the linker
+fn measured(dir: &Path, args: &[&str]) -> (bool, Duration, i64) {
+    use std::process::Stdio;
+    let started = std::time::Instant::now();
+    // Reaped with `wait4` rather than `wait` for its resource usage
+    let pid = Command::new(env!("CARGO_BIN_EXE_yabs")).args(args)
+                                                      .current_dir(dir)
+                                                      .stdout(Stdio::null())
+                                                      .spawn()
+                                                      .unwrap()
+                                                      .id() as libc::pid_t;
+    let mut status = 0;
+    let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+    assert_eq!(unsafe { libc::wait4(pid, &mut status, 0, &mut usage) }, pid);
+    (libc::WIFEXITED(status) && libc::WEXITSTATUS(status) == 0, started.elapsed(), usage.ru_maxrss)
+}
+
+// Relinks a binary of many objects after one of them changes, once in a
+// single link and once through partial links, printing the wall time and
+// peak memory of each. Run with `cargo test --test build -- --ignored`,
+// YABS_BENCH_OBJECTS sets how many objects (2000 by default).
+#[test]
+#[ignore]
+fn bench_incremental_link_groups() {
+    let count: usize = env::var("YABS_BENCH_OBJECTS").ok().and_then(|count| count.parse().ok()).unwrap_or(2000);
+    for groups in &[0, 16] {
+        let mut files = vec![("bench.toml",
+                              format!("[project]\n\
+                                       file-extensions = [\"c\"]\n\
+                                       [[bin]]\n\
+                                       name = \"bench\"\n\
+                                       path = \"./src/main.c\"\n\
+                                       incremental-link-groups = {}\n",
+                                      groups)),
+                             ("src/main.c", String::from("int main() { return 0; }\n"))];
+        let names: Vec<String> = (0..count).map(|i| format!("src/f{}.c", i)).collect();
+        for (i, name) in names.iter().enumerate() {
+            files.push((name,
+                        format!("static const int table[1024] = {{ {0} }};\n\
+                                 int f{0}(int n) {{ return table[n % 1024] + {0}; }}\n",
+                                i)));
+        }
+        let dir = project("bench", &files);
+        let (built, _, _) = measured(&dir, &["build"]);
+        assert!(built);
+        std::thread::sleep(Duration::from_millis(20));
+        fs::write(dir.join("src/f0.c"), "int f0(int n) { return n; }\n").unwrap();
+        let (relinked, elapsed, peak) = measured(&dir, &["build"]);
+        assert!(relinked);
+        eprintln!("{} objects, {} link group(s): relinked in {:.2}s with a peak of {} KiB",
+                  count + 1,
+                  groups,
+                  elapsed.as_secs_f64(),
+                  peak);
+    }
+}
+
+#[test]
+fn test_sources_appearing_during_build() {
+    let dir = project("late_sources",
+                      &[("late_sources.toml",
+                         String::from("[project]\n\
+                                       file-extensions = [\"c\"]\n\
+                                       before-script = [\"echo 'int late() { return 0; }' > src/late.c\"]\n\
+                                       [[bin]]\n\
+                                       name = \"late\"\n\
+                                       path = \"./src/main.c\"\n")),
+                        ("src/main.c", String::from("int main() { return 0; }\n"))]);
+    let output = yabs(&dir, &["build"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{}", stdout);
+    assert!(stdout.contains("1 source file(s) appeared during the build"), "{}", stdout);
+    assert!(!dir.join("src/late.o").exists());
+    let output = yabs(&dir, &["build"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(dir.join("src/late.o").exists(), "{}", stdout);
+    assert!(!stdout.contains("appeared during the build"), "{}", stdout);
+}
+
+#[test]
+fn test_changed_compile_flags_rebuild_objects() {
+    let dir = fixture("spaces");
+    assert!(yabs(&dir, &["build"]).status.success());
+    let toml = fs::read_to_string(dir.join("spaces.toml")).unwrap();
+    fs::write(dir.join("spaces.toml"),
+              toml.replace("[project]\n", "[project]\nsanitize = [\"undefined\"]\n"))
+        .unwrap();
+    let output = yabs(&dir, &["build", "-v"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{}", stdout);
+    assert!(stdout.contains("rebuilding all objects"), "{}", stdout);
+    assert_eq!(stdout.lines().filter(|line| line.contains("-fsanitize=undefined")).count(),
+               3);
+}
+
+#[test]
+fn test_run_task() {
+    let dir = project("tasks",
+                      &[("tasks.toml",
+                         String::from("[project]\n\
+                                       file-extensions = [\"c\"]\n\
+                                       [[bin]]\n\
+                                       name = \"app\"\n\
+                                       path = \"./main.c\"\n\
+                                       [[task]]\n\
+                                       name = \"package\"\n\
+                                       command = [\"cp\", \"app\", \"app.pkg\"]\n\
+                                       depends = [\"app\"]\n\
+                                       inputs = [\"app\"]\n\
+                                       outputs = [\"app.pkg\"]\n")),
+                        ("main.c", String::from("int main() { return 0; }\n"))]);
+    let output = yabs(&dir, &["task", "package"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stdout));
+    assert!(dir.join("app.pkg").exists());
+    let output = yabs(&dir, &["task", "package"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("task 'package' is up to date"), "{}", stdout);
+    assert!(!yabs(&dir, &["task", "deploy"]).status.success());
+    // Every subcommand taking `--jobs` rejects one that isn't a count
+    let subcommands = [vec!["build"], vec!["task", "package"], vec!["check-syntax"]];
+    for args in subcommands.iter().map(|subcommand| [&subcommand[..], &["-j", "many"]].concat()) {
+        let output = yabs(&dir, &args);
+        assert_eq!(output.status.code(), Some(2), "{}", logged(&output));
+        assert!(logged(&output).contains("invalid digit"), "{}", logged(&output));
+    }
+}
+
+#[test]
+fn test_declared_scripts_are_skipped() {
+    let dir = project("scripts",
+                      &[("scripts.toml",
+                         String::from("[project]\n\
+                                       file-extensions = [\"c\"]\n\
+                                       before-script = [\n\
+                                       { command = \"cat data/*.txt > gen.c; echo run >> runs\", \
+                                       inputs = [\"data/*.txt\"], outputs = [\"gen.c\"] },\n\
+                                       { command = \"true\", outputs = [\"never\"] },\n\
+                                       ]\n\
+                                       [[bin]]\n\
+                                       name = \"app\"\n\
+                                       path = \"./main.c\"\n")),
+                        ("data/a.txt", String::from("int gen() { return 0; }\n")),
+                        ("main.c", String::from("int main() { return 0; }\n"))]);
+    let output = yabs(&dir, &["build"]);
+    let stdout = logged(&output);
+    assert!(output.status.success(), "{}", stdout);
+    assert!(stdout.contains("didn't produce declared output 'never'"), "{}", stdout);
+    let stdout = String::from_utf8_lossy(&yabs(&dir, &["build"]).stdout).into_owned();
+    assert!(stdout.contains("is up to date"), "{}", stdout);
+    assert_eq!(fs::read_to_string(dir.join("runs")).unwrap(), "run\n");
+    // A script taken out of the buildfile loses its stamp, so it runs again
+    // once it's put back
+    let buildfile = fs::read_to_string(dir.join("scripts.toml")).unwrap();
+    let generate = "{ command = \"cat data/*.txt > gen.c; echo run >> runs\", \
+                    inputs = [\"data/*.txt\"], outputs = [\"gen.c\"] },\n";
+    assert!(buildfile.contains(generate));
+    fs::write(dir.join("scripts.toml"), buildfile.replace(generate, "")).unwrap();
+    assert!(yabs(&dir, &["build"]).status.success());
+    assert_eq!(fs::read_dir(dir.join(".yabs/scripts")).unwrap().count(), 1);
+    fs::write(dir.join("scripts.toml"), &buildfile).unwrap();
+    assert!(yabs(&dir, &["build"]).status.success());
+    assert_eq!(fs::read_to_string(dir.join("runs")).unwrap(), "run\nrun\n");
+}
+
+#[test]
+fn test_exported_dependency() {
+    let lib = project("answer",
+                      &[("answer.toml",
+                         String::from("[project]\n\
+                                       name = \"answer\"\n\
+                                       version = \"1.2.0\"\n\
+                                       file-extensions = [\"c\"]\n\
+                                       include = [\"include\"]\n\
+                                       [[lib]]\n\
+                                       name = \"libanswer\"\n\
+                                       types = [\"static\"]\n")),
+                        ("include/answer.h", String::from("int answer();\n")),
+                        ("answer.c", String::from("int answer() { return 42; }\n"))]);
+    assert!(yabs(&lib, &["build"]).status.success());
+    let prefix = lib.parent().unwrap().join("prefix");
+    assert!(yabs(&lib, &["export", prefix.to_str().unwrap()]).status.success());
+    let app_toml = |version: &str| {
+        format!("[project]\n\
+                 file-extensions = [\"c\"]\n\
+                 deps = [{{ name = \"answer\", version = \"{}\" }}]\n\
+                 dependency-prefixes = [\"{}\"]\n\
+                 [[bin]]\n\
+                 name = \"app\"\n\
+                 path = \"./main.c\"\n",
+                version,
+                prefix.display())
+    };
+    let app = project("app",
+                      &[("app.toml", app_toml(">=1.1")),
+                        ("main.c",
+                         String::from("#include <answer.h>\n\
+                                       int main() { return answer() == 42 ? 0 : 1; }\n"))]);
+    let output = yabs(&app, &["build"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stdout));
+    assert!(Command::new(app.join("app")).status().unwrap().success());
+    fs::write(app.join("app.toml"), app_toml(">=2")).unwrap();
+    let stdout = logged(&yabs(&app, &["build"]));
+    assert!(stdout.contains("requires version '>=2'"), "{}", stdout);
+    fs::write(app.join("app.toml"), app_toml(">=1").replace("\"answer\"", "\"question\"")).unwrap();
+    let stdout = logged(&yabs(&app, &["build"]));
+    assert!(stdout.contains("could not find an export file for question"), "{}", stdout);
+}
+
+#[test]
+fn test_pkg_config() {
+    let dir = project("pkgconf",
+                      &[("pkgconf.toml",
+                         String::from("[project]\n\
+                                       file-extensions = [\"c\"]\n\
+                                       pkg-config = [\"answer >= 1.2\"]\n\
+                                       [[bin]]\n\
+                                       name = \"pkgconf\"\n\
+                                       path = \"./main.c\"\n")),
+                        ("pc/answer.pc",
+                         String::from("Name: answer\nDescription: test\nVersion: 1.3\n\
+                                       Cflags: -I${pcfiledir}/include -DANSWER=42\nLibs: -lm\n")),
+                        ("pc/include/answer.h", String::from("static int answer() { return ANSWER; }\n")),
+                        ("main.c",
+                         String::from("#include <answer.h>\n\
+                                       int main() { return answer() == 42 ? 0 : 1; }\n"))]);
+    let build = || {
+        Command::new(env!("CARGO_BIN_EXE_yabs"))
+            .arg("build")
+            .current_dir(&dir)
+            .env("PKG_CONFIG_PATH", dir.join("pc"))
+            .output()
+            .unwrap()
+    };
+    let output = build();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stdout));
+    assert!(Command::new(dir.join("pkgconf")).status().unwrap().success());
+    let toml = fs::read_to_string(dir.join("pkgconf.toml")).unwrap();
+    fs::write(dir.join("pkgconf.toml"), toml.replace(">= 1.2", ">= 2")).unwrap();
+    let output = build();
+    let stdout = logged(&output);
+    assert!(!output.status.success());
+    assert!(stdout.contains("pkg-config could not provide 'answer >= 2'"), "{}", stdout);
+}
+
+#[test]
+fn test_pkg_config_flags_and_context() {
+    let project = TestProject::new("pkgflags",
+                                   "[project]\n\
+                                    compiler = \"./yabs-fake-cc\"\n\
+                                    file-extensions = [\"c\"]\n\
+                                    pkg-config = [\"answer\"]\n\
+                                    [[bin]]\n\
+                                    name = \"pkgflags\"\n\
+                                    path = \"main.c\"\n",
+                                   &[("pc/answer.pc",
+                                      "Name: answer\nDescription: test\nVersion: 1.3\n\
+                                       Cflags: -I\"${pcfiledir}/with space\" -isystem /opt/answer/include -DANSWER=42 \
+                                       --param=ssp-buffer-size=4\n\
+                                       Libs: -L${pcfiledir}/lib -lanswer -framework Answer\n"),
+                                     ("pc/with space/answer.h", ""),
+                                     ("pc/lib/.keep", ""),
+                                     ("main.c", "int main() { return 0; }\n")]);
+    // Only the context's PKG_CONFIG_PATH points at the package
+    let context = project.context().with_var("PKG_CONFIG_PATH", &project.path("pc").display().to_string());
+    project.build_with_context(context).unwrap();
+    let followed_by = |args: &[String], first: &str, second: &str| {
+        args.windows(2).any(|pair| pair[0] == first && pair[1] == second)
+    };
+    let invocations = project.invocations();
+    let compile = invocations.iter().find(|args| args.contains(&String::from("-c"))).unwrap();
+    assert!(followed_by(compile, "-isystem", "/opt/answer/include"), "{:?}", compile);
+    assert!(compile.contains(&String::from("--param=ssp-buffer-size=4")), "{:?}", compile);
+    assert!(compile.contains(&String::from("-DANSWER=42")), "{:?}", compile);
+    assert!(compile.iter().any(|arg| arg.starts_with("-I") && arg.ends_with("pc/with space")), "{:?}", compile);
+    let link = invocations.iter().find(|args| args.contains(&String::from("-lanswer"))).unwrap();
+    assert!(followed_by(link, "-framework", "Answer"), "{:?}", link);
+    assert!(link.iter().any(|arg| arg.starts_with("-L") && arg.ends_with("pc/lib")), "{:?}", link);
+    // Without it in the context pkg-config can't find the package, whatever
+    // the process has set
+    let error = project.build_with_context(project.context()).unwrap_err().to_string();
+    assert!(error.contains("pkg-config could not provide 'answer'"), "{}", error);
+}
+
+#[test]
+fn test_compiler_from_env() {
+    let dir = project("envcc",
+                      &[("envcc.toml",
+                         String::from("[project]\n\
+                                       file-extensions = [\"c\"]\n\
+                                       [[bin]]\n\
+                                       name = \"envcc\"\n\
+                                       path = \"./main.c\"\n")),
+                        ("mycc", String::from("#!/bin/sh\ntouch \"$(dirname \"$0\")/used\"\nexec gcc \"$@\"\n")),
+                        ("main.c", String::from("int main() { return 0; }\n"))]);
+    Command::new("chmod").arg("+x").arg(dir.join("mycc")).status().unwrap();
+    let build = || {
+        Command::new(env!("CARGO_BIN_EXE_yabs"))
+            .arg("build")
+            .current_dir(&dir)
+            .env("CC", dir.join("mycc"))
+            .output()
+            .unwrap()
+    };
+    let output = build();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{}", stdout);
+    assert!(stdout.contains("(from CC)"), "{}", stdout);
+    assert!(dir.join("used").exists());
+    let toml = fs::read_to_string(dir.join("envcc.toml")).unwrap();
+    fs::write(dir.join("envcc.toml"), toml.replace("[project]\n", "[project]\nrespect-env = false\n")).unwrap();
+    let stdout = String::from_utf8_lossy(&build().stdout).into_owned();
+    assert!(stdout.contains("using compiler 'gcc' (default)"), "{}", stdout);
+}
+
+#[test]
+fn test_library_targets() {
+    let dir = project("mixed",
+                      &[("mixed.toml",
+                         String::from("[project]\n\
+                                       file-extensions = [\"c\"]\n\
+                                       [[lib]]\n\
+                                       name = \"libmixed\"\n\
+                                       types = [\"static\", \"dynamic\"]\n")),
+                        ("mixed.c", String::from("int mixed() { return 1; }\n"))]);
+    assert!(yabs(&dir, &["build"]).status.success());
+    assert!(dir.join("libmixed.a").exists() && dir.join("libmixed.so").exists());
+    let stdout = String::from_utf8_lossy(&yabs(&dir, &["build"]).stdout).into_owned();
+    assert!(stdout.contains("'libmixed' is up to date"), "{}", stdout);
+    // Make sure the edit lands after the library on coarse timestamps
+    std::thread::sleep(std::time::Duration::from_millis(1100));
+    fs::write(dir.join("mixed.c"), "int mixed() { return 2; }\n").unwrap();
+    let stdout = String::from_utf8_lossy(&yabs(&dir, &["build"]).stdout).into_owned();
+    assert!(stdout.contains("mixed.c"), "{}", stdout);
+    let stdout = String::from_utf8_lossy(&yabs(&dir, &["clean"]).stdout).into_owned();
+    assert!(stdout.contains("removed library 'libmixed.a'") &&
+            stdout.contains("removed library 'libmixed.so'"),
+            "{}",
+            stdout);
+}
+
+#[test]
+fn test_env_flags() {
+    let dir = project("envflags",
+                      &[("envflags.toml",
+                         String::from("[project]\n\
+                                       file-extensions = [\"c\"]\n\
+                                       [[bin]]\n\
+                                       name = \"envflags\"\n\
+                                       path = \"./main.c\"\n")),
+                        ("main.c",
+                         String::from("#include <string.h>\n\
+                                       int main() { return strcmp(GREETING, \"hi there\"); }\n"))]);
+    let build = || {
+        Command::new(env!("CARGO_BIN_EXE_yabs"))
+            .arg("build")
+            .current_dir(&dir)
+            .env("CFLAGS", "-O1 '-DGREETING=\"hi there\"'")
+            .env("LDFLAGS", "-Wl,-z,relro")
+            .output()
+            .unwrap()
+    };
+    let output = build();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{}", stdout);
+    assert!(stdout.contains("using CFLAGS from the environment: -O1 '-DGREETING=\"hi there\"'"),
+            "{}",
+            stdout);
+    assert!(Command::new(dir.join("envflags")).status().unwrap().success());
+    let toml = fs::read_to_string(dir.join("envflags.toml")).unwrap();
+    fs::write(dir.join("envflags.toml"), toml.replace("[project]\n", "[project]\nuse-env-flags = false\n")).unwrap();
+    assert!(!build().status.success());
+}
+
+#[test]
+fn test_coarse_mtime_filesystem() {
+    let dir = project("coarse",
+                      &[("coarse.toml",
+                         String::from("[project]\n\
+                                       file-extensions = [\"c\"]\n\
+                                       [[bin]]\n\
+                                       name = \"coarse\"\n\
+                                       path = \"./main.c\"\n")),
+                        ("main.c", String::from("int main() { return 0; }\n"))]);
+    let stdout = String::from_utf8_lossy(&yabs(&dir, &["doctor"]).stdout).into_owned();
+    assert!(stdout.contains("fine-grained modification times: true"), "{}", stdout);
+    assert!(yabs(&dir, &["build"]).status.success());
+    // A source saved a second before the binary was linked
+    let built = fs::metadata(dir.join("coarse")).unwrap().modified().unwrap();
+    let source = fs::File::options().write(true).open(dir.join("main.c")).unwrap();
+    source.set_modified(built - std::time::Duration::from_secs(1)).unwrap();
+    let stdout = String::from_utf8_lossy(&yabs(&dir, &["build"]).stdout).into_owned();
+    assert!(stdout.contains("'coarse' is up to date"), "{}", stdout);
+    let state = fs::read_to_string(dir.join(".yabs/state")).unwrap();
+    assert!(state.contains("fine-mtime = true"), "{}", state);
+    fs::write(dir.join(".yabs/state"), state.replace("fine-mtime = true", "fine-mtime = false")).unwrap();
+    let stdout = logged(&yabs(&dir, &["doctor"]));
+    assert!(stdout.contains("coarse modification times"), "{}", stdout);
+    // Compared by their contents from then on
+    let stdout = String::from_utf8_lossy(&yabs(&dir, &["build"]).stdout).into_owned();
+    assert!(!stdout.contains("up to date"), "{}", stdout);
+    let built = fs::metadata(dir.join("coarse")).unwrap().modified().unwrap();
+    source.set_modified(built + std::time::Duration::from_secs(1)).unwrap();
+    let stdout = String::from_utf8_lossy(&yabs(&dir, &["build"]).stdout).into_owned();
+    assert!(stdout.contains("'coarse' is up to date"), "{}", stdout);
+}
+
+#[test]
+fn test_injected_fs_caps() {
+    let project = TestProject::new("injected",
+                                   "[project]\n\
+                                    compiler = \"./yabs-fake-cc\"\n\
+                                    file-extensions = [\"c\"]\n\
+                                    [[bin]]\n\
+                                    name = \"injected\"\n\
+                                    path = \"main.c\"\n",
+                                   &[("main.c", "int main() { return 0; }\n")]);
+    let mut build_file = project.load().unwrap();
+    build_file.set_fs_caps(FsCapabilities {
+                               fine_mtime: false,
+                               ..FsCapabilities::default()
+                           })
+              .unwrap();
+    build_file.build(1).unwrap();
+    project.assert_compiled(&["./main.c"]);
+    // The injected capabilities are cached, so later builds compare the
+    // source by its contents rather than its newer modification time
+    let source = fs::File::options().write(true).open(project.path("main.c")).unwrap();
+    source.set_modified(SystemTime::now() + Duration::from_secs(10)).unwrap();
+    project.clear_invocations();
+    project.build().unwrap();
+    assert!(project.invocations().is_empty(), "{:?}", project.invocations());
+    project.write("main.c", "int main() { return 1; }\n");
+    project.build().unwrap();
+    project.assert_compiled(&["./main.c"]);
+}
+
+#[test]
+fn test_reading_commands_write_nothing() {
+    let dir = project("untouched",
+                      &[("untouched.toml",
+                         String::from("[project]\n\
+                                       file-extensions = [\"c\"]\n\
+                                       [[bin]]\n\
+                                       name = \"untouched\"\n\
+                                       path = \"main.c\"\n")),
+                        ("main.c", String::from("int main() { return 0; }\n"))]);
+    for args in &[&["status"][..], &["targets"], &["sources"], &["check"], &["print-config"], &["clean", "--dry-run"]] {
+        let output = yabs(&dir, args);
+        assert!(!logged(&output).contains(".yabs"), "{:?}: {}", args, logged(&output));
+        assert!(!dir.join(".yabs").exists(), "{:?}", args);
+    }
+}
+
+#[test]
+fn test_target_outputs_are_segregated() {
+    let toml = |target: &str| {
+        format!("[project]\n\
+                 file-extensions = [\"c\"]\n\
+                 compiler = \"gcc\"\n\
+                 target = \"{}\"\n\
+                 sysroot = \"/\"\n\
+                 [[bin]]\n\
+                 name = \"cross\"\n\
+                 path = \"./main.c\"\n",
+                target)
+    };
+    let dir = project("cross",
+                      &[("cross.toml", toml("x86_64-linux-gnu")),
+                        ("main.c", String::from("int main() { return 0; }\n"))]);
+    let output = yabs(&dir, &["build"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stdout));
+    assert!(dir.join("x86_64-linux-gnu/cross").exists());
+    assert!(dir.join("obj/x86_64-linux-gnu/main.o").exists());
+    assert!(!dir.join("main.o").exists());
+    fs::write(dir.join("cross.toml"), toml("aarch64-yabs-none").replace("compiler = \"gcc\"\n", "")).unwrap();
+    let output = yabs(&dir, &["build"]);
+    let stdout = logged(&output);
+    assert!(!output.status.success());
+    assert!(stdout.contains("'aarch64-yabs-none-gcc' was not found"), "{}", stdout);
+}
+
+#[test]
+fn test_build_id_does_not_change_hashes() {
+    let dir = project("buildid",
+                      &[("buildid.toml",
+                         String::from("[project]\n\
+                                       file-extensions = [\"c\"]\n\
+                                       [[bin]]\n\
+                                       name = \"buildid\"\n\
+                                       path = \"./main.c\"\n")),
+                        ("main.c", String::from("int main() { return 0; }\n"))]);
+    let build = |id: &str| {
+        let output = Command::new(env!("CARGO_BIN_EXE_yabs"))
+            .arg("build")
+            .current_dir(&dir)
+            .env("YABS_BUILD_ID", id)
+            .output()
+            .unwrap();
+        let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+        assert!(stdout.contains(&format!("build {}", id)), "{}", stdout);
+        let meta = fs::read_to_string(dir.join("buildid.yabs-meta.toml")).unwrap();
+        assert!(meta.contains(&format!("build-id = \"{}\"", id)), "{}", meta);
+        assert!(yabs(&dir, &["clean"]).status.success());
+        meta.lines().filter(|line| line.contains("hash")).map(|line| line.to_owned()).collect::<Vec<_>>()
+    };
+    let first = build("ci-1");
+    assert_eq!(first.len(), 3);
+    assert_eq!(first, build("ci-2"));
+}
+
+#[test]
+fn test_staleness_strategies() {
+    let toml = |staleness: &str| {
+        format!("[project]\n\
+                 file-extensions = [\"c\"]\n\
+                 staleness = \"{}\"\n\
+                 compare-staleness = true\n\
+                 [[lib]]\n\
+                 name = \"libstale\"\n\
+                 types = [\"static\"]\n",
+                staleness)
+    };
+    let dir = project("stale",
+                      &[("stale.toml", toml("legacy")),
+                        ("stale.c", String::from("int stale() { return 1; }\n"))]);
+    let stdout = String::from_utf8_lossy(&yabs(&dir, &["build"]).stdout).into_owned();
+    assert!(!stdout.contains("differs"), "{}", stdout);
+    std::thread::sleep(std::time::Duration::from_millis(1100));
+    fs::write(dir.join("stale.c"), "int stale() { return 2; }\n").unwrap();
+    // Legacy never compares sources against a library
+    let stdout = logged(&yabs(&dir, &["build"]));
+    assert!(stdout.contains("staleness of './stale.c' differs: legacy decides up to date, \
+                             v2 decides stale (source newer than its object)"),
+            "{}",
+            stdout);
+    assert!(stdout.contains("'libstale' is up to date"), "{}", stdout);
+    fs::write(dir.join("stale.toml"), toml("v2")).unwrap();
+    let stdout = logged(&yabs(&dir, &["build"]));
+    assert!(stdout.contains("v2 decides stale") && !stdout.contains("is up to date"), "{}", stdout);
+    let stdout = String::from_utf8_lossy(&yabs(&dir, &["build"]).stdout).into_owned();
+    assert!(!stdout.contains("differs") && stdout.contains("is up to date"), "{}", stdout);
+}
+
+#[test]
+fn test_mixed_languages() {
+    let dir = project("mixed_lang",
+                      &[("mixed_lang.toml",
+                         String::from("[project]\n\
+                                       file-extensions = [\"c\", \"cpp\"]\n\
+                                       cflags = [\"std=c99\"]\n\
+                                       cxxflags = [\"fno-rtti\"]\n\
+                                       [[bin]]\n\
+                                       name = \"mixed_lang\"\n\
+                                       path = \"./main.cpp\"\n")),
+                        ("half.c", String::from("int half(int x) { return x / 2; }\n")),
+                        ("main.cpp",
+                         String::from("#include <string>\n\
+                                       extern \"C\" int half(int);\n\
+                                       int main() { std::string s(\"abcd\"); return half(s.size()) == 2 ? 0 : 1; }\n"))]);
+    let output = yabs(&dir, &["build", "-v"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{}", stdout);
+    assert!(stdout.contains("using C++ compiler 'g++'") && stdout.contains("using C compiler 'gcc'"),
+            "{}",
+            stdout);
+    assert!(stdout.contains("gcc -c -std=c99"), "{}", stdout);
+    assert!(stdout.contains("g++ -c -fno-rtti"), "{}", stdout);
+    assert!(Command::new(dir.join("mixed_lang")).status().unwrap().success());
+}
+
+#[cfg(target_arch = "x86_64")]
+#[test]
+fn test_assembly_sources() {
+    let note = "\t.section .note.GNU-stack,\"\",@progbits\n";
+    let dir = project("asm",
+                      &[("asm.toml",
+                         String::from("[project]\n\
+                                       file-extensions = [\"c\", \"s\", \"S\"]\n\
+                                       include = [\"include\"]\n\
+                                       defines = [\"OFFSET=2\"]\n\
+                                       [[bin]]\n\
+                                       name = \"asm\"\n\
+                                       path = \"./main.c\"\n")),
+                        ("include/answer.h", String::from("#define ANSWER (38 + OFFSET)\n")),
+                        ("answer.S",
+                         format!("#include \"answer.h\"\n\t.globl answer\nanswer:\n\
+                                  \tmovl $ANSWER, %eax\n\tret\n{}",
+                                 note)),
+                        ("two.s", format!("\t.globl two\ntwo:\n\tmovl $2, %eax\n\tret\n{}", note)),
+                        ("main.c",
+                         String::from("int answer(void);\nint two(void);\n\
+                                       int main(void) { return answer() + two() == 42 ? 0 : 1; }\n"))]);
+    let output = yabs(&dir, &["build"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stdout));
+    assert!(dir.join("answer.o").exists() && dir.join("two.o").exists());
+    assert!(Command::new(dir.join("asm")).status().unwrap().success());
+    assert!(yabs(&dir, &["clean"]).status.success());
+    assert!(!dir.join("answer.o").exists() && !dir.join("two.o").exists());
+}
+
+#[test]
+fn test_extra_deps_and_verify() {
+    let toml = |extra_deps: &str| {
+        format!("[project]\n\
+                 file-extensions = [\"c\"]\n\
+                 compiler-flags = [\"Ivendor\"]\n\
+                 {}\n\
+                 [[bin]]\n\
+                 name = \"extra\"\n\
+                 path = \"main.c\"\n",
+                extra_deps)
+    };
+    let dir = project("extra",
+                      &[("extra.toml", toml("")),
+                        ("main.c", String::from("#include \"config.h\"\nint main() { return VALUE; }\n")),
+                        ("vendor/config.h", String::from("#define VALUE 0\n"))]);
+    let output = yabs(&dir, &["verify"]);
+    let stdout = logged(&output);
+    assert!(output.status.success(), "{}", stdout);
+    if Command::new("strace").arg("-V").output().is_ok() {
+        assert!(stdout.contains("'extra' read 1 undeclared input(s)") && stdout.contains("  vendor/config.h"),
+                "{}",
+                stdout);
+        let suggested = fs::read_to_string(dir.join(".yabs/verify/suggested-deps.toml")).unwrap();
+        assert!(suggested.contains("vendor/config.h"), "{}", suggested);
+    } else {
+        assert!(stdout.contains("strace, which wasn't found, skipping"), "{}", stdout);
+    }
+    fs::write(dir.join("extra.toml"), toml("extra-deps = [\"vendor/config.h\"]")).unwrap();
+    assert!(yabs(&dir, &["build"]).status.success());
+    let stdout = String::from_utf8_lossy(&yabs(&dir, &["build"]).stdout).into_owned();
+    assert!(stdout.contains("'extra' is up to date"), "{}", stdout);
+    std::thread::sleep(std::time::Duration::from_millis(1100));
+    fs::write(dir.join("vendor/config.h"), "#define VALUE 3\n").unwrap();
+    let stdout = String::from_utf8_lossy(&yabs(&dir, &["build"]).stdout).into_owned();
+    assert!(!stdout.contains("is up to date"), "{}", stdout);
+    assert_eq!(Command::new(dir.join("extra")).status().unwrap().code(), Some(3));
+}
+
+#[test]
+fn test_interrupted_build_resumes() {
+    let toml = |flags: &str| {
+        format!("[project]\n\
+                 file-extensions = [\"c\"]\n\
+                 compiler = \"./cc.sh\"\n\
+                 compiler-flags = [{}]\n\
+                 [[bin]]\n\
+                 name = \"resume\"\n\
+                 path = \"main.c\"\n",
+                flags)
+    };
+    // Logs each compile, and with 'kill' kills yabs instead of compiling once
+    // the state cache records an object
+    let wrapper = String::from("#!/bin/sh\n\
+                                case \" $* \" in *\" -c \"*)\n\
+                                if [ -f kill ] && grep -q command-hash .yabs/objects.toml 2>/dev/null; then\n\
+                                rm kill; kill -9 $PPID; exit 1\n\
+                                fi\n\
+                                echo \"$*\" >> compiles.log;;\n\
+                                esac\n\
+                                exec gcc \"$@\"\n");
+    let mut files = vec![("resume.toml", toml("")),
+                         ("cc.sh", wrapper),
+                         ("main.c", String::from("int main() { return 0; }\n"))];
+    for name in &["a.c", "b.c", "c.c", "d.c"] {
+        files.push((name, format!("int {}() {{ return 0; }}\n", &name[..1])));
+    }
+    let dir = project("resume", &files);
+    Command::new("chmod").arg("+x").arg(dir.join("cc.sh")).status().unwrap();
+    let compiles = || fs::read_to_string(dir.join("compiles.log")).unwrap_or_default().lines().count();
+    assert!(yabs(&dir, &["build", "-j", "1"]).status.success());
+    assert_eq!(compiles(), 5);
+    fs::write(dir.join("resume.toml"), toml("\"DCHANGED\"")).unwrap();
+    fs::remove_file(dir.join("compiles.log")).unwrap();
+    // Killed once the first object compiled with the new flags is recorded,
+    // the records of the old ones are gone so it's the only one
+    fs::remove_file(dir.join(".yabs/objects.toml")).unwrap();
+    fs::write(dir.join("kill"), "").unwrap();
+    assert!(!yabs(&dir, &["build", "-j", "1"]).status.success());
+    assert_eq!(compiles(), 1);
+    fs::remove_file(dir.join("compiles.log")).unwrap();
+    let output = yabs(&dir, &["build", "-j", "1"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stdout));
+    assert_eq!(compiles(), 4);
+}
+
+#[test]
+fn test_compiler_launcher() {
+    let toml = |launcher: &str| {
+        format!("[project]\n\
+                 file-extensions = [\"c\"]\n\
+                 compiler-launcher = \"{}\"\n\
+                 [[bin]]\n\
+                 name = \"launched\"\n\
+                 path = \"main.c\"\n",
+                launcher)
+    };
+    let dir = project("launched",
+                      &[("launched.toml", toml("./launch.sh --tag")),
+                        ("launch.sh",
+                         String::from("#!/bin/sh\n[ \"$1\" = --tag ] || exit 1\nshift\n\
+                                       echo \"$*\" >> launches.log\nexec \"$@\"\n")),
+                        ("main.c", String::from("int main() { return 0; }\n"))]);
+    Command::new("chmod").arg("+x").arg(dir.join("launch.sh")).status().unwrap();
+    let output = yabs(&dir, &["build"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stdout));
+    assert!(dir.join("main.o").exists() && dir.join("launched").exists());
+    let launches = fs::read_to_string(dir.join("launches.log")).unwrap();
+    assert_eq!(launches.lines().count(), 1, "{}", launches);
+    assert!(launches.starts_with("gcc -c "), "{}", launches);
+    fs::write(dir.join("launched.toml"), toml("no-such-launcher")).unwrap();
+    let output = yabs(&dir, &["build"]);
+    let stdout = logged(&output);
+    assert!(!output.status.success());
+    assert!(stdout.contains("'no-such-launcher' was not found"), "{}", stdout);
+}
+
+#[test]
+fn test_object_cache() {
+    let dir = project("cached",
+                      &[("cached.toml",
+                         String::from("[project]\n\
+                                       file-extensions = [\"c\"]\n\
+                                       object-cache = true\n\
+                                       [[bin]]\n\
+                                       name = \"cached\"\n\
+                                       path = \"main.c\"\n")),
+                        ("main.c", String::from("#include \"value.h\"\nint value();\nint main() { return value() + VALUE; }\n")),
+                        ("value.c", String::from("int value() { return 1; }\n")),
+                        ("value.h", String::from("#define VALUE 1\n"))]);
+    let stdout = String::from_utf8_lossy(&yabs(&dir, &["build"]).stdout).into_owned();
+    assert!(stdout.contains("object cache: 0 hits, 2 misses"), "{}", stdout);
+    assert!(yabs(&dir, &["clean"]).status.success());
+    let stdout = String::from_utf8_lossy(&yabs(&dir, &["build"]).stdout).into_owned();
+    assert!(stdout.contains("cached ./main.o") && stdout.contains("cached ./value.o"), "{}", stdout);
+    assert!(stdout.contains("object cache: 2 hits, 0 misses"), "{}", stdout);
+    assert_eq!(Command::new(dir.join("cached")).status().unwrap().code(), Some(2));
+    // Headers are part of the preprocessed source the key is hashed from
+    fs::write(dir.join("value.h"), "#define VALUE 5\n").unwrap();
+    assert!(yabs(&dir, &["clean"]).status.success());
+    let stdout = String::from_utf8_lossy(&yabs(&dir, &["build"]).stdout).into_owned();
+    assert!(stdout.contains("object cache: 1 hits, 1 misses"), "{}", stdout);
+    assert_eq!(Command::new(dir.join("cached")).status().unwrap().code(), Some(6));
+}
+
+#[test]
+fn test_object_cache_in_state_dir() {
+    let dir = project("cached_state",
+                      &[("cached_state.toml",
+                         String::from("[project]\n\
+                                       file-extensions = [\"c\"]\n\
+                                       object-cache = true\n\
+                                       state-dir = \"out/state\"\n\
+                                       [[bin]]\n\
+                                       name = \"cached_state\"\n\
+                                       path = \"main.c\"\n")),
+                        ("main.c", String::from("int main() { return 0; }\n"))]);
+    assert!(yabs(&dir, &["build"]).status.success());
+    assert!(dir.join("out/state/cache").is_dir());
+    assert!(!dir.join(".yabs").exists());
+    dir.load().unwrap().clear_cache().unwrap();
+    assert!(!dir.join("out/state/cache").exists());
+}
+
+#[test]
+fn test_cxx_modules_need_clang() {
+    let dir = project("modules",
+                      &[("modules.toml",
+                         String::from("[project]\n\
+                                       file-extensions = [\"cppm\", \"cpp\"]\n\
+                                       compiler-cxx = \"g++\"\n\
+                                       cxx-modules = true\n\
+                                       [[bin]]\n\
+                                       name = \"modules\"\n\
+                                       path = \"main.cpp\"\n")),
+                        ("app.cppm", String::from("export module app;\nexport int value() { return 0; }\n")),
+                        ("main.cpp", String::from("import app;\nint main() { return value(); }\n"))]);
+    let output = yabs(&dir, &["build"]);
+    let stdout = logged(&output);
+    assert!(!output.status.success());
+    assert!(stdout.contains("'g++' doesn't support the module flags yabs passes"), "{}", stdout);
+    assert!(!dir.join("main.o").exists());
+    fs::write(dir.join("main.cpp"), "import missing;\nint main() { return 0; }\n").unwrap();
+    let stdout = logged(&yabs(&dir, &["build"]));
+    assert!(stdout.contains("'./main.cpp' imports module 'missing' but no source exports it"), "{}", stdout);
+}
+
+#[test]
+fn test_tool_requirements() {
+    let toml = |gcc_version: &str| {
+        format!("[project]\n\
+                 file-extensions = [\"c\"]\n\
+                 requires = [{{ tool = \"gcc\", min-version = \"{}\" }}]\n\
+                 [[bin]]\n\
+                 name = \"tools\"\n\
+                 path = \"main.c\"\n\
+                 [[task]]\n\
+                 name = \"generate\"\n\
+                 command = \"yabs-no-such-generator\"\n\
+                 requires = [{{ tool = \"yabs-no-such-generator\" }}]\n",
+                gcc_version)
+    };
+    let dir = project("tools",
+                      &[("tools.toml", toml("1.0")),
+                        ("main.c", String::from("int main() { return 0; }\n"))]);
+    let output = yabs(&dir, &["build"]);
+    let stdout = logged(&output);
+    assert!(!output.status.success());
+    assert!(stdout.contains("'yabs-no-such-generator' was not found (needed by task 'generate')"),
+            "{}",
+            stdout);
+    assert!(!stdout.contains("'gcc' is version"), "{}", stdout);
+    assert!(!dir.join("main.o").exists());
+    fs::write(dir.join("tools.toml"), toml("999")).unwrap();
+    let stdout = logged(&yabs(&dir, &["build"]));
+    assert!(stdout.contains("or newer is required (needed by [project])") &&
+            stdout.contains("'yabs-no-such-generator' was not found"),
+            "{}",
+            stdout);
+    let stdout = logged(&yabs(&dir, &["doctor"]));
+    assert!(stdout.contains("999 or newer is required"), "{}", stdout);
+    fs::write(dir.join("tools.toml"), toml("4.0").replace("requires = [{ tool = \"yabs-no-such-generator\" }]\n", ""))
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&yabs(&dir, &["doctor"]).stdout).into_owned();
+    assert!(stdout.contains("tool 'gcc' ") && stdout.contains("(needed by [project])"), "{}", stdout);
+    assert!(yabs(&dir, &["build"]).status.success());
+}
+
+#[test]
+fn test_hash_change_detection() {
+    let project = TestProject::new("hashed",
+                                   "[project]\n\
+                                    file-extensions = [\"c\"]\n\
+                                    compiler = \"./yabs-fake-cc\"\n\
+                                    change-detection = \"hash\"\n\
+                                    [[bin]]\n\
+                                    name = \"hashed\"\n\
+                                    path = \"main.c\"\n",
+                                   &[("main.c", "int main() { return value(); }\n"),
+                                     ("value.c", "int value(void) { return 1; }\n")]);
+    let report = project.build().unwrap();
+    assert_eq!((report.compiled(), report.skipped(), report.links.len()), (2, 0, 1));
+    project.assert_compiled(&["./main.c", "./value.c"]);
+    assert!(project.path(".yabs/hashes").exists());
+    assert!(project.state().sources["./value.c"].hash.is_some());
+    let set_modified = |modified: std::time::SystemTime| {
+        fs::File::options().write(true).open(project.path("value.c")).unwrap().set_modified(modified).unwrap();
+    };
+    // Touched but unchanged
+    project.clear_invocations();
+    set_modified(std::time::SystemTime::now() + std::time::Duration::from_secs(60));
+    let report = project.build().unwrap();
+    assert!(project.invocations().is_empty(), "{:?}", project.invocations());
+    assert_eq!((report.compiled(), report.skipped(), report.links.len()), (0, 2, 0));
+    project.write("value.c", "int value(void) { return 2; }\n");
+    project.build().unwrap();
+    project.assert_compiled(&["./value.c"]);
+    // An older version restored with an older modification time
+    project.clear_invocations();
+    project.write("value.c", "int value(void) { return 1; }\n");
+    set_modified(std::time::SystemTime::now() - std::time::Duration::from_secs(3600));
+    project.build().unwrap();
+    project.assert_compiled(&["./value.c"]);
+}
+
+#[test]
+fn test_state_dir_and_discarded_state() {
+    let dir = project("stateful",
+                      &[("stateful.toml",
+                         String::from("[project]\n\
+                                       file-extensions = [\"c\"]\n\
+                                       state-dir = \"state\"\n\
+                                       [[bin]]\n\
+                                       name = \"stateful\"\n\
+                                       path = \"main.c\"\n")),
+                        ("main.c", String::from("int main() { return 0; }\n"))]);
+    let stdout = String::from_utf8_lossy(&yabs(&dir, &["build", "-v"]).stdout).into_owned();
+    assert!(stdout.contains("[1/1] gcc -c") && stdout.contains("linking 'stateful'"), "{}", stdout);
+    assert!(dir.join("state/state").exists() && dir.join("state/objects.toml").exists());
+    assert!(!dir.join(".yabs/state").exists());
+    let stdout = String::from_utf8_lossy(&yabs(&dir, &["build"]).stdout).into_owned();
+    assert!(stdout.contains("'stateful' is up to date"), "{}", stdout);
+    fs::write(dir.join("state/state"), "version = 999\n").unwrap();
+    // Only a build removes what the discarded state left behind
+    let output = yabs(&dir, &["status"]);
+    assert!(logged(&output).contains("discarding build state"), "{}", logged(&output));
+    assert!(dir.join("state/objects.toml").exists());
+    let output = yabs(&dir, &["build"]);
+    let stdout = logged(&output);
+    assert!(output.status.success(), "{}", stdout);
+    assert!(stdout.contains("discarding build state") && stdout.contains("./main.c"), "{}", stdout);
+    let stdout = String::from_utf8_lossy(&yabs(&dir, &["build"]).stdout).into_owned();
+    assert!(stdout.contains("'stateful' is up to date"), "{}", stdout);
+}
+
+#[test]
+fn test_include_resolution_tracking() {
+    let dir = project("shadowed",
+                      &[("shadowed.toml",
+                         String::from("[project]\n\
+                                       file-extensions = [\"c\"]\n\
+                                       include = [\"vendor\", \"include\"]\n\
+                                       include-resolution-tracking = true\n\
+                                       [[bin]]\n\
+                                       name = \"shadowed\"\n\
+                                       path = \"main.c\"\n")),
+                        ("main.c", String::from("#include \"foo.h\"\nint main() { return VALUE; }\n")),
+                        ("include/foo.h", String::from("#define VALUE 1\n"))]);
+    assert!(yabs(&dir, &["build"]).status.success());
+    assert_eq!(Command::new(dir.join("shadowed")).status().unwrap().code(), Some(1));
+    let stdout = String::from_utf8_lossy(&yabs(&dir, &["build"]).stdout).into_owned();
+    assert!(stdout.contains("'shadowed' is up to date"), "{}", stdout);
+    // Shadows the header without touching anything the build knew about
+    fs::create_dir_all(dir.join("vendor")).unwrap();
+    fs::write(dir.join("vendor/foo.h"), "#define VALUE 2\n").unwrap();
+    let stdout = String::from_utf8_lossy(&yabs(&dir, &["build"]).stdout).into_owned();
+    assert!(stdout.contains("include resolution changed: foo.h now resolves to vendor/foo.h"), "{}", stdout);
+    assert_eq!(Command::new(dir.join("shadowed")).status().unwrap().code(), Some(2));
+    fs::remove_file(dir.join("vendor/foo.h")).unwrap();
+    let stdout = String::from_utf8_lossy(&yabs(&dir, &["build"]).stdout).into_owned();
+    assert!(stdout.contains("foo.h now resolves to include/foo.h"), "{}", stdout);
+    assert_eq!(Command::new(dir.join("shadowed")).status().unwrap().code(), Some(1));
+}
+
+#[test]
+fn test_out_of_disk_space() {
+    let toml = |factor: &str| {
+        format!("[project]\n\
+                 file-extensions = [\"c\"]\n\
+                 compiler = \"./cc.sh\"\n\
+                 {}\n\
+                 [[bin]]\n\
+                 name = \"full\"\n\
+                 path = \"main.c\"\n",
+                factor)
+    };
+    // Fails every compile like a compiler writing to a full disk once 'full'
+    // exists
+    let wrapper = String::from("#!/bin/sh\n\
+                                if [ -f full ]; then\n\
+                                echo \"cc1: fatal error: $2: No space left on device\" >&2; exit 1\n\
+                                fi\n\
+                                exec gcc \"$@\"\n");
+    let dir = project("full",
+                      &[("full.toml", toml("")),
+                        ("cc.sh", wrapper),
+                        ("main.c", String::from("int value();\nint main() { return value(); }\n")),
+                        ("value.c", String::from("int value() { return 0; }\n"))]);
+    Command::new("chmod").arg("+x").arg(dir.join("cc.sh")).status().unwrap();
+    assert!(yabs(&dir, &["build"]).status.success());
+    let records = fs::read_to_string(dir.join(".yabs/objects.toml")).unwrap();
+    fs::write(dir.join("main.c"), "int value();\nint main() { return value() + 0; }\n").unwrap();
+    fs::write(dir.join("value.c"), "int value() { return 1; }\n").unwrap();
+    fs::write(dir.join("full"), "").unwrap();
+    let output = yabs(&dir, &["build", "-j", "1"]);
+    let stdout = logged(&output);
+    assert!(!output.status.success());
+    assert!(stdout.contains("out of disk space on ") && stdout.contains(", needed approximately "),
+            "{}",
+            stdout);
+    assert!(!stdout.contains("exited with status"), "{}", stdout);
+    assert_eq!(fs::read_to_string(dir.join(".yabs/objects.toml")).unwrap(), records);
+    fs::remove_file(dir.join("full")).unwrap();
+    fs::write(dir.join("full.toml"), toml("disk-space-factor = 1000000000000000")).unwrap();
+    let output = yabs(&dir, &["build"]);
+    let stdout = logged(&output);
+    assert!(output.status.success(), "{}", stdout);
+    assert!(stdout.contains("the build may need about"), "{}", stdout);
+}
+
+#[test]
+fn test_explain_rebuilds() {
+    let dir = project("explain",
+                      &[("explain.toml",
+                         String::from("[project]\n\
+                                       file-extensions = [\"c\"]\n\
+                                       [[bin]]\n\
+                                       name = \"explain\"\n\
+                                       path = \"main.c\"\n")),
+                        ("main.c", String::from("int value();\nint main() { return value(); }\n")),
+                        ("value.c", String::from("int value() { return 0; }\n"))]);
+    let stdout = String::from_utf8_lossy(&yabs(&dir, &["build", "--explain"]).stdout).into_owned();
+    assert!(stdout.contains("rebuilding ./main.o: object missing"), "{}", stdout);
+    assert!(stdout.contains("rebuilding ./value.o: object missing"), "{}", stdout);
+    let stdout = String::from_utf8_lossy(&yabs(&dir, &["build", "--explain"]).stdout).into_owned();
+    assert!(stdout.contains("all 2 objects of 'explain' up to date"), "{}", stdout);
+    // Well past the object, whatever the filesystem's timestamp granularity
+    let later = SystemTime::now() + Duration::from_secs(120);
+    fs::File::options().write(true).open(dir.join("value.c")).unwrap().set_modified(later).unwrap();
+    let stdout = String::from_utf8_lossy(&yabs(&dir, &["build", "--explain"]).stdout).into_owned();
+    assert!(stdout.contains("rebuilding ./value.o: source modified "), "{}", stdout);
+    assert!(!stdout.contains("rebuilding ./main.o"), "{}", stdout);
+    let stdout = String::from_utf8_lossy(&yabs(&dir, &["build"]).stdout).into_owned();
+    assert!(!stdout.contains("rebuilding"), "{}", stdout);
+}
+
+#[test]
+fn test_size_budgets() {
+    let toml = |budgets: &str| {
+        format!("[project]\n\
+                 file-extensions = [\"c\"]\n\
+                 {}\n\
+                 [[bin]]\n\
+                 name = \"budget\"\n\
+                 path = \"main.c\"\n",
+                budgets)
+    };
+    let dir = project("budget",
+                      &[("budget.toml", toml("")),
+                        ("main.c", String::from("int main() { return 0; }\n"))]);
+    // Off unless a budget is set
+    let stdout = String::from_utf8_lossy(&yabs(&dir, &["build"]).stdout).into_owned();
+    assert!(!stdout.contains("budget of"), "{}", stdout);
+    assert!(!dir.join(".yabs/sizes.toml").exists());
+    let size = fs::metadata(dir.join("budget")).unwrap().len();
+    fs::write(dir.join("budget.toml"), toml(&format!("max-size = \"{}\"", size))).unwrap();
+    assert!(yabs(&dir, &["build"]).status.success());
+    fs::remove_file(dir.join("budget")).unwrap();
+    let stdout = logged(&yabs(&dir, &["build"]));
+    assert!(stdout.contains(&format!("'budget' is {} bytes, 100% of its budget of {} (no earlier size recorded)",
+                                     size,
+                                     size)),
+            "{}",
+            stdout);
+    assert!(dir.join(".yabs/sizes.toml").exists());
+    // A lowered budget fails the target without anything being relinked
+    fs::write(dir.join("budget.toml"), toml("max-size = \"1KB\"")).unwrap();
+    let output = yabs(&dir, &["build"]);
+    let stdout = logged(&output);
+    assert!(!output.status.success());
+    assert!(stdout.contains(&format!("'budget' is {} bytes, over its budget of 1KB by {} bytes (the same as the \
+                                      last build)",
+                                     size,
+                                     size - 1000)),
+            "{}",
+            stdout);
+    assert!(!dir.join("budget").exists());
+    // A target's own budget wins over the project's
+    fs::write(dir.join("budget.toml"),
+              toml("max-size = \"1KB\"") + "max-size = \"1.5MiB\"\n").unwrap();
+    let stdout = String::from_utf8_lossy(&yabs(&dir, &["build"]).stdout).into_owned();
+    assert!(dir.join("budget").exists(), "{}", stdout);
+    assert!(!stdout.contains("budget of"), "{}", stdout);
+    fs::write(dir.join("budget.toml"), toml("max-size = \"12XB\"")).unwrap();
+    let stdout = logged(&yabs(&dir, &["build"]));
+    assert!(stdout.contains("invalid value for 'max-size': '12XB' of 'budget'"), "{}", stdout);
+}
+
+#[test]
+fn test_deleted_objects_are_recovered() {
+    let dir = project("recover",
+                      &[("recover.toml",
+                         String::from("[project]\n\
+                                       file-extensions = [\"c\"]\n\
+                                       [[bin]]\n\
+                                       name = \"recover\"\n\
+                                       path = \"src/main.c\"\n")),
+                        ("src/main.c", String::from("int value();\nint main() { return value(); }\n")),
+                        ("src/value.c", String::from("int value() { return 1; }\n"))]);
+    assert!(yabs(&dir, &["build"]).status.success());
+    let binary = dir.join("recover");
+    let linked = fs::metadata(&binary).unwrap().modified().unwrap();
+    for object in &["src/main.o", "src/value.o"] {
+        fs::remove_file(dir.join(object)).unwrap();
+    }
+    // The binary is newer than every source, yet nothing it was linked from
+    // is left
+    fs::write(dir.join("src/value.c"), "int value() { return 2 }\n").unwrap();
+    let output = yabs(&dir, &["build", "--explain"]);
+    let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+    assert!(!output.status.success());
+    assert!(stdout.contains("rebuilding ./src/main.o: object missing (state cache said up to date)"),
+            "{}",
+            stdout);
+    assert!(stdout.contains("2 recorded object(s) no longer exist"), "{}", stdout);
+    assert_eq!(fs::metadata(&binary).unwrap().modified().unwrap(), linked);
+    let records = fs::read_to_string(dir.join(".yabs/objects.toml")).unwrap();
+    assert!(!records.contains("value.o"), "{}", records);
+    fs::write(dir.join("src/value.c"), "int value() { return 2; }\n").unwrap();
+    let stdout = String::from_utf8_lossy(&yabs(&dir, &["build", "--explain"]).stdout).into_owned();
+    assert!(stdout.contains("rebuilding ./src/value.o: object missing\n"), "{}", stdout);
+    assert!(stdout.contains("linking 'recover'"), "{}", stdout);
+    assert_eq!(Command::new(&binary).status().unwrap().code(), Some(2));
+    let stdout = String::from_utf8_lossy(&yabs(&dir, &["build", "--explain"]).stdout).into_owned();
+    assert!(stdout.contains("all 2 objects of 'recover' up to date"), "{}", stdout);
+}
+
+#[test]
+fn test_run_tool_over_sources() {
+    let dir = project("analyzed",
+                      &[("analyzed.toml",
+                         String::from("[project]\n\
+                                       file-extensions = [\"c\"]\n\
+                                       include = [\"include\"]\n\
+                                       defines = [\"VALUE=3\"]\n\
+                                       [[bin]]\n\
+                                       name = \"analyzed\"\n\
+                                       path = \"main.c\"\n")),
+                        ("include/value.h", String::from("#define TWICE (VALUE * 2)\n")),
+                        ("main.c", String::from("#include \"value.h\"\nint main() { return TWICE; }\n")),
+                        ("broken/util.c", String::from("int util( { }\n"))]);
+    let syntax_check = ["tool", "--", "gcc", "-fsyntax-only", "${flags}", "${src}"];
+    let output = yabs(&dir, &syntax_check);
+    let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+    assert!(!output.status.success());
+    assert!(stdout.contains("gcc -fsyntax-only -DVALUE=3 -Iinclude ./main.c"), "{}", stdout);
+    assert!(stdout.contains("./broken/util.c:1:"), "{}", stdout);
+    assert!(stdout.contains("1 source(s) passed, 1 failed"), "{}", stdout);
+    // Nothing was compiled
+    assert!(!dir.join("main.o").exists());
+    let output = yabs(&dir, &["tool", "--glob", "*.c", "--", "echo", "${defines}", "${obj}"]);
+    let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+    assert!(output.status.success(), "{}", stdout);
+    assert!(stdout.contains("-DVALUE=3 ./main.o"), "{}", stdout);
+    assert!(stdout.contains("1 source(s) passed, 0 failed"), "{}", stdout);
+    let stdout = logged(&yabs(&dir, &["tool", "--", "no-such-analyzer", "${src}"]));
+    assert!(stdout.contains("'no-such-analyzer' was not found"), "{}", stdout);
+}
+
+#[test]
+fn test_deadline() {
+    // Every compile takes as many seconds as 'delay' says. The sleep doesn't
+    // hold on to yabs' output, so a killed compile doesn't keep it open.
+    let wrapper = String::from("#!/bin/sh\nsleep $(cat delay) >/dev/null 2>&1\nexec gcc \"$@\"\n");
+    let dir = project("slow",
+                      &[("slow.toml",
+                         String::from("[project]\n\
+                                       file-extensions = [\"c\"]\n\
+                                       compiler = \"./cc.sh\"\n\
+                                       [[bin]]\n\
+                                       name = \"slow\"\n\
+                                       path = \"main.c\"\n")),
+                        ("cc.sh", wrapper),
+                        ("delay", String::from("1\n")),
+                        ("main.c", String::from("int a();\nint b();\nint main() { return a() + b(); }\n")),
+                        ("a.c", String::from("int a() { return 0; }\n")),
+                        ("b.c", String::from("int b() { return 0; }\n"))]);
+    Command::new("chmod").arg("+x").arg(dir.join("cc.sh")).status().unwrap();
+    // The first compile has nothing to go by and is started, the second is
+    // expected to take as long as the first and still fits, the third doesn't
+    let output = yabs(&dir, &["build", "-j", "1", "--deadline", "2500ms"]);
+    let stdout = logged(&output);
+    assert!(!output.status.success(), "{}", stdout);
+    assert!(stdout.contains("stopped at the deadline, 2 step(s) remain:\n"), "{}", stdout);
+    assert!(stdout.contains("  link 'slow'"), "{}", stdout);
+    assert!(stdout.contains("the build stopped at its deadline"), "{}", stdout);
+    let remaining: Vec<&str> = stdout.lines().filter(|line| line.starts_with("  compile ")).collect();
+    assert_eq!(remaining.len(), 1, "{}", stdout);
+    assert!(!dir.join("slow").exists());
+    // What was compiled before the deadline is kept
+    let source = remaining[0].trim_start_matches("  compile ");
+    let stdout = String::from_utf8_lossy(&yabs(&dir, &["build", "-j", "1"]).stdout).into_owned();
+    assert!(stdout.contains(&format!("[1/1] CC {}\n", source)), "{}", stdout);
+    assert!(dir.join("slow").exists());
+    // A compile expected to fit but running past the deadline and its grace
+    // is killed
+    fs::write(dir.join("delay"), "5\n").unwrap();
+    fs::remove_file(dir.join("b.o")).unwrap();
+    let started = ::std::time::Instant::now();
+    let output = yabs(&dir, &["build", "--deadline", "2s", "--deadline-grace", "0s"]);
+    let stdout = logged(&output);
+    assert!(started.elapsed() < Duration::from_secs(4), "{}", stdout);
+    assert!(!output.status.success());
+    assert!(stdout.contains("killing 1 compile(s) still running past the deadline"), "{}", stdout);
+    assert!(stdout.contains("  compile ./b.c\n  link 'slow'"), "{}", stdout);
+    assert!(!dir.join("b.o").exists());
+}
+
+#[test]
+fn test_interrupted_build() {
+    use std::io::{BufRead, BufReader, Read};
+    let wrapper = String::from("#!/bin/sh\nsleep 5 >/dev/null 2>&1\nexec gcc \"$@\"\n");
+    let dir = project("interrupted",
+                      &[("interrupted.toml",
+                         String::from("[project]\n\
+                                       file-extensions = [\"c\"]\n\
+                                       compiler = \"./cc.sh\"\n\
+                                       after-script = [\"touch after\"]\n\
+                                       [[bin]]\n\
+                                       name = \"interrupted\"\n\
+                                       path = \"main.c\"\n")),
+                        ("cc.sh", wrapper),
+                        ("main.c", String::from("int main() { return 0; }\n"))]);
+    Command::new("chmod").arg("+x").arg(dir.join("cc.sh")).status().unwrap();
+    // A half written object the next build would otherwise link
+    fs::write(dir.join("main.o"), "").unwrap();
+    let started = ::std::time::Instant::now();
+    let mut child = Command::new(env!("CARGO_BIN_EXE_yabs")).args(["build", "-j", "1"])
+                                                            .current_dir(&dir)
+                                                            .stdout(::std::process::Stdio::piped())
+                                                            .stderr(::std::process::Stdio::piped())
+                                                            .spawn()
+                                                            .unwrap();
+    let mut stdout = BufReader::new(child.stdout.take().unwrap());
+    let mut log = String::new();
+    while !log.contains("[1/1]") && stdout.read_line(&mut log).unwrap() > 0 {}
+    Command::new("kill").arg("-INT").arg(child.id().to_string()).status().unwrap();
+    while stdout.read_line(&mut log).unwrap() > 0 {}
+    child.stderr.take().unwrap().read_to_string(&mut log).unwrap();
+    let status = child.wait().unwrap();
+    assert!(started.elapsed() < Duration::from_secs(4), "{}", log);
+    assert_eq!(status.code(), Some(130), "{}", log);
+    assert!(log.contains("interrupted, killing 1 running compile(s)"), "{}", log);
+    assert!(log.contains("the build was interrupted"), "{}", log);
+    assert!(!dir.join("main.o").exists());
+    assert!(!dir.join("interrupted").exists());
+    assert!(!dir.join("after").exists());
+}
+
+#[test]
+fn test_build_context_environment() {
+    let project = TestProject::new("context",
+                                   "[project]\n\
+                                    file-extensions = [\"c\"]\n\
+                                    [[bin]]\n\
+                                    name = \"context\"\n\
+                                    path = \"main.c\"\n",
+                                   &[("main.c", "int main() { return 0; }\n")]);
+    // Whatever the host has set, the build only sees the context's variables
+    let context = project.context()
+                         .with_var("CC", "./yabs-fake-cc")
+                         .with_var("CFLAGS", "-DFROM_CONTEXT")
+                         .with_var("YABS_BUILD_ID", "context-build");
+    project.build_with_context(context).unwrap();
+    let compile = project.invocations().into_iter().find(|args| args.contains(&String::from("-c"))).unwrap();
+    assert!(compile.contains(&String::from("-DFROM_CONTEXT")), "{:?}", compile);
+    let meta = fs::read_to_string(project.path("context.yabs-meta.toml")).unwrap();
+    assert!(meta.contains("context-build"), "{}", meta);
+    // Without CC the default compiler is used and CFLAGS are gone
+    project.clear_invocations();
+    project.build_with_context(project.context()).unwrap();
+    assert!(project.invocations().is_empty(), "{:?}", project.invocations());
+}
+
+#[test]
+fn test_failed_outputs_are_not_kept() {
+    // Writes part of its output and fails while 'fail' exists
+    let wrapper = String::from("#!/bin/sh\n\
+                                if [ -e fail ]; then\n\
+                                \x20   while [ $# -gt 0 ]; do [ \"$1\" = -o ] && echo partial > \"$2\"; shift; done\n\
+                                \x20   exit 1\n\
+                                fi\n\
+                                exec gcc \"$@\"\n");
+    let dir = project("atomic",
+                      &[("atomic.toml",
+                         String::from("[project]\n\
+                                       file-extensions = [\"c\"]\n\
+                                       compiler = \"./cc.sh\"\n\
+                                       [[bin]]\n\
+                                       name = \"atomic\"\n\
+                                       path = \"main.c\"\n")),
+                        ("cc.sh", wrapper),
+                        ("main.c", String::from("int main() { return 0; }\n"))]);
+    Command::new("chmod").arg("+x").arg(dir.join("cc.sh")).status().unwrap();
+    assert!(yabs(&dir, &["build"]).status.success());
+    let object = fs::read(dir.join("main.o")).unwrap();
+    let binary = fs::read(dir.join("atomic")).unwrap();
+    let leftovers = || {
+        fs::read_dir(&dir).unwrap()
+                          .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+                          .filter(|name| name.contains(".tmp."))
+                          .collect::<Vec<String>>()
+    };
+    // A failed compile leaves the previous object
+    fs::write(dir.join("fail"), "").unwrap();
+    fs::write(dir.join("main.c"), "int main() { return 1; }\n").unwrap();
+    assert!(!yabs(&dir, &["build"]).status.success());
+    assert_eq!(fs::read(dir.join("main.o")).unwrap(), object);
+    assert!(leftovers().is_empty(), "{:?}", leftovers());
+    // A failed link leaves the previous binary
+    fs::remove_file(dir.join("fail")).unwrap();
+    Command::new("gcc").args(["-c", "-o", "main.o", "main.c"]).current_dir(&dir).status().unwrap();
+    fs::write(dir.join("fail"), "").unwrap();
+    let output = yabs(&dir, &["build"]);
+    let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+    assert!(!output.status.success());
+    assert!(stdout.contains("linking 'atomic'"), "{}", stdout);
+    assert_eq!(fs::read(dir.join("atomic")).unwrap(), binary);
+    assert!(leftovers().is_empty(), "{:?}", leftovers());
+}
+
+#[test]
+fn test_project_lock() {
+    let dir = project("locked",
+                      &[("locked.toml",
+                         String::from("[project]\n\
+                                       file-extensions = [\"c\"]\n\
+                                       [[bin]]\n\
+                                       name = \"locked\"\n\
+                                       path = \"main.c\"\n")),
+                        ("main.c", String::from("int main() { return 0; }\n"))]);
+    // Stands in for another yabs process holding the lock
+    fs::create_dir_all(dir.join(".yabs")).unwrap();
+    let mut holder = Command::new("flock").arg("-o")
+                                          .arg(dir.join(".yabs/lock"))
+                                          .args(["sleep", "30"])
+                                          .spawn()
+                                          .unwrap();
+    while Command::new("flock").arg("-n").arg(dir.join(".yabs/lock")).arg("true").status().unwrap().success() {
+        ::std::thread::sleep(Duration::from_millis(10));
+    }
+    fs::write(dir.join(".yabs/lock"), format!("{}\n", holder.id())).unwrap();
+    let output = yabs(&dir, &["build", "--no-wait"]);
+    let stdout = logged(&output);
+    assert!(!output.status.success());
+    assert!(stdout.contains(&format!("another yabs process (pid {}) is using this project", holder.id())),
+            "{}",
+            stdout);
+    assert!(!dir.join("locked").exists());
+    // A waiting build goes ahead once the holder is gone
+    let waiting = Command::new(env!("CARGO_BIN_EXE_yabs")).arg("build")
+                                                          .current_dir(&dir)
+                                                          .stdout(::std::process::Stdio::piped())
+                                                          .stderr(::std::process::Stdio::piped())
+                                                          .spawn()
+                                                          .unwrap();
+    ::std::thread::sleep(Duration::from_millis(500));
+    holder.kill().unwrap();
+    holder.wait().unwrap();
+    let output = waiting.wait_with_output().unwrap();
+    let stdout = logged(&output);
+    assert!(output.status.success(), "{}", stdout);
+    assert!(stdout.contains(&format!("waiting for other yabs process (pid {})", holder.id())), "{}", stdout);
+    // The holder died without emptying the lock file
+    assert!(stdout.contains(&format!("breaking stale lock left by pid {}", holder.id())), "{}", stdout);
+    assert!(dir.join("locked").exists());
+    let output = yabs(&dir, &["build"]);
+    assert!(output.status.success());
+    assert!(!logged(&output).contains("breaking stale lock"), "{}", logged(&output));
+}
+
+#[test]
+fn test_job_timeouts() {
+    // Hangs compiling while 'hang-compile' exists and linking while
+    // 'hang-link' does
+    let wrapper = String::from("#!/bin/sh\n\
+                                case \" $* \" in *\" -c \"*) step=compile;; *) step=link;; esac\n\
+                                [ -e hang-$step ] && exec sleep 30\n\
+                                exec gcc \"$@\"\n");
+    let buildfile = |script: &str| {
+        format!("[project]\n\
+                 file-extensions = [\"c\"]\n\
+                 compiler = \"./cc.sh\"\n\
+                 job-timeout-secs = 1\n\
+                 link-timeout-secs = 1\n\
+                 script-timeout-secs = 1\n\
+                 before-script = [\"{}\"]\n\
+                 [[bin]]\n\
+                 name = \"timeouts\"\n\
+                 path = \"main.c\"\n",
+                script)
+    };
+    let dir = project("timeouts",
+                      &[("timeouts.toml", buildfile("true")),
+                        ("cc.sh", wrapper),
+                        ("main.c", String::from("int main() { return 0; }\n"))]);
+    Command::new("chmod").arg("+x").arg(dir.join("cc.sh")).status().unwrap();
+    let timed_out = |expected: &str| {
+        let started = ::std::time::Instant::now();
+        let output = yabs(&dir, &["build"]);
+        let stdout = logged(&output);
+        assert!(started.elapsed() < Duration::from_secs(10), "{}", stdout);
+        assert!(!output.status.success(), "{}", stdout);
+        assert!(stdout.contains(&format!("{} timed out after 1s and was killed", expected)), "{}", stdout);
+    };
+    fs::write(dir.join("hang-compile"), "").unwrap();
+    timed_out("compiling './main.c'");
+    assert!(!dir.join("main.o").exists());
+    fs::remove_file(dir.join("hang-compile")).unwrap();
+    fs::write(dir.join("hang-link"), "").unwrap();
+    timed_out("linking 'timeouts'");
+    assert!(!dir.join("timeouts").exists());
+    fs::remove_file(dir.join("hang-link")).unwrap();
+    fs::write(dir.join("timeouts.toml"), buildfile("exec sleep 30")).unwrap();
+    timed_out("script 'exec sleep 30'");
+    fs::write(dir.join("timeouts.toml"), buildfile("true")).unwrap();
+    assert!(yabs(&dir, &["build"]).status.success());
+    assert!(dir.join("timeouts").exists());
+}
+
+#[test]
+fn test_json_events() {
+    let dir = project("events",
+                      &[("events.toml",
+                         String::from("[project]\n\
+                                       file-extensions = [\"c\"]\n\
+                                       [[bin]]\n\
+                                       name = \"events\"\n\
+                                       path = \"main.c\"\n")),
+                        ("main.c", String::from("int main() { return 0; }\n"))]);
+    let events = |args: &[&str]| {
+        let output = yabs(&dir, args);
+        let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+        assert!(output.status.success(), "{}", stdout);
+        stdout.lines()
+              .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap_or_else(|_| panic!("{}", line)))
+              .collect::<Vec<_>>()
+    };
+    let of_type = |events: &[serde_json::Value], kind: &str| {
+        events.iter().filter(|event| event.find("type").and_then(|kind| kind.as_str()) == Some(kind)).count()
+    };
+    let built = events(&["--json", "build"]);
+    for kind in &["compile_started", "compile_finished", "link_started", "link_finished"] {
+        assert_eq!(of_type(&built, kind), 1, "{:?}", built);
+    }
+    assert!(of_type(&built, "message") > 0, "{:?}", built);
+    let finished = built.iter().find(|event| event.find("success").is_some() && event.find("object").is_some());
+    assert_eq!(finished.and_then(|event| event.find("success")).and_then(|success| success.as_bool()), Some(true));
+    let cleaned = events(&["clean", "--json"]);
+    assert!(of_type(&cleaned, "removed") >= 2, "{:?}", cleaned);
+    let stamped = events(&["build", "--json", "--timestamps", "wall"]);
+    assert!(stamped.iter().all(|event| event.find("time").and_then(|time| time.as_str()).is_some()),
+            "{:?}",
+            stamped);
+}
+
+#[test]
+fn test_log_file() {
+    let dir = project("logged",
+                      &[("logged.toml",
+                         String::from("[project]\n\
+                                       file-extensions = [\"c\"]\n\
+                                       [[bin]]\n\
+                                       name = \"logged\"\n\
+                                       path = \"main.c\"\n")),
+                        ("main.c", String::from("int main() { return 0; }\n"))]);
+    let output = yabs(&dir, &["build", "--log-file", "build.log"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stdout));
+    let log = fs::read_to_string(dir.join("build.log")).unwrap();
+    assert!(log.contains("linking 'logged'"), "{}", log);
+    assert!(!log.contains('\x1b'), "{}", log);
+    // Appended to, then truncated
+    fs::write(dir.join("main.c"), "int main() { return missing(); }\n").unwrap();
+    assert!(!yabs(&dir, &["--log-file", "build.log", "--append-log", "build"]).status.success());
+    let log = fs::read_to_string(dir.join("build.log")).unwrap();
+    assert!(log.contains("linking 'logged'") && log.contains("error: "), "{}", log);
+    assert!(yabs(&dir, &["clean", "--log-file", "build.log"]).status.success());
+    let log = fs::read_to_string(dir.join("build.log")).unwrap();
+    assert!(!log.contains("linking 'logged'") && log.contains("removed"), "{}", log);
+    let output = yabs(&dir, &["--log-file", "missing/build.log", "build"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("could not open log file 'missing/build.log'"));
+}
+
+#[test]
+fn test_verbosity() {
+    let dir = project("verbosity",
+                      &[("verbosity.toml",
+                         String::from("[project]\n\
+                                       file-extensions = [\"c\", \"cpp\"]\n\
+                                       [[bin]]\n\
+                                       name = \"verbosity\"\n\
+                                       path = \"main.c\"\n")),
+                        ("main.c", String::from("int helper();\nint main() { return helper(); }\n")),
+                        ("helper.cpp", String::from("extern \"C\" int helper() { return 0; }\n"))]);
+    let build = |args: &[&str]| {
+        assert!(yabs(&dir, &["clean"]).status.success());
+        let output = yabs(&dir, args);
+        let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+        assert!(output.status.success(), "{}", stdout);
+        stdout
+    };
+    let normal = build(&["build", "-j", "1"]);
+    assert!(normal.contains("] CC ./main.c\n") && normal.contains("] CXX ./helper.cpp\n"), "{}", normal);
+    assert!(normal.contains("linking 'verbosity'") && !normal.contains("gcc -c"), "{}", normal);
+    let verbose = build(&["build", "-v", "-j", "1"]);
+    assert!(verbose.contains("gcc -c") && verbose.contains("object queue: "), "{}", verbose);
+    let quiet = build(&["-q", "build"]);
+    assert!(!quiet.contains(" CC ") && !quiet.contains("linking"), "{}", quiet);
+    assert!(quiet.contains("finished in "), "{}", quiet);
+}
+
+#[test]
+fn test_warnings_and_errors_go_to_stderr() {
+    let dir = project("diagnosed",
+                      &[("diagnosed.toml",
+                         String::from("[project]\n\
+                                       file-extensions = [\"c\"]\n\
+                                       include = [\"inclde\"]\n\
+                                       [[bin]]\n\
+                                       name = \"diagnosed\"\n\
+                                       path = \"main.c\"\n")),
+                        ("main.c", String::from("int main() { return 0; }\n"))]);
+    let output = yabs(&dir, &["build"]);
+    let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+    let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+    assert!(output.status.success(), "{}", stdout);
+    assert!(stderr.contains("'include' lists 'inclde', which is not a directory"), "{}", stderr);
+    assert!(stdout.contains("linking 'diagnosed'") && !stdout.contains("warning"), "{}", stdout);
+    fs::write(dir.join("main.c"), "int main() { return missing(); }\n").unwrap();
+    let output = yabs(&dir, &["build"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("exited with status"));
+    assert!(!String::from_utf8_lossy(&output.stdout).contains("exited with status"));
+    // Only colored when asked to, stderr isn't a terminal here
+    assert!(!String::from_utf8_lossy(&output.stderr).contains("\x1b["));
+    let output = yabs(&dir, &["build", "--color", "always"]);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("\x1b[1;31merror\x1b[0m: "));
+}
+
+#[test]
+fn test_timestamps() {
+    let dir = project("stamped",
+                      &[("stamped.toml",
+                         String::from("[project]\n\
+                                       file-extensions = [\"c\"]\n\
+                                       include = [\"missing\"]\n\
+                                       [[bin]]\n\
+                                       name = \"stamped\"\n\
+                                       path = \"main.c\"\n")),
+                        ("main.c", String::from("int main() { return 0; }\n"))]);
+    let output = yabs(&dir, &["build", "--timestamps", "elapsed"]);
+    let log = logged(&output);
+    assert!(output.status.success(), "{}", log);
+    // Info on stdout and the warning on stderr alike
+    assert!(log.lines().all(|line| line.starts_with("[+") && line.contains("s] ")), "{}", log);
+    assert!(log.contains("s] warning: 'include' lists 'missing'"), "{}", log);
+    let output = yabs(&dir, &["--timestamps", "wall", "clean"]);
+    let log = logged(&output);
+    assert!(output.status.success(), "{}", log);
+    for line in log.lines() {
+        let (stamp, _) = line.split_at(20);
+        assert!(stamp.as_bytes()[10] == b'T' && stamp.ends_with(' ') && stamp[..4].parse::<u32>().is_ok(),
+                "{}",
+                line);
+    }
+}
+
+#[test]
+fn test_compile_diagnostics_are_grouped() {
+    // Writes its diagnostics a line at a time, slowly enough that parallel
+    // compiles would interleave them
+    let wrapper = String::from("#!/bin/sh\n\
+                                for line in 1 2 3; do echo \"$$ line $line\" >&2; sleep 0.1; done\n\
+                                exec gcc \"$@\"\n");
+    let mut files = vec![("grouped.toml",
+                          String::from("[project]\n\
+                                        file-extensions = [\"c\"]\n\
+                                        compiler = \"./cc.sh\"\n\
+                                        [[bin]]\n\
+                                        name = \"grouped\"\n\
+                                        path = \"main.c\"\n")),
+                         ("cc.sh", wrapper),
+                         ("main.c", String::from("int f0(); int f1(); int f2();\n\
+                                                  int main() { return f0() + f1() + f2(); }\n"))];
+    let names: Vec<String> = (0..3).map(|i| format!("f{}.c", i)).collect();
+    for (i, name) in names.iter().enumerate() {
+        files.push((name, format!("int f{}() {{ return 0; }}\n", i)));
+    }
+    let dir = project("grouped", &files);
+    Command::new("chmod").arg("+x").arg(dir.join("cc.sh")).status().unwrap();
+    let output = yabs(&dir, &["build", "-j", "4"]);
+    let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+    assert!(output.status.success(), "{}", stderr);
+    let pids: Vec<&str> =
+        stderr.lines().filter(|line| line.contains(" line ")).map(|line| &line[..line.find(' ').unwrap()]).collect();
+    assert_eq!(pids.len(), 12, "{}", stderr);
+    for block in pids.chunks(3) {
+        assert!(block.iter().all(|pid| *pid == block[0]), "{}", stderr);
+    }
+    // A failed compile's diagnostics come right before the error
+    fs::write(dir.join("f1.c"), "int f1() { return missing; }\n").unwrap();
+    let stderr = String::from_utf8_lossy(&yabs(&dir, &["build", "-j", "1"]).stderr).into_owned();
+    let lines: Vec<&str> = stderr.lines().collect();
+    let failed = lines.iter().position(|line| line.contains("failed to compile ./f1.c (exit status 1)")).unwrap();
+    assert!(lines[failed - 1].starts_with("./f1.c:1:"), "{}", stderr);
+    assert!(lines[..failed].iter().any(|line| line.contains("'missing' undeclared")), "{}", stderr);
+}
+
+#[test]
+fn test_diagnostics_color() {
+    let buildfile = |color: &str| {
+        format!("[project]\n\
+                 file-extensions = [\"c\"]\n\
+                 compiler-flags = [\"Wall\"]\n\
+                 {}\
+                 [[bin]]\n\
+                 name = \"colored\"\n\
+                 path = \"main.c\"\n",
+                color)
+    };
+    let dir = project("colored",
+                      &[("colored.toml", buildfile("diagnostics-color = true\n")),
+                        ("main.c", String::from("int main() { int unused; return 0; }\n"))]);
+    let build = |args: &[&str]| {
+        fs::remove_file(dir.join("main.o")).ok();
+        let output = yabs(&dir, args);
+        assert!(output.status.success(), "{}", logged(&output));
+        output
+    };
+    let output = build(&["build", "-v", "--log-file", "build.log"]);
+    let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+    assert!(stderr.contains("\x1b[") && stderr.contains("unused"), "{}", stderr);
+    // Left out of the logged command, and of the log file
+    assert!(!String::from_utf8_lossy(&output.stdout).contains("-fdiagnostics-color"));
+    let log = fs::read_to_string(dir.join("build.log")).unwrap();
+    assert!(log.contains("unused") && !log.contains('\x1b'), "{}", log);
+    let diagnostics = build(&["--json", "build"]).stdout;
+    let diagnostics = String::from_utf8_lossy(&diagnostics).into_owned();
+    assert!(diagnostics.contains("\"type\":\"diagnostics\"") && !diagnostics.contains("\\u001b"),
+            "{}",
+            diagnostics);
+    fs::write(dir.join("colored.toml"), buildfile("")).unwrap();
+    let stderr = String::from_utf8_lossy(&build(&["build", "--color", "never"]).stderr).into_owned();
+    assert!(stderr.contains("unused") && !stderr.contains('\x1b'), "{}", stderr);
+}
+
+#[test]
+fn test_fast_fail_window() {
+    let dir = project("fast_fail",
+                      &[("fast_fail.toml",
+                         String::from("[project]\n\
+                                       file-extensions = [\"c\"]\n\
+                                       fast-fail-window = 1\n\
+                                       [[bin]]\n\
+                                       name = \"fast_fail\"\n\
+                                       path = \"main.c\"\n")),
+                        ("main.c", String::from("int main() { return 0; }\n")),
+                        ("old.c", String::from("int old() { return missing_old; }\n")),
+                        ("recent.c", String::from("int recent() { return missing_recent; }\n"))]);
+    let hour_ago = SystemTime::now() - Duration::from_secs(3600);
+    for name in &["main.c", "old.c"] {
+        fs::File::options().write(true).open(dir.join(name)).unwrap().set_modified(hour_ago).unwrap();
+    }
+    // The failure in the window stops the build before the one outside it
+    let output = yabs(&dir, &["build", "-j", "1"]);
+    assert!(!output.status.success());
+    assert!(logged(&output).contains("compiling 1 most recently modified sources first"), "{}", logged(&output));
+    assert!(logged(&output).contains("missing_recent"), "{}", logged(&output));
+    assert!(!logged(&output).contains("missing_old"), "{}", logged(&output));
+    assert!(!dir.join("main.o").exists());
+    fs::write(dir.join("recent.c"), "int recent() { return 0; }\n").unwrap();
+    let output = yabs(&dir, &["build", "-j", "1"]);
+    assert!(!output.status.success());
+    assert!(logged(&output).contains("missing_old"), "{}", logged(&output));
+}
+
+#[test]
+fn test_schedule_in_plan() {
+    let buildfile = |schedule: &str| {
+        format!("[project]\n\
+                 file-extensions = [\"c\"]\n\
+                 {}\n\
+                 [[bin]]\n\
+                 name = \"scheduled\"\n\
+                 path = \"main.c\"\n",
+                schedule)
+    };
+    let dir = project("scheduled",
+                      &[("scheduled.toml", buildfile("")),
+                        ("main.c", String::from("int main() { return 0; }\n")),
+                        ("old.c", String::from("int old() { return 0; }\n"))]);
+    let hour_ago = SystemTime::now() - Duration::from_secs(3600);
+    fs::File::options().write(true).open(dir.join("old.c")).unwrap().set_modified(hour_ago).unwrap();
+    let positions = |plan: BuildPlan| {
+        (plan.steps["./main.o"].position, plan.steps["./old.o"].position, plan.schedule, plan.fast_fail_window)
+    };
+    // Without a 'schedule' compiles go by path, the last is started first
+    let default = positions(dir.plan().unwrap());
+    assert_eq!(default, (Some(1), Some(0), String::from("ordered"), None));
+    fs::write(dir.join("scheduled.toml"), buildfile("schedule = \"ordered\"")).unwrap();
+    assert_eq!(positions(dir.plan().unwrap()), default);
+    fs::write(dir.join("scheduled.toml"), buildfile("schedule = \"recent-first\"")).unwrap();
+    assert_eq!(positions(dir.plan().unwrap()), (Some(0), Some(1), String::from("recent-first"), None));
+    fs::write(dir.join("scheduled.toml"), buildfile("fast-fail-window = 1")).unwrap();
+    assert_eq!(positions(dir.plan().unwrap()), (Some(0), Some(1), String::from("recent-first"), Some(1)));
+}
+
+#[test]
+fn test_warnings_summary() {
+    let buildfile = |max: &str| {
+        format!("[project]\n\
+                 file-extensions = [\"c\"]\n\
+                 compiler-flags = [\"Wall\"]\n\
+                 {}\
+                 [[bin]]\n\
+                 name = \"warned\"\n\
+                 path = \"main.c\"\n",
+                max)
+    };
+    let dir = project("warned",
+                      &[("warned.toml", buildfile("")),
+                        ("main.c", String::from("int f(); int main() { int a; int b; return f(); }\n")),
+                        ("f.c", String::from("int f() { int c; return 0; }\n"))]);
+    let output = yabs(&dir, &["build"]);
+    let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+    assert!(output.status.success(), "{}", logged(&output));
+    assert!(stdout.contains("build finished: 0 error(s), 3 warning(s) in 2 file(s)\n\
+                             most warnings:\n  2 main.c\n  1 f.c\n"),
+            "{}",
+            stdout);
+    // Up to date sources aren't compiled, so they don't warn again
+    let stdout = String::from_utf8_lossy(&yabs(&dir, &["build"]).stdout).into_owned();
+    assert!(!stdout.contains("build finished:"), "{}", stdout);
+    fs::write(dir.join("warned.toml"), buildfile("max-warnings = 2\n")).unwrap();
+    fs::remove_file(dir.join("main.o")).unwrap();
+    fs::remove_file(dir.join("f.o")).unwrap();
+    let output = yabs(&dir, &["--json", "build"]);
+    let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+    assert!(!output.status.success(), "{}", stdout);
+    assert!(stdout.contains("{\"type\":\"diagnostics_summary\",\"errors\":0,\"warnings\":3,\"files\":[\
+                             {\"path\":\"f.c\",\"warnings\":1,\"errors\":0},\
+                             {\"path\":\"main.c\",\"warnings\":2,\"errors\":0}]}"),
+            "{}",
+            stdout);
+    assert!(stdout.contains("the build produced 3 warning(s), more than 'max-warnings' allows (2)"), "{}", stdout);
+    // A failed build is summarized too
+    fs::write(dir.join("f.c"), "int f() { int c; return missing; }\n").unwrap();
+    let output = yabs(&dir, &["build"]);
+    let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+    assert!(!output.status.success(), "{}", logged(&output));
+    assert!(stdout.contains("0 object(s) compiled") && stdout.contains("build finished: 1 error(s), "),
+            "{}",
+            stdout);
+}
+
+#[test]
+fn test_check() {
+    let dir = project("checked",
+                      &[("checked.toml",
+                         String::from("[project]\n\
+                                       file-extensions = [\"c\"]\n\
+                                       compiler = \"no-such-cc\"\n\
+                                       include = [\"missing\"]\n\
+                                       before-script = [\"./gen.sh\"]\n\
+                                       [[bin]]\n\
+                                       name = \"checked\"\n\
+                                       path = \"main.c\"\n\
+                                       [[lib]]\n\
+                                       name = \"checked\"\n\
+                                       types = []\n")),
+                        ("main.c", String::from("int main() { return 0; }\n"))]);
+    let output = yabs(&dir, &["check"]);
+    let log = logged(&output);
+    assert!(!output.status.success(), "{}", log);
+    for finding in &["'include' lists 'missing', which is not a directory",
+                     "'types' of library 'checked': lists neither \"static\" nor \"dynamic\"",
+                     "'compiler': 'no-such-cc', which compiles the C sources, wasn't found",
+                     "'before-script': './gen.sh' runs './gen.sh', which doesn't exist",
+                     "check found 2 error(s), 1 warning(s)"] {
+        assert!(log.contains(finding), "{}", log);
+    }
+    // The binary and the library may share their name
+    assert!(!log.contains("named 'checked'"), "{}", log);
+    // Nothing was built
+    assert!(!dir.join("main.o").exists());
+    fs::write(dir.join("checked.toml"),
+              "[project]\nfile-extensions = [\"c\"]\n[[bin]]\nname = \"checked\"\npath = \"main.c\"\n").unwrap();
+    let output = yabs(&dir, &["check"]);
+    assert!(output.status.success(), "{}", logged(&output));
+    assert!(logged(&output).contains("check found 0 error(s), 0 warning(s)"));
+}
+
+#[test]
+fn test_unknown_keys() {
+    let buildfile = |strict: &str| {
+        format!("[project]\n\
+                 file-extensions = [\"c\"]\n\
+                 complier = \"no-such-cc\"\n\
+                 {}\
+                 [project.extras]\n\
+                 flag = true\n\
+                 [[bin]]\n\
+                 name = \"typo\"\n\
+                 path = \"main.c\"\n",
+                strict)
+    };
+    let dir = project("typo",
+                      &[("typo.toml", buildfile("")), ("main.c", String::from("int main() { return 0; }\n"))]);
+    let output = yabs(&dir, &["build"]);
+    let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+    assert!(output.status.success(), "{}", logged(&output));
+    assert!(stderr.contains("unknown key `complier` in [project]; did you mean `compiler`?\n"), "{}", stderr);
+    assert!(stderr.contains("unknown table [project.extras]\n"), "{}", stderr);
+    let output = yabs(&dir, &["--strict", "check"]);
+    assert!(!output.status.success());
+    assert!(logged(&output).contains("found 2 unknown key(s) in the buildfile, which 'strict' doesn't allow"),
+            "{}",
+            logged(&output));
+    fs::write(dir.join("typo.toml"), buildfile("strict = true\n")).unwrap();
+    assert!(!yabs(&dir, &["build"]).status.success());
+}
+
+#[test]
+fn test_new_project() {
+    let root = TestProject::from_files::<&str>("new", &[]);
+    for &(kind, name) in &[("--bin", "hello"), ("--lib", "greet"), ("--bin-lib", "both")] {
+        let output = yabs(&root, &["new", kind, name]);
+        assert!(output.status.success(), "{}", logged(&output));
+        let dir = root.join(name);
+        assert!(dir.join(".gitignore").exists());
+        let output = yabs(&dir, &["build"]);
+        assert!(output.status.success(), "{}", logged(&output));
+        assert!(!String::from_utf8_lossy(&output.stderr).contains("warning"), "{}", logged(&output));
+    }
+    assert!(root.join("greet/libgreet.a").exists());
+    assert!(root.join("greet/libgreet.so").exists());
+    for name in &["hello", "both"] {
+        let output = Command::new(root.join(name).join(name)).output().unwrap();
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "Hello, world!\n");
+    }
+    // Directories with anything in them are left alone
+    let output = yabs(&root, &["new", "--bin", "hello"]);
+    assert!(!output.status.success());
+    assert!(logged(&output).contains("directory 'hello' already exists"), "{}", logged(&output));
+}
+
+#[test]
+fn test_init() {
+    let dir = project("adopted",
+                      &[("include/util.h", String::from("int twice(int x);\n")),
+                        ("src/util.c", String::from("#include \"util.h\"\nint twice(int x) { return 2 * x; }\n")),
+                        ("src/main.c",
+                         String::from("#include \"util.h\"\nint main(void) {\n    return twice(0);\n}\n")),
+                        // Hidden directories aren't searched for headers
+                        (".git/hooks/sample.h", String::new()),
+                        (".yabs/cache/old.h", String::new())]);
+    let output = yabs(&dir, &["init"]);
+    assert!(output.status.success(), "{}", logged(&output));
+    assert!(logged(&output).contains("found 2 source(s): src/main.c, src/util.c\n"), "{}", logged(&output));
+    assert_eq!(fs::read_to_string(dir.join("adopted.toml")).unwrap(),
+               "[project]\nname = \"adopted\"\nversion = \"0.0.1\"\nfile-extensions = [\"c\"]\n\
+                include = [\"include\"]\n\n[[bin]]\nname = \"adopted\"\npath = \"src/main.c\"\n");
+    assert!(yabs(&dir, &["check"]).status.success());
+    let output = yabs(&dir, &["build"]);
+    assert!(output.status.success(), "{}", logged(&output));
+    assert!(Command::new(dir.join("adopted")).status().unwrap().success());
+    // A second main() makes a binary of its own
+    fs::write(dir.join("src/tool.c"), "int main(void) { return 0; }\n").unwrap();
+    let output = yabs(&dir, &["init"]);
+    assert!(!output.status.success());
+    assert!(logged(&output).contains("replacing it has to be forced"), "{}", logged(&output));
+    let output = yabs(&dir, &["init", "--force"]);
+    assert!(output.status.success(), "{}", logged(&output));
+    assert!(logged(&output).contains("main() is defined in 2 files, writing a [[bin]] for each: src/main.c, src/tool.c"),
+            "{}",
+            logged(&output));
+    let buildfile = fs::read_to_string(dir.join("adopted.toml")).unwrap();
+    assert!(buildfile.contains("[[bin]]\nname = \"main\"\npath = \"src/main.c\"\n\n\
+                                [[bin]]\nname = \"tool\"\npath = \"src/tool.c\"\n"),
+            "{}",
+            buildfile);
+}
+
+#[test]
+fn test_buildfile_path() {
+    let buildfile = |name: &str| {
+        format!("[project]\nfile-extensions = [\"c\"]\nsrc = [\"main.c\"]\n[[bin]]\nname = \"{}\"\npath = \"main.c\"\n",
+                name)
+    };
+    let dir = project("paths",
+                      &[("build/yabs.toml", buildfile("fallback")),
+                        ("build/ci.toml", buildfile("variant")),
+                        ("build/main.c", String::from("int main() { return 0; }\n"))]);
+    // Found in the directory without one named after it
+    let output = yabs(&dir.join("build"), &["build"]);
+    assert!(output.status.success(), "{}", logged(&output));
+    assert!(dir.join("build/fallback").exists());
+    // Paths are relative to the buildfile given
+    let output = yabs(&dir, &["--file", "build/ci.toml", "build"]);
+    assert!(output.status.success(), "{}", logged(&output));
+    assert!(dir.join("build/variant").exists());
+    let output = yabs(&dir, &["-f", "missing.toml", "build"]);
+    assert!(!output.status.success());
+    assert!(logged(&output).contains(&format!("buildfile '{}' doesn't exist", dir.join("missing.toml").display())),
+            "{}",
+            logged(&output));
+    // The one named after the directory wins
+    fs::write(dir.join("build/build.toml"), buildfile("named")).unwrap();
+    assert!(yabs(&dir.join("build"), &["build"]).status.success());
+    assert!(dir.join("build/named").exists());
+}
+
+#[test]
+fn test_json_buildfile() {
+    let dir = project("generated",
+                      &[("yabs.json",
+                         String::from("{\n  \"project\": {\"file-extensions\": [\"c\"], \"version\": null},\n  \
+                                       \"bin\": [{\"name\": \"generated\", \"path\": \"main.c\"}]\n}\n")),
+                        ("broken.json", String::from("{\n  \"project\": {\n    \"name\": \"x\",\n  }\n}\n")),
+                        ("main.c", String::from("int main() { return 0; }\n"))]);
+    let output = yabs(&dir, &["build"]);
+    assert!(output.status.success(), "{}", logged(&output));
+    assert!(dir.join("generated").exists());
+    let output = yabs(&dir, &["--file", "broken.json", "build"]);
+    assert!(!output.status.success());
+    assert!(logged(&output).contains("at line 4 column 3"), "{}", logged(&output));
+}
+
+#[test]
+fn test_targets() {
+    let dir = project("listed",
+                      &[("listed.toml",
+                         String::from("[project]\n\
+                                       file-extensions = [\"c\"]\n\
+                                       [[bin]]\n\
+                                       name = \"listed\"\n\
+                                       path = \"main.c\"\n\
+                                       [[task]]\n\
+                                       name = \"hello\"\n\
+                                       command = \"true\"\n")),
+                        ("main.c", String::from("int main() { return 0; }\n"))]);
+    let stdout = String::from_utf8_lossy(&yabs(&dir, &["targets"]).stdout).into_owned();
+    assert_eq!(stdout,
+               "NAME    KIND    STATUS       OUTPUT\n\
+                listed  binary  not built    listed\n\
+                hello   task\n");
+    assert!(yabs(&dir, &["build"]).status.success());
+    let stdout = String::from_utf8_lossy(&yabs(&dir, &["targets"]).stdout).into_owned();
+    assert!(stdout.contains("listed  binary  up to date   listed\n"), "{}", stdout);
+    let stdout = String::from_utf8_lossy(&yabs(&dir, &["--json", "targets"]).stdout).into_owned();
+    assert_eq!(stdout.lines().collect::<Vec<_>>(),
+               vec!["{\"type\":\"target\",\"name\":\"listed\",\"kind\":\"binary\",\"output\":\"listed\",\
+                     \"default\":true,\"exists\":true,\"up_to_date\":true}",
+                    "{\"type\":\"target\",\"name\":\"hello\",\"kind\":\"task\",\"output\":null,\
+                     \"default\":false,\"exists\":false,\"up_to_date\":false}"]);
+}
+
+#[test]
+fn test_sources() {
+    let dir = project("sourced",
+                      &[("sourced.toml",
+                         String::from("[project]\n\
+                                       file-extensions = [\"c\"]\n\
+                                       [[bin]]\n\
+                                       name = \"sourced\"\n\
+                                       path = \"main.c\"\n\
+                                       [[bin]]\n\
+                                       name = \"other\"\n\
+                                       path = \"other.c\"\n")),
+                        ("main.c", String::from("int answer(void);\nint main() { return answer(); }\n")),
+                        ("other.c", String::from("int main() { return 0; }\n")),
+                        ("answer.c", String::from("int answer(void) { return 0; }\n"))]);
+    let stdout = String::from_utf8_lossy(&yabs(&dir, &["sources", "--target", "sourced"]).stdout).into_owned();
+    assert_eq!(stdout, "./answer.c\n./main.c\n");
+    let stdout = String::from_utf8_lossy(&yabs(&dir, &["sources", "--format", "json"]).stdout).into_owned();
+    assert!(stdout.starts_with("[{\"source\":\"./answer.c\",\"object\":\"./answer.o\",\"modified\":"),
+            "{}",
+            stdout);
+    assert!(stdout.contains("{\"source\":\"./main.c\",\"object\":\"./main.o\""), "{}", stdout);
+    let output = yabs(&dir, &["sources", "--target", "missing"]);
+    assert!(!output.status.success());
+    assert!(logged(&output).contains("no binary or library with name 'missing' found"), "{}", logged(&output));
+}
+
+#[test]
+fn test_export_graph() {
+    let dir = project("graphed",
+                      &[("graphed.toml",
+                         String::from("[project]\n\
+                                       file-extensions = [\"c\"]\n\
+                                       include-resolution-tracking = true\n\
+                                       [[bin]]\n\
+                                       name = \"graphed\"\n\
+                                       path = \"main.c\"\n\
+                                       [[lib]]\n\
+                                       name = \"libgraphed\"\n\
+                                       types = [\"static\"]\n")),
+                        ("main.c", String::from("#include \"answer.h\"\nint main() { return ANSWER; }\n")),
+                        ("answer.h", String::from("#define ANSWER 0\n"))]);
+    let stdout = String::from_utf8_lossy(&yabs(&dir, &["graph"]).stdout).into_owned();
+    assert!(stdout.starts_with("digraph yabs {\n"), "{}", stdout);
+    assert!(stdout.contains("    \"./main.c\" -> \"./main.o\" [label = compile];\n"), "{}", stdout);
+    assert!(stdout.contains("    \"./main.o\" -> \"graphed\" [label = link];\n"), "{}", stdout);
+    assert!(stdout.contains("    \"./main.o\" -> \"libgraphed.a\" [label = archive];\n"), "{}", stdout);
+    assert!(!stdout.contains("answer.h"), "{}", stdout);
+    assert!(yabs(&dir, &["build"]).status.success());
+    let stdout = String::from_utf8_lossy(&yabs(&dir, &["graph"]).stdout).into_owned();
+    assert!(stdout.contains("    \"answer.h\" -> \"./main.o\" [label = include];\n"), "{}", stdout);
+}
+
+#[test]
+fn test_tasks_in_plan_and_graph() {
+    let dir = project("planned",
+                      &[("planned.toml",
+                         String::from("[project]\n\
+                                       file-extensions = [\"c\"]\n\
+                                       [[bin]]\n\
+                                       name = \"planned\"\n\
+                                       path = \"main.c\"\n\
+                                       [[task]]\n\
+                                       name = \"package\"\n\
+                                       command = \"tar cf planned.tar planned\"\n\
+                                       depends = [\"planned\"]\n\
+                                       inputs = [\"README\"]\n\
+                                       outputs = [\"planned.tar\"]\n\
+                                       [[task]]\n\
+                                       name = \"publish\"\n\
+                                       command = [\"true\"]\n\
+                                       depends = [\"package\"]\n")),
+                        ("main.c", String::from("int main() { return 0; }\n")),
+                        ("README", String::from("planned\n"))]);
+    let plan = dir.plan().unwrap();
+    let package = &plan.steps["task:package"];
+    assert_eq!(package.kind, StepKind::Task);
+    assert_eq!(package.inputs, vec!["README", "planned"]);
+    assert_eq!(package.outputs, vec!["planned.tar"]);
+    assert_eq!(package.command, vec!["sh", "-c", "tar cf planned.tar planned"]);
+    assert_eq!(package.stale.as_deref(), Some("no output"));
+    let publish = &plan.steps["task:publish"];
+    assert_eq!(publish.inputs, vec!["task:package"]);
+    assert_eq!(publish.command, vec!["true"]);
+    assert_eq!(publish.stale.as_deref(), Some("no outputs declared"));
+    let stdout = String::from_utf8_lossy(&yabs(&dir, &["graph"]).stdout).into_owned();
+    assert!(stdout.contains("    \"task:package\" [shape = cds, fillcolor = lightsalmon];\n"), "{}", stdout);
+    assert!(stdout.contains("    \"planned\" -> \"task:package\" [label = depends];\n"), "{}", stdout);
+    assert!(stdout.contains("    \"task:package\" -> \"task:publish\" [label = depends];\n"), "{}", stdout);
+    assert!(yabs(&dir, &["task", "package"]).status.success());
+    assert_eq!(dir.plan().unwrap().steps["task:package"].stale, None);
+}
+
+#[test]
+fn test_status() {
+    let dir = project("statused",
+                      &[("statused.toml",
+                         String::from("[project]\n\
+                                       file-extensions = [\"c\"]\n\
+                                       [[bin]]\n\
+                                       name = \"statused\"\n\
+                                       path = \"main.c\"\n")),
+                        ("main.c", String::from("int main() { return 0; }\n"))]);
+    let output = yabs(&dir, &["status"]);
+    assert_eq!(output.status.code(), Some(4));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "statused: 1 of 1 object(s) to compile, statused to link\n");
+    assert!(!dir.join("main.o").exists());
+    let stdout = String::from_utf8_lossy(&yabs(&dir, &["--verbose", "status"]).stdout).into_owned();
+    assert!(stdout.contains("  ./main.o: "), "{}", stdout);
+    assert!(yabs(&dir, &["build"]).status.success());
+    let output = yabs(&dir, &["status"]);
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "statused: up to date\n");
+}
+
+#[test]
+fn test_clean_target() {
+    let dir = project("cleaned",
+                      &[("cleaned.toml",
+                         String::from("[project]\n\
+                                       file-extensions = [\"c\"]\n\
+                                       [[bin]]\n\
+                                       name = \"cleaned\"\n\
+                                       path = \"main.c\"\n\
+                                       [[bin]]\n\
+                                       name = \"other\"\n\
+                                       path = \"other.c\"\n")),
+                        ("main.c", String::from("int answer(void);\nint main() { return answer(); }\n")),
+                        ("other.c", String::from("int answer(void);\nint main() { return answer(); }\n")),
+                        ("answer.c", String::from("int answer(void) { return 0; }\n"))]);
+    assert!(yabs(&dir, &["build"]).status.success());
+    let output = yabs(&dir, &["clean", "cleaned"]);
+    assert!(output.status.success(), "{}", logged(&output));
+    assert!(logged(&output).contains("kept 1 object(s) other targets are built from"), "{}", logged(&output));
+    assert!(!dir.join("cleaned").exists() && !dir.join("main.o").exists());
+    assert!(dir.join("answer.o").exists() && dir.join("other").exists() && dir.join("other.o").exists());
+    assert!(yabs(&dir, &["clean", "cleaned", "--force"]).status.success());
+    assert!(!dir.join("answer.o").exists() && dir.join("other").exists());
+    let output = yabs(&dir, &["clean", "missing"]);
+    assert!(!output.status.success());
+    assert!(logged(&output).contains("no binary or library with name 'missing' found, expected one of cleaned, other"),
+            "{}",
+            logged(&output));
+}
+
+#[test]
+#[cfg(unix)]
+fn test_distclean() {
+    let dir = project("pristine",
+                      &[("pristine.toml",
+                         String::from("[project]\n\
+                                       name = \"pristine\"\n\
+                                       file-extensions = [\"c\"]\n\
+                                       include-resolution-tracking = true\n\
+                                       [[bin]]\n\
+                                       name = \"pristine\"\n\
+                                       path = \"src/main.c\"\n")),
+                        ("src/main.c", String::from("int main() { return 0; }\n")),
+                        ("src/notes.d", String::from("not a dependency file\n"))]);
+    assert!(yabs(&dir, &["build"]).status.success());
+    assert!(yabs(&dir, &["export", "."]).status.success());
+    assert!(dir.join("src/main.d").exists() && dir.join("yabs-export/pristine.toml").exists());
+    let output = yabs(&dir, &["distclean"]);
+    assert!(output.status.success(), "{}", logged(&output));
+    for file in &["src/main.o", "src/main.d", "pristine", ".yabs", "yabs-export"] {
+        assert!(!dir.join(file).exists(), "{} wasn't removed: {}", file, logged(&output));
+    }
+    assert!(dir.join("src/main.c").exists() && dir.join("src/notes.d").exists());
+    // A state directory reached through a symlink out of the project is left
+    // alone
+    let outside = dir.parent().unwrap().join("outside");
+    fs::create_dir_all(outside.join("state")).unwrap();
+    fs::write(outside.join("state/objects.toml"), "").unwrap();
+    ::std::os::unix::fs::symlink(&outside, dir.join("elsewhere")).unwrap();
+    fs::write(dir.join("pristine.toml"),
+              "[project]\nfile-extensions = [\"c\"]\nstate-dir = \"elsewhere/state\"\n").unwrap();
+    let output = yabs(&dir, &["distclean"]);
+    assert!(output.status.success(), "{}", logged(&output));
+    assert!(logged(&output).contains("not removing 'elsewhere/state', it's outside the project"),
+            "{}",
+            logged(&output));
+    assert!(outside.join("state/objects.toml").exists());
+}
+
+#[test]
+fn test_clean_dry_run() {
+    let dir = project("previewed",
+                      &[("previewed.toml",
+                         String::from("[project]\n\
+                                       file-extensions = [\"c\"]\n\
+                                       [[bin]]\n\
+                                       name = \"previewed\"\n\
+                                       path = \"main.c\"\n")),
+                        ("main.c", String::from("int main() { return 0; }\n"))]);
+    // The paths of "<prefix>... '<path>'" lines
+    let paths = |stdout: &[u8], prefix: &str| -> Vec<String> {
+        let mut paths: Vec<String> = String::from_utf8_lossy(stdout)
+            .lines()
+            .filter(|line| line.starts_with(prefix))
+            .filter_map(|line| line.split('\'').nth(1).map(str::to_owned))
+            .collect();
+        paths.sort();
+        paths
+    };
+    for command in &["clean", "distclean"] {
+        assert!(yabs(&dir, &["build"]).status.success());
+        let output = yabs(&dir, &[command, "--dry-run"]);
+        assert!(output.status.success(), "{}", logged(&output));
+        let previewed = paths(&output.stdout, "would remove ");
+        assert!(previewed.contains(&String::from("./main.o")) && previewed.contains(&String::from("previewed")),
+                "{:?}",
+                previewed);
+        assert!(dir.join("main.o").exists() && dir.join("previewed").exists());
+        assert_eq!(paths(&yabs(&dir, &[command]).stdout, "removed "), previewed);
+    }
+    assert!(!dir.join(".yabs").exists());
+}
+
+#[test]
+fn test_force_rebuild() {
+    let dir = project("forced",
+                      &[("forced.toml",
+                         String::from("[project]\n\
+                                       file-extensions = [\"c\"]\n\
+                                       [[bin]]\n\
+                                       name = \"forced\"\n\
+                                       path = \"main.c\"\n")),
+                        ("main.c", String::from("int main() { return 0; }\n"))]);
+    assert!(yabs(&dir, &["build"]).status.success());
+    let stdout = String::from_utf8_lossy(&yabs(&dir, &["build"]).stdout).into_owned();
+    assert!(stdout.contains("'forced' is up to date"), "{}", stdout);
+    assert_eq!(yabs(&dir, &["status", "--force"]).status.code(), Some(4));
+    let log = logged(&yabs(&dir, &["build", "--force", "--explain", "--jobs", "2"]));
+    assert!(log.contains("rebuilding ./main.o: rebuild forced") && log.contains("linking 'forced'"),
+            "{}",
+            log);
+    assert!(yabs(&dir, &["status"]).status.success());
+}
+
+#[test]
+fn test_force_rebuild_shared_objects() {
+    let dir = project("forcedshared",
+                      &[("forcedshared.toml",
+                         String::from("[project]\n\
+                                       file-extensions = [\"c\"]\n\
+                                       [[bin]]\n\
+                                       name = \"forcedshared\"\n\
+                                       path = \"main.c\"\n\
+                                       [[lib]]\n\
+                                       name = \"libforcedshared\"\n\
+                                       types = [\"static\"]\n")),
+                        ("main.c", String::from("int util(void);\nint main() { return util(); }\n")),
+                        ("util.c", String::from("int util(void) { return 0; }\n"))]);
+    assert!(yabs(&dir, &["build"]).status.success());
+    // The objects the binary and the library share are compiled once
+    let log = logged(&yabs(&dir, &["build", "--force"]));
+    assert!(log.contains("2 object(s) compiled"), "{}", log);
+    assert!(log.contains("linking 'forcedshared'") && log.contains("linking 'libforcedshared'"), "{}", log);
+}
+
+#[test]
+fn test_objects_only() {
+    let dir = project("unlinked",
+                      &[("unlinked.toml",
+                         String::from("[project]\n\
+                                       file-extensions = [\"c\"]\n\
+                                       before-script = [\"touch before\"]\n\
+                                       [[bin]]\n\
+                                       name = \"unlinked\"\n\
+                                       path = \"main.c\"\n\
+                                       [[lib]]\n\
+                                       name = \"libunlinked\"\n\
+                                       types = [\"static\"]\n")),
+                        ("main.c", String::from("int main() { return 0; }\n"))]);
+    let output = yabs(&dir, &["build", "--objects-only", "--no-scripts"]);
+    assert!(output.status.success(), "{}", logged(&output));
+    assert!(logged(&output).contains("1 object(s) compiled, 0 cached, 0 unchanged, nothing linked, only objects were \
+                                      built"),
+            "{}",
+            logged(&output));
+    assert!(dir.join("main.o").exists() && !dir.join("before").exists());
+    assert!(!dir.join("unlinked").exists() && !dir.join("libunlinked.a").exists());
+    assert!(yabs(&dir, &["build", "--objects-only"]).status.success());
+    assert!(dir.join("before").exists());
+}
+
+#[test]
+fn test_check_syntax() {
+    let dir = project("checked",
+                      &[("checked.toml",
+                         String::from("[project]\n\
+                                       file-extensions = [\"c\"]\n\
+                                       include = [\"include\"]\n\
+                                       compiler-flags = [\"DANSWER=0\"]\n\
+                                       [[bin]]\n\
+                                       name = \"checked\"\n\
+                                       path = \"main.c\"\n")),
+                        ("include/answer.h", String::from("int answer(void);\n")),
+                        ("main.c", String::from("#include \"answer.h\"\nint main() { return ANSWER; }\n")),
+                        ("first.c", String::from("int first(void) { return missing_one; }\n")),
+                        ("second.c", String::from("int second(void) { return missing_two; }\n"))]);
+    let output = yabs(&dir, &["check-syntax"]);
+    assert_eq!(output.status.code(), Some(3));
+    let log = logged(&output);
+    assert!(log.contains("missing_one") && log.contains("missing_two"), "{}", log);
+    assert!(log.contains("syntax check: 1 source(s) passed, 2 failed"), "{}", log);
+    assert!(!log.contains("main.c:"), "{}", log);
+    assert!(!dir.join("main.o").exists() && !dir.join("first.o").exists() && !dir.join(".yabs/objects.toml").exists());
+    // Quiet drops progress, not the diagnostics
+    let log = logged(&yabs(&dir, &["-q", "check-syntax"]));
+    assert!(log.contains("missing_one") && log.contains("missing_two"), "{}", log);
+}
+
+#[test]
+fn test_preprocess() {
+    let dir = project("preprocessed",
+                      &[("preprocessed.toml",
+                         String::from("[project]\n\
+                                       file-extensions = [\"c\"]\n\
+                                       include = [\"include\"]\n\
+                                       compiler-flags = [\"DANSWER=42\"]\n\
+                                       [[bin]]\n\
+                                       name = \"preprocessed\"\n\
+                                       path = \"src/main.c\"\n")),
+                        ("include/answer.h", String::from("#define DOUBLED (ANSWER * 2)\n")),
+                        ("src/main.c", String::from("#include \"answer.h\"\nint main() { return DOUBLED; }\n"))]);
+    let output = yabs(&dir, &["preprocess", "src/main.c"]);
+    assert!(output.status.success(), "{}", logged(&output));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("int main() { return (42 * 2); }"),
+            "{}",
+            logged(&output));
+    assert!(yabs(&dir, &["preprocess", "./src/main.c", "-o", "main.i"]).status.success());
+    assert!(fs::read_to_string(dir.join("main.i")).unwrap().contains("return (42 * 2);"));
+    assert!(!dir.join("src/main.o").exists());
+    // The compiler's errors survive quiet mode
+    fs::write(dir.join("include/answer.h"), "#error broken header\n").unwrap();
+    let output = yabs(&dir, &["-q", "preprocess", "src/main.c"]);
+    assert!(!output.status.success());
+    assert!(logged(&output).contains("broken header"), "{}", logged(&output));
+    fs::write(dir.join("include/answer.h"), "#define DOUBLED (ANSWER * 2)\n").unwrap();
+    let output = yabs(&dir, &["preprocess", "src/mian.c"]);
+    assert!(!output.status.success());
+    assert!(logged(&output).contains("'src/mian.c' isn't a source of the project, did you mean src/main.c?"),
+            "{}",
+            logged(&output));
+}
+
+#[test]
+fn test_platform_sections() {
+    let dir = project("platforms",
+                      &[("platforms.toml",
+                         format!("[project]\n\
+                                  file-extensions = [\"c\"]\n\
+                                  libraries = [\"m\"]\n\
+                                  [[bin]]\n\
+                                  name = \"platforms\"\n\
+                                  path = \"main.c\"\n\
+                                  [target.{}]\n\
+                                  defines = [\"ANSWER=0\"]\n\
+                                  [target.linx]\n\
+                                  defines = [\"ANSWER=1\"]\n",
+                                 env::consts::OS)),
+                        ("main.c", String::from("int main() { return ANSWER; }\n"))]);
+    let output = yabs(&dir, &["build"]);
+    assert!(output.status.success(), "{}", logged(&output));
+    assert!(logged(&output).contains("[target.linx] is ignored, 'linx' isn't a known OS; did you mean `linux`?"),
+            "{}",
+            logged(&output));
+    assert!(Command::new(dir.join("platforms")).status().unwrap().success());
+    // A cross build takes the section of the target's OS instead of the host's
+    fs::write(dir.join("platforms.toml"),
+              "[project]\n\
+               file-extensions = [\"c\"]\n\
+               target = \"x86_64-w64-mingw32\"\n\
+               libraries = [\"m\"]\n\
+               [target.windows]\n\
+               libraries = [\"ws2_32\"]\n\
+               ar = \"x86_64-w64-mingw32-ar\"\n\
+               [target.linux]\n\
+               libraries = [\"rt\"]\n\
+               libaries = [\"dl\"]\n")
+        .unwrap();
+    let output = yabs(&dir, &["print-config"]);
+    assert!(output.status.success(), "{}", logged(&output));
+    let config = String::from_utf8_lossy(&output.stdout);
+    assert!(config.contains("libraries = [\"m\", \"ws2_32\"]"), "{}", config);
+    assert!(config.contains("ar = \"x86_64-w64-mingw32-ar\""), "{}", config);
+    assert!(!config.contains("target.linux") && !config.contains("\"rt\""), "{}", config);
+    assert!(logged(&output).contains("unknown key `libaries` in [target.linux]; did you mean `libraries`?"),
+            "{}",
+            logged(&output));
+}
+
+#[test]
+fn test_inherited_buildfile() {
+    let dir = project("inheriting",
+                      &[("common.toml",
+                         String::from("[project]\n\
+                                       file-extensions = [\"c\"]\n\
+                                       include = [\"include\"]\n\
+                                       compiler-flags = [\"DANSWER=0\"]\n\
+                                       [[bin]]\n\
+                                       name = \"first\"\n\
+                                       path = \"app/first.c\"\n")),
+                        ("app/app.toml",
+                         String::from("inherits = \"../common.toml\"\n\
+                                       [project]\n\
+                                       include = [\"local\"]\n\
+                                       [[bin]]\n\
+                                       name = \"second\"\n\
+                                       path = \"second.c\"\n")),
+                        // Found through the include of common.toml, relative to it
+                        ("include/answer.h", String::from("#define DOUBLED (ANSWER * 2)\n")),
+                        ("app/first.c", String::from("#include \"answer.h\"\nint main() { return DOUBLED; }\n")),
+                        ("app/second.c", String::from("int main() { return ANSWER; }\n"))]);
+    let app = dir.join("app");
+    let output = yabs(&app, &["build"]);
+    assert!(output.status.success(), "{}", logged(&output));
+    assert!(Command::new(app.join("first")).status().unwrap().success());
+    assert!(Command::new(app.join("second")).status().unwrap().success());
+    let config = String::from_utf8_lossy(&yabs(&app, &["print-config"]).stdout).into_owned();
+    assert!(config.contains("compiler-flags = [\"DANSWER=0\"]  # ../common.toml\n"), "{}", config);
+    assert!(config.contains("include = [\"../include\", \"local\"]  # ../common.toml, app.toml\n"), "{}", config);
+    assert!(config.contains("path = \"../app/first.c\"  # ../common.toml\n"), "{}", config);
+    assert!(config.contains("name = \"second\"  # app.toml\n"), "{}", config);
+    fs::write(dir.join("common.toml"), "inherits = \"app/app.toml\"\n").unwrap();
+    let output = yabs(&app, &["build"]);
+    assert!(!output.status.success());
+    let cycle = "buildfiles inherit from each other: app.toml -> ../common.toml -> ../app/app.toml";
+    assert!(logged(&output).contains(cycle), "{}", logged(&output));
+}
+
+#[test]
+fn test_target_overrides() {
+    let dir = project("overriding",
+                      &[("overriding.toml",
+                         String::from("[project]\n\
+                                       file-extensions = [\"c\"]\n\
+                                       [[bin]]\n\
+                                       name = \"overriding\"\n\
+                                       path = \"main.c\"\n\
+                                       [[lib]]\n\
+                                       name = \"libplugin\"\n\
+                                       types = [\"dynamic\"]\n\
+                                       compiler-flags = [\"fPIC\", \"fvisibility=hidden\"]\n\
+                                       defines = [\"PLUGIN\"]\n\
+                                       libraries = [\"m\"]\n")),
+                        ("main.c", String::from("int answer(void);\nint main() { return answer(); }\n")),
+                        ("answer.c",
+                         String::from("#ifdef PLUGIN\nint answer(void) { return 1; }\n\
+                                       #else\nint answer(void) { return 0; }\n#endif\n"))]);
+    let output = yabs(&dir, &["build", "-v"]);
+    assert!(output.status.success(), "{}", logged(&output));
+    assert!(logged(&output).contains("-fPIC -fvisibility=hidden -DPLUGIN -o libplugin.objects/answer.o"),
+            "{}",
+            logged(&output));
+    assert!(logged(&output).contains("-lm"), "{}", logged(&output));
+    assert!(dir.join("answer.o").exists() && dir.join("libplugin.so").exists());
+    // The binary links the objects compiled without the library's flags
+    assert!(Command::new(dir.join("overriding")).status().unwrap().success());
+    let output = yabs(&dir, &["build"]);
+    assert!(logged(&output).contains("'overriding' is up to date") &&
+            logged(&output).contains("'libplugin' is up to date"),
+            "{}",
+            logged(&output));
+    // A target is judged by the objects it links, not those of other targets
+    let later = std::time::SystemTime::now() + std::time::Duration::from_secs(5);
+    fs::File::options().write(true).open(dir.join("libplugin.objects/answer.o")).unwrap().set_modified(later).unwrap();
+    let output = yabs(&dir, &["build"]);
+    assert!(logged(&output).contains("'overriding' is up to date") &&
+            !logged(&output).contains("'libplugin' is up to date"),
+            "{}",
+            logged(&output));
+    assert!(yabs(&dir, &["clean"]).status.success());
+    assert!(!dir.join("libplugin.objects/answer.o").exists());
+}
+
+#[test]
+fn test_output_directories() {
+    let dir = project("placed",
+                      &[("placed.toml",
+                         String::from("[project]\n\
+                                       file-extensions = [\"c\"]\n\
+                                       bin-dir = \"build/bin\"\n\
+                                       lib-out-dir = \"build/lib\"\n\
+                                       [[bin]]\n\
+                                       name = \"placed\"\n\
+                                       path = \"main.c\"\n\
+                                       [[bin]]\n\
+                                       name = \"tool\"\n\
+                                       path = \"tool.c\"\n\
+                                       bin-dir = \"tools\"\n\
+                                       [[lib]]\n\
+                                       name = \"libplaced\"\n\
+                                       types = [\"static\"]\n")),
+                        ("main.c", String::from("int main() { return 0; }\n")),
+                        ("tool.c", String::from("int main() { return 0; }\n"))]);
+    let output = yabs(&dir, &["build"]);
+    assert!(output.status.success(), "{}", logged(&output));
+    assert!(dir.join("build/bin/placed").exists() && dir.join("tools/tool").exists());
+    assert!(dir.join("build/lib/libplaced.a").exists() && !dir.join("placed").exists());
+    let output = yabs(&dir, &["build"]);
+    assert!(logged(&output).contains("'placed' is up to date") && logged(&output).contains("'tool' is up to date"),
+            "{}",
+            logged(&output));
+    let stdout = String::from_utf8_lossy(&yabs(&dir, &["targets"]).stdout).into_owned();
+    assert!(stdout.contains("up to date   build/bin/placed\n") && stdout.contains("build/lib/libplaced.a\n"),
+            "{}",
+            stdout);
+    assert!(yabs(&dir, &["clean"]).status.success());
+    assert!(!dir.join("build/bin/placed").exists() && !dir.join("build/lib/libplaced.a").exists());
+    assert!(!dir.join("tools/tool").exists());
+}
+
+#[test]
+fn test_output_names() {
+    let dir = project("renamed",
+                      &[("renamed.toml",
+                         String::from("[project]\n\
+                                       file-extensions = [\"c\"]\n\
+                                       compiler-flags = [\"fPIC\"]\n\
+                                       [[bin]]\n\
+                                       name = \"app-debug\"\n\
+                                       path = \"main.c\"\n\
+                                       output = \"app\"\n\
+                                       [[lib]]\n\
+                                       name = \"core\"\n\
+                                       types = [\"dynamic\"]\n\
+                                       output = \"libmycompany-core\"\n")),
+                        ("main.c", String::from("int main() { return 0; }\n"))]);
+    let output = yabs(&dir, &["build"]);
+    assert!(output.status.success(), "{}", logged(&output));
+    assert!(dir.join("app").exists() && dir.join("libmycompany-core.so").exists());
+    assert!(!dir.join("app-debug").exists() && !dir.join("core.so").exists());
+    assert!(yabs(&dir, &["build", "--bin", "app-debug"]).status.success());
+    assert!(yabs(&dir, &["clean"]).status.success());
+    assert!(!dir.join("app").exists() && !dir.join("libmycompany-core.so").exists());
+    // Two targets can't be written to the same file
+    fs::write(dir.join("renamed.toml"),
+              "[project]\nfile-extensions = [\"c\"]\n\
+               [[bin]]\nname = \"app-debug\"\npath = \"main.c\"\noutput = \"app\"\n\
+               [[bin]]\nname = \"app\"\npath = \"main.c\"\n")
+        .unwrap();
+    let output = yabs(&dir, &["check"]);
+    assert!(!output.status.success());
+    assert!(logged(&output).contains("'app-debug' and 'app' are both written to 'app'"), "{}", logged(&output));
+    let output = yabs(&dir, &["build"]);
+    assert!(!output.status.success());
+    assert!(logged(&output).contains("give one of them another 'output'"), "{}", logged(&output));
+    assert!(!dir.join("app").exists());
+}
+
+#[test]
+fn test_non_default_targets() {
+    let dir = project("defaults",
+                      &[("defaults.toml",
+                         String::from("[project]\n\
+                                       file-extensions = [\"c\"]\n\
+                                       [[bin]]\n\
+                                       name = \"defaults\"\n\
+                                       path = \"main.c\"\n\
+                                       [[bin]]\n\
+                                       name = \"fuzzer\"\n\
+                                       path = \"fuzz.c\"\n\
+                                       default = false\n")),
+                        ("main.c", String::from("int main() { return 0; }\n")),
+                        ("fuzz.c", String::from("int main() { return 1; }\n"))]);
+    let output = yabs(&dir, &["build"]);
+    assert!(output.status.success(), "{}", logged(&output));
+    assert!(dir.join("defaults").exists() && !dir.join("fuzzer").exists());
+    let stdout = String::from_utf8_lossy(&yabs(&dir, &["targets"]).stdout).into_owned();
+    assert!(stdout.contains("fuzzer    binary  not built    fuzzer  (not built by default)\n"), "{}", stdout);
+    assert!(yabs(&dir, &["build", "--bin", "fuzzer"]).status.success());
+    assert!(dir.join("fuzzer").exists());
+    // Nothing to build is warned about
+    fs::write(dir.join("defaults.toml"),
+              "[project]\nfile-extensions = [\"c\"]\n\
+               [[bin]]\nname = \"fuzzer\"\npath = \"fuzz.c\"\ndefault = false\n")
+        .unwrap();
+    let output = yabs(&dir, &["build"]);
+    assert!(output.status.success(), "{}", logged(&output));
+    assert!(logged(&output).contains("every binary and library has 'default = false'"), "{}", logged(&output));
+}
+
+#[test]
+fn test_build_group() {
+    let dir = project("grouped",
+                      &[("grouped.toml",
+                         String::from("[project]\n\
+                                       file-extensions = [\"c\"]\n\
+                                       [groups]\n\
+                                       tools = [\"hexview\", \"dump\"]\n\
+                                       everything = [\"tools\", \"grouped\"]\n\
+                                       [[bin]]\n\
+                                       name = \"grouped\"\n\
+                                       path = \"main.c\"\n\
+                                       [[bin]]\n\
+                                       name = \"hexview\"\n\
+                                       path = \"hexview.c\"\n\
+                                       default = false\n\
+                                       [[bin]]\n\
+                                       name = \"dump\"\n\
+                                       path = \"dump.c\"\n\
+                                       default = false\n")),
+                        ("main.c", String::from("int main() { return 0; }\n")),
+                        ("hexview.c", String::from("int main() { return 0; }\n")),
+                        ("dump.c", String::from("int main() { return 0; }\n"))]);
+    let output = yabs(&dir, &["build", "--group", "tools"]);
+    assert!(output.status.success(), "{}", logged(&output));
+    assert!(dir.join("hexview").exists() && dir.join("dump").exists() && !dir.join("grouped").exists());
+    let output = yabs(&dir, &["build", "--group", "everything"]);
+    assert!(output.status.success(), "{}", logged(&output));
+    assert!(dir.join("grouped").exists());
+    assert!(logged(&output).contains("'hexview' is up to date"), "{}", logged(&output));
+    let output = yabs(&dir, &["build", "--group", "tool"]);
+    assert!(!output.status.success());
+    assert!(logged(&output).contains("no group with name 'tool' found; did you mean `tools`? \
+                                      Expected one of everything, tools"),
+            "{}",
+            logged(&output));
+}
+
+#[test]
+fn test_build_named_targets() {
+    let dir = project("named",
+                      &[("named.toml",
+                         String::from("[project]\n\
+                                       file-extensions = [\"c\"]\n\
+                                       [[bin]]\n\
+                                       name = \"named\"\n\
+                                       path = \"main.c\"\n\
+                                       [[bin]]\n\
+                                       name = \"other\"\n\
+                                       path = \"other.c\"\n\
+                                       [[lib]]\n\
+                                       name = \"named\"\n\
+                                       types = [\"static\"]\n")),
+                        ("main.c", String::from("int util(void);\nint main() { return util(); }\n")),
+                        ("other.c", String::from("int util(void);\nint main() { return util(); }\n")),
+                        ("util.c", String::from("int util(void) { return 0; }\n"))]);
+    // Unknown names fail before anything is compiled
+    let output = yabs(&dir, &["build", "other", "missing"]);
+    assert!(!output.status.success());
+    assert!(logged(&output).contains("no binary or library with name 'missing' found"), "{}", logged(&output));
+    assert!(!dir.join("util.o").exists());
+    let output = yabs(&dir, &["build", "named"]);
+    assert!(!output.status.success());
+    assert!(logged(&output).contains("name it 'bin:named' or 'lib:named'"), "{}", logged(&output));
+    let output = yabs(&dir, &["build", "-v", "bin:named", "other"]);
+    assert!(output.status.success(), "{}", logged(&output));
+    assert_eq!(logged(&output).matches("-o ./util.o").count(), 1, "{}", logged(&output));
+    assert!(dir.join("named").exists() && dir.join("other").exists() && !dir.join("named.a").exists());
+    assert!(yabs(&dir, &["build", "lib:named"]).status.success());
+    assert!(dir.join("named.a").exists());
+}
+
+#[test]
+fn test_binary_and_library_sharing_a_name() {
+    let dir = project("twins",
+                      &[("twins.toml",
+                         String::from("[project]\n\
+                                       file-extensions = [\"c\"]\n\
+                                       [groups]\n\
+                                       programs = [\"bin:twins\"]\n\
+                                       [[bin]]\n\
+                                       name = \"twins\"\n\
+                                       path = \"main.c\"\n\
+                                       [[lib]]\n\
+                                       name = \"twins\"\n\
+                                       types = [\"static\"]\n")),
+                        ("main.c", String::from("int util(void);\nint main() { return util(); }\n")),
+                        ("util.c", String::from("int util(void) { return 0; }\n"))]);
+    assert!(yabs(&dir, &["build", "--group", "programs"]).status.success());
+    assert!(dir.join("twins").exists() && !dir.join("twins.a").exists());
+    let output = yabs(&dir, &["sources", "--target", "twins"]);
+    assert!(logged(&output).contains("name it 'bin:twins' or 'lib:twins'"), "{}", logged(&output));
+    let output = yabs(&dir, &["sources", "--target", "lib:twins"]);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "./main.c\n./util.c\n");
+    assert!(yabs(&dir, &["build", "lib:twins"]).status.success());
+    // The library of the same name links the binary's objects, so they stay
+    let output = yabs(&dir, &["clean", "bin:twins"]);
+    assert!(logged(&output).contains("kept 2 object(s)"), "{}", logged(&output));
+    assert!(!dir.join("twins").exists() && dir.join("twins.a").exists());
+    assert!(dir.join("main.o").exists() && dir.join("util.o").exists());
+}
+
+#[test]
+fn test_unknown_target_names() {
+    let dir = project("misnamed",
+                      &[("misnamed.toml",
+                         String::from("[project]\n\
+                                       file-extensions = [\"c\"]\n\
+                                       [[bin]]\n\
+                                       name = \"app\"\n\
+                                       path = \"main.c\"\n\
+                                       [[lib]]\n\
+                                       name = \"libcore\"\n\
+                                       types = [\"static\"]\n")),
+                        ("main.c", String::from("int main() { return 0; }\n"))]);
+    let output = yabs(&dir, &["build", "--bin", "appp"]);
+    assert!(!output.status.success());
+    assert!(logged(&output).contains("no binary with name 'appp' found; did you mean `app`? Expected one of app"),
+            "{}",
+            logged(&output));
+    let output = yabs(&dir, &["build", "--lib", "zzz"]);
+    assert!(!output.status.success());
+    assert!(logged(&output).contains("no library with name 'zzz' found, expected one of libcore"),
+            "{}",
+            logged(&output));
+    assert!(!dir.join("app").exists() && !dir.join("main.o").exists());
+}
+
+#[test]
+fn test_sources_sharing_a_basename() {
+    let dir = project("basenames",
+                      &[("basenames.toml",
+                         String::from("[project]\n\
+                                       file-extensions = [\"c\"]\n\
+                                       [[bin]]\n\
+                                       name = \"basenames\"\n\
+                                       path = \"src/main.c\"\n")),
+                        ("src/main.c",
+                         String::from("int net_init(void);\nint db_init(void);\n\
+                                       int main() { return net_init() + db_init() == 3 ? 0 : 1; }\n")),
+                        ("src/net/init.c", String::from("int net_init(void) { return 1; }\n")),
+                        ("src/db/init.c", String::from("int db_init(void) { return 2; }\n"))]);
+    let output = yabs(&dir, &["build"]);
+    assert!(output.status.success(), "{}", logged(&output));
+    assert!(dir.join("src/net/init.o").exists() && dir.join("src/db/init.o").exists());
+    assert!(Command::new(dir.join("basenames")).status().unwrap().success());
+    // Sources that would be compiled into the same object fail the load
+    fs::write(dir.join("basenames.toml"),
+              "[project]\nfile-extensions = [\"c\", \"cpp\"]\n\
+               [[bin]]\nname = \"basenames\"\npath = \"src/main.c\"\n")
+        .unwrap();
+    fs::write(dir.join("src/db/init.cpp"), "extern \"C\" int db_init() { return 2; }\n").unwrap();
+    let output = yabs(&dir, &["build"]);
+    assert!(!output.status.success());
+    assert!(logged(&output).contains("would both be compiled into 'src/db/init.o'"), "{}", logged(&output));
+    // So would a source and the position independent object of another
+    fs::remove_file(dir.join("src/db/init.cpp")).unwrap();
+    fs::write(dir.join("basenames.toml"),
+              "[project]\nfile-extensions = [\"c\"]\n\
+               [[bin]]\nname = \"basenames\"\npath = \"src/main.c\"\n\
+               [[lib]]\nname = \"libbasenames\"\ntypes = [\"dynamic\"]\n")
+        .unwrap();
+    fs::write(dir.join("src/db/init.pic.c"), "int db_pic(void) { return 2; }\n").unwrap();
+    let output = yabs(&dir, &["build"]);
+    assert!(!output.status.success());
+    assert!(logged(&output).contains("would both be compiled into 'src/db/init.pic.o'"), "{}", logged(&output));
+}
+
+#[test]
+#[cfg(unix)]
+fn test_missing_compiler() {
+    let dir = project("uncompiled",
+                      &[("uncompiled.toml",
+                         String::from("[project]\n\
+                                       file-extensions = [\"c\"]\n\
+                                       compiler = \"clangg\"\n\
+                                       [[bin]]\n\
+                                       name = \"uncompiled\"\n\
+                                       path = \"main.c\"\n")),
+                        ("main.c", String::from("int main() { return 0; }\n")),
+                        ("other.c", String::from("int other(void) { return 0; }\n")),
+                        ("cc.sh", String::from("#!/bin/sh\nexec cc \"$@\"\n"))]);
+    let output = yabs(&dir, &["build"]);
+    assert!(!output.status.success());
+    assert_eq!(logged(&output).matches("C compiler `clangg` not found in PATH").count(), 1, "{}", logged(&output));
+    assert!(!dir.join("main.o").exists());
+    // A path has to be an executable file
+    fs::write(dir.join("uncompiled.toml"),
+              "[project]\nfile-extensions = [\"c\"]\ncompiler = \"./cc.sh\"\n\
+               [[bin]]\nname = \"uncompiled\"\npath = \"main.c\"\n")
+        .unwrap();
+    let output = yabs(&dir, &["build", "--bin", "uncompiled"]);
+    assert!(!output.status.success());
+    assert!(logged(&output).contains("C compiler `./cc.sh` doesn't exist or isn't executable"),
+            "{}",
+            logged(&output));
+    Command::new("chmod").arg("+x").arg(dir.join("cc.sh")).status().unwrap();
+    let output = yabs(&dir, &["build", "--bin", "uncompiled"]);
+    assert!(output.status.success(), "{}", logged(&output));
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_rpath() {
+    let dir = project("rpathed",
+                      &[("rpathed.toml",
+                         String::from("[project]\n\
+                                       file-extensions = [\"c\"]\n\
+                                       compiler-flags = [\"fPIC\"]\n\
+                                       bin-dir = \"build/bin\"\n\
+                                       lib-out-dir = \"build/lib\"\n\
+                                       rpath-origin = true\n\
+                                       [[bin]]\n\
+                                       name = \"rpathed\"\n\
+                                       path = \"main.c\"\n\
+                                       libraries = [\"greet\"]\n\
+                                       library-directories = [\"build/lib\"]\n\
+                                       rpath = [\"/opt/rpathed\"]\n\
+                                       [[lib]]\n\
+                                       name = \"libgreet\"\n\
+                                       types = [\"dynamic\"]\n")),
+                        ("main.c", String::from("int greet(void);\nint main() { return greet(); }\n")),
+                        ("greet.c", String::from("int greet(void) { return 0; }\n"))]);
+    assert!(yabs(&dir, &["build", "--lib", "libgreet"]).status.success());
+    let output = yabs(&dir, &["build", "-v", "--bin", "rpathed"]);
+    assert!(output.status.success(), "{}", logged(&output));
+    // `$ORIGIN` is passed on as it is, no shell runs the link
+    assert!(logged(&output).contains("-Wl,-rpath,/opt/rpathed '-Wl,-rpath,$ORIGIN/../lib'"), "{}", logged(&output));
+    let dynamic = Command::new("readelf").arg("-d").arg(dir.join("build/bin/rpathed")).output().unwrap();
+    assert!(String::from_utf8_lossy(&dynamic.stdout).contains("[/opt/rpathed:$ORIGIN/../lib]"));
+    assert!(Command::new(dir.join("build/bin/rpathed")).env_remove("LD_LIBRARY_PATH").status().unwrap().success());
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_library_groups() {
+    let dir = project("circular",
+                      &[("circular.toml",
+                         String::from("[project]\n\
+                                       file-extensions = [\"c\"]\n\
+                                       src = [\"main.c\"]\n\
+                                       library-directories = [\"vendor\"]\n\
+                                       libraries = [{ group = [\"a\", \"b\"] }, { name = \"-Wl,--as-needed\" }]\n\
+                                       [[bin]]\n\
+                                       name = \"circular\"\n\
+                                       path = \"main.c\"\n")),
+                        ("main.c", String::from("int a(void);\nint main() { return a(); }\n")),
+                        ("vendor/a.c", String::from("int b(void);\nint a(void) { return b(); }\nint a2(void) { return 0; }\n")),
+                        ("vendor/b.c", String::from("int a2(void);\nint b(void) { return a2(); }\n"))]);
+    // Each archive needs the other
+    for name in &["a", "b"] {
+        let vendor = dir.join("vendor");
+        assert!(Command::new("cc").args(["-c", &format!("{}.c", name)]).current_dir(&vendor).status().unwrap().success());
+        let archive = format!("lib{}.a", name);
+        assert!(Command::new("ar").args(["rcs", &archive, &format!("{}.o", name)]).current_dir(&vendor).status().unwrap().success());
+    }
+    let output = yabs(&dir, &["build", "-v"]);
+    assert!(output.status.success(), "{}", logged(&output));
+    assert!(logged(&output).contains("-Wl,--start-group -la -lb -Wl,--end-group -Wl,--as-needed"),
+            "{}",
+            logged(&output));
+    assert!(Command::new(dir.join("circular")).status().unwrap().success());
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_version_script() {
+    let dir = project("shape",
+                      &[("shape.toml",
+                         String::from("[project]\n\
+                                       file-extensions = [\"c\"]\n\
+                                       compiler-flags = [\"fPIC\"]\n\
+                                       [[lib]]\n\
+                                       name = \"libshape\"\n\
+                                       types = [\"dynamic\"]\n\
+                                       visibility = \"hidden\"\n\
+                                       version-script = \"shape.map\"\n")),
+                        ("shape.map", String::from("{ global: shape_area; local: *; };\n")),
+                        ("shape.c",
+                         String::from("int shape_helper(void) { return 4; }\n\
+                                       __attribute__((visibility(\"default\"))) int shape_area(void) \
+                                       { return shape_helper(); }\n\
+                                       __attribute__((visibility(\"default\"))) int shape_internal(void) \
+                                       { return 0; }\n"))]);
+    let output = yabs(&dir, &["build", "-v"]);
+    assert!(output.status.success(), "{}", logged(&output));
+    assert!(logged(&output).contains("-fvisibility=hidden"), "{}", logged(&output));
+    assert!(logged(&output).contains("-Wl,--version-script=shape.map"), "{}", logged(&output));
+    let symbols = Command::new("nm").arg("-D").arg("--defined-only").arg(dir.join("libshape.so")).output().unwrap();
+    let symbols = String::from_utf8_lossy(&symbols.stdout).into_owned();
+    assert!(symbols.contains("shape_area"), "{}", symbols);
+    assert!(!symbols.contains("shape_helper") && !symbols.contains("shape_internal"), "{}", symbols);
+    assert!(logged(&yabs(&dir, &["build"])).contains("'libshape' is up to date"));
+    // A changed version script relinks the library without compiling anything
+    std::thread::sleep(std::time::Duration::from_millis(1100));
+    fs::write(dir.join("shape.map"), "{ global: shape_area; shape_internal; local: *; };\n").unwrap();
+    let output = yabs(&dir, &["build"]);
+    assert!(logged(&output).contains("linking 'libshape'"), "{}", logged(&output));
+    fs::remove_file(dir.join("shape.map")).unwrap();
+    let output = yabs(&dir, &["build"]);
+    assert!(!output.status.success());
+    assert!(logged(&output).contains("'libshape' is linked with 'shape.map', which doesn't exist"),
+            "{}",
+            logged(&output));
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_strip() {
+    let buildfile = |tool: &str| {
+        format!("[project]\n\
+                 file-extensions = [\"c\"]\n\
+                 strip = true\n\
+                 strip-tool = \"{}\"\n\
+                 [[bin]]\n\
+                 name = \"stripped\"\n\
+                 path = \"main.c\"\n\
+                 [[lib]]\n\
+                 name = \"libkept\"\n\
+                 types = [\"static\"]\n",
+                tool)
+    };
+    let dir = project("stripped",
+                      &[("stripped.toml", buildfile("strip")),
+                        ("main.c", String::from("int main() { return 0; }\n"))]);
+    let output = yabs(&dir, &["build"]);
+    assert!(output.status.success(), "{}", logged(&output));
+    assert!(logged(&output).contains("stripped 'stripped' from "), "{}", logged(&output));
+    // Archives are left alone
+    assert!(!logged(&output).contains("stripped 'libkept.a'"), "{}", logged(&output));
+    let symbols = Command::new("nm").arg(dir.join("stripped")).output().unwrap();
+    assert!(String::from_utf8_lossy(&symbols.stderr).contains("no symbols"));
+    // The tool's arguments are split like a shell would
+    fs::write(dir.join("stripped.toml"), buildfile("strip '--strip-all'")).unwrap();
+    let output = yabs(&dir, &["build", "--force"]);
+    assert!(output.status.success(), "{}", logged(&output));
+    fs::write(dir.join("stripped.toml"), buildfile("no-such-strip")).unwrap();
+    let output = yabs(&dir, &["build", "--force"]);
+    assert!(!output.status.success());
+    assert!(logged(&output).contains("strip `no-such-strip` not found in PATH"), "{}", logged(&output));
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_split_debug_info() {
+    let dir = project("debuggable",
+                      &[("debuggable.toml",
+                         String::from("[project]\n\
+                                       file-extensions = [\"c\"]\n\
+                                       compiler-flags = [\"g\"]\n\
+                                       split-debug-info = true\n\
+                                       objcopy = \"objcopy '--enable-deterministic-archives'\"\n\
+                                       debug-dir = \"debug\"\n\
+                                       [[bin]]\n\
+                                       name = \"debuggable\"\n\
+                                       path = \"main.c\"\n")),
+                        ("main.c", String::from("int main() { return 0; }\n"))]);
+    let output = yabs(&dir, &["build"]);
+    assert!(output.status.success(), "{}", logged(&output));
+    assert!(logged(&output).contains("wrote the debug info of 'debuggable' to 'debug/debuggable.debug'"),
+            "{}",
+            logged(&output));
+    let sections = |path: &str| {
+        let output = Command::new("readelf").arg("-S").arg(dir.join(path)).output().unwrap();
+        String::from_utf8_lossy(&output.stdout).into_owned()
+    };
+    assert!(sections("debuggable").contains(".gnu_debuglink"));
+    assert!(!sections("debuggable").contains(".debug_info"));
+    assert!(sections("debug/debuggable.debug").contains(".debug_info"));
+    assert!(Command::new(dir.join("debuggable")).status().unwrap().success());
+    assert!(yabs(&dir, &["clean"]).status.success());
+    assert!(!dir.join("debug/debuggable.debug").exists());
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_pic_objects() {
+    let dir = project("both",
+                      &[("both.toml",
+                         String::from("[project]\n\
+                                       file-extensions = [\"c\"]\n\
+                                       [[bin]]\n\
+                                       name = \"both\"\n\
+                                       path = \"main.c\"\n\
+                                       [[lib]]\n\
+                                       name = \"libboth\"\n\
+                                       types = [\"static\", \"dynamic\"]\n")),
+                        ("main.c", String::from("int answer(void);\nint main() { return answer() - 42; }\n")),
+                        ("answer.c", String::from("int answer(void) { return 42; }\n"))]);
+    let output = yabs(&dir, &["build", "-v"]);
+    assert!(output.status.success(), "{}", logged(&output));
+    let log = logged(&output);
+    assert!(log.contains("-fPIC -o ./answer.pic.o ./answer.c"), "{}", log);
+    assert!(log.contains(" -o ./answer.o ./answer.c"), "{}", log);
+    assert!(!log.contains("-fPIC -o ./answer.o"), "{}", log);
+    let link = |prefix: &str| log.lines().find(|line| line.starts_with(prefix)).unwrap_or_default().to_owned();
+    assert!(link("ar rcs libboth.a").ends_with(" ./answer.o ./main.o"), "{}", log);
+    assert!(link("gcc -shared").ends_with(" ./answer.pic.o ./main.pic.o"), "{}", log);
+    assert!(Command::new(dir.join("both")).status().unwrap().success());
+    assert!(logged(&yabs(&dir, &["build"])).contains("'libboth' is up to date"));
+    assert!(yabs(&dir, &["clean"]).status.success());
+    assert!(!dir.join("answer.pic.o").exists() && !dir.join("answer.o").exists());
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_archive_options() {
+    let buildfile = |settings: &str| {
+        format!("[project]\n\
+                 file-extensions = [\"c\"]\n\
+                 {}\n\
+                 [[lib]]\n\
+                 name = \"libparts\"\n\
+                 types = [\"static\"]\n",
+                settings)
+    };
+    let dir = project("parts",
+                      &[("parts.toml", buildfile("thin-archive = true")),
+                        ("a.c", String::from("int a(void) { return 1; }\n")),
+                        ("b.c", String::from("int b(void) { return 2; }\n"))]);
+    let output = yabs(&dir, &["build", "-v"]);
+    assert!(output.status.success(), "{}", logged(&output));
+    assert!(logged(&output).contains("ar rcsT libparts.a.tmp."), "{}", logged(&output));
+    assert!(fs::read(dir.join("libparts.a")).unwrap().starts_with(b"!<thin>\n"));
+    // Without `s` the archive is indexed by ranlib
+    fs::write(dir.join("parts.toml"), buildfile("arflags = \"rc\"")).unwrap();
+    let output = yabs(&dir, &["build", "-v", "--force"]);
+    assert!(output.status.success(), "{}", logged(&output));
+    assert!(logged(&output).contains("ranlib libparts.a.tmp."), "{}", logged(&output));
+    fs::write(dir.join("parts.toml"), buildfile("arflags = \"rc\"\nranlib = \"ranlib '-D'\"")).unwrap();
+    let output = yabs(&dir, &["build", "-v", "--force"]);
+    assert!(output.status.success(), "{}", logged(&output));
+    assert!(logged(&output).contains("ranlib -D libparts.a.tmp."), "{}", logged(&output));
+    fs::write(dir.join("parts.toml"), buildfile("incremental-archive = true")).unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(1100));
+    fs::write(dir.join("b.c"), "int b(void) { return 3; }\n").unwrap();
+    let output = yabs(&dir, &["build", "-v"]);
+    assert!(output.status.success(), "{}", logged(&output));
+    assert!(logged(&output).contains("replacing 1 of the 2 member(s) of 'libparts.a'"), "{}", logged(&output));
+    assert!(!logged(&output).contains("./a.o ./b.o"), "{}", logged(&output));
+    // A member whose source is gone makes the archive be written again
+    fs::remove_file(dir.join("a.c")).unwrap();
+    let output = yabs(&dir, &["build", "-v", "--force"]);
+    assert!(output.status.success(), "{}", logged(&output));
+    assert!(!logged(&output).contains("replacing"), "{}", logged(&output));
+    let members = Command::new("ar").arg("t").arg(dir.join("libparts.a")).output().unwrap();
+    assert_eq!(String::from_utf8_lossy(&members.stdout), "b.o\n");
+}
+
+#[test]
+fn test_up_to_date_artifact_with_other_flags() {
+    let buildfile = |flags: &str| {
+        format!("[project]\n\
+                 file-extensions = [\"c\"]\n\
+                 {}\
+                 [[bin]]\n\
+                 name = \"flagged\"\n\
+                 path = \"main.c\"\n",
+                flags)
+    };
+    let dir = project("flagged",
+                      &[("flagged.toml", buildfile("")),
+                        ("main.c", String::from("int main() { return 0; }\n"))]);
+    assert!(yabs(&dir, &["build"]).status.success());
+    let log = logged(&yabs(&dir, &["build"]));
+    assert!(log.contains("'flagged' is up to date") && !log.contains("was built with"), "{}", log);
+    fs::write(dir.join("flagged.toml"), buildfile("linker-flags = [\"-Wl,-O1\"]\n")).unwrap();
+    let log = logged(&yabs(&dir, &["build"]));
+    assert!(log.contains("'flagged' is up to date") && log.contains("'flagged' was built with different flags"),
+            "{}",
+            log);
+}
+
+#[test]
+fn test_library_link_overrides() {
+    let project = TestProject::new("overridden_link",
+                                   "[project]\n\
+                                    compiler = \"./yabs-fake-cc\"\n\
+                                    file-extensions = [\"c\"]\n\
+                                    [[lib]]\n\
+                                    name = \"libplugin\"\n\
+                                    types = [\"dynamic\"]\n\
+                                    linker-flags = [\"Wl,--as-needed\"]\n\
+                                    library-directories = [\"vendor/lib\"]\n\
+                                    libraries = [\"m\"]\n",
+                                   &[("plugin.c", "int answer(void) { return 1; }\n"), ("vendor/lib/.keep", "")]);
+    project.build().unwrap();
+    let link = project.invocations().into_iter().find(|args| args.contains(&String::from("-shared"))).unwrap();
+    let position = |arg: &str| link.iter().position(|link_arg| link_arg == arg).unwrap_or_else(|| panic!("{:?}", link));
+    assert!(position("-Wl,--as-needed") < position("-o"), "{:?}", link);
+    assert!(position("-o") < position("-Lvendor/lib") && position("-Lvendor/lib") < position("-lm"),
+            "{:?}",
+            link);
+}
+
+#[test]
+fn test_batched_pipeline() {
+    let mut files = vec![("batched.toml",
+                          String::from("[project]\n\
+                                        file-extensions = [\"c\"]\n\
+                                        include = [\"include\"]\n\
+                                        pipeline = \"batched\"\n\
+                                        [[bin]]\n\
+                                        name = \"batched\"\n\
+                                        path = \"./src/main.c\"\n")),
+                         ("include/answer.h", String::from("#define ANSWER 3\n")),
+                         ("src/main.c",
+                          String::from("int f0(); int f1(); int f2();\n\
+                                        int main() { return f0() + f1() + f2() == 9 ? 0 : 1; }\n"))];
+    let names: Vec<String> = (0..3).map(|i| format!("src/f{}.c", i)).collect();
+    for (i, name) in names.iter().enumerate() {
+        files.push((name, format!("#include \"answer.h\"\nint f{}() {{ return ANSWER; }}\n", i)));
+    }
+    let dir = project("batched", &files);
+    let output = yabs(&dir, &["build", "-v"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{}", stdout);
+    assert_eq!(stdout.lines().filter(|line| line.contains("] cd ")).count(), 1, "{}", stdout);
+    assert!(Command::new(dir.join("batched")).status().unwrap().success());
+}
+
+#[test]
+fn test_batched_diagnostics() {
+    let dir = project("batched_warnings",
+                      &[("batched_warnings.toml",
+                         String::from("[project]\n\
+                                       file-extensions = [\"c\"]\n\
+                                       compiler-flags = [\"Wall\"]\n\
+                                       pipeline = \"batched\"\n\
+                                       [[bin]]\n\
+                                       name = \"batched_warnings\"\n\
+                                       path = \"./src/main.c\"\n")),
+                        ("src/main.c", String::from("int noisy();\nint main() { return noisy(); }\n")),
+                        ("src/noisy.c", String::from("int noisy() { int unused; return 0; }\n"))]);
+    let output = yabs(&dir, &["--json", "build", "-v"]);
+    let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+    assert!(output.status.success(), "{}", stdout);
+    assert!(stdout.contains("\"command\":\"cd ./src && "), "{}", stdout);
+    // The warning is told apart from the batch it was compiled in
+    assert!(stdout.contains("{\"type\":\"diagnostics\",\"sources\":[\"./src/noisy.c\"],\"output\":"),
+            "{}",
+            stdout);
+    assert!(!stdout.contains("\"sources\":[\"./src/main.c\""), "{}", stdout);
+    assert!(stdout.contains("\"files\":[{\"path\":\"src/noisy.c\",\"warnings\":1,\"errors\":0}]"),
+            "{}",
+            stdout);
+}
+
+#[test]
+fn test_batched_durations() {
+    let dir = project("batched_times",
+                      &[("batched_times.toml",
+                         String::from("[project]\n\
+                                       file-extensions = [\"c\"]\n\
+                                       compiler = \"./slow-cc\"\n\
+                                       pipeline = \"batched\"\n\
+                                       [[bin]]\n\
+                                       name = \"batched_times\"\n\
+                                       path = \"main.c\"\n")),
+                        ("slow-cc",
+                         String::from("#!/bin/sh\ncase \" $* \" in *\" -c \"*) sleep 0.5;; esac\nexec gcc \"$@\"\n")),
+                        ("main.c", String::from("int value();\nint main() { return value(); }\n")),
+                        ("value.c", String::from("int value() { return 0; }\n"))]);
+    Command::new("chmod").arg("+x").arg(dir.join("slow-cc")).status().unwrap();
+    let started = std::time::Instant::now();
+    assert!(yabs(&dir, &["build"]).status.success());
+    let elapsed = started.elapsed().as_secs_f64();
+    let seconds: Vec<f64> = dir.object_records().objects.values().map(|record| record.seconds).collect();
+    // Both were compiled in one batch, whose time is split between them
+    assert_eq!(seconds.len(), 2);
+    assert!(seconds[0] + seconds[1] >= 0.5, "{:?}", seconds);
+    assert!(seconds.iter().all(|seconds| *seconds <= elapsed / 2.0), "{:?} of {}", seconds, elapsed);
+}
+
+#[test]
+fn test_build_pgo() {
+    let dir = project("pgo",
+                      &[("pgo.toml",
+                         String::from("[project]\n\
+                                       file-extensions = [\"c\"]\n\
+                                       [[bin]]\n\
+                                       name = \"app\"\n\
+                                       path = \"./main.c\"\n")),
+                        ("main.c",
+                         String::from("int main(int argc, char **argv) { return argc > 5; }\n"))]);
+    let output = yabs(&dir, &["pgo", "true"]);
+    let stdout = logged(&output);
+    assert!(!output.status.success());
+    assert!(stdout.contains("produced no profile data"), "{}", stdout);
+    let output = yabs(&dir, &["pgo", "-v", "./app"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{}", stdout);
+    assert!(stdout.contains("-fprofile-use="), "{}", stdout);
+    assert!(!dir.join("main.o").exists());
+    assert!(Command::new(dir.join("app")).status().unwrap().success());
+}
+
+#[test]
+fn test_build_from_another_directory() {
+    let dir = project("elsewhere",
+                      &[("elsewhere.toml",
+                         String::from("[project]\n\
+                                       file-extensions = [\"c\"]\n\
+                                       [[bin]]\n\
+                                       name = \"elsewhere\"\n\
+                                       path = \"./src/main.c\"\n")),
+                        ("src/main.c", String::from("int main() { return 0; }\n"))]);
+    // The test process runs in the crate's directory, nowhere near the project
+    let cwd = env::current_dir().unwrap();
+    let mut build_file = util::build::find_build_file(&dir.join("src")).unwrap();
+    build_file.build(1).unwrap();
+    assert_eq!(env::current_dir().unwrap(), cwd);
+    assert!(dir.join("src/main.o").exists() && dir.join(".yabs").is_dir());
+    assert!(!Path::new(env!("CARGO_MANIFEST_DIR")).join(".yabs").exists());
+    assert!(Command::new(dir.join("elsewhere")).status().unwrap().success());
+}